@@ -1,4 +1,9 @@
 import api, { type ApiResponse } from './index'
+import type { ThemeConfigValue } from './themes'
+
+export type PostStatus = 'draft' | 'scheduled' | 'published' | 'unlisted'
+export type PostKind = 'post' | 'page'
+export type PostVisibility = 'public' | 'hidden' | 'protected'
 
 export interface Post {
     id: number
@@ -9,8 +14,15 @@ export interface Post {
     illustration?: string | null
     tags?: string[]
     categories?: string[]
-    hidden?: boolean
+    status?: PostStatus
+    publish_at?: string | null
+    kind?: PostKind
     functions?: string[]
+    meta?: Record<string, ThemeConfigValue>
+    pinned?: boolean
+    featured?: boolean
+    weight?: number
+    protected?: boolean
     created_at: string
     updated_at?: string | null
 }
@@ -30,11 +42,58 @@ export interface PostListParams {
     order?: 'asc' | 'desc'
     title?: string
     name?: string
+    q?: string
+    status?: PostStatus
+    visibility?: PostVisibility
+    kind?: PostKind
+    tag?: string
+    category?: string
+    author?: number
+    function?: string
+    created_from?: number
+    created_to?: number
+    updated_from?: number
+    updated_to?: number
 }
 
-export type PostWriteRequest = Omit<Partial<Post>, 'created_at' | 'updated_at'> & {
+export type PostFilter = Omit<PostListParams, 'page' | 'page_size' | 'sort_by' | 'order'>
+
+export type PostBulkAction =
+    | { action: 'hide' | 'unhide' | 'delete' }
+    | { action: 'add_terms' | 'remove_terms', tags?: string[], categories?: string[] }
+    | { action: 'set_functions', functions: string[] }
+    | { action: 'change_author', author: number }
+
+export type PostBulkRequest = PostBulkAction & ({ ids: number[] } | { filter: PostFilter })
+
+export interface PostBulkItem {
+    id: number
+    name: string | null
+    outcome: 'updated' | 'unchanged' | 'deleted' | 'not_found'
+}
+
+export interface PostBulkResponse {
+    matched: number
+    changed: number
+    items: PostBulkItem[]
+}
+
+export interface PostPreview {
+    id: number
+    post_id: number
+    token: string
+    url: string
+    expires_at: string
+    expired: boolean
+    created_by: number
+    created_at: string
+}
+
+export type PostWriteRequest = Omit<Partial<Post>, 'created_at' | 'updated_at' | 'publish_at' | 'protected'> & {
+    password?: string
     created_at?: number
     updated_at?: number
+    publish_at?: number
 }
 
 export const postsApi = {
@@ -56,5 +115,21 @@ export const postsApi = {
 
     delete: (id: number) => {
         return api.delete<ApiResponse<void>>(`/posts/${id}`)
+    },
+
+    bulk: (data: PostBulkRequest) => {
+        return api.post<ApiResponse<PostBulkResponse>>('/posts/bulk', data)
+    },
+
+    listPreviews: (id: number) => {
+        return api.get<ApiResponse<PostPreview[]>>(`/posts/${id}/previews`)
+    },
+
+    createPreview: (id: number, expiresIn?: number) => {
+        return api.post<ApiResponse<PostPreview>>(`/posts/${id}/previews`, { expires_in: expiresIn })
+    },
+
+    revokePreview: (id: number, previewId: number) => {
+        return api.delete<ApiResponse<void>>(`/posts/${id}/previews/${previewId}`)
     }
 }
@@ -0,0 +1,32 @@
+import api, { type ApiResponse } from './index'
+
+export interface Redirect {
+    id: number
+    source: string
+    match_kind: 'exact' | 'prefix' | 'regex'
+    target?: string | null
+    status_code: 301 | 302 | 410
+    hits: number
+    last_hit_at?: string | null
+    created_at: string
+}
+
+export type RedirectPayload = Pick<Redirect, 'source' | 'match_kind' | 'target' | 'status_code'>
+
+export const redirectsApi = {
+    list: () => {
+        return api.get<ApiResponse<Redirect[]>>('/redirects')
+    },
+
+    create: (data: RedirectPayload) => {
+        return api.post<ApiResponse<Redirect>>('/redirects', data)
+    },
+
+    update: (id: number, data: Partial<RedirectPayload>) => {
+        return api.put<ApiResponse<Redirect>>(`/redirects/${id}`, data)
+    },
+
+    delete: (id: number) => {
+        return api.delete<ApiResponse<void>>(`/redirects/${id}`)
+    }
+}
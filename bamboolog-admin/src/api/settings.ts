@@ -11,10 +11,16 @@ export interface SiteSettings {
     sitemap_enabled: boolean
     posts_per_page: number
     attachment_cache_control: string
+    ping_urls: string[]
+    cache_purge_urls: string[]
+    attachment_gc_enabled: boolean
+    code_highlight_theme: string
+    related_posts_count: number
 }
 
 export interface Settings {
     site: SiteSettings
+    highlight_themes?: string[]
 }
 
 export const settingsApi = {
@@ -31,6 +31,7 @@ export interface ThemeConfigField {
 export interface ThemeConfiguration {
     theme: ThemeDetails
     schema: ThemeConfigField[]
+    post_meta: ThemeConfigField[]
     values: Record<string, ThemeConfigValue>
 }
 
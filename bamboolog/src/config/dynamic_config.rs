@@ -1,8 +1,8 @@
 pub mod config_entries {
 
     use sea_orm::{
-        ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
-        ExprTrait, IntoActiveModel, QueryFilter,
+        ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr,
+        EntityTrait, ExprTrait, IntoActiveModel, QueryFilter,
     };
     use serde::{Serialize, de::DeserializeOwned};
 
@@ -25,12 +25,9 @@ pub mod config_entries {
             })
         }
 
-        pub async fn set<T>(
-            &self,
-            database: &DatabaseConnection,
-            value: Option<T>,
-        ) -> Result<(), ConfigError>
+        pub async fn set<T, C>(&self, database: &C, value: Option<T>) -> Result<(), ConfigError>
         where
+            C: ConnectionTrait,
             T: Serialize,
         {
             self.set_string(database, serde_json::to_string(&value)?)
@@ -38,11 +35,10 @@ pub mod config_entries {
             Ok(())
         }
 
-        pub async fn set_string(
-            &self,
-            database: &DatabaseConnection,
-            value: String,
-        ) -> Result<(), DbErr> {
+        pub async fn set_string<C>(&self, database: &C, value: String) -> Result<(), DbErr>
+        where
+            C: ConnectionTrait,
+        {
             let exists = config_entry::Entity::find()
                 .filter(
                     config_entry::Column::Component
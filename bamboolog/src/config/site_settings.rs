@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::utils::RenderOptions;
+
 pub const DEFAULT_ATTACHMENT_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +24,23 @@ pub struct SiteSettings {
     pub posts_per_page: u64,
     #[serde(default = "default_attachment_cache_control")]
     pub attachment_cache_control: String,
+    /// Endpoints notified with a WebSub-style publish request after content changes.
+    #[serde(default)]
+    pub ping_urls: Vec<String>,
+    /// Endpoints sent an empty POST after content changes, such as the purge
+    /// hook of a CDN or reverse proxy caching the public pages.
+    #[serde(default)]
+    pub cache_purge_urls: Vec<String>,
+    /// Deletes attachments that no post or setting references any more.
+    #[serde(default)]
+    pub attachment_gc_enabled: bool,
+    /// Syntax highlighting theme for code blocks, rendered on the server.
+    /// Empty leaves code blocks to client-side highlighters.
+    #[serde(default)]
+    pub code_highlight_theme: String,
+    /// How many related posts the post layout receives; 0 turns them off.
+    #[serde(default = "default_related_posts_count")]
+    pub related_posts_count: u64,
 }
 
 fn default_language() -> String {
@@ -32,6 +51,10 @@ fn default_posts_per_page() -> u64 {
     10
 }
 
+fn default_related_posts_count() -> u64 {
+    5
+}
+
 fn default_attachment_cache_control() -> String {
     DEFAULT_ATTACHMENT_CACHE_CONTROL.to_string()
 }
@@ -57,6 +80,11 @@ impl Default for SiteSettings {
             sitemap_enabled: default_sitemap_enabled(),
             posts_per_page: default_posts_per_page(),
             attachment_cache_control: default_attachment_cache_control(),
+            ping_urls: Vec::new(),
+            cache_purge_urls: Vec::new(),
+            attachment_gc_enabled: false,
+            code_highlight_theme: String::new(),
+            related_posts_count: default_related_posts_count(),
         }
     }
 }
@@ -65,6 +93,17 @@ impl SiteSettings {
     pub fn public_posts_per_page(&self) -> u64 {
         self.posts_per_page.clamp(1, 100)
     }
+
+    pub fn public_related_posts_count(&self) -> u64 {
+        self.related_posts_count.min(20)
+    }
+
+    pub fn render_options(&self) -> RenderOptions<'static> {
+        RenderOptions {
+            highlight_code: !self.code_highlight_theme.is_empty(),
+            ..Default::default()
+        }
+    }
 }
 
 #[cfg(test)]
@@ -110,5 +149,14 @@ mod tests {
             .public_posts_per_page(),
             100
         );
+        assert_eq!(SiteSettings::default().public_related_posts_count(), 5);
+        assert_eq!(
+            SiteSettings {
+                related_posts_count: 500,
+                ..Default::default()
+            }
+            .public_related_posts_count(),
+            20
+        );
     }
 }
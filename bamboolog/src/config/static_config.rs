@@ -22,6 +22,18 @@ fn get_default_asset_dir() -> String {
 }
 
 impl ApplicationConfiguration {
+    /// An in-memory configuration for tests that keeps assets in `asset_dir`.
+    #[cfg(test)]
+    pub(crate) fn for_tests(asset_dir: impl AsRef<Path>) -> std::sync::Arc<Self> {
+        let asset_dir = asset_dir.as_ref();
+        std::sync::Arc::new(Self {
+            listen_addr: "127.0.0.1:0".to_string(),
+            database: "sqlite::memory:".to_string(),
+            raw_asset_dir: asset_dir.display().to_string(),
+            asset_dir: asset_dir.to_path_buf(),
+        })
+    }
+
     pub fn load() -> Result<Self, anyhow::Error> {
         let config_path: String = env::var("CONFIG_PATH").unwrap_or_else(|_| "config.toml".into());
         Self::from_path(PathBuf::from(config_path))
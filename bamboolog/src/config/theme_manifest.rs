@@ -14,6 +14,12 @@ pub struct ThemeManifest {
     pub layout_mapping: HashMap<String, String>,
     #[serde(default)]
     pub config: Vec<ThemeConfigField>,
+    #[serde(default)]
+    pub shortcodes: Vec<ThemeShortcode>,
+    /// Per-post fields the theme reads from `post.meta`, such as a subtitle or
+    /// a hero colour.
+    #[serde(default)]
+    pub post_meta: Vec<ThemeConfigField>,
 }
 
 impl ThemeManifest {
@@ -30,66 +36,141 @@ impl ThemeManifest {
         reject_unknown: bool,
     ) -> Result<Map<String, Value>, ThemeConfigError> {
         self.validate_config_schema()?;
+        resolve_fields(&self.config, values, reject_unknown)
+    }
 
-        if reject_unknown {
-            for key in values.keys() {
-                if !self.config.iter().any(|field| field.key == *key) {
-                    return Err(ThemeConfigError::UnknownField(key.clone()));
-                }
-            }
+    /// Validates post metadata against `post_meta` and fills in its defaults.
+    /// Undeclared keys are only accepted when `stored` already has them, since
+    /// another theme may have set them; their stored values are kept as they are.
+    pub fn resolve_post_meta(
+        &self,
+        values: &Map<String, Value>,
+        stored: &Map<String, Value>,
+    ) -> Result<Map<String, Value>, ThemeConfigError> {
+        validate_fields(&self.post_meta)?;
+        let declared = |key: &String| self.post_meta.iter().any(|field| field.key == *key);
+        if let Some(key) = values
+            .keys()
+            .find(|key| !declared(key) && !stored.contains_key(*key))
+        {
+            return Err(ThemeConfigError::UnknownField(key.clone()));
         }
 
-        let mut resolved = Map::new();
-        for field in &self.config {
-            let value = match values.get(&field.key) {
-                Some(value) => Some(value.clone()),
-                None => field.default.as_ref().map(toml_value_to_json).transpose()?,
-            };
-            match value {
-                Some(value) => {
-                    field.validate_value(&value)?;
-                    resolved.insert(field.key.clone(), value);
-                }
-                None if field.required => {
-                    return Err(ThemeConfigError::RequiredField(field.key.clone()));
-                }
-                None => {}
+        let mut resolved = resolve_fields(&self.post_meta, values, false)?;
+        for (key, value) in stored {
+            if !declared(key) {
+                resolved.insert(key.clone(), value.clone());
             }
         }
-
         Ok(resolved)
     }
 
     pub fn validate_config_schema(&self) -> Result<(), ThemeConfigError> {
-        let mut keys = std::collections::HashSet::new();
-        for field in &self.config {
-            if field.key.trim().is_empty() || !keys.insert(&field.key) {
+        validate_fields(&self.config)?;
+        validate_fields(&self.post_meta)
+    }
+
+    pub fn validate_shortcodes(&self) -> Result<(), ThemeConfigError> {
+        let mut names = std::collections::HashSet::new();
+        for shortcode in &self.shortcodes {
+            let valid_name = !shortcode.name.is_empty()
+                && shortcode
+                    .name
+                    .bytes()
+                    .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_'));
+            if !valid_name || !names.insert(&shortcode.name) {
                 return Err(ThemeConfigError::InvalidSchema(format!(
-                    "configuration keys must be unique and non-empty: `{}`",
-                    field.key
+                    "shortcode names must be unique and use only letters, digits, `-` and `_`: `{}`",
+                    shortcode.name
                 )));
             }
-            if field.kind == ThemeConfigFieldType::Select && field.options.is_empty() {
+            if shortcode.template.trim().is_empty() {
                 return Err(ThemeConfigError::InvalidSchema(format!(
-                    "select field `{}` has no options",
-                    field.key
+                    "shortcode `{}` has no template",
+                    shortcode.name
                 )));
             }
-            if field
-                .min
-                .is_some_and(|min| field.max.is_some_and(|max| min > max))
-            {
-                return Err(ThemeConfigError::InvalidSchema(format!(
-                    "field `{}` has a minimum greater than its maximum",
-                    field.key
-                )));
+        }
+        Ok(())
+    }
+}
+
+fn resolve_fields(
+    fields: &[ThemeConfigField],
+    values: &Map<String, Value>,
+    reject_unknown: bool,
+) -> Result<Map<String, Value>, ThemeConfigError> {
+    if reject_unknown {
+        for key in values.keys() {
+            if !fields.iter().any(|field| field.key == *key) {
+                return Err(ThemeConfigError::UnknownField(key.clone()));
             }
-            if let Some(default) = &field.default {
-                field.validate_value(&toml_value_to_json(default)?)?;
+        }
+    }
+
+    let mut resolved = Map::new();
+    for field in fields {
+        let value = match values.get(&field.key) {
+            Some(value) => Some(value.clone()),
+            None => field.default.as_ref().map(toml_value_to_json).transpose()?,
+        };
+        match value {
+            Some(value) => {
+                field.validate_value(&value)?;
+                resolved.insert(field.key.clone(), value);
+            }
+            None if field.required => {
+                return Err(ThemeConfigError::RequiredField(field.key.clone()));
             }
+            None => {}
         }
-        Ok(())
     }
+
+    Ok(resolved)
+}
+
+fn validate_fields(fields: &[ThemeConfigField]) -> Result<(), ThemeConfigError> {
+    let mut keys = std::collections::HashSet::new();
+    for field in fields {
+        if field.key.trim().is_empty() || !keys.insert(&field.key) {
+            return Err(ThemeConfigError::InvalidSchema(format!(
+                "configuration keys must be unique and non-empty: `{}`",
+                field.key
+            )));
+        }
+        if field.kind == ThemeConfigFieldType::Select && field.options.is_empty() {
+            return Err(ThemeConfigError::InvalidSchema(format!(
+                "select field `{}` has no options",
+                field.key
+            )));
+        }
+        if field
+            .min
+            .is_some_and(|min| field.max.is_some_and(|max| min > max))
+        {
+            return Err(ThemeConfigError::InvalidSchema(format!(
+                "field `{}` has a minimum greater than its maximum",
+                field.key
+            )));
+        }
+        if let Some(default) = &field.default {
+            field.validate_value(&toml_value_to_json(default)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// A shortcode the theme implements as a template. Themes may replace a
+/// built-in shortcode by declaring one with the same name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThemeShortcode {
+    pub name: String,
+    /// Template path relative to the theme's `layouts` directory.
+    pub template: String,
+    /// Hosts the template may embed with an `<iframe>`. Embeds must use https;
+    /// any other iframe source is removed.
+    #[serde(default)]
+    pub iframe_hosts: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -138,14 +219,14 @@ impl ThemeConfigField {
                 message: "must be one of the declared options".to_string(),
             });
         }
-        if let Some(number) = value.as_f64() {
-            if self.min.is_some_and(|min| number < min) || self.max.is_some_and(|max| number > max)
-            {
-                return Err(ThemeConfigError::InvalidValue {
-                    key: self.key.clone(),
-                    message: "is outside the allowed range".to_string(),
-                });
-            }
+        if let Some(number) = value.as_f64()
+            && (self.min.is_some_and(|min| number < min)
+                || self.max.is_some_and(|max| number > max))
+        {
+            return Err(ThemeConfigError::InvalidValue {
+                key: self.key.clone(),
+                message: "is outside the allowed range".to_string(),
+            });
         }
         Ok(())
     }
@@ -280,4 +361,99 @@ mod tests {
 
         assert_eq!(config["navigation"][0]["label"], "About");
     }
+
+    #[test]
+    fn resolves_declared_post_meta_and_keeps_stored_keys() {
+        let manifest: ThemeManifest = toml::from_str(
+            r#"
+                [[post_meta]]
+                key = "subtitle"
+                label = "Subtitle"
+                type = "string"
+                required = true
+
+                [[post_meta]]
+                key = "hero_colour"
+                label = "Hero colour"
+                type = "select"
+                default = "blue"
+                options = [{ label = "Blue", value = "blue" }, { label = "Red", value = "red" }]
+            "#,
+        )
+        .unwrap();
+        let stored: serde_json::Map<_, _> =
+            serde_json::from_value(json!({ "subtitle": "Draft", "source": "elsewhere" })).unwrap();
+        let meta = manifest
+            .resolve_post_meta(
+                &serde_json::from_value(json!({ "subtitle": "Part one", "source": "changed" }))
+                    .unwrap(),
+                &stored,
+            )
+            .unwrap();
+
+        assert_eq!(meta["subtitle"], "Part one");
+        assert_eq!(meta["hero_colour"], "blue");
+        assert_eq!(meta["source"], "elsewhere");
+        for invalid in [
+            json!({}),
+            json!({ "subtitle": 1 }),
+            json!({ "subtitle": "Part one", "hero_colour": "green" }),
+            json!({ "subtitle": "Part one", "unknown": true }),
+        ] {
+            let invalid = serde_json::from_value(invalid).unwrap();
+            assert!(manifest.resolve_post_meta(&invalid, &stored).is_err());
+        }
+        assert!(
+            manifest
+                .resolve_post_meta(
+                    &serde_json::from_value(json!({ "subtitle": "Part one", "source": "x" }))
+                        .unwrap(),
+                    &serde_json::Map::new(),
+                )
+                .is_err()
+        );
+        assert!(
+            manifest
+                .resolve_config(&serde_json::Map::new(), true)
+                .is_ok()
+        );
+
+        let free_form = serde_json::from_value(json!({ "anything": [1, 2] })).unwrap();
+        assert!(
+            ThemeManifest::default()
+                .resolve_post_meta(&free_form, &serde_json::Map::new())
+                .is_err()
+        );
+        assert_eq!(
+            ThemeManifest::default()
+                .resolve_post_meta(&free_form, &free_form)
+                .unwrap(),
+            free_form
+        );
+    }
+
+    #[test]
+    fn validates_shortcode_declarations() {
+        let manifest: ThemeManifest = toml::from_str(
+            r#"
+                [[shortcodes]]
+                name = "vimeo"
+                template = "shortcodes/vimeo.html"
+                iframe_hosts = ["player.vimeo.com"]
+            "#,
+        )
+        .unwrap();
+        assert!(manifest.validate_shortcodes().is_ok());
+        assert_eq!(manifest.shortcodes[0].iframe_hosts, ["player.vimeo.com"]);
+
+        for name in ["", "two words", "vimeo"] {
+            let mut invalid = manifest.clone();
+            invalid.shortcodes.push(super::ThemeShortcode {
+                name: name.to_string(),
+                template: "shortcodes/other.html".to_string(),
+                iframe_hosts: Vec::new(),
+            });
+            assert!(invalid.validate_shortcodes().is_err(), "{name:?}");
+        }
+    }
 }
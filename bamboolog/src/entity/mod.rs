@@ -1,9 +1,15 @@
 pub mod attachment;
 pub mod category;
 pub mod config_entry;
+pub mod job;
 pub mod post;
 pub mod post_category;
+pub mod post_preview;
+pub mod post_render;
+pub mod post_revision;
+pub mod post_slug;
 pub mod post_tag;
+pub mod redirect;
 pub mod storage_engine;
 pub mod tag;
 pub mod user;
@@ -5,6 +5,48 @@ use serde::{Deserialize, Serialize};
 #[serde(transparent)]
 pub struct PostFunctions(pub Vec<String>);
 
+/// Theme-defined fields of a post, validated against the `post_meta` schema
+/// of the active theme when the post is saved.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, FromJsonQueryResult)]
+#[serde(transparent)]
+pub struct PostMeta(pub serde_json::Map<String, serde_json::Value>);
+
+/// Publication lifecycle of a post.
+///
+/// Only published posts, and scheduled posts whose `publish_at` has passed,
+/// appear in public listings. Unlisted posts are reachable by URL only.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum PostStatus {
+    #[sea_orm(string_value = "draft")]
+    Draft,
+    #[sea_orm(string_value = "scheduled")]
+    Scheduled,
+    #[default]
+    #[sea_orm(string_value = "published")]
+    Published,
+    #[sea_orm(string_value = "unlisted")]
+    Unlisted,
+}
+
+/// Chronological posts appear in listings and feeds under `/posts/{name}`;
+/// pages are standalone documents served from `/{name}`.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum PostKind {
+    #[default]
+    #[sea_orm(string_value = "post")]
+    Post,
+    #[sea_orm(string_value = "page")]
+    Page,
+}
+
 #[sea_orm::model]
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
 #[sea_orm(table_name = "posts")]
@@ -18,9 +60,27 @@ pub struct Model {
     pub author: i32,
     pub description: Option<String>,
     pub illustration: Option<String>,
-    pub hidden: Option<bool>,
+    #[sea_orm(default_value = "published", indexed)]
+    pub status: PostStatus,
+    pub publish_at: Option<DateTimeUtc>,
+    #[sea_orm(default_value = "post", indexed)]
+    pub kind: PostKind,
     #[sea_orm(default_value = "[]")]
     pub functions: PostFunctions,
+    #[sea_orm(default_value = "{}")]
+    pub meta: PostMeta,
+    /// Pinned posts lead the home page, ahead of newer posts.
+    #[sea_orm(default_value = false)]
+    pub pinned: bool,
+    /// Featured posts are also listed on their own on the home page.
+    #[sea_orm(default_value = false)]
+    pub featured: bool,
+    /// Orders pinned posts, and featured posts, among themselves; higher
+    /// weights come first.
+    #[sea_orm(default_value = 0)]
+    pub weight: i32,
+    /// Bcrypt hash of the password readers must enter; `None` for open posts.
+    pub password_hash: Option<String>,
     #[sea_orm(default_expr = "Expr::current_timestamp()")]
     pub created_at: DateTimeUtc,
     pub updated_at: Option<DateTimeUtc>,
@@ -0,0 +1,23 @@
+use sea_orm::entity::prelude::*;
+
+/// A link that lets someone without an account read a post before it is
+/// public. Deleting the row revokes the link.
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "post_previews")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub post_id: i32,
+    #[sea_orm(unique, indexed)]
+    pub token: String,
+    pub expires_at: DateTimeUtc,
+    pub created_by: i32,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created_at: DateTimeUtc,
+    #[sea_orm(belongs_to, from = "post_id", to = "id")]
+    pub post: BelongsTo<super::post::Entity>,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
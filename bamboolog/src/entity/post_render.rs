@@ -0,0 +1,37 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::TocEntry;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, FromJsonQueryResult)]
+#[serde(transparent)]
+pub struct RenderedToc(pub Vec<TocEntry>);
+
+/// The rendered form of a post's content, kept so page views do not compile
+/// Markdown again. A row is stale once its `render_key` no longer matches the
+/// post; see `PostRenderService`.
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "post_renders")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub post_id: i32,
+    /// Digest of the content and of every setting that shapes the output.
+    pub render_key: String,
+    pub html: String,
+    /// Plain-text opening of the rendered content.
+    pub excerpt: String,
+    /// Words, counting every CJK character as one.
+    pub word_count: i64,
+    /// CJK characters among `word_count`, which are read at a different pace.
+    #[sea_orm(default_value = 0)]
+    pub cjk_character_count: i64,
+    #[sea_orm(default_value = "[]")]
+    pub toc: RenderedToc,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub rendered_at: DateTimeUtc,
+    #[sea_orm(belongs_to, from = "post_id", to = "id")]
+    pub post: BelongsTo<super::post::Entity>,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,25 @@
+use sea_orm::entity::prelude::*;
+
+/// Snapshot of a post's editable text, written every time the post is saved.
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "post_revisions")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub post_id: i32,
+    pub title: String,
+    pub name: String,
+    pub content: String,
+    pub description: Option<String>,
+    pub illustration: Option<String>,
+    /// The user who saved this revision, which may differ from the post author.
+    pub editor: i32,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created_at: DateTimeUtc,
+    #[sea_orm(belongs_to, from = "post_id", to = "id")]
+    pub post: BelongsTo<super::post::Entity>,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,44 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter, DeriveActiveEnum, Serialize, Deserialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+#[serde(rename_all = "snake_case")]
+pub enum RedirectMatch {
+    /// The request path equals `source`.
+    #[sea_orm(string_value = "exact")]
+    Exact,
+    /// The request path starts with `source`; the remainder is appended to
+    /// `target`.
+    #[sea_orm(string_value = "prefix")]
+    Prefix,
+    /// `source` is a regular expression that must match the whole path;
+    /// `target` may refer to its captures as `$1` or `${name}`.
+    #[sea_orm(string_value = "regex")]
+    Regex,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize, Deserialize)]
+#[sea_orm(table_name = "redirects")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub source: String,
+    #[sea_orm(default_value = "exact")]
+    pub match_kind: RedirectMatch,
+    /// Unused for 410 rules.
+    pub target: Option<String>,
+    #[sea_orm(default_value = 301)]
+    pub status_code: i32,
+    #[sea_orm(default_value = 0)]
+    pub hits: i64,
+    pub last_hit_at: Option<DateTimeUtc>,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
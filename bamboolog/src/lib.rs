@@ -3,6 +3,7 @@ pub mod entity;
 pub mod maintenance;
 pub mod router;
 pub mod service;
+pub mod static_site;
 pub mod storage;
 pub mod utils;
 pub mod web;
@@ -1,7 +1,9 @@
-use bamboolog::{config::ApplicationConfiguration, maintenance, web};
+use bamboolog::{
+    config::ApplicationConfiguration, maintenance, service::export::ExportOptions, web,
+};
 use clap::{Parser, Subcommand};
 use dotenvy::dotenv;
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
 
 #[derive(Debug, Parser)]
@@ -16,10 +18,52 @@ struct Cli {
 
 #[derive(Debug, Subcommand)]
 enum Command {
-    /// Synchronize database entities and seed the default storage engine.
+    /// Synchronize database entities and the search index, and seed the default storage engine.
     SyncEntitiesEf,
     /// Interactively create an administrator account.
     CreateAdmin,
+    /// Render every post again and store the HTML, excerpt, word count and table of contents.
+    RebuildRenderedPosts,
+    /// Export every post, attachment, the site settings and the theme configuration to a ZIP
+    /// archive that `import-markdown` reads back.
+    Export {
+        /// Where to write the archive.
+        path: PathBuf,
+        /// Keep the password hashes of protected posts. Without it they are imported as drafts
+        /// that need a new password.
+        #[arg(long)]
+        include_secrets: bool,
+    },
+    /// Render every public page, feed and theme file, with the attachments they use, into a
+    /// directory that any static file host can serve.
+    BuildStatic {
+        /// The directory to write the site to; existing files are overwritten.
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Import a directory or ZIP archive of Markdown posts with YAML or TOML front matter.
+    ImportMarkdown {
+        /// The site directory or archive, such as a Hugo `content` directory or a Jekyll site.
+        path: PathBuf,
+        /// Username of the author of the imported posts; defaults to the first user.
+        #[arg(long)]
+        author: Option<String>,
+        /// Write the posts; without it the import is only reported.
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Import a WordPress export (WXR), optionally zipped with its `wp-content/uploads` directory.
+    ImportWordpress {
+        /// The exported `.xml` file, or a directory or archive containing it.
+        path: PathBuf,
+        /// Username of the author of posts whose WordPress author has no account here;
+        /// defaults to the first user.
+        #[arg(long)]
+        author: Option<String>,
+        /// Write the posts; without it the import is only reported.
+        #[arg(long)]
+        apply: bool,
+    },
 }
 
 fn configure_tracing() {
@@ -29,7 +73,7 @@ fn configure_tracing() {
         .init();
 }
 
-async fn run_maintenance(command: Command, config: &ApplicationConfiguration) {
+async fn run_maintenance(command: Command, config: &Arc<ApplicationConfiguration>) {
     let database = config
         .connect_database()
         .await
@@ -40,6 +84,32 @@ async fn run_maintenance(command: Command, config: &ApplicationConfiguration) {
             .await
             .expect("Failed to sync entities"),
         Command::CreateAdmin => maintenance::create_admin(&database).await,
+        Command::RebuildRenderedPosts => maintenance::rebuild_rendered_posts(&database, config)
+            .await
+            .expect("Failed to rebuild rendered posts"),
+        Command::Export {
+            path,
+            include_secrets,
+        } => maintenance::export_site(&database, config, &path, ExportOptions { include_secrets })
+            .await
+            .expect("Failed to export the site"),
+        Command::BuildStatic { out } => maintenance::build_static(&database, config, &out)
+            .await
+            .expect("Failed to build the static site"),
+        Command::ImportMarkdown {
+            path,
+            author,
+            apply,
+        } => maintenance::import_markdown(&database, config, &path, author.as_deref(), apply)
+            .await
+            .expect("Failed to import Markdown posts"),
+        Command::ImportWordpress {
+            path,
+            author,
+            apply,
+        } => maintenance::import_wordpress(&database, config, &path, author.as_deref(), apply)
+            .await
+            .expect("Failed to import the WordPress export"),
     }
 }
 
@@ -73,9 +143,46 @@ mod tests {
     fn parses_each_maintenance_subcommand() {
         let sync = Cli::try_parse_from(["bamboolog", "sync-entities-ef"]).unwrap();
         let create_admin = Cli::try_parse_from(["bamboolog", "create-admin"]).unwrap();
+        let rebuild = Cli::try_parse_from(["bamboolog", "rebuild-rendered-posts"]).unwrap();
+        let import = Cli::try_parse_from([
+            "bamboolog",
+            "import-markdown",
+            "site.zip",
+            "--author",
+            "admin",
+            "--apply",
+        ])
+        .unwrap();
+        let export = Cli::try_parse_from(["bamboolog", "export", "site.zip"]).unwrap();
+        let wordpress =
+            Cli::try_parse_from(["bamboolog", "import-wordpress", "export.xml"]).unwrap();
+        let build_static =
+            Cli::try_parse_from(["bamboolog", "build-static", "--out", "public"]).unwrap();
 
         assert!(matches!(sync.command, Some(Command::SyncEntitiesEf)));
         assert!(matches!(create_admin.command, Some(Command::CreateAdmin)));
+        assert!(matches!(
+            rebuild.command,
+            Some(Command::RebuildRenderedPosts)
+        ));
+        assert!(matches!(
+            import.command,
+            Some(Command::ImportMarkdown { path, author, apply: true })
+                if path.to_str() == Some("site.zip") && author.as_deref() == Some("admin")
+        ));
+        assert!(matches!(
+            export.command,
+            Some(Command::Export { path, include_secrets: false }) if path.to_str() == Some("site.zip")
+        ));
+        assert!(matches!(
+            wordpress.command,
+            Some(Command::ImportWordpress { path, author: None, apply: false })
+                if path.to_str() == Some("export.xml")
+        ));
+        assert!(matches!(
+            build_static.command,
+            Some(Command::BuildStatic { out }) if out.to_str() == Some("public")
+        ));
     }
 
     #[test]
@@ -1,7 +1,30 @@
-use crate::entity::user;
-use anyhow::Result;
-use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, Set};
-use std::io::{self, Write};
+use crate::{
+    config::ApplicationConfiguration,
+    entity::user,
+    service::{
+        export::{ExportOptions, ExportService},
+        import::{ImportFiles, ImportReport},
+        markdown_import::MarkdownImportService,
+        post_render::PostRenderService,
+        reloadable::ReloadableService,
+        search::SearchService,
+        site_settings::SiteSettingsService,
+        storage::StorageService,
+        theme::ThemeService,
+        wordpress_import::WordPressImportService,
+    },
+    static_site::build_static_site,
+};
+use anyhow::{Context, Result};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseBackend, DatabaseConnection,
+    EntityTrait, FromQueryResult, QueryFilter, QueryOrder, Set, Statement,
+};
+use std::{
+    io::{self, Write},
+    path::Path,
+    sync::Arc,
+};
 
 pub async fn sync_entities(database: &DatabaseConnection) -> Result<()> {
     tracing::info!("Sync entities (Entity first)");
@@ -10,6 +33,9 @@ pub async fn sync_entities(database: &DatabaseConnection) -> Result<()> {
         .get_schema_registry("bamboolog::entity::*")
         .sync(database)
         .await?;
+    migrate_legacy_hidden_posts(database).await?;
+    // Sync only knows about tables and columns, not full-text indexes.
+    SearchService::ensure_index(database).await?;
 
     // Seed default storage engine
     use crate::entity::storage_engine;
@@ -34,6 +60,261 @@ pub async fn sync_entities(database: &DatabaseConnection) -> Result<()> {
 
     Ok(())
 }
+
+/// Posts created before the publication lifecycle used a nullable `hidden`
+/// flag. Sync never drops that column, so hidden posts are moved to drafts
+/// once and the flag is cleared to keep later syncs from touching them again.
+async fn migrate_legacy_hidden_posts(database: &DatabaseConnection) -> Result<()> {
+    // Databases created after the lifecycle change have no `hidden` column.
+    if !has_legacy_hidden_column(database).await? {
+        return Ok(());
+    }
+    let result = database
+        .execute_unprepared("UPDATE posts SET status = 'draft', hidden = NULL WHERE hidden = TRUE")
+        .await
+        .context("Failed to move legacy hidden posts to drafts")?;
+    if result.rows_affected() > 0 {
+        tracing::info!(
+            "Moved {} legacy hidden posts to drafts",
+            result.rows_affected()
+        );
+    }
+    Ok(())
+}
+
+#[derive(FromQueryResult)]
+struct ColumnCount {
+    count: i64,
+}
+
+async fn has_legacy_hidden_column(database: &DatabaseConnection) -> Result<bool> {
+    let backend = database.get_database_backend();
+    let sql = match backend {
+        DatabaseBackend::Sqlite => {
+            "SELECT COUNT(*) AS count FROM pragma_table_info('posts') WHERE name = 'hidden'"
+        }
+        DatabaseBackend::Postgres => {
+            "SELECT COUNT(*) AS count FROM information_schema.columns \
+            WHERE table_schema = current_schema() AND table_name = 'posts' AND column_name = 'hidden'"
+        }
+        DatabaseBackend::MySql => {
+            "SELECT COUNT(*) AS count FROM information_schema.columns \
+            WHERE table_schema = DATABASE() AND table_name = 'posts' AND column_name = 'hidden'"
+        }
+        backend => anyhow::bail!("Unsupported database backend {backend:?}"),
+    };
+    let count = ColumnCount::find_by_statement(Statement::from_string(backend, sql))
+        .one(database)
+        .await?
+        .map_or(0, |row| row.count);
+    Ok(count > 0)
+}
+
+/// Stores a fresh render of every post, as the web server would on the next
+/// view. Useful after upgrading or after changing the active theme's files.
+pub async fn rebuild_rendered_posts(
+    database: &DatabaseConnection,
+    config: &Arc<ApplicationConfiguration>,
+) -> Result<()> {
+    let (site_settings, theme_service) = load_theme_service(database, config).await;
+    let site = site_settings.read().await.clone();
+
+    let rebuilt = PostRenderService::rebuild_all(database, &theme_service, &site).await?;
+    println!("Rebuilt {rebuilt} rendered posts.");
+    Ok(())
+}
+
+/// The site settings and theme services, loaded as the server loads them.
+async fn load_theme_service(
+    database: &DatabaseConnection,
+    config: &Arc<ApplicationConfiguration>,
+) -> (SiteSettingsService, ThemeService) {
+    let site_settings = SiteSettingsService::new(database.to_owned());
+    site_settings.reload().await;
+    let theme_service = ThemeService::new(
+        database.to_owned(),
+        config.to_owned(),
+        site_settings.clone(),
+    );
+    theme_service.reload().await;
+    (site_settings, theme_service)
+}
+
+/// Writes the whole site to a ZIP archive at `path`, which `import-markdown`
+/// reads back into another instance.
+pub async fn export_site(
+    database: &DatabaseConnection,
+    config: &Arc<ApplicationConfiguration>,
+    path: &Path,
+    options: ExportOptions,
+) -> Result<()> {
+    let (_, theme_service) = load_theme_service(database, config).await;
+    let storage = StorageService::new(config.to_owned());
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    // Written next to the destination so a failed export leaves nothing behind.
+    let archive = tempfile::NamedTempFile::new_in(directory)
+        .with_context(|| format!("Failed to create a file in {}", directory.display()))?;
+    let writer = archive.as_file().try_clone()?;
+    let (_, summary) =
+        ExportService::export(database, &storage, &theme_service, writer, options).await?;
+    archive
+        .persist(path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    println!(
+        "Exported {} posts and {} attachments to {}.",
+        summary.posts,
+        summary.attachments,
+        path.display()
+    );
+    Ok(())
+}
+
+/// Renders the public site into `out` as plain files that need no running
+/// server.
+pub async fn build_static(
+    database: &DatabaseConnection,
+    config: &Arc<ApplicationConfiguration>,
+    out: &Path,
+) -> Result<()> {
+    let summary = build_static_site(config, database.to_owned(), out).await?;
+    println!(
+        "Wrote {} pages and {} attachments to {}.",
+        summary.pages,
+        summary.attachments,
+        out.display()
+    );
+    for path in &summary.skipped {
+        println!("Skipped {path}, which needs the server.");
+    }
+    Ok(())
+}
+
+/// Imports a directory or ZIP archive of Markdown posts. Without `apply` it
+/// only prints what would be imported.
+pub async fn import_markdown(
+    database: &DatabaseConnection,
+    config: &Arc<ApplicationConfiguration>,
+    path: &Path,
+    author: Option<&str>,
+    apply: bool,
+) -> Result<()> {
+    let files = if path.is_dir() {
+        ImportFiles::from_directory(path)?
+    } else {
+        ImportFiles::from_zip(
+            &std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?,
+        )?
+    };
+    let author = import_author(database, author).await?;
+    let storage = StorageService::new(config.to_owned());
+    let (_, theme_service) = load_theme_service(database, config).await;
+    let report =
+        MarkdownImportService::import(database, &storage, &theme_service, &files, author.id, apply)
+            .await?;
+    print_import_report(&report);
+    Ok(())
+}
+
+/// Imports a WordPress export, either a single `.xml` file or a directory or
+/// ZIP archive holding exports and optionally `wp-content/uploads`.
+pub async fn import_wordpress(
+    database: &DatabaseConnection,
+    config: &Arc<ApplicationConfiguration>,
+    path: &Path,
+    author: Option<&str>,
+    apply: bool,
+) -> Result<()> {
+    let files = if path.is_dir() {
+        ImportFiles::from_directory(path)?
+    } else {
+        let data =
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("xml"))
+        {
+            ImportFiles::from_iter([(path.display().to_string(), data)])
+        } else {
+            ImportFiles::from_zip(&data)?
+        }
+    };
+    let author = import_author(database, author).await?;
+    let storage = StorageService::new(config.to_owned());
+    let report =
+        WordPressImportService::import(database, &storage, &files, author.id, apply).await?;
+    print_import_report(&report);
+    Ok(())
+}
+
+/// The named user, or the first account when no name is given.
+async fn import_author(
+    database: &DatabaseConnection,
+    username: Option<&str>,
+) -> Result<user::Model> {
+    let mut select = user::Entity::find().order_by_asc(user::Column::Id);
+    if let Some(username) = username {
+        select = select.filter(user::Column::Username.eq(username));
+    }
+    select.one(database).await?.ok_or_else(|| match username {
+        Some(username) => anyhow::anyhow!("No user named `{username}` exists"),
+        None => anyhow::anyhow!("No user exists yet; run create-admin first"),
+    })
+}
+
+fn print_import_report(report: &ImportReport) {
+    for post in &report.posts {
+        println!(
+            "{} {:?} `{}` from {}",
+            if report.applied {
+                "Imported"
+            } else {
+                "Would import"
+            },
+            post.kind,
+            post.name,
+            post.source
+        );
+    }
+    for attachment in &report.attachments {
+        match &attachment.url {
+            Some(url) => println!("Stored {} at {url}", attachment.source),
+            None => println!("Would store {}", attachment.source),
+        }
+    }
+    if report.settings {
+        println!(
+            "{} the site settings and theme configuration",
+            if report.applied {
+                "Restored"
+            } else {
+                "Would restore"
+            }
+        );
+    }
+    for redirect in &report.redirects {
+        println!("Redirect {} to {}", redirect.source, redirect.target);
+    }
+    for issue in &report.warnings {
+        println!("Warning: {}: {}", issue.source, issue.reason);
+    }
+    for issue in &report.skipped {
+        println!("Skipped {}: {}", issue.source, issue.reason);
+    }
+    println!(
+        "{} posts, {} attachments, {} warnings, {} skipped.",
+        report.posts.len(),
+        report.attachments.len(),
+        report.warnings.len(),
+        report.skipped.len()
+    );
+    if !report.applied {
+        println!("This was a dry run; run again with --apply to import.");
+    }
+}
+
 pub async fn create_admin(database: &DatabaseConnection) {
     println!("Creating admin user...");
 
@@ -82,3 +363,57 @@ pub async fn create_admin(database: &DatabaseConnection) {
         Err(e) => eprintln!("Failed to create admin user: {}", e),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{ActiveModelTrait, ConnectionTrait, Database, EntityTrait, Set};
+
+    use crate::entity::post::{self, PostStatus};
+
+    use super::sync_entities;
+
+    #[tokio::test]
+    async fn moves_legacy_hidden_posts_to_drafts() {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        sync_entities(&database).await.unwrap();
+        database
+            .execute_unprepared("ALTER TABLE posts ADD COLUMN hidden BOOLEAN NULL")
+            .await
+            .unwrap();
+        for (id, name) in [(1, "hidden"), (2, "visible")] {
+            post::ActiveModel {
+                id: Set(id),
+                name: Set(name.to_string()),
+                title: Set(name.to_string()),
+                content: Set(String::new()),
+                author: Set(1),
+                status: Set(PostStatus::Published),
+                ..Default::default()
+            }
+            .insert(&database)
+            .await
+            .unwrap();
+        }
+        database
+            .execute_unprepared("UPDATE posts SET hidden = TRUE WHERE id = 1")
+            .await
+            .unwrap();
+
+        sync_entities(&database).await.unwrap();
+
+        let statuses = post::Entity::find()
+            .all(&database)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|post| (post.name, post.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            [
+                ("hidden".to_string(), PostStatus::Draft),
+                ("visible".to_string(), PostStatus::Published)
+            ]
+        );
+    }
+}
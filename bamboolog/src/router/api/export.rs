@@ -0,0 +1,60 @@
+use std::io::SeekFrom;
+
+use axum::{
+    Extension, Router,
+    extract::Query,
+    http::header,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use axum_extra::response::FileStream;
+use chrono::Utc;
+use sea_orm::DatabaseConnection;
+use tokio::{fs::File, io::AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    service::{
+        export::{ExportOptions, ExportService},
+        jwt::JwtClaims,
+        storage::StorageService,
+        theme::ThemeService,
+    },
+    utils::HttpFailibleOperationExts,
+};
+
+pub fn get_routes() -> Router {
+    Router::new().route("/", get(export_site))
+}
+
+/// Downloads the whole site as a ZIP archive that `/api/import/markdown`
+/// reads back. Password hashes are left out unless `include_secrets=true`.
+async fn export_site(
+    Extension(database): Extension<DatabaseConnection>,
+    Extension(storage): Extension<StorageService>,
+    Extension(theme_service): Extension<ThemeService>,
+    _claims: JwtClaims,
+    Query(options): Query<ExportOptions>,
+) -> Result<Response, Response> {
+    let archive = tokio::task::spawn_blocking(tempfile::tempfile)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    let (archive, _) = ExportService::export(&database, &storage, &theme_service, archive, options)
+        .await
+        .traced_and_response(|e| tracing::error!("Failed to export the site: {}", e))?;
+    let mut archive = File::from_std(archive);
+    let size = archive
+        .seek(SeekFrom::End(0))
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    archive
+        .rewind()
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+
+    let stream = FileStream::new(ReaderStream::new(archive))
+        .file_name(format!("bamboolog-{}.zip", Utc::now().format("%Y%m%d")))
+        .content_size(size);
+    Ok(([(header::CONTENT_TYPE, "application/zip")], stream).into_response())
+}
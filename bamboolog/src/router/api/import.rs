@@ -0,0 +1,166 @@
+use axum::{
+    Extension, Router,
+    extract::{DefaultBodyLimit, Multipart, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use sea_orm::DatabaseConnection;
+use serde::Deserialize;
+
+use crate::{
+    service::{
+        import::{ImportError, ImportFiles},
+        markdown_import::MarkdownImportService,
+        reloadable::ServiceReloader,
+        storage::StorageService,
+        theme::ThemeService,
+        user::User,
+        wordpress_import::WordPressImportService,
+    },
+    utils::ApiResponse,
+};
+
+const MAX_IMPORT_UPLOAD_SIZE: usize = 100 * 1024 * 1024;
+
+pub fn get_routes() -> Router {
+    Router::new()
+        .route(
+            "/markdown",
+            post(import_markdown)
+                .layer(DefaultBodyLimit::max(MAX_IMPORT_UPLOAD_SIZE + 1024 * 1024)),
+        )
+        .route(
+            "/wordpress",
+            post(import_wordpress)
+                .layer(DefaultBodyLimit::max(MAX_IMPORT_UPLOAD_SIZE + 1024 * 1024)),
+        )
+}
+
+#[derive(Debug, Deserialize)]
+struct ImportQuery {
+    /// Without it the import is a dry run that only reports what it would do.
+    #[serde(default)]
+    apply: bool,
+}
+
+/// Imports a ZIP of Markdown files with front matter, such as a Hugo or
+/// Jekyll site or a site export, as posts by the current user.
+async fn import_markdown(
+    Extension(database): Extension<DatabaseConnection>,
+    Extension(storage): Extension<StorageService>,
+    Extension(theme_service): Extension<ThemeService>,
+    Extension(reloader): Extension<ServiceReloader>,
+    User(user): User,
+    Query(query): Query<ImportQuery>,
+    multipart: Multipart,
+) -> Result<Response, Response> {
+    let (_, archive) = read_upload(multipart, &["zip"]).await?;
+    let files = ImportFiles::from_zip(&archive).map_err(import_error_response)?;
+    let report = MarkdownImportService::import(
+        &database,
+        &storage,
+        &theme_service,
+        &files,
+        user.id,
+        query.apply,
+    )
+    .await
+    .map_err(import_error_response)?;
+    if report.applied && report.settings {
+        reloader.reload().await;
+    }
+    Ok(ApiResponse::ok(report).into_response())
+}
+
+/// Imports a WordPress export (WXR), uploaded as the `.xml` file or as a ZIP
+/// archive that may also hold `wp-content/uploads`. Posts whose WordPress
+/// author has no account here belong to the current user.
+async fn import_wordpress(
+    Extension(database): Extension<DatabaseConnection>,
+    Extension(storage): Extension<StorageService>,
+    Extension(reloader): Extension<ServiceReloader>,
+    User(user): User,
+    Query(query): Query<ImportQuery>,
+    multipart: Multipart,
+) -> Result<Response, Response> {
+    let (file_name, data) = read_upload(multipart, &["xml", "zip"]).await?;
+    let files = if file_name.to_ascii_lowercase().ends_with(".xml") {
+        ImportFiles::from_iter([(file_name, data)])
+    } else {
+        ImportFiles::from_zip(&data).map_err(import_error_response)?
+    };
+    let report = WordPressImportService::import(&database, &storage, &files, user.id, query.apply)
+        .await
+        .map_err(import_error_response)?;
+    if report.applied && !report.redirects.is_empty() {
+        reloader.reload().await;
+    }
+    Ok(ApiResponse::ok(report).into_response())
+}
+
+/// Reads the `file` field, which must have one of `extensions`, returning its
+/// file name and contents.
+async fn read_upload(
+    mut multipart: Multipart,
+    extensions: &[&str],
+) -> Result<(String, Vec<u8>), Response> {
+    let mut upload = None;
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|error| message_response(StatusCode::BAD_REQUEST, error.to_string()))?
+    {
+        if field.name() != Some("file") {
+            continue;
+        }
+        if upload.is_some() {
+            return Err(message_response(
+                StatusCode::BAD_REQUEST,
+                "Only one file may be uploaded",
+            ));
+        }
+        let file_name = field.file_name().unwrap_or_default().to_string();
+        let extension = file_name
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_ascii_lowercase())
+            .unwrap_or_default();
+        if !extensions.contains(&extension.as_str()) {
+            return Err(message_response(
+                StatusCode::BAD_REQUEST,
+                format!("The import must be a .{} file", extensions.join(" or .")),
+            ));
+        }
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field
+            .chunk()
+            .await
+            .map_err(|error| message_response(StatusCode::BAD_REQUEST, error.to_string()))?
+        {
+            if bytes.len() + chunk.len() > MAX_IMPORT_UPLOAD_SIZE {
+                return Err(message_response(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    "The upload must not exceed 100 MB",
+                ));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        upload = Some((file_name, bytes));
+    }
+    upload.ok_or_else(|| message_response(StatusCode::BAD_REQUEST, "No file field 'file' found"))
+}
+
+fn import_error_response(error: ImportError) -> Response {
+    match error {
+        ImportError::InvalidInput(message) => message_response(StatusCode::BAD_REQUEST, message),
+        error => {
+            tracing::error!("Failed to import content: {error}");
+            ApiResponse::internal_server_error().into_response()
+        }
+    }
+}
+
+fn message_response(status: StatusCode, message: impl Into<String>) -> Response {
+    ApiResponse::code_and_message(status, message.into()).into_response()
+}
@@ -0,0 +1,47 @@
+use crate::{
+    service::{
+        jwt::JwtClaims,
+        scheduler::{Job, SchedulerService},
+    },
+    utils::{ApiResponse, HttpFailibleOperationExts},
+};
+use axum::{
+    Extension, Router,
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use sea_orm::DatabaseConnection;
+
+pub fn get_routes() -> Router {
+    Router::new()
+        .route("/", get(list_jobs))
+        .route("/{name}/run", post(run_job))
+}
+
+async fn list_jobs(
+    Extension(scheduler): Extension<SchedulerService>,
+    _user: JwtClaims,
+) -> Result<Response, Response> {
+    let jobs = scheduler
+        .list_jobs()
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    Ok(ApiResponse::ok(jobs).into_response())
+}
+
+async fn run_job(
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(scheduler): Extension<SchedulerService>,
+    _user: JwtClaims,
+    Path(name): Path<String>,
+) -> Result<Response, Response> {
+    let job = Job::from_name(&name)
+        .ok_or_else(|| ApiResponse::code(StatusCode::NOT_FOUND).into_response())?;
+    SchedulerService::request(&db, job)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    scheduler.wake();
+    Ok(ApiResponse::ok(()).into_response())
+}
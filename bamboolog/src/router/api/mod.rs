@@ -1,7 +1,15 @@
 use axum::Router;
 
 mod attachments;
+mod export;
+mod import;
+mod jobs;
+mod post_bulk;
+mod post_previews;
+mod post_revisions;
 mod posts;
+mod public;
+mod redirects;
 mod settings;
 mod storage_engines;
 mod themes;
@@ -10,11 +18,16 @@ mod user;
 pub fn get_routes() -> Router {
     Router::new()
         .nest("/posts", posts::get_routes())
+        .nest("/public", public::get_routes())
         .nest("/user", user::get_routes())
         .nest("/settings", settings::get_routes())
         .nest("/themes", themes::get_routes())
         .nest("/attachments", attachments::get_routes())
         .nest("/storage_engines", storage_engines::get_routes())
+        .nest("/jobs", jobs::get_routes())
+        .nest("/redirects", redirects::get_routes())
+        .nest("/import", import::get_routes())
+        .nest("/export", export::get_routes())
 }
 
 #[cfg(test)]
@@ -26,8 +39,91 @@ mod tests {
     };
     use tower::ServiceExt;
 
+    use sea_orm::Database;
+
+    use crate::{config::ApplicationConfiguration, maintenance::sync_entities, web::AppServices};
+
     use super::get_routes;
 
+    /// Every `/api` route that changes state or reads anything not on the public
+    /// site. Login, logout and `/api/public` are the only routes left out. New
+    /// routes are added here rather than tested for authentication one module
+    /// at a time.
+    const PRIVATE_ROUTES: &[(&str, &str)] = &[
+        ("GET", "/api/posts"),
+        ("PUT", "/api/posts"),
+        ("POST", "/api/posts/bulk"),
+        ("GET", "/api/posts/1"),
+        ("POST", "/api/posts/1"),
+        ("DELETE", "/api/posts/1"),
+        ("GET", "/api/posts/1/rendered"),
+        ("GET", "/api/posts/1/revisions"),
+        ("GET", "/api/posts/1/revisions/diff?from=1&to=2"),
+        ("GET", "/api/posts/1/revisions/1"),
+        ("POST", "/api/posts/1/revisions/1/restore"),
+        ("GET", "/api/posts/1/previews"),
+        ("POST", "/api/posts/1/previews"),
+        ("DELETE", "/api/posts/1/previews/1"),
+        ("GET", "/api/user/me"),
+        ("POST", "/api/user/me"),
+        ("GET", "/api/settings"),
+        ("POST", "/api/settings"),
+        ("POST", "/api/settings/reload"),
+        ("GET", "/api/themes"),
+        ("POST", "/api/themes"),
+        ("GET", "/api/themes/active/config"),
+        ("POST", "/api/themes/active/config"),
+        ("POST", "/api/themes/default/activate"),
+        ("DELETE", "/api/themes/default"),
+        ("GET", "/api/attachments"),
+        ("POST", "/api/attachments"),
+        ("DELETE", "/api/attachments/1"),
+        ("GET", "/api/storage_engines"),
+        ("POST", "/api/storage_engines"),
+        ("PUT", "/api/storage_engines/1"),
+        ("DELETE", "/api/storage_engines/1"),
+        ("GET", "/api/jobs"),
+        ("POST", "/api/jobs/ping_subscribers/run"),
+        ("GET", "/api/redirects"),
+        ("POST", "/api/redirects"),
+        ("PUT", "/api/redirects/1"),
+        ("DELETE", "/api/redirects/1"),
+        ("POST", "/api/import/markdown"),
+        ("POST", "/api/import/wordpress"),
+        ("GET", "/api/export"),
+    ];
+
+    #[tokio::test]
+    async fn private_routes_reject_anonymous_requests() {
+        let assets = tempfile::tempdir().unwrap();
+        let config = ApplicationConfiguration::for_tests(assets.path());
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        sync_entities(&database).await.unwrap();
+        let app = AppServices::configure(&config, database)
+            .await
+            .router(config);
+
+        for (method, uri) in PRIVATE_ROUTES {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method(*method)
+                        .uri(*uri)
+                        .header("content-type", "application/json")
+                        .body(Body::from("{}"))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                response.status(),
+                StatusCode::UNAUTHORIZED,
+                "{method} {uri} must require authentication"
+            );
+        }
+    }
+
     #[tokio::test]
     async fn collection_routes_match_without_a_trailing_slash() {
         let app = Router::new().nest("/api", get_routes());
@@ -0,0 +1,567 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    Extension, Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, DatabaseTransaction,
+    EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entity::{
+        post::{self, PostFunctions, PostStatus},
+        user,
+    },
+    service::{
+        jwt::JwtClaims,
+        post_render::PostRenderService,
+        post_revision::PostRevisionService,
+        scheduler::{Job, SchedulerService},
+        taxonomy::{PostTerms, TaxonomyService},
+    },
+    utils::{ApiResponse, HttpFailibleOperationExts},
+};
+
+use super::posts::{PostFilter, delete_post_records};
+
+/// Applies one action to many posts. Posts are chosen either by id or by the
+/// same filter the admin post list uses, never both.
+#[derive(Debug, Deserialize)]
+pub struct PostBulkRequest {
+    pub ids: Option<Vec<i32>>,
+    pub filter: Option<PostFilter>,
+    #[serde(flatten)]
+    pub action: PostBulkAction,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PostBulkAction {
+    /// Moves posts back to drafts, taking them off every public route.
+    Hide,
+    /// Publishes drafts. Posts in any other state are left alone.
+    Unhide,
+    Delete,
+    AddTerms {
+        #[serde(default)]
+        tags: Vec<String>,
+        #[serde(default)]
+        categories: Vec<String>,
+    },
+    RemoveTerms {
+        #[serde(default)]
+        tags: Vec<String>,
+        #[serde(default)]
+        categories: Vec<String>,
+    },
+    SetFunctions {
+        functions: Vec<String>,
+    },
+    ChangeAuthor {
+        author: i32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PostBulkOutcome {
+    Updated,
+    Unchanged,
+    Deleted,
+    NotFound,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PostBulkItem {
+    pub id: i32,
+    pub name: Option<String>,
+    pub outcome: PostBulkOutcome,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PostBulkResponse {
+    pub matched: usize,
+    pub changed: usize,
+    pub items: Vec<PostBulkItem>,
+}
+
+pub async fn bulk_update_posts(
+    Extension(database): Extension<DatabaseConnection>,
+    claims: JwtClaims,
+    Json(request): Json<PostBulkRequest>,
+) -> Result<ApiResponse<PostBulkResponse>, Response> {
+    validate_action(&database, &request.action).await?;
+
+    let transaction = database
+        .begin()
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    let targets = match (request.ids, request.filter) {
+        (Some(ids), None) => posts_by_id(&transaction, ids).await?,
+        (None, Some(filter)) => filter
+            .apply(
+                &transaction,
+                post::Entity::find().order_by_asc(post::Column::Id),
+            )
+            .await?
+            .all(&transaction)
+            .await
+            .traced_and_response(|e| tracing::error!("{}", e))?
+            .into_iter()
+            .map(|post| (post.id, Some(post)))
+            .collect(),
+        _ => {
+            return Err(ApiResponse::code_and_message(
+                StatusCode::BAD_REQUEST,
+                "Provide either ids or a filter",
+            )
+            .into_response());
+        }
+    };
+
+    let mut terms = match request.action {
+        PostBulkAction::AddTerms { .. } | PostBulkAction::RemoveTerms { .. } => {
+            let ids = targets
+                .iter()
+                .filter(|(_, post)| post.is_some())
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>();
+            TaxonomyService::terms_for_posts(&transaction, &ids)
+                .await
+                .traced_and_response(|e| tracing::error!("{}", e))?
+        }
+        _ => HashMap::new(),
+    };
+
+    let mut items = Vec::with_capacity(targets.len());
+    for (id, post) in targets {
+        let Some(post) = post else {
+            items.push(PostBulkItem {
+                id,
+                name: None,
+                outcome: PostBulkOutcome::NotFound,
+            });
+            continue;
+        };
+        let name = post.name.clone();
+        let current_terms = terms.remove(&id).unwrap_or_default();
+        let outcome = apply_action(
+            &transaction,
+            &request.action,
+            post,
+            current_terms,
+            claims.user_id,
+        )
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+        items.push(PostBulkItem {
+            id,
+            name: Some(name),
+            outcome,
+        });
+    }
+
+    let changed = items
+        .iter()
+        .filter(|item| {
+            matches!(
+                item.outcome,
+                PostBulkOutcome::Updated | PostBulkOutcome::Deleted
+            )
+        })
+        .count();
+    if changed > 0 {
+        SchedulerService::request(&transaction, Job::PingSubscribers)
+            .await
+            .traced_and_response(|e| tracing::error!("{}", e))?;
+    }
+    transaction
+        .commit()
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+
+    Ok(ApiResponse::ok(PostBulkResponse {
+        matched: items
+            .iter()
+            .filter(|item| item.outcome != PostBulkOutcome::NotFound)
+            .count(),
+        changed,
+        items,
+    }))
+}
+
+async fn validate_action(
+    database: &DatabaseConnection,
+    action: &PostBulkAction,
+) -> Result<(), Response> {
+    match action {
+        PostBulkAction::AddTerms { tags, categories }
+        | PostBulkAction::RemoveTerms { tags, categories }
+            if tags.is_empty() && categories.is_empty() =>
+        {
+            Err(ApiResponse::code_and_message(
+                StatusCode::BAD_REQUEST,
+                "No tags or categories given",
+            )
+            .into_response())
+        }
+        PostBulkAction::ChangeAuthor { author } => {
+            let exists = user::Entity::find_by_id(*author)
+                .one(database)
+                .await
+                .traced_and_response(|e| tracing::error!("{}", e))?
+                .is_some();
+            if exists {
+                Ok(())
+            } else {
+                Err(
+                    ApiResponse::code_and_message(StatusCode::BAD_REQUEST, "No such author")
+                        .into_response(),
+                )
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The requested posts in request order, with `None` for ids that match no
+/// post. Repeated ids are reported once.
+async fn posts_by_id(
+    transaction: &DatabaseTransaction,
+    ids: Vec<i32>,
+) -> Result<Vec<(i32, Option<post::Model>)>, Response> {
+    let mut posts = post::Entity::find()
+        .filter(post::Column::Id.is_in(ids.iter().copied()))
+        .all(transaction)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?
+        .into_iter()
+        .map(|post| (post.id, post))
+        .collect::<HashMap<_, _>>();
+    let mut seen = HashSet::new();
+    Ok(ids
+        .into_iter()
+        .filter(|id| seen.insert(*id))
+        .map(|id| (id, posts.remove(&id)))
+        .collect())
+}
+
+/// Applies `action` to one post the way a single edit by `editor` would,
+/// recording a revision and dropping the stored render when it changes.
+async fn apply_action(
+    transaction: &DatabaseTransaction,
+    action: &PostBulkAction,
+    post: post::Model,
+    terms: PostTerms,
+    editor: i32,
+) -> Result<PostBulkOutcome, sea_orm::DbErr> {
+    let id = post.id;
+    let mut active_model = post.clone().into_active_model();
+    match action {
+        PostBulkAction::Hide if post.status != PostStatus::Draft => {
+            active_model.status = ActiveValue::Set(PostStatus::Draft);
+        }
+        PostBulkAction::Unhide if post.status == PostStatus::Draft => {
+            active_model.status = ActiveValue::Set(PostStatus::Published);
+        }
+        PostBulkAction::Delete => {
+            delete_post_records(transaction, post).await?;
+            return Ok(PostBulkOutcome::Deleted);
+        }
+        PostBulkAction::AddTerms { tags, categories }
+        | PostBulkAction::RemoveTerms { tags, categories } => {
+            let add = matches!(action, PostBulkAction::AddTerms { .. });
+            let tags = edited_terms(&terms.tags, tags, add);
+            let categories = edited_terms(&terms.categories, categories, add);
+            if tags.is_none() && categories.is_none() {
+                return Ok(PostBulkOutcome::Unchanged);
+            }
+            TaxonomyService::replace_post_terms(transaction, id, tags, categories).await?;
+            PostRenderService::invalidate(transaction, id).await?;
+            return Ok(PostBulkOutcome::Updated);
+        }
+        PostBulkAction::SetFunctions { functions } if post.functions.0 != *functions => {
+            active_model.functions = ActiveValue::Set(PostFunctions(functions.clone()));
+        }
+        PostBulkAction::ChangeAuthor { author } if post.author != *author => {
+            active_model.author = ActiveValue::Set(*author);
+        }
+        _ => return Ok(PostBulkOutcome::Unchanged),
+    }
+    active_model.updated_at = ActiveValue::Set(Some(Utc::now()));
+    let updated = active_model.update(transaction).await?;
+    PostRevisionService::record_updated(transaction, &post, &updated, editor).await?;
+    PostRenderService::invalidate(transaction, id).await?;
+    Ok(PostBulkOutcome::Updated)
+}
+
+/// `current` with `requested` added or removed, or `None` when that changes
+/// nothing.
+fn edited_terms(current: &[String], requested: &[String], add: bool) -> Option<Vec<String>> {
+    let requested = requested
+        .iter()
+        .map(|term| term.trim())
+        .filter(|term| !term.is_empty())
+        .collect::<Vec<_>>();
+    let edited = if add {
+        let mut edited = current.to_vec();
+        for term in requested {
+            if !edited.iter().any(|existing| existing == term) {
+                edited.push(term.to_string());
+            }
+        }
+        edited
+    } else {
+        current
+            .iter()
+            .filter(|term| !requested.contains(&term.as_str()))
+            .cloned()
+            .collect()
+    };
+    (edited != current).then_some(edited)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Extension, Json, http::StatusCode, response::IntoResponse};
+    use sea_orm::{ActiveModelTrait, Database, DatabaseConnection, EntityTrait, Set};
+
+    use crate::{
+        entity::{
+            post::{self, PostStatus},
+            post_render, user,
+        },
+        maintenance::sync_entities,
+        service::{jwt::JwtClaims, post_revision::PostRevisionService, taxonomy::TaxonomyService},
+    };
+
+    use super::{PostBulkOutcome, PostBulkResponse, bulk_update_posts};
+
+    async fn database_with_posts() -> DatabaseConnection {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        sync_entities(&database).await.unwrap();
+        for id in [1, 2] {
+            user::ActiveModel {
+                id: Set(id),
+                username: Set(format!("user-{id}")),
+                email: Set(format!("user-{id}@example.test")),
+                nickname: Set(format!("User {id}")),
+                password_hash: Set("password-hash".to_string()),
+                ..Default::default()
+            }
+            .insert(&database)
+            .await
+            .unwrap();
+        }
+        for (id, status) in [
+            (1, PostStatus::Published),
+            (2, PostStatus::Draft),
+            (3, PostStatus::Draft),
+        ] {
+            post::ActiveModel {
+                id: Set(id),
+                name: Set(format!("post-{id}")),
+                title: Set(format!("Post {id}")),
+                content: Set(String::new()),
+                author: Set(1),
+                status: Set(status),
+                ..Default::default()
+            }
+            .insert(&database)
+            .await
+            .unwrap();
+            TaxonomyService::replace_post_terms(
+                &database,
+                id,
+                Some(vec!["imported".to_string()]),
+                None,
+            )
+            .await
+            .unwrap();
+        }
+        database
+    }
+
+    async fn bulk(
+        database: &DatabaseConnection,
+        request: serde_json::Value,
+    ) -> Result<PostBulkResponse, StatusCode> {
+        let claims = JwtClaims {
+            sub: "user-1".to_string(),
+            exp: 0,
+            jti: String::new(),
+            user_id: 1,
+        };
+        bulk_update_posts(
+            Extension(database.clone()),
+            claims,
+            Json(serde_json::from_value(request).unwrap()),
+        )
+        .await
+        .map(|response| response.data.unwrap())
+        .map_err(|response| response.into_response().status())
+    }
+
+    #[tokio::test]
+    async fn reports_each_requested_post() {
+        let database = database_with_posts().await;
+
+        let report = bulk(
+            &database,
+            serde_json::json!({
+                "ids": [1, 2, 99, 1],
+                "action": "add_terms",
+                "tags": ["imported", " cleanup "],
+                "categories": ["Archive"],
+            }),
+        )
+        .await
+        .unwrap();
+
+        let outcomes = report
+            .items
+            .iter()
+            .map(|item| (item.id, item.outcome))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outcomes,
+            [
+                (1, PostBulkOutcome::Updated),
+                (2, PostBulkOutcome::Updated),
+                (99, PostBulkOutcome::NotFound),
+            ]
+        );
+        assert_eq!((report.matched, report.changed), (2, 2));
+        let terms = TaxonomyService::terms_for_posts(&database, &[1, 3])
+            .await
+            .unwrap();
+        assert_eq!(terms[&1].tags, ["cleanup", "imported"]);
+        assert_eq!(terms[&1].categories, ["Archive"]);
+        assert_eq!(terms[&3].tags, ["imported"]);
+
+        let report = bulk(
+            &database,
+            serde_json::json!({ "ids": [1, 3], "action": "remove_terms", "tags": ["cleanup"] }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.items[0].outcome, PostBulkOutcome::Updated);
+        assert_eq!(report.items[1].outcome, PostBulkOutcome::Unchanged);
+    }
+
+    #[tokio::test]
+    async fn applies_actions_to_filtered_posts() {
+        let database = database_with_posts().await;
+
+        let report = bulk(
+            &database,
+            serde_json::json!({ "filter": { "status": "draft" }, "action": "unhide" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.changed, 2);
+        let statuses = post::Entity::find()
+            .all(&database)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|post| post.status)
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, [PostStatus::Published; 3]);
+
+        let report = bulk(
+            &database,
+            serde_json::json!({ "filter": { "name": "post-2" }, "action": "change_author", "author": 2 }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.items[0].id, 2);
+        let post = post::Entity::find_by_id(2).one(&database).await.unwrap();
+        assert_eq!(post.unwrap().author, 2);
+
+        let report = bulk(
+            &database,
+            serde_json::json!({ "filter": { "name": "post-" }, "action": "delete" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.changed, 3);
+        assert!(
+            post::Entity::find()
+                .all(&database)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn records_changes_like_single_edits() {
+        let database = database_with_posts().await;
+        post_render::ActiveModel {
+            post_id: Set(1),
+            render_key: Set("stale".to_string()),
+            html: Set("<p>Stale</p>".to_string()),
+            excerpt: Set(String::new()),
+            word_count: Set(1),
+            cjk_character_count: Set(0),
+            toc: Set(post_render::RenderedToc(Vec::new())),
+            rendered_at: Set(chrono::Utc::now()),
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+
+        bulk(
+            &database,
+            serde_json::json!({ "ids": [1], "action": "hide" }),
+        )
+        .await
+        .unwrap();
+
+        let post = post::Entity::find_by_id(1)
+            .one(&database)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(post.status, PostStatus::Draft);
+        assert!(post.updated_at.is_some());
+        assert!(
+            PostRevisionService::latest(&database, 1)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            post_render::Entity::find_by_id(1)
+                .one(&database)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_ambiguous_targets_and_unknown_authors() {
+        let database = database_with_posts().await;
+
+        for request in [
+            serde_json::json!({ "action": "hide" }),
+            serde_json::json!({ "ids": [1], "filter": {}, "action": "hide" }),
+            serde_json::json!({ "ids": [1], "action": "change_author", "author": 42 }),
+            serde_json::json!({ "ids": [1], "action": "add_terms" }),
+        ] {
+            assert_eq!(
+                bulk(&database, request).await.unwrap_err(),
+                StatusCode::BAD_REQUEST
+            );
+        }
+        let post = post::Entity::find_by_id(1).one(&database).await.unwrap();
+        assert_eq!(post.unwrap().status, PostStatus::Published);
+    }
+}
@@ -0,0 +1,226 @@
+use axum::{
+    Extension, Json, Router,
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get},
+};
+use chrono::{Duration, Utc};
+use sea_orm::{DatabaseConnection, EntityTrait, prelude::DateTimeUtc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entity::{post, post_preview},
+    service::{
+        jwt::JwtClaims,
+        post::PostService,
+        post_preview::{DEFAULT_PREVIEW_LIFETIME, MAX_PREVIEW_LIFETIME, PostPreviewService},
+    },
+    utils::{ApiResponse, HttpFailibleOperationExts},
+};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PreviewCreateRequest {
+    /// Seconds until the link stops working; seven days when omitted.
+    pub expires_in: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreviewResponse {
+    pub id: i32,
+    pub post_id: i32,
+    pub token: String,
+    /// Site-relative link to share with the reviewer.
+    pub url: String,
+    pub expires_at: DateTimeUtc,
+    pub expired: bool,
+    pub created_by: i32,
+    pub created_at: DateTimeUtc,
+}
+
+pub fn get_routes() -> Router {
+    Router::new()
+        .route("/", get(list_previews).post(create_preview))
+        .route("/{preview_id}", delete(revoke_preview))
+}
+
+async fn list_previews(
+    Extension(database): Extension<DatabaseConnection>,
+    Path(post_id): Path<i32>,
+    _claims: JwtClaims,
+) -> Result<Response, Response> {
+    let post = find_post(&database, post_id).await?;
+    let previews = PostPreviewService::list(&database, post_id)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    Ok(ApiResponse::ok(
+        previews
+            .into_iter()
+            .map(|preview| preview_response(&post, preview))
+            .collect::<Vec<_>>(),
+    )
+    .into_response())
+}
+
+async fn create_preview(
+    Extension(database): Extension<DatabaseConnection>,
+    Path(post_id): Path<i32>,
+    claims: JwtClaims,
+    Json(request): Json<PreviewCreateRequest>,
+) -> Result<Response, Response> {
+    let lifetime = match request.expires_in {
+        None => DEFAULT_PREVIEW_LIFETIME,
+        Some(seconds) if seconds > 0 && seconds <= MAX_PREVIEW_LIFETIME.num_seconds() => {
+            Duration::seconds(seconds)
+        }
+        Some(_) => {
+            return Err(ApiResponse::code_and_message(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "expires_in must be between 1 and {} seconds",
+                    MAX_PREVIEW_LIFETIME.num_seconds()
+                ),
+            )
+            .into_response());
+        }
+    };
+    let post = find_post(&database, post_id).await?;
+    let preview = PostPreviewService::issue(&database, post_id, claims.user_id, lifetime)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    Ok(ApiResponse::ok(preview_response(&post, preview)).into_response())
+}
+
+async fn revoke_preview(
+    Extension(database): Extension<DatabaseConnection>,
+    Path((post_id, preview_id)): Path<(i32, i32)>,
+    _claims: JwtClaims,
+) -> Result<Response, Response> {
+    let revoked = PostPreviewService::revoke(&database, post_id, preview_id)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    if !revoked {
+        return Err(
+            ApiResponse::code_and_message(StatusCode::NOT_FOUND, "No preview found")
+                .into_response(),
+        );
+    }
+    Ok(ApiResponse::ok(()).into_response())
+}
+
+async fn find_post(database: &DatabaseConnection, post_id: i32) -> Result<post::Model, Response> {
+    post::Entity::find_by_id(post_id)
+        .one(database)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?
+        .ok_or_else(|| {
+            ApiResponse::code_and_message(StatusCode::NOT_FOUND, "No post found").into_response()
+        })
+}
+
+fn preview_response(post: &post::Model, preview: post_preview::Model) -> PreviewResponse {
+    PreviewResponse {
+        url: format!(
+            "{}?preview={}",
+            PostService::public_path(post),
+            preview.token
+        ),
+        expired: preview.expires_at <= Utc::now(),
+        id: preview.id,
+        post_id: preview.post_id,
+        token: preview.token,
+        expires_at: preview.expires_at,
+        created_by: preview.created_by,
+        created_at: preview.created_at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Extension, Json,
+        extract::Path,
+        http::StatusCode,
+        response::{IntoResponse, Response},
+    };
+    use sea_orm::{ActiveModelTrait, Database, DatabaseConnection, Set};
+    use serde_json::Value;
+
+    use crate::{
+        entity::post::{self, PostStatus},
+        maintenance::sync_entities,
+        service::jwt::JwtClaims,
+    };
+
+    use super::{create_preview, list_previews, revoke_preview};
+
+    fn claims() -> JwtClaims {
+        JwtClaims {
+            sub: "user-1".to_string(),
+            exp: 0,
+            jti: String::new(),
+            user_id: 1,
+        }
+    }
+
+    async fn json(response: Response) -> Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    async fn create(database: &DatabaseConnection, request: Value) -> Response {
+        create_preview(
+            Extension(database.clone()),
+            Path(1),
+            claims(),
+            Json(serde_json::from_value(request).unwrap()),
+        )
+        .await
+        .unwrap_or_else(|response| response)
+    }
+
+    #[tokio::test]
+    async fn issues_lists_and_revokes_preview_links() {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        sync_entities(&database).await.unwrap();
+        post::ActiveModel {
+            id: Set(1),
+            name: Set("draft".to_string()),
+            title: Set("Draft".to_string()),
+            content: Set(String::new()),
+            author: Set(1),
+            status: Set(PostStatus::Draft),
+            ..Default::default()
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+
+        for expires_in in [0, 31 * 24 * 3600] {
+            let response = create(&database, serde_json::json!({ "expires_in": expires_in })).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        let preview =
+            json(create(&database, serde_json::json!({ "expires_in": 3600 })).await).await;
+        let token = preview["data"]["token"].as_str().unwrap();
+        assert_eq!(token.len(), 32);
+        assert_eq!(
+            preview["data"]["url"],
+            format!("/posts/draft?preview={token}")
+        );
+        assert_eq!(preview["data"]["expired"], false);
+
+        let list = list_previews(Extension(database.clone()), Path(1), claims())
+            .await
+            .unwrap();
+        assert_eq!(json(list).await["data"].as_array().unwrap().len(), 1);
+
+        let id = preview["data"]["id"].as_i64().unwrap() as i32;
+        let revoke = |id| revoke_preview(Extension(database.clone()), Path((1, id)), claims());
+        assert!(revoke(id).await.is_ok());
+        let missing = revoke(id).await.unwrap_err().into_response();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,260 @@
+use axum::{
+    Extension, Router,
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseConnection, DerivePartialModel, EntityTrait, QueryFilter,
+    QueryOrder, TransactionTrait, prelude::DateTimeUtc,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entity::{post, post_revision},
+    service::{
+        jwt::JwtClaims,
+        post::PostService,
+        post_render::PostRenderService,
+        post_revision::PostRevisionService,
+        scheduler::{Job, SchedulerService},
+    },
+    utils::{ApiResponse, HttpFailibleOperationExts},
+};
+
+#[derive(Debug, Serialize, DerivePartialModel)]
+#[sea_orm(entity = "post_revision::Entity")]
+pub struct RevisionListItem {
+    pub id: i32,
+    pub post_id: i32,
+    pub title: String,
+    pub name: String,
+    pub editor: i32,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevisionDetailResponse {
+    pub id: i32,
+    pub post_id: i32,
+    pub title: String,
+    pub name: String,
+    pub content: String,
+    pub description: Option<String>,
+    pub illustration: Option<String>,
+    pub editor: i32,
+    pub created_at: DateTimeUtc,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevisionDiffRequest {
+    pub from: i32,
+    pub to: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevisionDiffResponse {
+    pub from: i32,
+    pub to: i32,
+    pub diff: String,
+}
+
+pub fn get_routes() -> Router {
+    Router::new()
+        .route("/", get(list_revisions))
+        .route("/diff", get(diff_revisions))
+        .route("/{revision_id}", get(get_revision))
+        .route("/{revision_id}/restore", post(restore_revision))
+}
+
+async fn list_revisions(
+    Extension(database): Extension<DatabaseConnection>,
+    Path(post_id): Path<i32>,
+    _claims: JwtClaims,
+) -> Result<Response, Response> {
+    let revisions = post_revision::Entity::find()
+        .filter(post_revision::Column::PostId.eq(post_id))
+        .order_by_desc(post_revision::Column::Id)
+        .into_partial_model::<RevisionListItem>()
+        .all(&database)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    Ok(ApiResponse::ok(revisions).into_response())
+}
+
+async fn get_revision(
+    Extension(database): Extension<DatabaseConnection>,
+    Path((post_id, revision_id)): Path<(i32, i32)>,
+    _claims: JwtClaims,
+) -> Result<Response, Response> {
+    let revision = find_revision(&database, post_id, revision_id).await?;
+    Ok(ApiResponse::ok(RevisionDetailResponse {
+        id: revision.id,
+        post_id: revision.post_id,
+        title: revision.title,
+        name: revision.name,
+        content: revision.content,
+        description: revision.description,
+        illustration: revision.illustration,
+        editor: revision.editor,
+        created_at: revision.created_at,
+    })
+    .into_response())
+}
+
+async fn diff_revisions(
+    Extension(database): Extension<DatabaseConnection>,
+    Path(post_id): Path<i32>,
+    _claims: JwtClaims,
+    Query(query): Query<RevisionDiffRequest>,
+) -> Result<Response, Response> {
+    let from = find_revision(&database, post_id, query.from).await?;
+    let to = find_revision(&database, post_id, query.to).await?;
+    Ok(ApiResponse::ok(RevisionDiffResponse {
+        from: from.id,
+        to: to.id,
+        diff: PostRevisionService::unified_diff(&from, &to),
+    })
+    .into_response())
+}
+
+async fn restore_revision(
+    Extension(database): Extension<DatabaseConnection>,
+    Path((post_id, revision_id)): Path<(i32, i32)>,
+    claims: JwtClaims,
+) -> Result<Response, Response> {
+    let post = post::Entity::find_by_id(post_id)
+        .one(&database)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?
+        .ok_or_else(|| {
+            ApiResponse::code_and_message(StatusCode::NOT_FOUND, "No post found").into_response()
+        })?;
+    let revision = find_revision(&database, post_id, revision_id).await?;
+
+    let transaction = database
+        .begin()
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    if revision.name != post.name
+        && PostService::name_taken(&transaction, &revision.name, post_id)
+            .await
+            .traced_and_response(|e| tracing::error!("{}", e))?
+    {
+        return Err(ApiResponse::code_and_message(
+            StatusCode::CONFLICT,
+            "Another post now uses the name of this revision",
+        )
+        .into_response());
+    }
+    PostRevisionService::restore(&transaction, post, revision, claims.user_id)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    PostRenderService::invalidate(&transaction, post_id)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    SchedulerService::request(&transaction, Job::PingSubscribers)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    transaction
+        .commit()
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+
+    Ok(ApiResponse::ok(()).into_response())
+}
+
+async fn find_revision<C>(
+    db: &C,
+    post_id: i32,
+    revision_id: i32,
+) -> Result<post_revision::Model, Response>
+where
+    C: ConnectionTrait,
+{
+    post_revision::Entity::find_by_id(revision_id)
+        .filter(post_revision::Column::PostId.eq(post_id))
+        .one(db)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?
+        .ok_or_else(|| {
+            ApiResponse::code_and_message(StatusCode::NOT_FOUND, "No revision found")
+                .into_response()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Extension, http::StatusCode};
+    use sea_orm::{ActiveModelTrait, Database, DatabaseConnection, EntityTrait, Set};
+
+    use crate::{
+        entity::{post, post_revision},
+        maintenance::sync_entities,
+        service::{jwt::JwtClaims, post::PostService},
+    };
+
+    use super::restore_revision;
+
+    async fn insert_post(database: &DatabaseConnection, id: i32, name: &str) {
+        post::ActiveModel {
+            id: Set(id),
+            name: Set(name.to_string()),
+            title: Set(name.to_string()),
+            content: Set(String::new()),
+            author: Set(1),
+            ..Default::default()
+        }
+        .insert(database)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_to_restore_a_name_another_post_now_uses() {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        sync_entities(&database).await.unwrap();
+        insert_post(&database, 1, "renamed").await;
+        insert_post(&database, 2, "original").await;
+        let revision = post_revision::ActiveModel {
+            post_id: Set(1),
+            title: Set("original".to_string()),
+            name: Set("original".to_string()),
+            content: Set(String::new()),
+            editor: Set(1),
+            created_at: Set(chrono::Utc::now()),
+            ..Default::default()
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+        let claims = || JwtClaims {
+            sub: "user-1".to_string(),
+            exp: 0,
+            jti: String::new(),
+            user_id: 1,
+        };
+        let restore = || {
+            restore_revision(
+                Extension(database.clone()),
+                axum::extract::Path((1, revision.id)),
+                claims(),
+            )
+        };
+
+        let response = restore().await.unwrap_err();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let post = post::Entity::find_by_id(1).one(&database).await.unwrap();
+        assert_eq!(post.unwrap().name, "renamed");
+
+        post::Entity::delete_by_id(2).exec(&database).await.unwrap();
+        assert!(restore().await.is_ok());
+        let post = post::Entity::find_by_id(1).one(&database).await.unwrap();
+        assert_eq!(post.unwrap().name, "original");
+        let previous = PostService::find_by_previous_name(&database, "renamed")
+            .await
+            .unwrap();
+        assert_eq!(previous.map(|post| post.id), Some(1));
+    }
+}
@@ -3,19 +3,34 @@ use axum::{
     extract::{Path, Query},
     http::StatusCode,
     response::{IntoResponse, Response},
-    routing::get,
+    routing::{get, post},
 };
 use chrono::Utc;
 use sea_orm::{
-    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, DerivePartialModel,
-    EntityTrait, IntoActiveModel, ModelTrait, PaginatorTrait, QueryFilter, QueryOrder,
-    TransactionTrait, prelude::DateTimeUtc,
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr,
+    DerivePartialModel, EntityTrait, IntoActiveModel, ModelTrait, PaginatorTrait, QueryFilter,
+    QueryOrder, Select, TransactionTrait, prelude::DateTimeUtc,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::{Map as JsonMap, Value as JsonValue};
 
 use crate::{
-    entity,
-    service::{jwt::JwtClaims, taxonomy::TaxonomyService, user::User},
+    entity::{
+        self,
+        post::{PostKind, PostMeta, PostStatus},
+    },
+    service::{
+        jwt::JwtClaims,
+        post::PostService,
+        post_preview::PostPreviewService,
+        post_render::PostRenderService,
+        post_revision::PostRevisionService,
+        scheduler::{Job, SchedulerService},
+        search::{SearchService, like_contains},
+        taxonomy::TaxonomyService,
+        theme::ThemeService,
+        user::User,
+    },
     utils::{ApiResponse, HttpFailibleOperationExts, Pagination, render_markdown},
 };
 
@@ -30,8 +45,18 @@ pub struct PostCreateRequest {
     pub illustration: Option<String>,
     pub tags: Option<Vec<String>>,
     pub categories: Option<Vec<String>>,
-    pub hidden: Option<bool>,
+    pub status: Option<PostStatus>,
+    pub publish_at: Option<i64>,
+    pub kind: Option<PostKind>,
     pub functions: Option<Vec<String>>,
+    /// Theme-defined fields, checked against the active theme's `post_meta`.
+    pub meta: Option<JsonMap<String, JsonValue>>,
+    pub pinned: Option<bool>,
+    pub featured: Option<bool>,
+    /// Orders pinned and featured posts; higher weights come first.
+    pub weight: Option<i32>,
+    /// Readers must enter this password before the post is shown.
+    pub password: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -45,20 +70,174 @@ pub struct PostUpdateRequest {
     pub illustration: Option<String>,
     pub tags: Option<Vec<String>>,
     pub categories: Option<Vec<String>>,
-    pub hidden: Option<bool>,
+    pub status: Option<PostStatus>,
+    pub publish_at: Option<i64>,
+    pub kind: Option<PostKind>,
     pub functions: Option<Vec<String>>,
+    /// Replaces the theme-defined fields, checked against the active theme's
+    /// `post_meta`.
+    pub meta: Option<JsonMap<String, JsonValue>>,
+    pub pinned: Option<bool>,
+    pub featured: Option<bool>,
+    pub weight: Option<i32>,
+    /// A new reader password; an empty string removes the protection.
+    pub password: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct PostListRequest {
     pub page: Option<u64>,
     pub page_size: Option<u64>,
-    pub title: Option<String>,
-    pub name: Option<String>,
     pub sort_by: Option<String>,
     pub order: Option<String>,
 }
 
+/// Narrows the admin post list. Bulk updates accept the same filter, so what
+/// an editor sees listed is what a bulk action applies to.
+#[derive(Debug, Default, Deserialize)]
+pub struct PostFilter {
+    pub title: Option<String>,
+    pub name: Option<String>,
+    /// Full-text search over title, description and content.
+    pub q: Option<String>,
+    pub status: Option<PostStatus>,
+    pub visibility: Option<PostVisibility>,
+    pub kind: Option<PostKind>,
+    pub tag: Option<String>,
+    pub category: Option<String>,
+    pub author: Option<i32>,
+    /// Posts whose `functions` include this entry.
+    pub function: Option<String>,
+    /// Unix timestamps bounding `created_at`, both inclusive.
+    pub created_from: Option<i64>,
+    pub created_to: Option<i64>,
+    /// Unix timestamps bounding `updated_at`, both inclusive. Posts never
+    /// updated match neither bound.
+    pub updated_from: Option<i64>,
+    pub updated_to: Option<i64>,
+}
+
+/// Whether readers can reach a post, independent of its exact status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PostVisibility {
+    /// Readable through its public URL, listed or not.
+    Public,
+    /// Drafts and scheduled posts that are not due yet.
+    Hidden,
+    /// Asks readers for a password.
+    Protected,
+}
+
+impl PostFilter {
+    pub(super) async fn apply<C>(
+        self,
+        database: &C,
+        mut select: Select<entity::post::Entity>,
+    ) -> Result<Select<entity::post::Entity>, Response>
+    where
+        C: ConnectionTrait,
+    {
+        if let Some(title) = self.title {
+            select = select.filter(entity::post::Column::Title.contains(&title));
+        }
+
+        if let Some(name) = self.name {
+            select = select.filter(entity::post::Column::Name.contains(&name));
+        }
+
+        if let Some(q) = self.q.filter(|q| !q.trim().is_empty()) {
+            let matches = SearchService::matching_condition(database.get_database_backend(), &q)
+                .traced_and_response(|e| tracing::error!("{}", e))?;
+            select = select.filter(matches);
+        }
+
+        if let Some(status) = self.status {
+            select = select.filter(entity::post::Column::Status.eq(status));
+        }
+
+        select = match self.visibility {
+            None => select,
+            Some(PostVisibility::Public) => select.filter(PostService::readable_condition()),
+            Some(PostVisibility::Hidden) => select.filter(PostService::readable_condition().not()),
+            Some(PostVisibility::Protected) => {
+                select.filter(entity::post::Column::PasswordHash.is_not_null())
+            }
+        };
+
+        if let Some(kind) = self.kind {
+            select = select.filter(entity::post::Column::Kind.eq(kind));
+        }
+
+        if let Some(tag) = self.tag {
+            select = select
+                .inner_join(entity::tag::Entity)
+                .filter(entity::tag::Column::Name.eq(tag.trim()));
+        }
+
+        if let Some(category) = self.category {
+            select = select
+                .inner_join(entity::category::Entity)
+                .filter(entity::category::Column::Name.eq(category.trim()));
+        }
+
+        if let Some(author) = self.author {
+            select = select.filter(entity::post::Column::Author.eq(author));
+        }
+
+        if let Some(function) = self.function {
+            // Functions are stored as a JSON array, so match the quoted entry.
+            let entry = JsonValue::String(function).to_string();
+            select = select.filter(entity::post::Column::Functions.like(like_contains(&entry)));
+        }
+
+        for (bound, value, column, earliest) in [
+            (
+                "created_from",
+                self.created_from,
+                entity::post::Column::CreatedAt,
+                true,
+            ),
+            (
+                "created_to",
+                self.created_to,
+                entity::post::Column::CreatedAt,
+                false,
+            ),
+            (
+                "updated_from",
+                self.updated_from,
+                entity::post::Column::UpdatedAt,
+                true,
+            ),
+            (
+                "updated_to",
+                self.updated_to,
+                entity::post::Column::UpdatedAt,
+                false,
+            ),
+        ] {
+            let Some(value) = value else {
+                continue;
+            };
+            let time = DateTimeUtc::from_timestamp_secs(value).ok_or_else(|| {
+                ApiResponse::code_and_message(
+                    StatusCode::BAD_REQUEST,
+                    format!("Failed to parse {bound}"),
+                )
+                .into_response()
+            })?;
+            select = if earliest {
+                select.filter(column.gte(time))
+            } else {
+                select.filter(column.lte(time))
+            };
+        }
+
+        Ok(select)
+    }
+}
+
 #[derive(Debug, Serialize, DerivePartialModel)]
 #[sea_orm(entity = "entity::post::Entity")]
 pub struct PostListItem {
@@ -70,8 +249,17 @@ pub struct PostListItem {
     pub updated_at: Option<DateTimeUtc>,
     pub description: Option<String>,
     pub illustration: Option<String>,
-    pub hidden: Option<bool>,
+    pub status: PostStatus,
+    pub publish_at: Option<DateTimeUtc>,
+    pub kind: PostKind,
     pub functions: entity::post::PostFunctions,
+    pub pinned: bool,
+    pub featured: bool,
+    pub weight: i32,
+    #[sea_orm(skip)]
+    pub tags: Vec<String>,
+    #[sea_orm(skip)]
+    pub categories: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -87,8 +275,15 @@ pub struct PostDetailResponse {
     pub illustration: Option<String>,
     pub tags: Vec<String>,
     pub categories: Vec<String>,
-    pub hidden: bool,
+    pub status: PostStatus,
+    pub publish_at: Option<DateTimeUtc>,
+    pub kind: PostKind,
     pub functions: Vec<String>,
+    pub meta: JsonMap<String, JsonValue>,
+    pub pinned: bool,
+    pub featured: bool,
+    pub weight: i32,
+    pub protected: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -107,22 +302,21 @@ pub fn get_routes() -> Router {
             get(get_post_content).delete(delete_post).post(edit_post),
         )
         .route("/{id}/rendered", get(get_rendered_post_content))
+        .nest("/{id}/revisions", super::post_revisions::get_routes())
+        .nest("/{id}/previews", super::post_previews::get_routes())
+        .route("/bulk", post(super::post_bulk::bulk_update_posts))
         .route("/", get(list_posts).put(create_post))
 }
 
 pub async fn list_posts(
     Extension(database): Extension<DatabaseConnection>,
     Query(query): Query<PostListRequest>,
+    Query(filter): Query<PostFilter>,
+    _claims: JwtClaims,
 ) -> Result<ApiResponse<PostListResponse>, Response> {
-    let mut select = entity::post::Entity::find();
-
-    if let Some(title) = query.title {
-        select = select.filter(entity::post::Column::Title.contains(&title));
-    }
-
-    if let Some(name) = query.name {
-        select = select.filter(entity::post::Column::Name.contains(&name));
-    }
+    let mut select = filter
+        .apply(&database, entity::post::Entity::find())
+        .await?;
 
     let sort_by = query.sort_by.unwrap_or_else(|| "id".to_string());
     let order_by = query.order.unwrap_or_else(|| "desc".to_string());
@@ -132,6 +326,9 @@ pub async fn list_posts(
         "title" => entity::post::Column::Title,
         "name" => entity::post::Column::Name,
         "created_at" => entity::post::Column::CreatedAt,
+        "updated_at" => entity::post::Column::UpdatedAt,
+        "status" => entity::post::Column::Status,
+        "author" => entity::post::Column::Author,
         _ => entity::post::Column::Id,
     };
 
@@ -144,7 +341,7 @@ pub async fn list_posts(
     let pagination = Pagination::new(query.page, query.page_size, 10);
 
     let paginator = select
-        .into_partial_model()
+        .into_partial_model::<PostListItem>()
         .paginate(&database, pagination.size());
 
     let total = paginator
@@ -153,10 +350,19 @@ pub async fn list_posts(
         .traced_and_response(|e| tracing::error!("{}", e))?;
     let total_pages = pagination.total_pages(total);
 
-    let posts = paginator
+    let mut posts = paginator
         .fetch_page(pagination.offset())
         .await
         .traced_and_response(|e| tracing::error!("{}", e))?;
+    let ids = posts.iter().map(|post| post.id).collect::<Vec<_>>();
+    let mut terms = TaxonomyService::terms_for_posts(&database, &ids)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    for post in &mut posts {
+        let terms = terms.remove(&post.id).unwrap_or_default();
+        post.tags = terms.tags;
+        post.categories = terms.categories;
+    }
 
     Ok(ApiResponse::ok(PostListResponse {
         posts,
@@ -170,6 +376,7 @@ pub async fn list_posts(
 pub async fn get_post_content(
     Extension(database): Extension<DatabaseConnection>,
     Path(id): Path<i32>,
+    _claims: JwtClaims,
 ) -> Result<Response, Response> {
     let post = entity::post::Entity::find_by_id(id)
         .one(&database)
@@ -192,6 +399,7 @@ pub async fn get_post_content(
 pub async fn get_rendered_post_content(
     Extension(database): Extension<DatabaseConnection>,
     Path(id): Path<i32>,
+    _claims: JwtClaims,
 ) -> Result<Response, Response> {
     let post = entity::post::Entity::find_by_id(id)
         .one(&database)
@@ -203,7 +411,9 @@ pub async fn get_rendered_post_content(
             ApiResponse::code_and_message(StatusCode::NOT_FOUND, "No post found").into_response()
         }
         Some(post) => ApiResponse::ok(
-            render_markdown(&post.content).traced_and_response(|e| tracing::error!("{}", e))?,
+            render_markdown(&post.content)
+                .traced_and_response(|e| tracing::error!("{}", e))?
+                .html,
         )
         .into_response(),
     })
@@ -228,10 +438,10 @@ pub async fn delete_post(
                 .begin()
                 .await
                 .traced_and_response(|e| tracing::error!("{}", e))?;
-            TaxonomyService::delete_post_terms(&transaction, post.id)
+            delete_post_records(&transaction, post)
                 .await
                 .traced_and_response(|e| tracing::error!("{}", e))?;
-            post.delete(&transaction)
+            SchedulerService::request(&transaction, Job::PingSubscribers)
                 .await
                 .traced_and_response(|e| tracing::error!("{}", e))?;
             transaction
@@ -245,6 +455,7 @@ pub async fn delete_post(
 
 pub async fn create_post(
     Extension(database): Extension<DatabaseConnection>,
+    Extension(theme_service): Extension<ThemeService>,
     User(user): User,
     Json(post_payload): Json<PostCreateRequest>,
 ) -> Result<ApiResponse, Response> {
@@ -255,10 +466,25 @@ pub async fn create_post(
     let updated_at = post_payload
         .updated_at
         .and_then(DateTimeUtc::from_timestamp_secs)
-        .unwrap_or_else(|| created_at.clone());
+        .unwrap_or(created_at);
+    let status = post_payload.status.unwrap_or_default();
+    let publish_at = parse_publish_at(post_payload.publish_at)?;
+    validate_schedule(status, publish_at)?;
+    let kind = post_payload.kind.unwrap_or_default();
+    validate_page_name(kind, &post_payload.name)?;
     let tags = post_payload.tags.unwrap_or_default();
     let categories = post_payload.categories.unwrap_or_default();
     let functions = post_payload.functions.unwrap_or_default();
+    let meta = resolve_post_meta(
+        &theme_service,
+        post_payload.meta.unwrap_or_default(),
+        &JsonMap::new(),
+    )
+    .await?;
+    let password_hash = match post_payload.password.as_deref() {
+        Some(password) => hash_post_password(password)?,
+        None => None,
+    };
     let active_model = entity::post::ActiveModel {
         id: ActiveValue::NotSet,
         name: ActiveValue::Set(post_payload.name),
@@ -267,9 +493,16 @@ pub async fn create_post(
         author: ActiveValue::Set(user.id),
         description: ActiveValue::Set(post_payload.description),
         illustration: ActiveValue::Set(post_payload.illustration),
-        hidden: ActiveValue::Set(Some(post_payload.hidden.unwrap_or(false))),
+        status: ActiveValue::Set(status),
+        publish_at: ActiveValue::Set(publish_at),
+        kind: ActiveValue::Set(kind),
         functions: ActiveValue::Set(entity::post::PostFunctions(functions)),
-        created_at: ActiveValue::Set(created_at.clone()),
+        meta: ActiveValue::Set(meta),
+        pinned: ActiveValue::Set(post_payload.pinned.unwrap_or_default()),
+        featured: ActiveValue::Set(post_payload.featured.unwrap_or_default()),
+        weight: ActiveValue::Set(post_payload.weight.unwrap_or_default()),
+        password_hash: ActiveValue::Set(password_hash),
+        created_at: ActiveValue::Set(created_at),
         updated_at: ActiveValue::Set(Some(updated_at)),
     };
 
@@ -284,6 +517,15 @@ pub async fn create_post(
     TaxonomyService::replace_post_terms(&transaction, post.id, Some(tags), Some(categories))
         .await
         .traced_and_response(|e| tracing::error!("{}", e))?;
+    PostRevisionService::record_created(&transaction, &post, user.id)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    PostRenderService::invalidate(&transaction, post.id)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    SchedulerService::request(&transaction, Job::PingSubscribers)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
     transaction
         .commit()
         .await
@@ -294,8 +536,9 @@ pub async fn create_post(
 
 pub async fn edit_post(
     Extension(database): Extension<DatabaseConnection>,
+    Extension(theme_service): Extension<ThemeService>,
     Path(id): Path<i32>,
-    _claims: JwtClaims,
+    claims: JwtClaims,
     Json(post_payload): Json<PostUpdateRequest>,
 ) -> Result<ApiResponse, Response> {
     let old_post = entity::post::Entity::find_by_id(id)
@@ -304,7 +547,16 @@ pub async fn edit_post(
         .traced_and_response(|e| tracing::error!("{}", e))?
         .ok_or_else(|| ApiResponse::code(StatusCode::NOT_FOUND).into_response())?;
 
-    let mut active_model = old_post.into_active_model();
+    let status = post_payload.status.unwrap_or(old_post.status);
+    let publish_at = match post_payload.publish_at {
+        Some(_) => parse_publish_at(post_payload.publish_at)?,
+        None => old_post.publish_at,
+    };
+    validate_schedule(status, publish_at)?;
+    let kind = post_payload.kind.unwrap_or(old_post.kind);
+    validate_page_name(kind, post_payload.name.as_deref().unwrap_or(&old_post.name))?;
+
+    let mut active_model = old_post.clone().into_active_model();
     if let Some(new_content) = post_payload.content {
         active_model.content = ActiveValue::Set(new_content);
     }
@@ -337,14 +589,35 @@ pub async fn edit_post(
     let tags = post_payload.tags;
     let categories = post_payload.categories;
 
-    if let Some(hidden) = post_payload.hidden {
-        active_model.hidden = ActiveValue::Set(Some(hidden));
-    }
+    active_model.status = ActiveValue::Set(status);
+    active_model.publish_at = ActiveValue::Set(publish_at);
+    active_model.kind = ActiveValue::Set(kind);
 
     if let Some(functions) = post_payload.functions {
         active_model.functions = ActiveValue::Set(entity::post::PostFunctions(functions));
     }
 
+    if let Some(meta) = post_payload.meta {
+        active_model.meta =
+            ActiveValue::Set(resolve_post_meta(&theme_service, meta, &old_post.meta.0).await?);
+    }
+
+    if let Some(pinned) = post_payload.pinned {
+        active_model.pinned = ActiveValue::Set(pinned);
+    }
+
+    if let Some(featured) = post_payload.featured {
+        active_model.featured = ActiveValue::Set(featured);
+    }
+
+    if let Some(weight) = post_payload.weight {
+        active_model.weight = ActiveValue::Set(weight);
+    }
+
+    if let Some(password) = post_payload.password.as_deref() {
+        active_model.password_hash = ActiveValue::Set(hash_post_password(password)?);
+    }
+
     active_model.updated_at = ActiveValue::Set(Some(
         post_payload
             .updated_at
@@ -356,13 +629,37 @@ pub async fn edit_post(
         .begin()
         .await
         .traced_and_response(|e| tracing::error!("{}", e))?;
-    active_model
+    if let ActiveValue::Set(name) = &active_model.name
+        && *name != old_post.name
+        && PostService::name_taken(&transaction, name, id)
+            .await
+            .traced_and_response(|e| tracing::error!("{}", e))?
+    {
+        return Err(ApiResponse::code_and_message(
+            StatusCode::CONFLICT,
+            "Another post already uses this name",
+        )
+        .into_response());
+    }
+    let post = active_model
         .update(&transaction)
         .await
         .traced_and_response(|e| tracing::error!("{}", e))?;
     TaxonomyService::replace_post_terms(&transaction, id, tags, categories)
         .await
         .traced_and_response(|e| tracing::error!("{}", e))?;
+    PostRevisionService::record_updated(&transaction, &old_post, &post, claims.user_id)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    PostService::record_rename(&transaction, id, &old_post.name, &post.name)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    PostRenderService::invalidate(&transaction, id)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    SchedulerService::request(&transaction, Job::PingSubscribers)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
     transaction
         .commit()
         .await
@@ -371,6 +668,21 @@ pub async fn edit_post(
     Ok(ApiResponse::ok(()))
 }
 
+/// Deletes a post together with its terms, revisions, previous names,
+/// preview links and cached render.
+pub(super) async fn delete_post_records<C>(db: &C, post: entity::post::Model) -> Result<(), DbErr>
+where
+    C: ConnectionTrait,
+{
+    TaxonomyService::delete_post_terms(db, post.id).await?;
+    PostRevisionService::delete_post_revisions(db, post.id).await?;
+    PostService::delete_slug_history(db, post.id).await?;
+    PostPreviewService::delete_post_previews(db, post.id).await?;
+    PostRenderService::invalidate(db, post.id).await?;
+    post.delete(db).await?;
+    Ok(())
+}
+
 async fn post_detail_response(
     database: &DatabaseConnection,
     post: entity::post::Model,
@@ -380,45 +692,137 @@ async fn post_detail_response(
         .remove(&post.id)
         .unwrap_or_default();
     Ok(PostDetailResponse {
+        protected: PostService::is_protected(&post),
         id: post.id,
         title: post.title,
         name: post.name,
         content: post.content,
         author: post.author,
         created_at: post.created_at,
-        updated_at: post
-            .updated_at
-            .clone()
-            .unwrap_or_else(|| post.created_at.clone()),
+        updated_at: post.updated_at.unwrap_or(post.created_at),
         description: post.description,
         illustration: post.illustration,
         tags: terms.tags,
         categories: terms.categories,
-        hidden: post.hidden.unwrap_or(false),
+        status: post.status,
+        publish_at: post.publish_at,
+        kind: post.kind,
         functions: post.functions.0,
+        meta: post.meta.0,
+        pinned: post.pinned,
+        featured: post.featured,
+        weight: post.weight,
     })
 }
 
+async fn resolve_post_meta(
+    theme_service: &ThemeService,
+    meta: JsonMap<String, JsonValue>,
+    stored: &JsonMap<String, JsonValue>,
+) -> Result<PostMeta, Response> {
+    theme_service
+        .resolve_post_meta(&meta, stored)
+        .await
+        .map(PostMeta)
+        .map_err(|error| {
+            ApiResponse::code_and_message(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid post metadata: {error}"),
+            )
+            .into_response()
+        })
+}
+
+#[allow(
+    clippy::result_large_err,
+    reason = "Axum handlers use Response as their established rejection type."
+)]
+fn hash_post_password(password: &str) -> Result<Option<String>, Response> {
+    if password.is_empty() {
+        return Ok(None);
+    }
+    PostService::hash_password(password)
+        .map(Some)
+        .traced_and_response(|e| tracing::error!("{}", e))
+}
+
+#[allow(
+    clippy::result_large_err,
+    reason = "Axum handlers use Response as their established rejection type."
+)]
+fn parse_publish_at(publish_at: Option<i64>) -> Result<Option<DateTimeUtc>, Response> {
+    match publish_at {
+        None => Ok(None),
+        Some(timestamp) => match DateTimeUtc::from_timestamp_secs(timestamp) {
+            Some(publish_at) => Ok(Some(publish_at)),
+            None => Err(ApiResponse::code_and_message(
+                StatusCode::BAD_REQUEST,
+                "Failed to parse publish_at",
+            )
+            .into_response()),
+        },
+    }
+}
+
+#[allow(
+    clippy::result_large_err,
+    reason = "Axum handlers use Response as their established rejection type."
+)]
+fn validate_schedule(status: PostStatus, publish_at: Option<DateTimeUtc>) -> Result<(), Response> {
+    if status == PostStatus::Scheduled && publish_at.is_none() {
+        return Err(ApiResponse::code_and_message(
+            StatusCode::BAD_REQUEST,
+            "Scheduled posts require publish_at",
+        )
+        .into_response());
+    }
+    Ok(())
+}
+
+#[allow(
+    clippy::result_large_err,
+    reason = "Axum handlers use Response as their established rejection type."
+)]
+fn validate_page_name(kind: PostKind, name: &str) -> Result<(), Response> {
+    if kind == PostKind::Page && PostService::is_reserved_page_name(name) {
+        return Err(ApiResponse::code_and_message(
+            StatusCode::BAD_REQUEST,
+            "This name is reserved by another route",
+        )
+        .into_response());
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use axum::{
         Extension, Json,
         body::Body,
-        extract::Query,
+        extract::{Path, Query},
         http::{Request, StatusCode},
     };
     use sea_orm::{
         ActiveModelTrait, ConnectionTrait, Database, DatabaseBackend, DatabaseConnection,
-        EntityTrait, Schema, Set,
+        EntityTrait, IntoActiveModel, Schema, Set, prelude::DateTimeUtc,
     };
     use tower::ServiceExt;
 
     use crate::{
-        entity::{self, user},
-        service::{taxonomy::TaxonomyService, user::User},
+        config::ApplicationConfiguration,
+        entity::{
+            self,
+            post::{PostMeta, PostStatus},
+            user,
+        },
+        service::{
+            jwt::JwtClaims, post::PostService, reloadable::ReloadableService,
+            search::SearchService, site_settings::SiteSettingsService, taxonomy::TaxonomyService,
+            theme::ThemeService, user::User,
+        },
     };
 
-    use super::{PostListRequest, create_post, get_routes, list_posts};
+    use super::{PostFilter, PostListRequest, create_post, edit_post, get_routes, list_posts};
 
     async fn database_with_post_schema() -> DatabaseConnection {
         let database = Database::connect("sqlite::memory:").await.unwrap();
@@ -431,6 +835,11 @@ mod tests {
             schema.create_table_from_entity(entity::category::Entity),
             schema.create_table_from_entity(entity::post_tag::Entity),
             schema.create_table_from_entity(entity::post_category::Entity),
+            schema.create_table_from_entity(entity::post_revision::Entity),
+            schema.create_table_from_entity(entity::post_slug::Entity),
+            schema.create_table_from_entity(entity::post_render::Entity),
+            schema.create_table_from_entity(entity::job::Entity),
+            schema.create_table_from_entity(entity::config_entry::Entity),
         ] {
             database.execute(&statement).await.unwrap();
         }
@@ -438,6 +847,24 @@ mod tests {
         database
     }
 
+    fn theme_service(database: &DatabaseConnection) -> ThemeService {
+        let config = ApplicationConfiguration::for_tests("assets");
+        ThemeService::new(
+            database.clone(),
+            config,
+            SiteSettingsService::new(database.clone()),
+        )
+    }
+
+    fn claims() -> JwtClaims {
+        JwtClaims {
+            sub: "user-1".to_string(),
+            exp: 0,
+            jti: String::new(),
+            user_id: 1,
+        }
+    }
+
     async fn insert_user(database: &DatabaseConnection, id: i32) -> user::Model {
         user::ActiveModel {
             id: Set(id),
@@ -455,7 +882,9 @@ mod tests {
     #[tokio::test]
     async fn rejects_anonymous_post_edits_before_database_access() {
         let database = Database::connect("sqlite::memory:").await.unwrap();
-        let app = get_routes().layer(Extension(database));
+        let app = get_routes()
+            .layer(Extension(theme_service(&database)))
+            .layer(Extension(database));
         let request = Request::builder()
             .method("POST")
             .uri("/1")
@@ -491,11 +920,11 @@ mod tests {
             Query(PostListRequest {
                 page: Some(0),
                 page_size: Some(1),
-                title: None,
-                name: None,
                 sort_by: Some("id".to_string()),
                 order: Some("asc".to_string()),
             }),
+            Query(PostFilter::default()),
+            claims(),
         )
         .await
         .unwrap();
@@ -508,6 +937,160 @@ mod tests {
         assert_eq!(data.total_pages, 2);
     }
 
+    #[tokio::test]
+    async fn list_posts_searches_post_content() {
+        let database = database_with_post_schema().await;
+        SearchService::ensure_index(&database).await.unwrap();
+        let user = insert_user(&database, 1).await;
+        for (name, content) in [
+            ("ownership", "Values move by default."),
+            (
+                "lifetimes",
+                "The borrow checker rejects dangling references.",
+            ),
+        ] {
+            entity::post::ActiveModel {
+                name: Set(name.to_string()),
+                title: Set(name.to_string()),
+                content: Set(content.to_string()),
+                author: Set(user.id),
+                ..Default::default()
+            }
+            .insert(&database)
+            .await
+            .unwrap();
+        }
+
+        let response = list_posts(
+            Extension(database),
+            Query(serde_json::from_value(serde_json::json!({})).unwrap()),
+            Query(serde_json::from_value(serde_json::json!({ "q": "checker" })).unwrap()),
+            claims(),
+        )
+        .await
+        .unwrap();
+        let data = response.data.unwrap();
+
+        assert_eq!(data.total, 1);
+        assert_eq!(data.posts[0].name, "lifetimes");
+    }
+
+    #[tokio::test]
+    async fn list_posts_filters_by_terms_state_author_and_dates() {
+        let database = database_with_post_schema().await;
+        insert_user(&database, 1).await;
+        insert_user(&database, 2).await;
+        for (id, author, status, functions, created_at, tags) in [
+            (
+                1,
+                1,
+                PostStatus::Published,
+                vec!["mermaid"],
+                1_700_000_000,
+                vec!["Rust"],
+            ),
+            (
+                2,
+                2,
+                PostStatus::Draft,
+                vec!["no-comment"],
+                1_700_100_000,
+                vec!["Rust", "Web"],
+            ),
+            (
+                3,
+                1,
+                PostStatus::Unlisted,
+                vec!["math"],
+                1_700_200_000,
+                vec![],
+            ),
+        ] {
+            entity::post::ActiveModel {
+                id: Set(id),
+                name: Set(format!("post-{id}")),
+                title: Set(format!("Post {id}")),
+                content: Set(String::new()),
+                author: Set(author),
+                status: Set(status),
+                functions: Set(entity::post::PostFunctions(
+                    functions.into_iter().map(str::to_string).collect(),
+                )),
+                created_at: Set(DateTimeUtc::from_timestamp_secs(created_at).unwrap()),
+                ..Default::default()
+            }
+            .insert(&database)
+            .await
+            .unwrap();
+            TaxonomyService::replace_post_terms(
+                &database,
+                id,
+                Some(tags.into_iter().map(str::to_string).collect()),
+                Some(vec!["Notes".to_string()]),
+            )
+            .await
+            .unwrap();
+        }
+
+        let list = |filter: serde_json::Value| {
+            let database = database.clone();
+            async move {
+                list_posts(
+                    Extension(database),
+                    Query(serde_json::from_value(serde_json::json!({ "order": "asc" })).unwrap()),
+                    Query(serde_json::from_value(filter).unwrap()),
+                    claims(),
+                )
+                .await
+                .unwrap()
+                .data
+                .unwrap()
+                .posts
+            }
+        };
+        let ids =
+            |posts: Vec<super::PostListItem>| posts.iter().map(|post| post.id).collect::<Vec<_>>();
+
+        let posts = list(serde_json::json!({ "tag": "Rust" })).await;
+        assert_eq!(posts[1].tags, ["Rust", "Web"]);
+        assert_eq!(posts[1].categories, ["Notes"]);
+        assert_eq!(ids(posts), [1, 2]);
+        assert_eq!(
+            ids(list(serde_json::json!({ "category": "Notes", "author": 1 })).await),
+            [1, 3]
+        );
+        assert_eq!(
+            ids(list(serde_json::json!({ "visibility": "public" })).await),
+            [1, 3]
+        );
+        assert_eq!(
+            ids(list(serde_json::json!({ "visibility": "hidden" })).await),
+            [2]
+        );
+        assert_eq!(
+            ids(list(serde_json::json!({ "function": "math" })).await),
+            [3]
+        );
+        // `_` is not a wildcard.
+        assert!(
+            list(serde_json::json!({ "function": "no_comment" }))
+                .await
+                .is_empty()
+        );
+        assert_eq!(
+            ids(list(
+                serde_json::json!({ "created_from": 1_700_050_000, "created_to": 1_700_200_000 })
+            )
+            .await),
+            [2, 3]
+        );
+        assert!(
+            list(serde_json::json!({ "updated_from": 0 }))
+                .await
+                .is_empty()
+        );
+    }
+
     #[tokio::test]
     async fn create_post_uses_the_authenticated_user_as_author() {
         let database = database_with_post_schema().await;
@@ -520,7 +1103,8 @@ mod tests {
             "illustration": "/attachments/cover",
             "tags": ["Rust", "Web"],
             "categories": ["Engineering"],
-            "hidden": true,
+            "status": "scheduled",
+            "publish_at": 1700000200,
             "functions": ["mermaid"],
             "created_at": 1700000000,
             "updated_at": 1700000100,
@@ -528,9 +1112,14 @@ mod tests {
         }))
         .unwrap();
 
-        create_post(Extension(database.clone()), User(user), Json(request))
-            .await
-            .unwrap();
+        create_post(
+            Extension(database.clone()),
+            Extension(theme_service(&database)),
+            User(user),
+            Json(request),
+        )
+        .await
+        .unwrap();
 
         let post = entity::post::Entity::find()
             .one(&database)
@@ -547,9 +1136,300 @@ mod tests {
             .unwrap();
         assert_eq!(terms.tags, ["Rust", "Web"]);
         assert_eq!(terms.categories, ["Engineering"]);
-        assert_eq!(post.hidden, Some(true));
+        assert_eq!(post.status, PostStatus::Scheduled);
+        assert_eq!(post.publish_at.unwrap().timestamp(), 1_700_000_200);
         assert_eq!(post.functions.0, ["mermaid"]);
         assert_eq!(post.created_at.timestamp(), 1_700_000_000);
         assert_eq!(post.updated_at.unwrap().timestamp(), 1_700_000_100);
     }
+
+    #[tokio::test]
+    async fn edit_post_records_revisions_and_previous_names() {
+        let database = database_with_post_schema().await;
+        let user = insert_user(&database, 1).await;
+        let request = serde_json::from_value(serde_json::json!({
+            "title": "Post",
+            "name": "old-name",
+            "content": "Draft",
+        }))
+        .unwrap();
+        create_post(
+            Extension(database.clone()),
+            Extension(theme_service(&database)),
+            User(user),
+            Json(request),
+        )
+        .await
+        .unwrap();
+        let post = entity::post::Entity::find()
+            .one(&database)
+            .await
+            .unwrap()
+            .unwrap();
+        let claims = claims();
+        let request = serde_json::from_value(serde_json::json!({
+            "name": "new-name",
+            "content": "Final",
+        }))
+        .unwrap();
+
+        edit_post(
+            Extension(database.clone()),
+            Extension(theme_service(&database)),
+            Path(post.id),
+            claims,
+            Json(request),
+        )
+        .await
+        .unwrap();
+
+        let renamed = PostService::find_by_previous_name(&database, "old-name")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(renamed.name, "new-name");
+        let revisions = entity::post_revision::Entity::find()
+            .all(&database)
+            .await
+            .unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[1].content, "Final");
+    }
+
+    #[tokio::test]
+    async fn refuses_to_rename_onto_another_post() {
+        let database = database_with_post_schema().await;
+        let user = insert_user(&database, 1).await;
+        for name in ["first", "second"] {
+            let request = serde_json::from_value(serde_json::json!({
+                "title": name,
+                "name": name,
+                "content": "Content",
+            }))
+            .unwrap();
+            create_post(
+                Extension(database.clone()),
+                Extension(theme_service(&database)),
+                User(user.clone()),
+                Json(request),
+            )
+            .await
+            .unwrap();
+        }
+        let second = entity::post::Entity::find()
+            .all(&database)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|post| post.name == "second")
+            .unwrap();
+
+        let Err(response) = edit_post(
+            Extension(database.clone()),
+            Extension(theme_service(&database)),
+            Path(second.id),
+            claims(),
+            Json(serde_json::from_value(serde_json::json!({ "name": "first" })).unwrap()),
+        )
+        .await
+        else {
+            panic!("renaming onto another post must be refused");
+        };
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let unchanged = entity::post::Entity::find_by_id(second.id)
+            .one(&database)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(unchanged.name, "second");
+    }
+
+    #[tokio::test]
+    async fn rejects_scheduled_posts_without_a_publish_time() {
+        let database = database_with_post_schema().await;
+        let user = insert_user(&database, 1).await;
+        let request = serde_json::from_value(serde_json::json!({
+            "title": "Later",
+            "name": "later",
+            "content": "Content",
+            "status": "scheduled",
+        }))
+        .unwrap();
+
+        let Err(response) = create_post(
+            Extension(database.clone()),
+            Extension(theme_service(&database)),
+            User(user),
+            Json(request),
+        )
+        .await
+        else {
+            panic!("scheduled posts without publish_at must be rejected");
+        };
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(
+            entity::post::Entity::find()
+                .one(&database)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_pages_named_after_other_routes() {
+        let database = database_with_post_schema().await;
+        let user = insert_user(&database, 1).await;
+        let request = serde_json::from_value(serde_json::json!({
+            "title": "Archives",
+            "name": "archives",
+            "content": "Content",
+            "kind": "page",
+        }))
+        .unwrap();
+
+        let Err(response) = create_post(
+            Extension(database.clone()),
+            Extension(theme_service(&database)),
+            User(user),
+            Json(request),
+        )
+        .await
+        else {
+            panic!("pages must not shadow other routes");
+        };
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn validates_post_meta_against_the_active_theme() {
+        let assets = tempfile::tempdir().unwrap();
+        let theme = assets.path().join("themes/installed/default");
+        std::fs::create_dir_all(theme.join("layouts")).unwrap();
+        std::fs::write(
+            theme.join("manifest.toml"),
+            r#"
+                [[post_meta]]
+                key = "subtitle"
+                label = "Subtitle"
+                type = "string"
+
+                [[post_meta]]
+                key = "hero_colour"
+                label = "Hero colour"
+                type = "string"
+                default = "teal"
+            "#,
+        )
+        .unwrap();
+        for layout in ["home", "post", "archive", "terms", "taxonomy", "not-found"] {
+            std::fs::write(theme.join("layouts").join(layout), "").unwrap();
+        }
+        let database = database_with_post_schema().await;
+        let user = insert_user(&database, 1).await;
+        let theme_service = ThemeService::new(
+            database.clone(),
+            ApplicationConfiguration::for_tests(assets.path()),
+            SiteSettingsService::new(database.clone()),
+        );
+        theme_service.reload().await;
+        let create = |meta: serde_json::Value| {
+            serde_json::from_value(serde_json::json!({
+                "title": "Post",
+                "name": "post",
+                "content": "Content",
+                "meta": meta,
+            }))
+            .unwrap()
+        };
+
+        for meta in [
+            serde_json::json!({ "subtitle": 1 }),
+            serde_json::json!({ "subtitle": "Part one", "source": "elsewhere" }),
+        ] {
+            let Err(response) = create_post(
+                Extension(database.clone()),
+                Extension(theme_service.clone()),
+                User(user.clone()),
+                Json(create(meta)),
+            )
+            .await
+            else {
+                panic!("metadata of the wrong type or undeclared keys must be rejected");
+            };
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+
+        create_post(
+            Extension(database.clone()),
+            Extension(theme_service.clone()),
+            User(user),
+            Json(create(serde_json::json!({ "subtitle": "Part one" }))),
+        )
+        .await
+        .unwrap();
+        let post = entity::post::Entity::find()
+            .one(&database)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            serde_json::Value::Object(post.meta.0.clone()),
+            serde_json::json!({ "subtitle": "Part one", "hero_colour": "teal" })
+        );
+
+        let mut stored = post.meta.0.clone();
+        stored.insert("source".into(), "elsewhere".into());
+        let mut active_model = post.into_active_model();
+        active_model.meta = Set(PostMeta(stored));
+        let post = active_model.update(&database).await.unwrap();
+        let edit = |meta: serde_json::Value| {
+            serde_json::from_value(serde_json::json!({ "meta": meta })).unwrap()
+        };
+
+        let Err(response) = edit_post(
+            Extension(database.clone()),
+            Extension(theme_service.clone()),
+            Path(post.id),
+            claims(),
+            Json(edit(
+                serde_json::json!({ "subtitle": "Part two", "extra": true }),
+            )),
+        )
+        .await
+        else {
+            panic!("keys the post does not store must be rejected on edit");
+        };
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Fields an earlier theme declared survive an edit under this one,
+        // with the values the post already stored.
+        edit_post(
+            Extension(database.clone()),
+            Extension(theme_service),
+            Path(post.id),
+            claims(),
+            Json(edit(
+                serde_json::json!({ "subtitle": "Part two", "source": "changed" }),
+            )),
+        )
+        .await
+        .unwrap();
+        let post = entity::post::Entity::find_by_id(post.id)
+            .one(&database)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            serde_json::Value::Object(post.meta.0),
+            serde_json::json!({
+                "subtitle": "Part two",
+                "hero_colour": "teal",
+                "source": "elsewhere"
+            })
+        );
+    }
 }
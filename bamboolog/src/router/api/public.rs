@@ -0,0 +1,276 @@
+use axum::{
+    Extension, Router,
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, prelude::DateTimeUtc,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entity::post::{self, PostKind},
+    service::{
+        post::PostService,
+        post_render::PostRenderService,
+        site_settings::SiteSettingsService,
+        taxonomy::{TaxonomyKind, TaxonomyService},
+        theme::ThemeService,
+    },
+    utils::{ApiResponse, HttpFailibleOperationExts, Pagination},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct PublicPostListRequest {
+    pub page: Option<u64>,
+    pub page_size: Option<u64>,
+    pub tag: Option<String>,
+    pub category: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublicPostSummary {
+    pub title: String,
+    pub name: String,
+    pub url: String,
+    pub description: Option<String>,
+    pub illustration: Option<String>,
+    pub tags: Vec<String>,
+    pub categories: Vec<String>,
+    pub created_at: DateTimeUtc,
+    pub updated_at: Option<DateTimeUtc>,
+    pub protected: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublicPostListResponse {
+    pub posts: Vec<PublicPostSummary>,
+    pub total: u64,
+    pub page: u64,
+    pub page_size: u64,
+    pub total_pages: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublicPostResponse {
+    #[serde(flatten)]
+    pub summary: PublicPostSummary,
+    pub kind: PostKind,
+    pub content: String,
+    pub html: String,
+}
+
+/// Read-only routes for anonymous readers. Everything here is already visible
+/// on the public site; drafts, future scheduled posts and the content of
+/// password-protected posts never leave through this surface.
+pub fn get_routes() -> Router {
+    Router::new()
+        .route("/posts", get(list_public_posts))
+        .route("/posts/{name}", get(get_public_post))
+}
+
+/// Listed posts, newest first, as on the archive pages.
+pub async fn list_public_posts(
+    Extension(database): Extension<DatabaseConnection>,
+    Query(query): Query<PublicPostListRequest>,
+) -> Result<ApiResponse<PublicPostListResponse>, Response> {
+    let select = match (query.tag, query.category) {
+        (Some(tag), _) => TaxonomyService::visible_posts_for_term(TaxonomyKind::Tag, &tag),
+        (None, Some(category)) => {
+            TaxonomyService::visible_posts_for_term(TaxonomyKind::Category, &category)
+        }
+        (None, None) => PostService::listed_posts(),
+    };
+    let pagination = Pagination::new(query.page, query.page_size, 10);
+    let paginator = select.paginate(&database, pagination.size());
+    let total = paginator
+        .num_items()
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    let posts = paginator
+        .fetch_page(pagination.offset())
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    let ids = posts.iter().map(|post| post.id).collect::<Vec<_>>();
+    let mut terms = TaxonomyService::terms_for_posts(&database, &ids)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+
+    Ok(ApiResponse::ok(PublicPostListResponse {
+        posts: posts
+            .into_iter()
+            .map(|post| {
+                let terms = terms.remove(&post.id).unwrap_or_default();
+                summary(post, terms.tags, terms.categories)
+            })
+            .collect(),
+        total,
+        page: pagination.page(),
+        page_size: pagination.size(),
+        total_pages: pagination.total_pages(total),
+    }))
+}
+
+/// A publicly readable post or page by name. Unlisted posts are included, as
+/// they are on the site; protected ones are refused without their content.
+/// The HTML is the same stored render the post page shows.
+pub async fn get_public_post(
+    Extension(database): Extension<DatabaseConnection>,
+    Extension(theme_service): Extension<ThemeService>,
+    Extension(site_settings): Extension<SiteSettingsService>,
+    Path(name): Path<String>,
+) -> Result<Response, Response> {
+    let post = post::Entity::find()
+        .filter(post::Column::Name.eq(&name))
+        .filter(PostService::readable_condition())
+        .one(&database)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    let Some(post) = post else {
+        return Ok(
+            ApiResponse::code_and_message(StatusCode::NOT_FOUND, "No post found").into_response(),
+        );
+    };
+    if PostService::is_protected(&post) {
+        return Ok(ApiResponse::code_and_message(
+            StatusCode::FORBIDDEN,
+            "This post is password protected",
+        )
+        .into_response());
+    }
+
+    let terms = TaxonomyService::terms_for_posts(&database, &[post.id])
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?
+        .remove(&post.id)
+        .unwrap_or_default();
+    let site = site_settings.read().await.clone();
+    let html = PostRenderService::rendered_one(&database, &theme_service, &site, &post)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?
+        .html;
+    let kind = post.kind;
+    let content = post.content.clone();
+    Ok(ApiResponse::ok(PublicPostResponse {
+        summary: summary(post, terms.tags, terms.categories),
+        kind,
+        content,
+        html,
+    })
+    .into_response())
+}
+
+fn summary(post: post::Model, tags: Vec<String>, categories: Vec<String>) -> PublicPostSummary {
+    PublicPostSummary {
+        url: PostService::public_path(&post),
+        protected: PostService::is_protected(&post),
+        title: post.title,
+        name: post.name,
+        description: post.description,
+        illustration: post.illustration,
+        tags,
+        categories,
+        created_at: post.created_at,
+        updated_at: post.updated_at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Extension,
+        body::{Body, to_bytes},
+        http::{Request, StatusCode},
+    };
+    use sea_orm::{ActiveModelTrait, Database, DatabaseConnection, EntityTrait, Set};
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use crate::{
+        config::ApplicationConfiguration,
+        entity::{
+            post::{self, PostStatus},
+            post_render,
+        },
+        maintenance::sync_entities,
+        service::{site_settings::SiteSettingsService, theme::ThemeService},
+    };
+
+    use super::get_routes;
+
+    async fn get(database: &DatabaseConnection, uri: &str) -> (StatusCode, Value) {
+        let config = ApplicationConfiguration::for_tests("assets");
+        let site_settings = SiteSettingsService::new(database.clone());
+        let theme_service = ThemeService::new(database.clone(), config, site_settings.clone());
+        let response = get_routes()
+            .layer(Extension(theme_service))
+            .layer(Extension(site_settings))
+            .layer(Extension(database.clone()))
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn serves_only_posts_visible_on_the_site() {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        sync_entities(&database).await.unwrap();
+        for (id, name, status, password_hash) in [
+            (1, "published", PostStatus::Published, None),
+            (2, "draft", PostStatus::Draft, None),
+            (3, "unlisted", PostStatus::Unlisted, None),
+            (4, "locked", PostStatus::Published, Some("hash".to_string())),
+            (5, "upcoming", PostStatus::Scheduled, None),
+        ] {
+            post::ActiveModel {
+                id: Set(id),
+                name: Set(name.to_string()),
+                title: Set(name.to_string()),
+                content: Set(format!("The *{name}* post")),
+                author: Set(1),
+                status: Set(status),
+                password_hash: Set(password_hash),
+                ..Default::default()
+            }
+            .insert(&database)
+            .await
+            .unwrap();
+        }
+
+        let (status, body) = get(&database, "/posts").await;
+        assert_eq!(status, StatusCode::OK);
+        let names = body["data"]["posts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|post| post["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&"published") && names.contains(&"locked"));
+        assert!(body["data"]["posts"][0].get("content").is_none());
+
+        let (status, body) = get(&database, "/posts/unlisted").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["html"], "<p>The <em>unlisted</em> post</p>");
+        let stored = post_render::Entity::find_by_id(3)
+            .one(&database)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.html, body["data"]["html"]);
+        for (name, expected) in [
+            ("draft", StatusCode::NOT_FOUND),
+            ("upcoming", StatusCode::NOT_FOUND),
+            ("locked", StatusCode::FORBIDDEN),
+        ] {
+            let (status, body) = get(&database, &format!("/posts/{name}")).await;
+            assert_eq!(status, expected, "{name}");
+            assert!(body["data"].get("content").is_none());
+        }
+    }
+}
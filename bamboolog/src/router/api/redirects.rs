@@ -0,0 +1,189 @@
+use crate::{
+    entity::redirect::{self, RedirectMatch},
+    service::{jwt::JwtClaims, redirect::RedirectService, reloadable::ReloadableService},
+    utils::{ApiResponse, HttpFailibleOperationExts},
+};
+use axum::{
+    Extension, Json, Router,
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+};
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, DatabaseConnection, EntityTrait, IntoActiveModel, QueryOrder, Set,
+};
+use serde::{Deserialize, Deserializer};
+
+pub fn get_routes() -> Router {
+    Router::new()
+        .route("/", get(list_redirects))
+        .route("/", post(create_redirect))
+        .route("/{id}", put(update_redirect))
+        .route("/{id}", delete(delete_redirect))
+}
+
+async fn list_redirects(
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(redirects): Extension<RedirectService>,
+    _user: JwtClaims,
+) -> Result<Response, Response> {
+    // Shows hits counted since the last periodic flush as well.
+    redirects
+        .flush_hits()
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    let redirects = redirect::Entity::find()
+        .order_by_asc(redirect::Column::Id)
+        .all(&db)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    Ok(ApiResponse::ok(redirects).into_response())
+}
+
+#[derive(Deserialize)]
+struct CreateRedirectRequest {
+    source: String,
+    match_kind: Option<RedirectMatch>,
+    target: Option<String>,
+    status_code: Option<i32>,
+}
+
+async fn create_redirect(
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(redirects): Extension<RedirectService>,
+    _user: JwtClaims,
+    Json(payload): Json<CreateRedirectRequest>,
+) -> Result<Response, Response> {
+    let match_kind = payload.match_kind.unwrap_or(RedirectMatch::Exact);
+    let status_code = payload
+        .status_code
+        .unwrap_or(i32::from(StatusCode::MOVED_PERMANENTLY.as_u16()));
+    validate_rule(
+        match_kind,
+        &payload.source,
+        payload.target.as_deref(),
+        status_code,
+    )?;
+
+    let res = redirect::ActiveModel {
+        source: Set(payload.source),
+        match_kind: Set(match_kind),
+        target: Set(payload.target),
+        status_code: Set(status_code),
+        created_at: Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await
+    .traced_and_response(|e| tracing::error!("{}", e))?;
+    redirects.reload().await;
+    Ok(ApiResponse::ok(res).into_response())
+}
+
+#[derive(Deserialize)]
+struct UpdateRedirectRequest {
+    source: Option<String>,
+    match_kind: Option<RedirectMatch>,
+    /// Absent keeps the current target; `null` clears it, as 410 rules
+    /// have none.
+    #[serde(default, deserialize_with = "present")]
+    target: Option<Option<String>>,
+    status_code: Option<i32>,
+}
+
+/// Tells a field sent as `null` apart from a missing one, which `default`
+/// leaves as `None`.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+async fn update_redirect(
+    Path(id): Path<i32>,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(redirects): Extension<RedirectService>,
+    _user: JwtClaims,
+    Json(payload): Json<UpdateRedirectRequest>,
+) -> Result<Response, Response> {
+    let current = redirect::Entity::find_by_id(id)
+        .one(&db)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?
+        .ok_or_else(|| {
+            ApiResponse::code_and_message(StatusCode::NOT_FOUND, "Redirect not found")
+                .into_response()
+        })?;
+
+    let source = payload.source.unwrap_or_else(|| current.source.clone());
+    let match_kind = payload.match_kind.unwrap_or(current.match_kind);
+    let target = payload.target.unwrap_or_else(|| current.target.clone());
+    let status_code = payload.status_code.unwrap_or(current.status_code);
+    validate_rule(match_kind, &source, target.as_deref(), status_code)?;
+
+    let mut rule = current.into_active_model();
+    rule.source = Set(source);
+    rule.match_kind = Set(match_kind);
+    rule.target = Set(target);
+    rule.status_code = Set(status_code);
+    let res = rule
+        .update(&db)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    redirects.reload().await;
+    Ok(ApiResponse::ok(res).into_response())
+}
+
+async fn delete_redirect(
+    Path(id): Path<i32>,
+    Extension(db): Extension<DatabaseConnection>,
+    Extension(redirects): Extension<RedirectService>,
+    _user: JwtClaims,
+) -> Result<Response, Response> {
+    redirect::Entity::delete_by_id(id)
+        .exec(&db)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    redirects.reload().await;
+    Ok(ApiResponse::ok(()).into_response())
+}
+
+#[allow(
+    clippy::result_large_err,
+    reason = "Axum handlers use Response as their established rejection type."
+)]
+fn validate_rule(
+    match_kind: RedirectMatch,
+    source: &str,
+    target: Option<&str>,
+    status_code: i32,
+) -> Result<(), Response> {
+    RedirectService::validate(match_kind, source, target, status_code).map_err(|error| {
+        ApiResponse::code_and_message(StatusCode::BAD_REQUEST, error.to_string()).into_response()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UpdateRedirectRequest;
+
+    #[test]
+    fn tells_a_cleared_target_from_a_missing_one() {
+        let target = |json: &str| {
+            serde_json::from_str::<UpdateRedirectRequest>(json)
+                .unwrap()
+                .target
+        };
+
+        assert_eq!(target("{}"), None);
+        assert_eq!(target(r#"{"target": null}"#), Some(None));
+        assert_eq!(
+            target(r#"{"target": "/new"}"#),
+            Some(Some("/new".to_string()))
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use axum::{
     Extension, Json, Router,
+    http::StatusCode,
     response::{IntoResponse, Response},
     routing::{get, post},
 };
@@ -10,7 +11,7 @@ use serde_json::Value as JsonValue;
 use crate::{
     config::{SiteSettings, config_entries},
     service::{jwt::JwtClaims, reloadable::ServiceReloader},
-    utils::{ApiResponse, HttpFailibleOperationExts},
+    utils::{ApiResponse, HttpFailibleOperationExts, highlight_themes},
 };
 
 pub fn get_routes() -> Router {
@@ -31,6 +32,7 @@ async fn get_settings(
 
     Ok(ApiResponse::ok(serde_json::json!({
         "site": site_settings,
+        "highlight_themes": highlight_themes(),
     })))
 }
 
@@ -46,6 +48,18 @@ async fn update_settings(
     Json(payload): Json<UpdateSettingsPayload>,
 ) -> Result<ApiResponse, Response> {
     if let Some(site) = payload.site {
+        if !site.code_highlight_theme.is_empty()
+            && !highlight_themes().contains(&site.code_highlight_theme.as_str())
+        {
+            return Err(ApiResponse::code_and_message(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Unknown code highlight theme: {}",
+                    site.code_highlight_theme
+                ),
+            )
+            .into_response());
+        }
         config_entries::SITE_SETTINGS
             .set(&db, Some(site))
             .await
@@ -1,4 +1,4 @@
-use std::fmt::Write;
+use std::{collections::HashMap, fmt::Write};
 
 use axum::{
     Extension, Router,
@@ -6,12 +6,15 @@ use axum::{
     response::{IntoResponse, Response},
     routing::get,
 };
-use sea_orm::{ColumnTrait, Condition, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use sea_orm::DatabaseConnection;
 
 use crate::{
     config::SiteSettings,
-    entity::post::{Column as PostColumn, Entity as PostEntity, Model as Post},
-    service::site_settings::SiteSettingsService,
+    entity::post::Model as Post,
+    service::{
+        post::PostService, post_render::PostRenderService, site_settings::SiteSettingsService,
+        theme::ThemeService,
+    },
     utils::HttpFailibleOperationExts,
 };
 
@@ -24,16 +27,23 @@ pub fn get_routes() -> Router {
 async fn display_rss(
     Extension(database): Extension<DatabaseConnection>,
     Extension(site_settings): Extension<SiteSettingsService>,
+    Extension(theme_service): Extension<ThemeService>,
 ) -> Result<Response, Response> {
     let site = site_settings.read().await.clone();
     if !site.rss_enabled {
         return Err(StatusCode::NOT_FOUND.into_response());
     }
     let posts = visible_posts(&database).await?;
+    let excerpts = PostRenderService::rendered(&database, &theme_service, &site, &posts)
+        .await
+        .traced_and_response(|error| tracing::error!("{error}"))?
+        .into_iter()
+        .map(|(post_id, render)| (post_id, render.excerpt))
+        .collect();
 
     Ok((
         [(header::CONTENT_TYPE, "application/rss+xml; charset=utf-8")],
-        render_rss(&site, &posts),
+        render_rss(&site, &posts, &excerpts),
     )
         .into_response())
 }
@@ -46,7 +56,13 @@ async fn display_sitemap(
     if !site.sitemap_enabled {
         return Err(StatusCode::NOT_FOUND.into_response());
     }
-    let posts = visible_posts(&database).await?;
+    let mut posts = visible_posts(&database).await?;
+    posts.extend(
+        PostService::listed_pages()
+            .all(&database)
+            .await
+            .traced_and_response(|error| tracing::error!("{error}"))?,
+    );
 
     Ok((
         [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
@@ -56,19 +72,15 @@ async fn display_sitemap(
 }
 
 async fn visible_posts(database: &DatabaseConnection) -> Result<Vec<Post>, Response> {
-    PostEntity::find()
-        .filter(
-            Condition::any()
-                .add(PostColumn::Hidden.eq(false))
-                .add(PostColumn::Hidden.is_null()),
-        )
-        .order_by_desc(PostColumn::CreatedAt)
+    PostService::listed_posts()
         .all(database)
         .await
         .traced_and_response(|error| tracing::error!("{error}"))
 }
 
-fn render_rss(site: &SiteSettings, posts: &[Post]) -> String {
+/// `excerpts` holds the plain-text excerpt of each post's rendered content,
+/// keyed by post id.
+fn render_rss(site: &SiteSettings, posts: &[Post], excerpts: &HashMap<i32, String>) -> String {
     let mut result = String::from(
         r#"<?xml version="1.0" encoding="UTF-8"?><rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel>"#,
     );
@@ -87,12 +99,19 @@ fn render_rss(site: &SiteSettings, posts: &[Post]) -> String {
 
     for post in posts {
         let url = site_url(site, &post_url(post));
+        // Protected posts keep their body out of the feed.
         let description = post
             .description
             .as_deref()
             .filter(|description| !description.is_empty())
             .map(str::to_owned)
-            .unwrap_or_else(|| excerpt(&post.content, 240));
+            .unwrap_or_else(|| {
+                if PostService::is_protected(post) {
+                    String::new()
+                } else {
+                    excerpts.get(&post.id).cloned().unwrap_or_default()
+                }
+            });
         write!(
             result,
             "<item><title>{}</title><link>{}</link><guid isPermaLink=\"true\">{}</guid><pubDate>{}</pubDate><description>{}</description></item>",
@@ -141,7 +160,7 @@ fn render_sitemap(site: &SiteSettings, posts: &[Post]) -> String {
 }
 
 fn post_url(post: &Post) -> String {
-    format!("/posts/{}", post.name)
+    PostService::public_path(post)
 }
 
 fn site_url(site: &SiteSettings, path: &str) -> String {
@@ -152,19 +171,6 @@ fn site_url(site: &SiteSettings, path: &str) -> String {
     )
 }
 
-fn excerpt(markdown: &str, max_characters: usize) -> String {
-    let text = markdown
-        .lines()
-        .map(|line| line.trim_start_matches(['#', '>', '-', '*', ' ']))
-        .collect::<Vec<_>>()
-        .join(" ");
-    let mut result = text.chars().take(max_characters).collect::<String>();
-    if text.chars().count() > max_characters {
-        result.push_str("...");
-    }
-    result
-}
-
 fn xml_escape(value: &str) -> String {
     value
         .replace('&', "&amp;")
@@ -176,10 +182,12 @@ fn xml_escape(value: &str) -> String {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use super::{render_rss, render_sitemap, xml_escape};
     use crate::{
         config::SiteSettings,
-        entity::post::{Model as Post, PostFunctions},
+        entity::post::{Model as Post, PostFunctions, PostKind, PostMeta, PostStatus},
     };
     use chrono::Utc;
 
@@ -192,8 +200,15 @@ mod tests {
             author: 1,
             description: Some("A <description>".to_string()),
             illustration: None,
-            hidden: Some(false),
+            status: PostStatus::Published,
+            publish_at: None,
+            kind: PostKind::Post,
             functions: PostFunctions::default(),
+            meta: PostMeta::default(),
+            pinned: false,
+            featured: false,
+            weight: 0,
+            password_hash: None,
             created_at: Utc::now(),
             updated_at: None,
         }
@@ -211,7 +226,7 @@ mod tests {
 
     #[test]
     fn renders_a_system_rss_feed_without_theme_data() {
-        let rss = render_rss(&site(), &[post()]);
+        let rss = render_rss(&site(), &[post()], &HashMap::new());
 
         assert!(rss.starts_with("<?xml version=\"1.0\""));
         assert!(rss.contains("Bamboo &amp; Blog"));
@@ -220,13 +235,54 @@ mod tests {
         assert!(rss.contains("A &lt;description&gt;"));
     }
 
+    #[test]
+    fn leaves_protected_post_content_out_of_the_feed() {
+        let protected = Post {
+            content: "The secret plan".to_string(),
+            description: None,
+            password_hash: Some("$2b$04$hash".to_string()),
+            ..post()
+        };
+
+        let rss = render_rss(
+            &site(),
+            &[protected],
+            &HashMap::from([(1, "The secret plan".to_string())]),
+        );
+
+        assert!(rss.contains("Fish &amp; Chips"));
+        assert!(!rss.contains("secret plan"));
+    }
+
+    #[test]
+    fn describes_posts_with_their_rendered_excerpt() {
+        let post = Post {
+            description: None,
+            ..post()
+        };
+
+        let rss = render_rss(
+            &site(),
+            &[post],
+            &HashMap::from([(1, "Rendered \"text\"".to_string())]),
+        );
+
+        assert!(rss.contains("<description>Rendered &quot;text&quot;</description>"));
+    }
+
     #[test]
     fn renders_a_sitemap_for_system_pages_and_posts() {
-        let sitemap = render_sitemap(&site(), &[post()]);
+        let page = Post {
+            name: "about".to_string(),
+            kind: PostKind::Page,
+            ..post()
+        };
+        let sitemap = render_sitemap(&site(), &[post(), page]);
 
         assert!(sitemap.starts_with("<?xml version=\"1.0\""));
         assert!(sitemap.contains("https://example.com/archives"));
         assert!(sitemap.contains("https://example.com/posts/first-post"));
+        assert!(sitemap.contains("https://example.com/about"));
         assert!(sitemap.contains("<lastmod>"));
     }
 
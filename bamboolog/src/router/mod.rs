@@ -2,10 +2,11 @@ mod admin;
 mod api;
 mod information;
 mod pages;
+mod redirects;
 
 use std::sync::Arc;
 
-use axum::Router;
+use axum::{Router, middleware};
 
 use crate::config::ApplicationConfiguration;
 
@@ -15,4 +16,5 @@ pub fn get_routes(_config: &Arc<ApplicationConfiguration>) -> Router {
         .nest("/api", api::get_routes())
         .merge(information::get_routes())
         .merge(pages::get_routes())
+        .layer(middleware::from_fn(redirects::redirect_fallback))
 }
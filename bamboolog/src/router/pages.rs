@@ -1,15 +1,19 @@
 use std::collections::BTreeMap;
 
 use axum::{
-    Extension, Router,
+    Extension, Form, Router,
     extract::{Path, Query},
-    http::{HeaderValue, StatusCode, header::CACHE_CONTROL},
-    response::{Html, IntoResponse, Response},
+    http::{
+        HeaderName, HeaderValue, StatusCode,
+        header::{CACHE_CONTROL, CONTENT_TYPE, LOCATION},
+    },
+    response::{Html, IntoResponse, Redirect, Response},
     routing::get,
 };
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use chrono::Utc;
 use sea_orm::{
-    ColumnTrait, Condition, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter,
-    QueryOrder,
+    ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, Select,
 };
 use serde::Deserialize;
 use serde_json::{Value, json};
@@ -17,14 +21,22 @@ use tracing::instrument;
 
 use crate::{
     config::{DEFAULT_ATTACHMENT_CACHE_CONTROL, SiteSettings},
-    entity::post::{Column as PostColumn, Entity as PostEntity, Model as Post},
+    entity::{
+        post::{Column as PostColumn, Entity as PostEntity, Model as Post, PostKind},
+        post_render,
+    },
     service::{
+        jwt::JwtService,
+        post::PostService,
+        post_preview::PostPreviewService,
+        post_render::PostRenderService,
+        search::SearchService,
         site_settings::SiteSettingsService,
         storage::StorageService,
         taxonomy::{PostTerms, TaxonomyKind, TaxonomyService},
         theme::ThemeService,
     },
-    utils::{HttpFailibleOperationExts, Pagination, render_markdown},
+    utils::{HttpFailibleOperationExts, Pagination, highlight_stylesheet},
 };
 
 #[derive(Debug, Deserialize)]
@@ -37,22 +49,44 @@ struct PageQuery {
     page: Option<u64>,
 }
 
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    q: Option<String>,
+    page: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PreviewQuery {
+    preview: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UnlockForm {
+    password: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct TaxonomyQuery {
     name: Option<String>,
     page: Option<u64>,
 }
 
+const X_ROBOTS_TAG: HeaderName = HeaderName::from_static("x-robots-tag");
+
 const LAYOUT_HOME: &str = "home";
 const LAYOUT_ARCHIVE: &str = "archive";
 const LAYOUT_TERMS: &str = "terms";
 const LAYOUT_TAXONOMY: &str = "taxonomy";
 const LAYOUT_POST: &str = "post";
+const LAYOUT_PAGE: &str = "page";
+const LAYOUT_SEARCH: &str = "search";
+const LAYOUT_PASSWORD: &str = "password";
 const LAYOUT_NOT_FOUND: &str = "not-found";
 
 struct PostWithTerms {
     post: Post,
     terms: PostTerms,
+    render: post_render::Model,
 }
 
 pub fn get_routes() -> Router {
@@ -63,9 +97,15 @@ pub fn get_routes() -> Router {
         .route("/tags/{term}", get(display_tag))
         .route("/categories", get(display_categories))
         .route("/categories/{term}", get(display_category))
-        .route("/posts/{id_or_name}", get(display_post))
+        .route("/posts/{id_or_name}", get(display_post).post(unlock_post))
+        .route("/search", get(display_search))
         .route("/static/theme/{*path}", get(serve_theme_static))
+        .route(
+            "/static/highlight/{stylesheet}",
+            get(serve_highlight_stylesheet),
+        )
         .route("/attachments/{hash}", get(serve_attachment))
+        .route("/{name}", get(display_page).post(unlock_page))
 }
 
 async fn display_archives(
@@ -76,8 +116,9 @@ async fn display_archives(
 ) -> Result<Html<String>, Response> {
     let site = site_settings.read().await.clone();
     let pagination = public_pagination(query.page, &site);
-    let (total, posts) = paginated_visible_posts(&database, pagination).await?;
-    let posts = posts_with_terms(&database, posts).await?;
+    let (total, posts) =
+        paginated_posts(&database, PostService::listed_posts(), pagination).await?;
+    let posts = posts_with_terms(&database, &theme_service, &site, posts).await?;
     let mut years = BTreeMap::<String, Vec<Value>>::new();
     for post in posts {
         years
@@ -201,6 +242,8 @@ async fn display_taxonomy(
             .traced_and_response(|e| tracing::error!("{}", e))?;
         let posts = posts_with_terms(
             &database,
+            &theme_service,
+            &site,
             paginator
                 .fetch_page(pagination.offset())
                 .await
@@ -245,6 +288,70 @@ async fn display_taxonomy(
     ))
 }
 
+async fn display_search(
+    Query(query): Query<SearchQuery>,
+    Extension(database): Extension<DatabaseConnection>,
+    Extension(theme_service): Extension<ThemeService>,
+    Extension(site_settings): Extension<SiteSettingsService>,
+) -> Result<Html<String>, Response> {
+    let site = site_settings.read().await.clone();
+    let search_query = query.q.unwrap_or_default().trim().to_string();
+    let path = format!("/search?q={}", encode_query_component(&search_query));
+    let pagination = public_pagination(query.page, &site);
+    let results = SearchService::search(
+        &database,
+        &search_query,
+        PostService::searchable_condition(),
+    )
+    .await
+    .traced_and_response(|e| tracing::error!("{}", e))?;
+    let total = results.len() as u64;
+    let (posts, hits): (Vec<_>, Vec<_>) = results
+        .into_iter()
+        .skip(pagination.offset() as usize * pagination.size() as usize)
+        .take(pagination.size() as usize)
+        .unzip();
+    let posts = posts_with_terms(&database, &theme_service, &site, posts)
+        .await?
+        .iter()
+        .zip(hits)
+        .map(|(post, hit)| {
+            let mut summary = post_summary(post);
+            summary["snippet"] = Value::String(hit.snippet);
+            summary["score"] = json!(hit.score);
+            summary
+        })
+        .collect::<Vec<_>>();
+    let title = if search_query.is_empty() {
+        "Search".to_string()
+    } else {
+        format!("Search: {search_query}")
+    };
+    // `search` is optional for themes; taxonomy pages already list posts.
+    let layout = if theme_service.has_layout(LAYOUT_SEARCH).await {
+        LAYOUT_SEARCH
+    } else {
+        LAYOUT_TAXONOMY
+    };
+
+    Ok(Html(
+        theme_service
+            .render(
+                layout,
+                json!({
+                    "site": site_context(&site),
+                    "page": { "kind": "search", "title": title, "description": title, "url": path },
+                    "search": { "query": search_query, "total": total },
+                    "taxonomy": { "kind": "search", "name": "Search", "term": search_query },
+                    "posts": posts,
+                    "pagination": pagination_context(pagination, total, &path),
+                }),
+            )
+            .await
+            .traced_and_response(|e| tracing::error!("{}", e))?,
+    ))
+}
+
 #[instrument(skip_all)]
 async fn display_home(
     Query(query): Query<HomeQuery>,
@@ -254,8 +361,13 @@ async fn display_home(
 ) -> Result<Html<String>, Response> {
     let site = site_settings.read().await.clone();
     let pagination = public_pagination(query.page, &site);
-    let (total, posts) = paginated_visible_posts(&database, pagination).await?;
-    let posts = posts_with_terms(&database, posts).await?;
+    let (total, posts) = paginated_posts(&database, PostService::home_posts(), pagination).await?;
+    let posts = posts_with_terms(&database, &theme_service, &site, posts).await?;
+    let featured_posts = PostService::featured_posts()
+        .all(&database)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    let featured_posts = posts_with_terms(&database, &theme_service, &site, featured_posts).await?;
 
     Ok(Html(
         theme_service
@@ -265,6 +377,7 @@ async fn display_home(
                     "site": site_context(&site),
                     "page": { "kind": "home", "title": site.site_name, "description": site.description, "url": "/" },
                     "posts": posts.iter().map(post_summary).collect::<Vec<_>>(),
+                    "featured_posts": featured_posts.iter().map(post_summary).collect::<Vec<_>>(),
                     "pagination": pagination_context(pagination, total, "/"),
                 }),
             )
@@ -275,75 +388,65 @@ async fn display_home(
 
 async fn display_post(
     Path(id_or_name): Path<String>,
+    Query(query): Query<PreviewQuery>,
     Extension(database): Extension<DatabaseConnection>,
     Extension(theme_service): Extension<ThemeService>,
     Extension(site_settings): Extension<SiteSettingsService>,
-) -> Result<Html<String>, Response> {
-    // Is `is_or_name` a number?
-    let post = match id_or_name.parse::<i32>() {
-        Err(_) => PostEntity::find()
-            .filter(PostColumn::Name.eq(id_or_name))
-            .one(&database)
-            .await
-            .traced_and_response(|e| tracing::error!("{}", e))?,
-        Ok(id) => PostEntity::find_by_id(id)
-            .one(&database)
-            .await
-            .traced_and_response(|e| tracing::error!("{}", e))?,
-    };
+    Extension(jwt_service): Extension<JwtService>,
+    jar: CookieJar,
+) -> Result<Response, Response> {
+    let post = find_post(&database, &id_or_name).await?;
+    let preview = has_preview_access(
+        &database,
+        post.as_ref().filter(|post| post.kind == PostKind::Post),
+        query.preview.as_deref(),
+    )
+    .await?;
 
-    // Really found?
+    // Really found? Pages are only served from their top-level URL.
     let post = match post {
-        None => {
-            let site = site_settings.read().await.clone();
-            let content = theme_service
-                .render(
-                    LAYOUT_NOT_FOUND,
-                    json!({
-                        "site": site_context(&site),
-                        "page": { "kind": "not-found", "title": "Not found", "description": "The requested post does not exist.", "url": "" },
-                    }),
-                )
-                .await
-                .traced_and_response(|e| tracing::error!("{}", e))?;
-            return Err((StatusCode::NOT_FOUND, Html(content)).into_response());
+        Some(post)
+            if post.kind == PostKind::Post
+                && (preview || PostService::is_publicly_readable(&post, Utc::now())) =>
+        {
+            post
         }
-        Some(v) => v,
-    };
-
-    if post.hidden.unwrap_or(false) {
-        let site = site_settings.read().await.clone();
-        let content = theme_service
-            .render(
-                LAYOUT_NOT_FOUND,
-                json!({
-                    "site": site_context(&site),
-                    "page": { "kind": "not-found", "title": "Not found", "description": "The requested post does not exist.", "url": "" },
-                }),
+        post => {
+            if post.is_none()
+                && let Some(redirect) = redirect_previous_name(&database, &id_or_name).await?
+            {
+                return Ok(redirect);
+            }
+            return Err(render_not_found(
+                &theme_service,
+                &site_settings,
+                "The requested post does not exist.",
             )
-            .await
-            .traced_and_response(|e| tracing::error!("{}", e))?;
-        return Err((StatusCode::NOT_FOUND, Html(content)).into_response());
-    }
+            .await);
+        }
+    };
 
-    let post_with_terms = posts_with_terms(&database, vec![post.clone()])
+    let site = site_settings.read().await.clone();
+    let post_with_terms = posts_with_terms(&database, &theme_service, &site, vec![post.clone()])
         .await?
         .pop()
         .expect("a post always has a term context");
+    if !preview
+        && PostService::is_protected(&post)
+        && !has_post_access(&jwt_service, &jar, &post).await
+    {
+        return render_password_prompt(&theme_service, &site_settings, &post_with_terms, false)
+            .await;
+    }
 
-    // Render markdown
-    let rendered_content =
-        render_markdown(&post.content).traced_and_response(|e| tracing::error!("{}", e))?;
-
-    let site = site_settings.read().await.clone();
-    let newer_post = visible_posts_query()
+    let newer_post = PostService::listed_posts()
         .filter(PostColumn::Id.ne(post.id))
         .filter(PostColumn::CreatedAt.gt(post.created_at))
         .order_by_asc(PostColumn::CreatedAt)
         .one(&database)
         .await
         .traced_and_response(|e| tracing::error!("{}", e))?;
-    let older_post = visible_posts_query()
+    let older_post = PostService::listed_posts()
         .filter(PostColumn::Id.ne(post.id))
         .filter(PostColumn::CreatedAt.lt(post.created_at))
         .order_by_desc(PostColumn::CreatedAt)
@@ -351,27 +454,43 @@ async fn display_post(
         .await
         .traced_and_response(|e| tracing::error!("{}", e))?;
 
+    let related_posts = TaxonomyService::related_posts(
+        &database,
+        &post,
+        &post_with_terms.terms,
+        site.public_related_posts_count(),
+    )
+    .await
+    .traced_and_response(|e| tracing::error!("{}", e))?;
+    let related_posts = posts_with_terms(&database, &theme_service, &site, related_posts)
+        .await?
+        .iter()
+        .map(post_summary)
+        .collect::<Vec<_>>();
+
     // Render jinja
-    let related_posts = posts_with_terms(
+    let adjacent_posts = posts_with_terms(
         &database,
+        &theme_service,
+        &site,
         [newer_post.clone(), older_post.clone()]
             .into_iter()
             .flatten()
             .collect(),
     )
     .await?;
-    let newer_post = related_posts.iter().find(|candidate| {
+    let newer_post = adjacent_posts.iter().find(|candidate| {
         newer_post
             .as_ref()
             .is_some_and(|post| candidate.post.id == post.id)
     });
-    let older_post = related_posts.iter().find(|candidate| {
+    let older_post = adjacent_posts.iter().find(|candidate| {
         older_post
             .as_ref()
             .is_some_and(|post| candidate.post.id == post.id)
     });
 
-    Ok(Html(
+    let response = Html(
         theme_service
             .render(
                 LAYOUT_POST,
@@ -380,20 +499,389 @@ async fn display_post(
                     "page": {
                         "kind": "post", 
                         "title": post.title, 
-                        "description": post.description.clone().unwrap_or_else(|| excerpt(&post.content, 240)), 
+                        "description": post.description.clone().unwrap_or_else(|| post_with_terms.render.excerpt.clone()), 
                         "illustration": post.illustration.clone(), 
                         "url": post_url(&post), 
-                        "functions": post.functions.0 
+                        "functions": post.functions.0,
+                        "preview": preview
                     },
-                    "content": rendered_content,
+                    "content": post_with_terms.render.html,
+                    "toc": post_with_terms.render.toc,
                     "post": post_detail(&post_with_terms),
                     "newer_post": newer_post.map(post_summary),
                     "older_post": older_post.map(post_summary),
+                    "related_posts": related_posts,
                 }),
             )
             .await
             .traced_and_response(|e| tracing::error!("{}", e))?,
-    ))
+    )
+    .into_response();
+    Ok(if preview {
+        preview_response(response)
+    } else if PostService::is_protected(&post) {
+        private_response(response)
+    } else {
+        response
+    })
+}
+
+async fn unlock_post(
+    Path(id_or_name): Path<String>,
+    Extension(database): Extension<DatabaseConnection>,
+    Extension(theme_service): Extension<ThemeService>,
+    Extension(site_settings): Extension<SiteSettingsService>,
+    Extension(jwt_service): Extension<JwtService>,
+    jar: CookieJar,
+    Form(form): Form<UnlockForm>,
+) -> Result<Response, Response> {
+    let post = find_post(&database, &id_or_name).await?.filter(|post| {
+        post.kind == PostKind::Post && PostService::is_publicly_readable(post, Utc::now())
+    });
+    let Some(post) = post else {
+        return Err(render_not_found(
+            &theme_service,
+            &site_settings,
+            "The requested post does not exist.",
+        )
+        .await);
+    };
+    unlock(
+        &database,
+        &theme_service,
+        &site_settings,
+        &jwt_service,
+        jar,
+        post,
+        &form.password,
+    )
+    .await
+}
+
+async fn display_page(
+    Path(name): Path<String>,
+    Query(query): Query<PreviewQuery>,
+    Extension(database): Extension<DatabaseConnection>,
+    Extension(theme_service): Extension<ThemeService>,
+    Extension(site_settings): Extension<SiteSettingsService>,
+    Extension(jwt_service): Extension<JwtService>,
+    jar: CookieJar,
+) -> Result<Response, Response> {
+    let page = find_page_by_name(&database, &name).await?;
+    let preview = has_preview_access(&database, page.as_ref(), query.preview.as_deref()).await?;
+    let page = page.filter(|page| preview || PostService::is_publicly_readable(page, Utc::now()));
+    let Some(page) = page else {
+        if let Some(redirect) = redirect_previous_name(&database, &name).await? {
+            return Ok(redirect);
+        }
+        return Err(render_not_found(
+            &theme_service,
+            &site_settings,
+            "The requested page does not exist.",
+        )
+        .await);
+    };
+
+    let site = site_settings.read().await.clone();
+    let page_with_terms = posts_with_terms(&database, &theme_service, &site, vec![page.clone()])
+        .await?
+        .pop()
+        .expect("a page always has a term context");
+    if !preview
+        && PostService::is_protected(&page)
+        && !has_post_access(&jwt_service, &jar, &page).await
+    {
+        return render_password_prompt(&theme_service, &site_settings, &page_with_terms, false)
+            .await;
+    }
+    // `page` is optional for themes; the post layout is the closest fit.
+    let layout = if theme_service.has_layout(LAYOUT_PAGE).await {
+        LAYOUT_PAGE
+    } else {
+        LAYOUT_POST
+    };
+
+    let response = Html(
+        theme_service
+            .render(
+                layout,
+                json!({
+                    "site": site_context(&site),
+                    "page": {
+                        "kind": "page",
+                        "title": page.title,
+                        "description": page.description.clone().unwrap_or_else(|| page_with_terms.render.excerpt.clone()),
+                        "illustration": page.illustration.clone(),
+                        "url": post_url(&page),
+                        "functions": page.functions.0,
+                        "preview": preview
+                    },
+                    "content": page_with_terms.render.html,
+                    "toc": page_with_terms.render.toc,
+                    "post": post_detail(&page_with_terms),
+                }),
+            )
+            .await
+            .traced_and_response(|e| tracing::error!("{}", e))?,
+    )
+    .into_response();
+    Ok(if preview {
+        preview_response(response)
+    } else if PostService::is_protected(&page) {
+        private_response(response)
+    } else {
+        response
+    })
+}
+
+async fn unlock_page(
+    Path(name): Path<String>,
+    Extension(database): Extension<DatabaseConnection>,
+    Extension(theme_service): Extension<ThemeService>,
+    Extension(site_settings): Extension<SiteSettingsService>,
+    Extension(jwt_service): Extension<JwtService>,
+    jar: CookieJar,
+    Form(form): Form<UnlockForm>,
+) -> Result<Response, Response> {
+    let Some(page) = find_page(&database, &name).await? else {
+        return Err(render_not_found(
+            &theme_service,
+            &site_settings,
+            "The requested page does not exist.",
+        )
+        .await);
+    };
+    unlock(
+        &database,
+        &theme_service,
+        &site_settings,
+        &jwt_service,
+        jar,
+        page,
+        &form.password,
+    )
+    .await
+}
+
+/// Looks a post up by id or by name, whatever its kind or status.
+async fn find_post(
+    database: &DatabaseConnection,
+    id_or_name: &str,
+) -> Result<Option<Post>, Response> {
+    // Is `is_or_name` a number?
+    match id_or_name.parse::<i32>() {
+        Err(_) => PostEntity::find()
+            .filter(PostColumn::Name.eq(id_or_name))
+            .one(database)
+            .await
+            .traced_and_response(|e| tracing::error!("{}", e)),
+        Ok(id) => PostEntity::find_by_id(id)
+            .one(database)
+            .await
+            .traced_and_response(|e| tracing::error!("{}", e)),
+    }
+}
+
+/// The page served at `/{name}`, if readers may open it.
+async fn find_page(database: &DatabaseConnection, name: &str) -> Result<Option<Post>, Response> {
+    Ok(find_page_by_name(database, name)
+        .await?
+        .filter(|page| PostService::is_publicly_readable(page, Utc::now())))
+}
+
+/// The page named `name`, whatever its status.
+async fn find_page_by_name(
+    database: &DatabaseConnection,
+    name: &str,
+) -> Result<Option<Post>, Response> {
+    PostEntity::find()
+        .filter(PostColumn::Name.eq(name))
+        .filter(PostColumn::Kind.eq(PostKind::Page))
+        .one(database)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))
+}
+
+/// Whether `token` is a live preview link for `post`. A preview shows the post
+/// whatever its status and skips the password prompt.
+async fn has_preview_access(
+    database: &DatabaseConnection,
+    post: Option<&Post>,
+    token: Option<&str>,
+) -> Result<bool, Response> {
+    let (Some(post), Some(token)) = (post, token) else {
+        return Ok(false);
+    };
+    PostPreviewService::is_valid(database, post.id, token, Utc::now())
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))
+}
+
+fn post_access_cookie_name(post: &Post) -> String {
+    format!("bamboolog_post_{}", post.id)
+}
+
+async fn has_post_access(jwt_service: &JwtService, jar: &CookieJar, post: &Post) -> bool {
+    let Some(fingerprint) = PostService::password_fingerprint(post) else {
+        return true;
+    };
+    match jar.get(&post_access_cookie_name(post)) {
+        Some(cookie) => {
+            jwt_service
+                .verify_post_access(cookie.value(), post.id, fingerprint)
+                .await
+        }
+        None => false,
+    }
+}
+
+/// Checks a submitted post password. The right one earns a cookie scoped to
+/// the post URL and a redirect back to it; a wrong one shows the form again.
+async fn unlock(
+    database: &DatabaseConnection,
+    theme_service: &ThemeService,
+    site_settings: &SiteSettingsService,
+    jwt_service: &JwtService,
+    jar: CookieJar,
+    post: Post,
+    password: &str,
+) -> Result<Response, Response> {
+    let location = encoded_post_url(&post);
+    let Some(fingerprint) = PostService::password_fingerprint(&post) else {
+        return Ok(Redirect::to(&location).into_response());
+    };
+    if !PostService::verify_password(&post, password) {
+        let site = site_settings.read().await.clone();
+        let post_with_terms = posts_with_terms(database, theme_service, &site, vec![post.clone()])
+            .await?
+            .pop()
+            .expect("a post always has a term context");
+        let mut response =
+            render_password_prompt(theme_service, site_settings, &post_with_terms, true).await?;
+        *response.status_mut() = StatusCode::FORBIDDEN;
+        return Ok(response);
+    }
+
+    let token = jwt_service
+        .issue_post_access(post.id, fingerprint)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    let cookie = Cookie::build((post_access_cookie_name(&post), token))
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .path(location.clone())
+        .build();
+    Ok((jar.add(cookie), Redirect::to(&location)).into_response())
+}
+
+/// Asks for the password of a protected post. Themes without a `password`
+/// layout get a plain form in place of the post content.
+async fn render_password_prompt(
+    theme_service: &ThemeService,
+    site_settings: &SiteSettingsService,
+    post: &PostWithTerms,
+    failed: bool,
+) -> Result<Response, Response> {
+    let site = site_settings.read().await.clone();
+    let url = encoded_post_url(&post.post);
+    let form = format!(
+        "<form class=\"post-password\" method=\"post\" action=\"{url}\">\
+         <p>{}</p>\
+         <input type=\"password\" name=\"password\" autocomplete=\"current-password\" required autofocus>\
+         <button type=\"submit\">Unlock</button></form>",
+        if failed {
+            "The password is incorrect. Please try again."
+        } else {
+            "This content is protected. Enter the password to read it."
+        },
+    );
+    let layout = if theme_service.has_layout(LAYOUT_PASSWORD).await {
+        LAYOUT_PASSWORD
+    } else {
+        LAYOUT_POST
+    };
+    let mut post_context = post_summary(post);
+    post_context["content"] = Value::String(String::new());
+
+    let content = theme_service
+        .render(
+            layout,
+            json!({
+                "site": site_context(&site),
+                "page": {
+                    "kind": "password",
+                    "title": post.post.title,
+                    "description": post.post.description.clone().unwrap_or_default(),
+                    "illustration": post.post.illustration.clone(),
+                    "url": post_url(&post.post),
+                    "functions": post.post.functions.0
+                },
+                "content": form,
+                "post": post_context,
+                "password": { "action": url, "failed": failed },
+            }),
+        )
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
+    Ok(private_response(Html(content).into_response()))
+}
+
+/// Keeps pages that depend on a reader's unlock cookie out of shared caches.
+fn private_response(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("private, no-store"));
+    response
+}
+
+/// Keeps previews out of caches and search engines, which would otherwise
+/// publish a draft through the preview link.
+fn preview_response(response: Response) -> Response {
+    let mut response = private_response(response);
+    response
+        .headers_mut()
+        .insert(X_ROBOTS_TAG, HeaderValue::from_static("noindex, nofollow"));
+    response
+}
+
+/// Sends links to a renamed post or page on to its current URL.
+async fn redirect_previous_name(
+    database: &DatabaseConnection,
+    name: &str,
+) -> Result<Option<Response>, Response> {
+    let post = PostService::find_by_previous_name(database, name)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?
+        .filter(|post| PostService::is_publicly_readable(post, Utc::now()));
+    Ok(post.map(|post| {
+        (
+            StatusCode::MOVED_PERMANENTLY,
+            [(LOCATION, encoded_post_url(&post))],
+        )
+            .into_response()
+    }))
+}
+
+async fn render_not_found(
+    theme_service: &ThemeService,
+    site_settings: &SiteSettingsService,
+    description: &str,
+) -> Response {
+    let site = site_settings.read().await.clone();
+    let content = theme_service
+        .render(
+            LAYOUT_NOT_FOUND,
+            json!({
+                "site": site_context(&site),
+                "page": { "kind": "not-found", "title": "Not found", "description": description, "url": "" },
+            }),
+        )
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e));
+    match content {
+        Ok(content) => (StatusCode::NOT_FOUND, Html(content)).into_response(),
+        Err(response) => response,
+    }
 }
 
 fn site_context(site: &SiteSettings) -> Value {
@@ -405,6 +893,9 @@ fn site_context(site: &SiteSettings) -> Value {
         "language": if site.language.trim().is_empty() { "en" } else { site.language.as_str() },
         "favicon_url": site.favicon_url,
         "home_url": "/",
+        "highlight_stylesheet": (!site.code_highlight_theme.is_empty()).then(|| {
+            format!("/static/highlight/{}.css", encode_query_component(&site.code_highlight_theme))
+        }),
     })
 }
 
@@ -441,7 +932,17 @@ fn encode_query_component(value: &str) -> String {
 }
 
 fn post_url(post: &Post) -> String {
-    format!("/posts/{}", post.name)
+    PostService::public_path(post)
+}
+
+/// `post_url` with each path segment percent-encoded, for headers and
+/// attributes.
+fn encoded_post_url(post: &Post) -> String {
+    post_url(post)
+        .split('/')
+        .map(encode_query_component)
+        .collect::<Vec<_>>()
+        .join("/")
 }
 
 fn post_summary(context: &PostWithTerms) -> Value {
@@ -451,35 +952,29 @@ fn post_summary(context: &PostWithTerms) -> Value {
         "name": post.name,
         "title": post.title,
         "created_at": post.created_at,
-        "updated_at": post
-            .updated_at
-            .clone()
-            .unwrap_or_else(|| post.created_at.clone()),
+        "updated_at": post.updated_at.unwrap_or(post.created_at),
         "url": post_url(post),
-        "summary": post.description.clone().filter(|description| !description.is_empty()).unwrap_or_else(|| excerpt(&post.content, 240)),
-        "reading_minutes": reading_minutes(&post.content),
+        "summary": post.description.clone().filter(|description| !description.is_empty()).unwrap_or_else(|| {
+            if PostService::is_protected(post) { String::new() } else { context.render.excerpt.clone() }
+        }),
+        "protected": PostService::is_protected(post),
+        "reading_minutes": reading_minutes(context.render.word_count, context.render.cjk_character_count),
         "illustration": post.illustration,
         "tags": context.terms.tags,
         "categories": context.terms.categories,
         "functions": post.functions.0,
+        "meta": post.meta.0,
+        "pinned": post.pinned,
+        "featured": post.featured,
     })
 }
 
-fn visible_posts_query() -> sea_orm::Select<PostEntity> {
-    PostEntity::find()
-        .filter(
-            Condition::any()
-                .add(PostColumn::Hidden.eq(false))
-                .add(PostColumn::Hidden.is_null()),
-        )
-        .order_by_desc(PostColumn::CreatedAt)
-}
-
-async fn paginated_visible_posts(
+async fn paginated_posts(
     database: &DatabaseConnection,
+    select: Select<PostEntity>,
     pagination: Pagination,
 ) -> Result<(u64, Vec<Post>), Response> {
-    let paginator = visible_posts_query().paginate(database, pagination.size());
+    let paginator = select.paginate(database, pagination.size());
     let total = paginator
         .num_items()
         .await
@@ -497,39 +992,42 @@ fn post_detail(post: &PostWithTerms) -> Value {
     result
 }
 
+/// Pairs posts with their terms and stored render.
 async fn posts_with_terms(
     database: &DatabaseConnection,
+    theme_service: &ThemeService,
+    site: &SiteSettings,
     posts: Vec<Post>,
 ) -> Result<Vec<PostWithTerms>, Response> {
     let post_ids = posts.iter().map(|post| post.id).collect::<Vec<_>>();
     let terms = TaxonomyService::terms_for_posts(database, &post_ids)
         .await
         .traced_and_response(|e| tracing::error!("{}", e))?;
+    let mut renders = PostRenderService::rendered(database, theme_service, site, &posts)
+        .await
+        .traced_and_response(|e| tracing::error!("{}", e))?;
     Ok(posts
         .into_iter()
         .map(|post| PostWithTerms {
             terms: terms.get(&post.id).cloned().unwrap_or_default(),
+            render: renders
+                .remove(&post.id)
+                .expect("every listed post is rendered"),
             post,
         })
         .collect())
 }
 
-fn excerpt(markdown: &str, max_characters: usize) -> String {
-    let text = markdown
-        .lines()
-        .map(|line| line.trim_start_matches(['#', '>', '-', '*', ' ']))
-        .collect::<Vec<_>>()
-        .join(" ");
-    let mut result = text.chars().take(max_characters).collect::<String>();
-    if text.chars().count() > max_characters {
-        result.push_str("...");
-    }
-    result
-}
+/// Reading time at 220 words or 400 CJK characters per minute.
+fn reading_minutes(word_count: i64, cjk_character_count: i64) -> u64 {
+    const WORDS_PER_MINUTE: u64 = 220;
+    const CJK_CHARACTERS_PER_MINUTE: u64 = 400;
 
-fn reading_minutes(markdown: &str) -> u64 {
-    let words = markdown.split_whitespace().count() as u64;
-    words.div_ceil(220).max(1)
+    let cjk_characters = cjk_character_count.clamp(0, word_count.max(0)) as u64;
+    let words = word_count.max(0) as u64 - cjk_characters;
+    (words * CJK_CHARACTERS_PER_MINUTE + cjk_characters * WORDS_PER_MINUTE)
+        .div_ceil(WORDS_PER_MINUTE * CJK_CHARACTERS_PER_MINUTE)
+        .max(1)
 }
 
 async fn serve_theme_static(
@@ -542,6 +1040,31 @@ async fn serve_theme_static(
         .traced_and_response(|e| tracing::error!("{}", e))
 }
 
+/// Serves `/static/highlight/{theme}.css` for the classes emitted by
+/// server-side code highlighting.
+async fn serve_highlight_stylesheet(Path(stylesheet): Path<String>) -> Response {
+    let stylesheet = stylesheet
+        .strip_suffix(".css")
+        .and_then(highlight_stylesheet);
+    match stylesheet {
+        Some(stylesheet) => (
+            [
+                (
+                    CONTENT_TYPE,
+                    HeaderValue::from_static("text/css; charset=utf-8"),
+                ),
+                (
+                    CACHE_CONTROL,
+                    HeaderValue::from_static("public, max-age=86400"),
+                ),
+            ],
+            stylesheet,
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
 async fn serve_attachment(
     Path(hash): Path<String>,
     Extension(db): Extension<DatabaseConnection>,
@@ -569,24 +1092,40 @@ fn cache_control_header(value: &str) -> HeaderValue {
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
+
+    use axum::{
+        body::{Body, to_bytes},
+        http::{Request, StatusCode},
+    };
+    use chrono::Duration;
+    use sea_orm::{ActiveModelTrait, Database, Set};
+    use tower::ServiceExt;
+
     use super::{
-        cache_control_header, encode_query_component, excerpt, pagination_context, reading_minutes,
+        cache_control_header, encode_query_component, pagination_context, reading_minutes,
+    };
+    use crate::{
+        config::{ApplicationConfiguration, DEFAULT_ATTACHMENT_CACHE_CONTROL},
+        entity::post::{self, PostKind, PostStatus},
+        maintenance::sync_entities,
+        service::post_preview::PostPreviewService,
+        utils::Pagination,
+        web::AppServices,
     };
-    use crate::{config::DEFAULT_ATTACHMENT_CACHE_CONTROL, utils::Pagination};
 
     #[test]
-    fn creates_a_short_plain_text_excerpt() {
-        assert_eq!(
-            excerpt("# Heading\n\nA paragraph", 240),
-            "Heading  A paragraph"
-        );
-        assert_eq!(excerpt("abcdefgh", 5), "abcde...");
+    fn reading_time_is_at_least_one_minute() {
+        assert_eq!(reading_minutes(0, 0), 1);
+        assert_eq!(reading_minutes(1, 0), 1);
+        assert_eq!(reading_minutes(221, 0), 2);
     }
 
     #[test]
-    fn reading_time_is_at_least_one_minute() {
-        assert_eq!(reading_minutes("brief"), 1);
-        assert_eq!(reading_minutes(&"word ".repeat(221)), 2);
+    fn reads_cjk_characters_faster_than_words() {
+        assert_eq!(reading_minutes(800, 800), 2);
+        assert_eq!(reading_minutes(801, 800), 3);
+        assert_eq!(reading_minutes(620, 400), 2);
     }
 
     #[test]
@@ -609,4 +1148,85 @@ mod tests {
             DEFAULT_ATTACHMENT_CACHE_CONTROL
         );
     }
+
+    #[tokio::test]
+    async fn previews_hidden_posts_with_a_live_token_only() {
+        let assets = tempfile::tempdir().unwrap();
+        let theme = assets.path().join("themes/installed/default");
+        fs::create_dir_all(theme.join("layouts")).unwrap();
+        fs::write(theme.join("manifest.toml"), "name = 'Default'\n").unwrap();
+        for layout in ["home", "archive", "terms", "taxonomy", "not-found"] {
+            fs::write(theme.join("layouts").join(layout), "{{ page.kind }}").unwrap();
+        }
+        fs::write(
+            theme.join("layouts/post"),
+            "{{ page.title }} preview={{ page.preview }}",
+        )
+        .unwrap();
+        let config = ApplicationConfiguration::for_tests(assets.path());
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        sync_entities(&database).await.unwrap();
+        for (id, name, kind, password_hash) in [
+            (1, "draft", PostKind::Post, None),
+            (2, "about", PostKind::Page, Some("hash".to_string())),
+        ] {
+            post::ActiveModel {
+                id: Set(id),
+                name: Set(name.to_string()),
+                title: Set(name.to_string()),
+                content: Set(String::new()),
+                author: Set(1),
+                status: Set(PostStatus::Draft),
+                kind: Set(kind),
+                password_hash: Set(password_hash),
+                ..Default::default()
+            }
+            .insert(&database)
+            .await
+            .unwrap();
+        }
+        let live = PostPreviewService::issue(&database, 1, 1, Duration::hours(1))
+            .await
+            .unwrap();
+        let page = PostPreviewService::issue(&database, 2, 1, Duration::hours(1))
+            .await
+            .unwrap();
+        let expired = PostPreviewService::issue(&database, 1, 1, Duration::seconds(-1))
+            .await
+            .unwrap();
+        let app = AppServices::configure(&config, database.clone())
+            .await
+            .router(config);
+        let get = |uri: String| {
+            let app = app.clone();
+            async move {
+                app.oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let response = get(format!("/posts/draft?preview={}", live.token)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-robots-tag"], "noindex, nofollow");
+        assert_eq!(response.headers()["cache-control"], "private, no-store");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "draft preview=true");
+
+        // Previews skip the password prompt, which would otherwise replace the page.
+        let response = get(format!("/about?preview={}", page.token)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "about preview=true");
+
+        for token in ["", "wrong", expired.token.as_str(), page.token.as_str()] {
+            let response = get(format!("/posts/draft?preview={token}")).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{token}");
+        }
+        PostPreviewService::revoke(&database, 1, live.id)
+            .await
+            .unwrap();
+        let response = get(format!("/posts/draft?preview={}", live.token)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
 }
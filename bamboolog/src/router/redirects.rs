@@ -0,0 +1,134 @@
+use crate::service::redirect::{RedirectAction, RedirectService};
+use axum::{
+    extract::Request,
+    http::{HeaderValue, Method, StatusCode, header::LOCATION},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// Consults the redirect rules for public GET and HEAD requests that would
+/// otherwise end in a 404. Admin and API paths keep their own not-found
+/// responses.
+pub async fn redirect_fallback(request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    let eligible = matches!(*request.method(), Method::GET | Method::HEAD)
+        && !is_reserved_path(&path, "/api")
+        && !is_reserved_path(&path, "/admin");
+    let redirects = request.extensions().get::<RedirectService>().cloned();
+
+    let mut response = next.run(request).await;
+    if !eligible || response.status() != StatusCode::NOT_FOUND {
+        return response;
+    }
+    let Some(redirects) = redirects else {
+        return response;
+    };
+
+    match redirects.resolve(&path).await {
+        Some(RedirectAction::Redirect { status, location }) => {
+            match HeaderValue::from_str(&location) {
+                Ok(location) => (status, [(LOCATION, location)]).into_response(),
+                Err(error) => {
+                    tracing::warn!("Ignoring redirect to invalid location {location}: {error}");
+                    response
+                }
+            }
+        }
+        Some(RedirectAction::Gone) => {
+            *response.status_mut() = StatusCode::GONE;
+            response
+        }
+        None => response,
+    }
+}
+
+fn is_reserved_path(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Extension, Router,
+        body::Body,
+        http::{Request, StatusCode, header::LOCATION},
+        middleware,
+        routing::get,
+    };
+    use chrono::Utc;
+    use sea_orm::{ActiveModelTrait, ConnectionTrait, Database, DatabaseBackend, Schema, Set};
+    use tower::ServiceExt;
+
+    use crate::{
+        entity::redirect::{self, RedirectMatch},
+        service::{redirect::RedirectService, reloadable::ReloadableService},
+    };
+
+    use super::redirect_fallback;
+
+    #[tokio::test]
+    async fn redirects_only_unmatched_public_paths() {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        let schema = Schema::new(DatabaseBackend::Sqlite);
+        database
+            .execute(&schema.create_table_from_entity(redirect::Entity))
+            .await
+            .unwrap();
+        for (source, target, status_code) in [
+            ("/", Some("/elsewhere/"), 301),
+            ("/api/", Some("/"), 301),
+            ("/removed", None, 410),
+        ] {
+            redirect::ActiveModel {
+                source: Set(source.to_string()),
+                match_kind: Set(RedirectMatch::Prefix),
+                target: Set(target.map(str::to_string)),
+                status_code: Set(status_code),
+                created_at: Set(Utc::now()),
+                ..Default::default()
+            }
+            .insert(&database)
+            .await
+            .unwrap();
+        }
+        let redirects = RedirectService::new(database);
+        redirects.reload().await;
+        let app = Router::new()
+            .route("/", get(|| async { "home" }))
+            .layer(middleware::from_fn(redirect_fallback))
+            .layer(Extension(redirects));
+
+        let status_and_location = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let response = app
+                    .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .map(|value| value.to_str().unwrap().to_string());
+                (response.status(), location)
+            }
+        };
+
+        assert_eq!(status_and_location("/").await, (StatusCode::OK, None));
+        assert_eq!(
+            status_and_location("/old/post").await,
+            (
+                StatusCode::MOVED_PERMANENTLY,
+                Some("/elsewhere/old/post".to_string())
+            )
+        );
+        assert_eq!(
+            status_and_location("/removed/post").await,
+            (StatusCode::GONE, None)
+        );
+        assert_eq!(
+            status_and_location("/api/missing").await,
+            (StatusCode::NOT_FOUND, None)
+        );
+    }
+}
@@ -0,0 +1,635 @@
+use std::io::{self, Seek, Write};
+
+use chrono::Utc;
+use sea_orm::{
+    DatabaseConnection, DbErr, EntityTrait, PaginatorTrait, QueryOrder, prelude::DateTimeUtc,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map as JsonMap, Value as JsonValue, json};
+use tokio::sync::mpsc;
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    config::{SiteSettings, config_entries},
+    entity::{
+        attachment,
+        post::{self, PostKind, PostStatus},
+    },
+    service::{
+        storage::StorageService,
+        taxonomy::{PostTerms, TaxonomyService},
+        theme::ThemeService,
+    },
+};
+
+/// Describes an export: its settings, theme configuration and attachments.
+/// Its presence tells the Markdown importer to restore them.
+pub const EXPORT_MANIFEST: &str = "bamboolog-export.json";
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+const EXPORT_BATCH_SIZE: u64 = 100;
+/// Entries read ahead of the archive writer.
+const EXPORT_QUEUE_SIZE: usize = 16;
+/// Names Windows reserves for devices, whatever the extension.
+const RESERVED_FILE_STEMS: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExportError {
+    #[error(transparent)]
+    Database(#[from] DbErr),
+    #[error(transparent)]
+    Settings(#[from] config_entries::ConfigError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Zip(#[from] zip::result::ZipError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to read attachment `{hash}`: {source}")]
+    Storage { hash: String, source: anyhow::Error },
+    #[error("The archive writer stopped: {0}")]
+    Writer(#[from] tokio::task::JoinError),
+    #[error("The archive writer stopped early")]
+    WriterClosed,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct ExportOptions {
+    /// Keeps the password hashes of protected posts. Without it they are
+    /// marked `protected` and come back as drafts that need a new password.
+    #[serde(default)]
+    pub include_secrets: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportManifest {
+    pub version: u32,
+    pub exported_at: DateTimeUtc,
+    pub site: SiteSettings,
+    /// The theme that was active, if one was loaded.
+    pub theme: Option<ExportedTheme>,
+    pub attachments: Vec<ExportedAttachment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedTheme {
+    pub id: String,
+    pub config: JsonMap<String, JsonValue>,
+}
+
+/// An attachment blob in the archive. Stored again, it keeps its hash and so
+/// the `/attachments/{hash}` URL posts refer to it by.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedAttachment {
+    pub path: String,
+    pub hash: String,
+    pub filename: String,
+    pub mime: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ExportSummary {
+    pub posts: usize,
+    pub attachments: usize,
+}
+
+/// A file for the archive writer.
+struct ArchiveEntry {
+    path: String,
+    data: Vec<u8>,
+}
+
+/// Writes the whole site to a ZIP archive that the Markdown importer reads
+/// back: posts and pages as Markdown with front matter, attachment blobs, and
+/// an `EXPORT_MANIFEST` with the site settings and theme configuration.
+pub struct ExportService;
+
+impl ExportService {
+    /// Writes the archive to `writer` and hands it back once finished.
+    /// Compression and writes run on a blocking thread while the site is read.
+    pub async fn export<W>(
+        db: &DatabaseConnection,
+        storage: &StorageService,
+        theme_service: &ThemeService,
+        writer: W,
+        options: ExportOptions,
+    ) -> Result<(W, ExportSummary), ExportError>
+    where
+        W: Write + Seek + Send + 'static,
+    {
+        let (entries, receiver) = mpsc::channel(EXPORT_QUEUE_SIZE);
+        let archive = tokio::task::spawn_blocking(move || write_archive(writer, receiver));
+        let summary = read_site(db, storage, theme_service, options, &entries).await;
+        // Closing the queue lets the writer finish the archive.
+        drop(entries);
+        let writer = archive.await??;
+        Ok((writer, summary?))
+    }
+}
+
+async fn read_site(
+    db: &DatabaseConnection,
+    storage: &StorageService,
+    theme_service: &ThemeService,
+    options: ExportOptions,
+    entries: &mpsc::Sender<ArchiveEntry>,
+) -> Result<ExportSummary, ExportError> {
+    let send = |path: String, data: Vec<u8>| async move {
+        entries
+            .send(ArchiveEntry { path, data })
+            .await
+            .map_err(|_| ExportError::WriterClosed)
+    };
+    let mut summary = ExportSummary::default();
+
+    let mut pages = post::Entity::find()
+        .order_by_asc(post::Column::Id)
+        .paginate(db, EXPORT_BATCH_SIZE);
+    while let Some(posts) = pages.fetch_and_next().await? {
+        let ids = posts.iter().map(|post| post.id).collect::<Vec<_>>();
+        let mut terms = TaxonomyService::terms_for_posts(db, &ids).await?;
+        for post in posts {
+            let terms = terms.remove(&post.id).unwrap_or_default();
+            let document = post_document(&post, &terms, options);
+            send(post_path(&post), document.into_bytes()).await?;
+            summary.posts += 1;
+        }
+    }
+
+    let mut attachments = Vec::new();
+    for attachment in attachment::Entity::find()
+        .order_by_asc(attachment::Column::Id)
+        .all(db)
+        .await?
+    {
+        let data = storage
+            .read(db, &attachment)
+            .await
+            .map_err(|source| ExportError::Storage {
+                hash: attachment.hash.clone(),
+                source,
+            })?;
+        let path = format!("attachments/{}", StorageService::file_name(&attachment));
+        send(path.clone(), data).await?;
+        attachments.push(ExportedAttachment {
+            path,
+            hash: attachment.hash,
+            filename: attachment.filename,
+            mime: attachment.mime,
+        });
+    }
+    summary.attachments = attachments.len();
+
+    let manifest = ExportManifest {
+        version: EXPORT_FORMAT_VERSION,
+        exported_at: Utc::now(),
+        site: config_entries::SITE_SETTINGS
+            .get::<SiteSettings>(db)
+            .await?
+            .unwrap_or_default(),
+        theme: theme_service
+            .active_theme_configuration()
+            .await
+            .ok()
+            .map(|configuration| ExportedTheme {
+                id: configuration.theme.id,
+                config: configuration.values,
+            }),
+        attachments,
+    };
+    send(
+        EXPORT_MANIFEST.to_string(),
+        serde_json::to_vec_pretty(&manifest)?,
+    )
+    .await?;
+
+    Ok(summary)
+}
+
+fn write_archive<W>(writer: W, mut entries: mpsc::Receiver<ArchiveEntry>) -> Result<W, ExportError>
+where
+    W: Write + Seek,
+{
+    let mut archive = ZipWriter::new(writer);
+    let options = SimpleFileOptions::default();
+    while let Some(entry) = entries.blocking_recv() {
+        archive.start_file(entry.path, options)?;
+        archive.write_all(&entry.data)?;
+    }
+    Ok(archive.finish()?)
+}
+
+/// Where a post goes in the archive. The importer takes the name from the
+/// front matter, so the file name only has to be safe to extract anywhere.
+fn post_path(post: &post::Model) -> String {
+    let directory = match post.kind {
+        PostKind::Post => "posts",
+        PostKind::Page => "pages",
+    };
+    format!("{directory}/{}.md", file_stem(&post.name))
+}
+
+/// Percent-encodes every ASCII character of `name` but letters, digits and
+/// `-`, which leaves no separators, dots or characters Windows rejects.
+/// Device names such as `CON` get an encoded `_` in front.
+fn file_stem(name: &str) -> String {
+    let mut stem = String::with_capacity(name.len());
+    for character in name.chars() {
+        if character.is_ascii_alphanumeric() || character == '-' || !character.is_ascii() {
+            stem.push(character);
+        } else {
+            stem.push_str(&format!("%{:02X}", character as u32));
+        }
+    }
+    if stem.is_empty()
+        || RESERVED_FILE_STEMS
+            .iter()
+            .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        stem.insert_str(0, "%5F");
+    }
+    stem
+}
+
+/// A post as Markdown with YAML front matter. Values are written as JSON,
+/// which YAML reads as flow scalars and sequences.
+fn post_document(post: &post::Model, terms: &PostTerms, options: ExportOptions) -> String {
+    let mut fields = vec![
+        ("title", json!(post.title)),
+        ("slug", json!(post.name)),
+        (
+            "type",
+            json!(match post.kind {
+                PostKind::Post => "post",
+                PostKind::Page => "page",
+            }),
+        ),
+        ("status", json!(post.status)),
+        ("date", json!(post.created_at.to_rfc3339())),
+    ];
+    if let Some(updated_at) = post.updated_at {
+        fields.push(("lastmod", json!(updated_at.to_rfc3339())));
+    }
+    if let Some(publish_at) = post.publish_at {
+        fields.push(("publish_at", json!(publish_at.to_rfc3339())));
+    }
+    // Lets other static site generators tell drafts apart.
+    if post.status == PostStatus::Draft {
+        fields.push(("draft", json!(true)));
+    }
+    if let Some(description) = &post.description {
+        fields.push(("description", json!(description)));
+    }
+    if let Some(illustration) = &post.illustration {
+        fields.push(("images", json!([illustration])));
+    }
+    fields.push(("tags", json!(terms.tags)));
+    fields.push(("categories", json!(terms.categories)));
+    fields.push(("functions", json!(post.functions.0)));
+    if !post.meta.0.is_empty() {
+        fields.push(("meta", json!(post.meta.0)));
+    }
+    if post.pinned {
+        fields.push(("pinned", json!(true)));
+    }
+    if post.featured {
+        fields.push(("featured", json!(true)));
+    }
+    if post.weight != 0 {
+        fields.push(("weight", json!(post.weight)));
+    }
+    match &post.password_hash {
+        Some(password_hash) if options.include_secrets => {
+            fields.push(("password_hash", json!(password_hash)));
+        }
+        Some(_) => fields.push(("protected", json!(true))),
+        None => {}
+    }
+
+    let mut document = String::from("---\n");
+    for (key, value) in fields {
+        document.push_str(&format!("{key}: {value}\n"));
+    }
+    document.push_str("---\n\n");
+    document.push_str(&post.content);
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use chrono::{TimeZone, Utc};
+    use sea_orm::{
+        ActiveModelTrait, ConnectionTrait, Database, DatabaseBackend, DatabaseConnection,
+        EntityTrait, Schema, Set,
+    };
+
+    use crate::{
+        config::{ApplicationConfiguration, SiteSettings, config_entries},
+        entity::{
+            attachment, category, config_entry, job,
+            post::{self, PostFunctions, PostKind, PostMeta, PostStatus},
+            post_category, post_revision, post_tag, storage_engine, tag,
+        },
+        service::{
+            import::ImportFiles, markdown_import::MarkdownImportService,
+            site_settings::SiteSettingsService, storage::StorageService, taxonomy::TaxonomyService,
+            theme::ThemeService,
+        },
+    };
+
+    use super::{ExportOptions, ExportService, file_stem};
+
+    struct Instance {
+        _assets: tempfile::TempDir,
+        database: DatabaseConnection,
+        storage: StorageService,
+        theme_service: ThemeService,
+    }
+
+    async fn instance() -> Instance {
+        let assets = tempfile::tempdir().unwrap();
+        let config = ApplicationConfiguration::for_tests(assets.path());
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        let schema = Schema::new(DatabaseBackend::Sqlite);
+        for statement in [
+            schema.create_table_from_entity(post::Entity),
+            schema.create_table_from_entity(tag::Entity),
+            schema.create_table_from_entity(category::Entity),
+            schema.create_table_from_entity(post_tag::Entity),
+            schema.create_table_from_entity(post_category::Entity),
+            schema.create_table_from_entity(post_revision::Entity),
+            schema.create_table_from_entity(storage_engine::Entity),
+            schema.create_table_from_entity(attachment::Entity),
+            schema.create_table_from_entity(config_entry::Entity),
+            schema.create_table_from_entity(job::Entity),
+        ] {
+            database.execute(&statement).await.unwrap();
+        }
+        storage_engine::ActiveModel {
+            name: Set("Local Storage".to_string()),
+            comments: Set(String::new()),
+            kind: Set("local".to_string()),
+            config_json: Set(None),
+            is_default: Set(true),
+            enabled: Set(true),
+            ..Default::default()
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+        Instance {
+            _assets: assets,
+            storage: StorageService::new(config.clone()),
+            theme_service: ThemeService::new(
+                database.clone(),
+                config,
+                SiteSettingsService::new(database.clone()),
+            ),
+            database,
+        }
+    }
+
+    #[tokio::test]
+    async fn exports_a_site_that_imports_into_a_fresh_instance() {
+        let source = instance().await;
+        let image = source
+            .storage
+            .upload(
+                &source.database,
+                b"image".to_vec(),
+                "image/png".to_string(),
+                Some("cover.png".to_string()),
+                None,
+            )
+            .await
+            .unwrap();
+        let image_url = format!("/attachments/{}", image.hash);
+        let created_at = Utc.with_ymd_and_hms(2024, 1, 31, 8, 0, 0).unwrap();
+        let secret = post::ActiveModel {
+            name: Set("secret".to_string()),
+            title: Set("A \"quoted\": title".to_string()),
+            content: Set(format!("Intro\n\n![Cover]({image_url})\n")),
+            author: Set(1),
+            description: Set(Some("Line one\nline two".to_string())),
+            illustration: Set(Some(image_url.clone())),
+            status: Set(PostStatus::Unlisted),
+            functions: Set(PostFunctions(vec!["math".to_string()])),
+            meta: Set(PostMeta(
+                serde_json::json!({ "subtitle": "Part one" })
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            )),
+            password_hash: Set(Some("$2b$12$hash".to_string())),
+            created_at: Set(created_at),
+            updated_at: Set(Some(created_at)),
+            ..Default::default()
+        }
+        .insert(&source.database)
+        .await
+        .unwrap();
+        TaxonomyService::replace_post_terms(
+            &source.database,
+            secret.id,
+            Some(vec!["Rust".to_string()]),
+            Some(vec!["Notes".to_string()]),
+        )
+        .await
+        .unwrap();
+        post::ActiveModel {
+            name: Set("about".to_string()),
+            title: Set("About".to_string()),
+            content: Set("About me".to_string()),
+            author: Set(1),
+            kind: Set(PostKind::Page),
+            status: Set(PostStatus::Scheduled),
+            publish_at: Set(Some(Utc.with_ymd_and_hms(2999, 1, 1, 0, 0, 0).unwrap())),
+            created_at: Set(created_at),
+            ..Default::default()
+        }
+        .insert(&source.database)
+        .await
+        .unwrap();
+        let site = SiteSettings {
+            site_name: "Exported".to_string(),
+            ..Default::default()
+        };
+        config_entries::SITE_SETTINGS
+            .set(&source.database, Some(&site))
+            .await
+            .unwrap();
+
+        let (archive, summary) = ExportService::export(
+            &source.database,
+            &source.storage,
+            &source.theme_service,
+            Cursor::new(Vec::new()),
+            ExportOptions {
+                include_secrets: true,
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!((summary.posts, summary.attachments), (2, 1));
+
+        let target = instance().await;
+        let files = ImportFiles::from_zip(archive.get_ref()).unwrap();
+        let report = MarkdownImportService::import(
+            &target.database,
+            &target.storage,
+            &target.theme_service,
+            &files,
+            1,
+            true,
+        )
+        .await
+        .unwrap();
+        assert!(report.skipped.is_empty(), "{:?}", report.skipped);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        assert!(report.settings);
+        assert_eq!(report.attachments.len(), 1);
+        assert_eq!(
+            report.attachments[0].url.as_deref(),
+            Some(image_url.as_str())
+        );
+
+        let imported = post::Entity::find().all(&target.database).await.unwrap();
+        let secret_copy = imported.iter().find(|post| post.name == "secret").unwrap();
+        assert_eq!(secret_copy.title, secret.title);
+        assert_eq!(secret_copy.content, secret.content);
+        assert_eq!(secret_copy.description, secret.description);
+        assert_eq!(secret_copy.illustration, secret.illustration);
+        assert_eq!(secret_copy.status, PostStatus::Unlisted);
+        assert_eq!(secret_copy.functions, secret.functions);
+        assert_eq!(secret_copy.meta, secret.meta);
+        assert_eq!(secret_copy.password_hash, secret.password_hash);
+        assert_eq!(secret_copy.created_at, created_at);
+        let terms = TaxonomyService::terms_for_posts(&target.database, &[secret_copy.id])
+            .await
+            .unwrap();
+        assert_eq!(terms[&secret_copy.id].tags, ["Rust"]);
+        assert_eq!(terms[&secret_copy.id].categories, ["Notes"]);
+        let about = imported.iter().find(|post| post.name == "about").unwrap();
+        assert_eq!(about.kind, PostKind::Page);
+        assert_eq!(about.status, PostStatus::Scheduled);
+        assert_eq!(
+            about.publish_at,
+            Some(Utc.with_ymd_and_hms(2999, 1, 1, 0, 0, 0).unwrap())
+        );
+
+        let stored = attachment::Entity::find()
+            .one(&target.database)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.hash, image.hash);
+        assert_eq!(stored.filename, "cover.png");
+        assert_eq!(
+            target
+                .storage
+                .read(&target.database, &stored)
+                .await
+                .unwrap(),
+            b"image"
+        );
+        assert_eq!(
+            config_entries::SITE_SETTINGS
+                .get::<SiteSettings>(&target.database)
+                .await
+                .unwrap()
+                .unwrap()
+                .site_name,
+            "Exported"
+        );
+    }
+
+    #[test]
+    fn file_stems_cannot_leave_the_archive_directory() {
+        assert_eq!(file_stem("hello-world"), "hello-world");
+        assert_eq!(
+            file_stem("notes/../a: b?*"),
+            "notes%2F%2E%2E%2Fa%3A%20b%3F%2A"
+        );
+        assert_eq!(file_stem("竹子"), "竹子");
+        assert_eq!(file_stem("nul"), "%5Fnul");
+        assert_eq!(file_stem(""), "%5F");
+    }
+
+    #[tokio::test]
+    async fn keeps_unsafe_names_and_leaves_out_password_hashes() {
+        let source = instance().await;
+        for (name, password_hash) in [
+            ("notes/../a: b?*", None),
+            ("locked", Some("$2b$12$hash".to_string())),
+        ] {
+            post::ActiveModel {
+                name: Set(name.to_string()),
+                title: Set(name.to_string()),
+                content: Set("Body".to_string()),
+                author: Set(1),
+                status: Set(PostStatus::Published),
+                password_hash: Set(password_hash),
+                ..Default::default()
+            }
+            .insert(&source.database)
+            .await
+            .unwrap();
+        }
+
+        let (archive, _) = ExportService::export(
+            &source.database,
+            &source.storage,
+            &source.theme_service,
+            Cursor::new(Vec::new()),
+            ExportOptions::default(),
+        )
+        .await
+        .unwrap();
+        let files = ImportFiles::from_zip(archive.get_ref()).unwrap();
+        let mut paths = files.iter().map(|(path, _)| path).collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                "bamboolog-export.json",
+                "posts/locked.md",
+                "posts/notes%2F%2E%2E%2Fa%3A%20b%3F%2A.md"
+            ]
+        );
+        let (_, locked) = files
+            .iter()
+            .find(|(path, _)| *path == "posts/locked.md")
+            .unwrap();
+        let locked = std::str::from_utf8(locked).unwrap();
+        assert!(!locked.contains("password_hash") && !locked.contains("$2b$"));
+
+        let target = instance().await;
+        let report = MarkdownImportService::import(
+            &target.database,
+            &target.storage,
+            &target.theme_service,
+            &files,
+            1,
+            true,
+        )
+        .await
+        .unwrap();
+        assert!(report.skipped.is_empty(), "{:?}", report.skipped);
+        assert_eq!(report.warnings.len(), 1);
+
+        let imported = post::Entity::find().all(&target.database).await.unwrap();
+        let unsafe_name = imported
+            .iter()
+            .find(|post| post.title == "notes/../a: b?*")
+            .unwrap();
+        assert_eq!(unsafe_name.name, "notes/../a: b?*");
+        assert_eq!(unsafe_name.status, PostStatus::Published);
+        let locked = imported.iter().find(|post| post.name == "locked").unwrap();
+        assert_eq!(locked.password_hash, None);
+        assert_eq!(locked.status, PostStatus::Draft);
+    }
+}
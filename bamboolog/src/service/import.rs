@@ -0,0 +1,529 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    io::{self, Cursor, Read},
+    path::{Component, Path},
+};
+
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ConnectionTrait, DatabaseConnection, DatabaseTransaction, DbErr,
+    EntityTrait, QuerySelect, prelude::DateTimeUtc,
+};
+use serde::Serialize;
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use zip::ZipArchive;
+
+use crate::{
+    config::config_entries,
+    entity::{
+        attachment,
+        post::{self, PostFunctions, PostKind, PostMeta, PostStatus},
+    },
+    service::{
+        post::PostService, post_revision::PostRevisionService, storage::StorageService,
+        taxonomy::TaxonomyService, theme::ThemeError,
+    },
+};
+
+/// Uploaded archives and imported directories may not hold more than this.
+pub const MAX_IMPORT_SIZE: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImportError {
+    #[error("Invalid import: {0}")]
+    InvalidInput(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Database(#[from] DbErr),
+    #[error("Failed to store `{path}`: {source}")]
+    Storage { path: String, source: anyhow::Error },
+    #[error("Failed to hash a post password: {0}")]
+    Password(#[from] bcrypt::BcryptError),
+    #[error(transparent)]
+    Settings(#[from] config_entries::ConfigError),
+    #[error(transparent)]
+    Theme(#[from] ThemeError),
+}
+
+/// The files of an import, keyed by their `/`-separated path inside the
+/// archive or directory they were read from.
+#[derive(Debug, Default)]
+pub struct ImportFiles {
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl ImportFiles {
+    pub fn from_zip(archive: &[u8]) -> Result<Self, ImportError> {
+        let mut archive = ZipArchive::new(Cursor::new(archive))
+            .map_err(|error| ImportError::InvalidInput(error.to_string()))?;
+        let mut files = BTreeMap::new();
+        let mut extracted_size: u64 = 0;
+        for index in 0..archive.len() {
+            let mut entry = archive
+                .by_index(index)
+                .map_err(|error| ImportError::InvalidInput(error.to_string()))?;
+            if entry.is_dir() || entry.is_symlink() {
+                continue;
+            }
+            let Some(path) = entry.enclosed_name() else {
+                return Err(ImportError::InvalidInput(format!(
+                    "archive entry `{}` has an unsafe path",
+                    entry.name()
+                )));
+            };
+            let Some(path) = import_path(&path) else {
+                continue;
+            };
+            extracted_size = extracted_size.saturating_add(entry.size());
+            if extracted_size > MAX_IMPORT_SIZE {
+                return Err(ImportError::InvalidInput(
+                    "extracted files must not exceed 512 MB".to_string(),
+                ));
+            }
+            let mut data = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut data)?;
+            files.insert(path, data);
+        }
+        Ok(Self { files })
+    }
+
+    /// Reads every file below `directory`. Hidden files and directories, such
+    /// as `.git`, and symbolic links are left out.
+    pub fn from_directory(directory: &Path) -> Result<Self, ImportError> {
+        let mut files = BTreeMap::new();
+        let mut total_size: u64 = 0;
+        let mut pending = vec![directory.to_path_buf()];
+        while let Some(current) = pending.pop() {
+            for entry in fs::read_dir(&current)? {
+                let entry = entry?;
+                let file_type = entry.file_type()?;
+                let path = entry.path();
+                let relative_path = path.strip_prefix(directory).expect("walked below root");
+                let Some(import_path) = import_path(relative_path) else {
+                    continue;
+                };
+                if file_type.is_dir() {
+                    pending.push(path);
+                } else if file_type.is_file() {
+                    total_size = total_size.saturating_add(entry.metadata()?.len());
+                    if total_size > MAX_IMPORT_SIZE {
+                        return Err(ImportError::InvalidInput(
+                            "imported files must not exceed 512 MB".to_string(),
+                        ));
+                    }
+                    files.insert(import_path, fs::read(&path)?);
+                }
+            }
+        }
+        Ok(Self { files })
+    }
+
+    pub fn get(&self, path: &str) -> Option<&[u8]> {
+        self.files.get(path).map(Vec::as_slice)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.files
+            .iter()
+            .map(|(path, data)| (path.as_str(), data.as_slice()))
+    }
+
+    /// Resolves `reference`, as written in the file at `from`, to the path of
+    /// a file in the import. Relative references start next to `from`;
+    /// root-relative ones are looked up at every ancestor of `from`, and in
+    /// its `static` directory as Hugo lays sites out.
+    pub fn resolve(&self, from: &str, reference: &str) -> Option<String> {
+        if !is_local_reference(reference) {
+            return None;
+        }
+        let reference = percent_decode(reference.split(['?', '#']).next().unwrap_or_default());
+        let directory = from.rsplit_once('/').map_or("", |(directory, _)| directory);
+        if let Some(reference) = reference.strip_prefix('/') {
+            let mut ancestors = vec![directory];
+            let mut ancestor = directory;
+            while let Some((parent, _)) = ancestor.rsplit_once('/') {
+                ancestors.push(parent);
+                ancestor = parent;
+            }
+            if !directory.is_empty() {
+                ancestors.push("");
+            }
+            return ancestors.into_iter().find_map(|ancestor| {
+                ["", "static/"].into_iter().find_map(|prefix| {
+                    let path = join_path(ancestor, &format!("{prefix}{reference}"))?;
+                    self.files.contains_key(&path).then_some(path)
+                })
+            });
+        }
+        join_path(directory, &reference).filter(|path| self.files.contains_key(path))
+    }
+}
+
+impl<P: Into<String>> FromIterator<(P, Vec<u8>)> for ImportFiles {
+    fn from_iter<I: IntoIterator<Item = (P, Vec<u8>)>>(iter: I) -> Self {
+        Self {
+            files: iter
+                .into_iter()
+                .map(|(path, data)| (path.into(), data))
+                .collect(),
+        }
+    }
+}
+
+/// A post read by an importer, ready to be created.
+#[derive(Debug)]
+pub struct ImportPost {
+    /// Where the post came from, for the report.
+    pub source: String,
+    pub name: String,
+    pub title: String,
+    pub content: String,
+    pub description: Option<String>,
+    pub illustration: Option<String>,
+    pub kind: PostKind,
+    pub status: PostStatus,
+    pub publish_at: Option<DateTimeUtc>,
+    pub created_at: DateTimeUtc,
+    pub updated_at: DateTimeUtc,
+    pub tags: Vec<String>,
+    pub categories: Vec<String>,
+    pub functions: Vec<String>,
+    pub meta: JsonMap<String, JsonValue>,
+    pub pinned: bool,
+    pub featured: bool,
+    pub weight: i32,
+    pub password_hash: Option<String>,
+}
+
+impl ImportPost {
+    /// Claims the post's name among `taken_names`, or explains why it cannot
+    /// be imported under it.
+    pub fn claim_name(&self, taken_names: &mut HashSet<String>) -> Result<(), String> {
+        if self.kind == PostKind::Page && PostService::is_reserved_page_name(&self.name) {
+            return Err(format!(
+                "The page name `{}` is reserved by another route",
+                self.name
+            ));
+        }
+        if !taken_names.insert(self.name.clone()) {
+            return Err(format!("A post named `{}` already exists", self.name));
+        }
+        Ok(())
+    }
+
+    pub fn public_path(&self) -> String {
+        PostService::path_of(self.kind, &self.name)
+    }
+
+    /// The report entry of the post, without an id until it is created.
+    pub fn report(&self) -> ImportedPost {
+        ImportedPost {
+            source: self.source.clone(),
+            id: None,
+            name: self.name.clone(),
+            title: self.title.clone(),
+            kind: self.kind,
+            status: self.status,
+            created_at: self.created_at,
+            tags: self.tags.clone(),
+            categories: self.categories.clone(),
+        }
+    }
+
+    /// Creates the post with its terms and first revision.
+    pub async fn create<C>(self, db: &C, author: i32) -> Result<post::Model, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let created = post::ActiveModel {
+            id: ActiveValue::NotSet,
+            name: ActiveValue::Set(self.name),
+            title: ActiveValue::Set(self.title),
+            content: ActiveValue::Set(self.content),
+            author: ActiveValue::Set(author),
+            description: ActiveValue::Set(self.description),
+            illustration: ActiveValue::Set(self.illustration),
+            status: ActiveValue::Set(self.status),
+            publish_at: ActiveValue::Set(self.publish_at),
+            kind: ActiveValue::Set(self.kind),
+            functions: ActiveValue::Set(PostFunctions(self.functions)),
+            meta: ActiveValue::Set(PostMeta(self.meta)),
+            pinned: ActiveValue::Set(self.pinned),
+            featured: ActiveValue::Set(self.featured),
+            weight: ActiveValue::Set(self.weight),
+            password_hash: ActiveValue::Set(self.password_hash),
+            created_at: ActiveValue::Set(self.created_at),
+            updated_at: ActiveValue::Set(Some(self.updated_at)),
+        }
+        .insert(db)
+        .await?;
+        TaxonomyService::replace_post_terms(db, created.id, Some(self.tags), Some(self.categories))
+            .await?;
+        PostRevisionService::record_created(db, &created, author).await?;
+        Ok(created)
+    }
+}
+
+/// Names of every existing post and page, which imported posts may not reuse.
+pub async fn taken_post_names<C>(db: &C) -> Result<HashSet<String>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    Ok(post::Entity::find()
+        .select_only()
+        .column(post::Column::Name)
+        .into_tuple::<String>()
+        .all(db)
+        .await?
+        .into_iter()
+        .collect())
+}
+
+/// What an import created, or would create on a dry run, and what it left
+/// behind.
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    /// `false` for a dry run, which writes nothing.
+    pub applied: bool,
+    pub posts: Vec<ImportedPost>,
+    pub attachments: Vec<ImportedAttachment>,
+    /// Redirects from the URLs posts had on the old site.
+    pub redirects: Vec<ImportedRedirect>,
+    /// Whether the site settings and theme configuration of a site export
+    /// are restored.
+    pub settings: bool,
+    /// Entries that were not imported at all.
+    pub skipped: Vec<ImportIssue>,
+    /// Entries that were imported with something missing.
+    pub warnings: Vec<ImportIssue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportedPost {
+    pub source: String,
+    /// Set once the post has been created.
+    pub id: Option<i32>,
+    pub name: String,
+    pub title: String,
+    pub kind: PostKind,
+    pub status: PostStatus,
+    pub created_at: DateTimeUtc,
+    pub tags: Vec<String>,
+    pub categories: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportedAttachment {
+    pub source: String,
+    /// Where the stored file is served from, once it has been uploaded.
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportedRedirect {
+    pub source: String,
+    pub target: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportIssue {
+    pub source: String,
+    pub reason: String,
+}
+
+impl ImportIssue {
+    pub fn new(source: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Whether `reference` names a file rather than a URL, a data URI, or an
+/// anchor on the same page.
+pub fn is_local_reference(reference: &str) -> bool {
+    !reference.is_empty()
+        && !reference.starts_with("//")
+        && !reference.starts_with('#')
+        && !reference.contains(':')
+}
+
+/// Public path of a stored attachment.
+pub fn attachment_url(hash: &str) -> String {
+    format!("/attachments/{hash}")
+}
+
+/// Uploads an imported file through the default storage engine and returns
+/// its public path. The attachment is added to `stored` for `finish_import`.
+pub async fn store_import_file<C>(
+    db: &C,
+    storage: &StorageService,
+    path: &str,
+    data: Vec<u8>,
+    stored: &mut Vec<attachment::Model>,
+) -> Result<String, ImportError>
+where
+    C: ConnectionTrait,
+{
+    let mime_type = mime_guess::from_path(path)
+        .first_or_octet_stream()
+        .essence_str()
+        .to_string();
+    let filename = path.rsplit('/').next().map(str::to_owned);
+    let attachment = storage
+        .upload(db, data, mime_type, filename, None)
+        .await
+        .map_err(|source| ImportError::Storage {
+            path: path.to_string(),
+            source,
+        })?;
+    let url = attachment_url(&attachment.hash);
+    stored.push(attachment);
+    Ok(url)
+}
+
+/// Commits the transaction of an import that succeeded. Otherwise it is
+/// rolled back and the files stored in it are deleted again, since their
+/// rows went with the rollback.
+pub async fn finish_import(
+    db: &DatabaseConnection,
+    storage: &StorageService,
+    transaction: DatabaseTransaction,
+    stored: &[attachment::Model],
+    result: Result<(), ImportError>,
+) -> Result<(), ImportError> {
+    let result = match result {
+        Ok(()) => transaction.commit().await.map_err(ImportError::from),
+        Err(error) => {
+            if let Err(rollback_error) = transaction.rollback().await {
+                tracing::warn!("Failed to roll back an import: {rollback_error}");
+            }
+            Err(error)
+        }
+    };
+    if result.is_err()
+        && let Err(error) = storage.discard_orphans(db, stored).await
+    {
+        tracing::error!("Failed to delete the files of a failed import: {error}");
+    }
+    result
+}
+
+/// `/`-separated form of a relative path, or `None` for hidden entries and
+/// the metadata directories archivers add.
+fn import_path(path: &Path) -> Option<String> {
+    let mut segments = Vec::new();
+    for component in path.components() {
+        let Component::Normal(segment) = component else {
+            return None;
+        };
+        let segment = segment.to_str()?;
+        if segment.starts_with('.') || segment == "__MACOSX" {
+            return None;
+        }
+        segments.push(segment);
+    }
+    (!segments.is_empty()).then(|| segments.join("/"))
+}
+
+/// Joins a relative reference onto a directory, resolving `.` and `..`.
+/// References that climb above the root resolve to nothing.
+fn join_path(directory: &str, reference: &str) -> Option<String> {
+    let mut segments = directory
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    for segment in reference.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    (!segments.is_empty()).then(|| segments.join("/"))
+}
+
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%'
+            && let Some(byte) = value
+                .get(index + 1..index + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{ZipWriter, write::SimpleFileOptions};
+
+    use super::ImportFiles;
+
+    #[test]
+    fn reads_archives_without_hidden_entries() {
+        let mut archive = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (path, content) in [
+            ("site/content/post.md", "post"),
+            ("site/.git/HEAD", "ref"),
+            ("__MACOSX/site/._post.md", "fork"),
+        ] {
+            archive
+                .start_file(path, SimpleFileOptions::default())
+                .unwrap();
+            archive.write_all(content.as_bytes()).unwrap();
+        }
+        let archive = archive.finish().unwrap().into_inner();
+
+        let files = ImportFiles::from_zip(&archive).unwrap();
+
+        assert_eq!(
+            files.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+            ["site/content/post.md"]
+        );
+    }
+
+    #[test]
+    fn resolves_relative_and_root_relative_references() {
+        let files = ImportFiles::from_iter([
+            ("site/content/posts/hello/index.md", Vec::new()),
+            ("site/content/posts/hello/cover image.png", Vec::new()),
+            ("site/static/images/logo.png", Vec::new()),
+            ("site/content/shared.png", Vec::new()),
+        ]);
+        let from = "site/content/posts/hello/index.md";
+
+        assert_eq!(
+            files
+                .resolve(from, "cover%20image.png?width=300")
+                .as_deref(),
+            Some("site/content/posts/hello/cover image.png")
+        );
+        assert_eq!(
+            files.resolve(from, "../../shared.png").as_deref(),
+            Some("site/content/shared.png")
+        );
+        assert_eq!(
+            files.resolve(from, "/images/logo.png").as_deref(),
+            Some("site/static/images/logo.png")
+        );
+        assert_eq!(files.resolve(from, "https://example.com/a.png"), None);
+        assert_eq!(files.resolve(from, "../../../../../etc/passwd"), None);
+        assert_eq!(files.resolve(from, "missing.png"), None);
+    }
+}
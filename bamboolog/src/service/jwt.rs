@@ -33,6 +33,9 @@ use crate::{
 
 pub const JWT_COOKIE_NAME: &str = "bamboolog_jwt";
 
+/// How long readers stay unlocked after entering a post password.
+pub const POST_ACCESS_EXPIRE: usize = 7 * 24 * 3600;
+
 pub fn uses_cookie_authorization(headers: &HeaderMap) -> bool {
     headers
         .get(header::AUTHORIZATION)
@@ -153,6 +156,34 @@ impl JwtService {
             &state.encoding_key,
         )
     }
+
+    /// Signs proof that a reader entered the password of a post.
+    pub async fn issue_post_access(
+        &self,
+        post_id: i32,
+        fingerprint: &str,
+    ) -> Result<String, jsonwebtoken::errors::Error> {
+        let state = self.state.read().await;
+        encode(
+            &Header::default(),
+            &PostAccessClaims {
+                post_id,
+                fingerprint: fingerprint.to_string(),
+                exp: Utc::now().timestamp() as usize + POST_ACCESS_EXPIRE,
+            },
+            &state.encoding_key,
+        )
+    }
+
+    /// Whether `token` grants access to the post under its current password.
+    pub async fn verify_post_access(&self, token: &str, post_id: i32, fingerprint: &str) -> bool {
+        let state = self.state.read().await;
+        let mut validation = state.validation.clone();
+        validation.validate_aud = false;
+        decode::<PostAccessClaims>(token, &state.decoding_key, &validation).is_ok_and(|data| {
+            data.claims.post_id == post_id && data.claims.fingerprint == fingerprint
+        })
+    }
 }
 
 #[async_trait]
@@ -211,6 +242,15 @@ impl IntoResponse for AuthError {
     }
 }
 
+/// Claims of the cookie that unlocks a password-protected post. They carry no
+/// audience, so they can never pass as a login token.
+#[derive(Debug, Serialize, Deserialize)]
+struct PostAccessClaims {
+    post_id: i32,
+    fingerprint: String,
+    exp: usize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JwtClaims {
     pub sub: String,
@@ -254,3 +294,21 @@ where
         Ok(token_data.claims)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::Database;
+
+    use super::JwtService;
+
+    #[tokio::test]
+    async fn post_access_is_bound_to_the_post_and_its_password() {
+        let service = JwtService::new(Database::connect("sqlite::memory:").await.unwrap());
+        let token = service.issue_post_access(1, "$2b$04$salt").await.unwrap();
+
+        assert!(service.verify_post_access(&token, 1, "$2b$04$salt").await);
+        assert!(!service.verify_post_access(&token, 2, "$2b$04$salt").await);
+        assert!(!service.verify_post_access(&token, 1, "$2b$04$other").await);
+        assert!(service.decode(&token).await.is_err());
+    }
+}
@@ -0,0 +1,968 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    ops::Range,
+    sync::LazyLock,
+};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use regex::Regex;
+use sea_orm::{ConnectionTrait, DatabaseConnection, TransactionTrait, prelude::DateTimeUtc};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use yaml_rust2::{Yaml, YamlLoader};
+
+use crate::{
+    config::{SiteSettings, config_entries},
+    entity::{
+        attachment,
+        post::{PostKind, PostStatus},
+    },
+    service::{
+        export::{EXPORT_FORMAT_VERSION, EXPORT_MANIFEST, ExportManifest, ExportedAttachment},
+        import::{
+            ImportError, ImportFiles, ImportIssue, ImportPost, ImportReport, ImportedAttachment,
+            attachment_url, finish_import, is_local_reference, store_import_file, taken_post_names,
+        },
+        scheduler::{Job, SchedulerService},
+        storage::StorageService,
+        theme::ThemeService,
+    },
+};
+
+/// Dates in Jekyll post file names, as in `_posts/2024-01-31-hello.md`.
+static DATED_FILE_NAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\d{4}-\d{2}-\d{2})-(.+)$").expect("dated file name pattern is valid")
+});
+/// Image sources in Markdown images, HTML `img` tags, and `figure` shortcodes.
+static IMAGE_REFERENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"!\[[^\]]*\]\(\s*<?([^)\s>]+)|<img\b[^>]*?\ssrc\s*=\s*["']([^"']+)["']|\{\{[<%]\s*figure\b[^}]*?\ssrc\s*=\s*"([^"]+)""#,
+    )
+    .expect("image reference pattern is valid")
+});
+
+/// Imports Markdown files with YAML (`---`) or TOML (`+++`) front matter, as
+/// Hugo and Jekyll sites keep them, and archives written by `ExportService`.
+pub struct MarkdownImportService;
+
+impl MarkdownImportService {
+    /// Reads every Markdown file in `files` as a post by `author`. Local
+    /// images they reference are stored as attachments and their links
+    /// rewritten. A site export also restores its attachments, site settings
+    /// and theme configuration. Without `apply` nothing is written and the
+    /// report tells what would happen.
+    pub async fn import(
+        db: &DatabaseConnection,
+        storage: &StorageService,
+        theme_service: &ThemeService,
+        files: &ImportFiles,
+        author: i32,
+        apply: bool,
+    ) -> Result<ImportReport, ImportError> {
+        let now = Utc::now();
+        let mut report = ImportReport {
+            applied: apply,
+            ..Default::default()
+        };
+        let mut taken_names = taken_post_names(db).await?;
+        let export = read_export_manifest(files)?;
+        // Exported attachments keep their hash, so posts may go on linking to
+        // their URLs.
+        let (exported_paths, exported_urls) = match &export {
+            Some((prefix, manifest)) => (
+                manifest
+                    .attachments
+                    .iter()
+                    .map(|attachment| format!("{prefix}{}", attachment.path))
+                    .collect::<HashSet<_>>(),
+                manifest
+                    .attachments
+                    .iter()
+                    .map(|attachment| attachment_url(&attachment.hash))
+                    .collect::<HashSet<_>>(),
+            ),
+            None => Default::default(),
+        };
+
+        let mut posts = Vec::new();
+        for (path, data) in files.iter() {
+            if !is_markdown(path) || exported_paths.contains(path) {
+                continue;
+            }
+            let post = match parse_post(path, data, now, export.is_some()) {
+                Ok((post, warning)) => {
+                    if let Some(warning) = warning {
+                        report.warnings.push(ImportIssue::new(path, warning));
+                    }
+                    post
+                }
+                Err(reason) => {
+                    report.skipped.push(ImportIssue::new(path, reason));
+                    continue;
+                }
+            };
+            match post.claim_name(&mut taken_names) {
+                Ok(()) => posts.push(post),
+                Err(reason) => report.skipped.push(ImportIssue::new(path, reason)),
+            }
+        }
+
+        // Every local file the posts refer to, with its URL once stored.
+        let mut uploads = BTreeMap::<String, Option<String>>::new();
+        for post in &posts {
+            let mut references = image_references(&post.content)
+                .into_iter()
+                .map(|(_, reference)| reference)
+                .collect::<Vec<_>>();
+            references.extend(post.illustration.clone());
+            for reference in references {
+                if exported_urls.contains(&reference) {
+                    continue;
+                }
+                match resolve_reference(files, &post.source, &reference) {
+                    Some(path) => {
+                        uploads.entry(path).or_default();
+                    }
+                    None if is_local_reference(&reference) => {
+                        report.warnings.push(ImportIssue::new(
+                            &post.source,
+                            format!("Referenced file `{reference}` was not found"),
+                        ))
+                    }
+                    None => {}
+                }
+            }
+        }
+        let restore = match &export {
+            Some((prefix, manifest)) => {
+                Some(check_export(theme_service, files, prefix, manifest, &mut report).await?)
+            }
+            None => None,
+        };
+
+        // Everything is checked by now; the writes either all happen or none
+        // of them do.
+        let transaction = db.begin().await?;
+        let mut stored = Vec::new();
+        let result = async {
+            if apply {
+                for (path, url) in &mut uploads {
+                    let data = files.get(path).expect("resolved paths exist").to_vec();
+                    *url = Some(
+                        store_import_file(&transaction, storage, path, data, &mut stored).await?,
+                    );
+                }
+            }
+            report.attachments = uploads
+                .iter()
+                .map(|(path, url)| ImportedAttachment {
+                    source: path.clone(),
+                    url: url.clone(),
+                })
+                .collect();
+            if let Some(restore) = &restore {
+                restore_export(
+                    &transaction,
+                    storage,
+                    theme_service,
+                    restore,
+                    &mut stored,
+                    &mut report,
+                )
+                .await?;
+            }
+
+            for mut post in posts {
+                if apply {
+                    let stored_url = |reference: &str| {
+                        resolve_reference(files, &post.source, reference)
+                            .and_then(|path| uploads.get(&path).cloned().flatten())
+                    };
+                    post.content = rewrite_image_references(&post.content, stored_url);
+                    // Local images that were not found would only be broken links.
+                    post.illustration = post.illustration.as_deref().and_then(|illustration| {
+                        stored_url(illustration).or_else(|| {
+                            (exported_urls.contains(illustration)
+                                || !is_local_reference(illustration))
+                            .then(|| illustration.to_string())
+                        })
+                    });
+                }
+                let mut imported = post.report();
+                if apply {
+                    imported.id = Some(post.create(&transaction, author).await?.id);
+                }
+                report.posts.push(imported);
+            }
+            if apply && !report.posts.is_empty() {
+                SchedulerService::request(&transaction, Job::PingSubscribers).await?;
+            }
+            Ok(())
+        }
+        .await;
+        finish_import(db, storage, transaction, &stored, result).await?;
+
+        Ok(report)
+    }
+}
+
+/// The manifest of a site export and the directory it is in, if `files` is
+/// one.
+fn read_export_manifest(
+    files: &ImportFiles,
+) -> Result<Option<(String, ExportManifest)>, ImportError> {
+    let Some((path, data)) = files.iter().find(|(path, _)| {
+        path.rsplit('/').next() == Some(EXPORT_MANIFEST) && path.matches('/').count() <= 1
+    }) else {
+        return Ok(None);
+    };
+    let manifest = serde_json::from_slice::<ExportManifest>(data)
+        .map_err(|error| ImportError::InvalidInput(format!("{path}: {error}")))?;
+    if manifest.version > EXPORT_FORMAT_VERSION {
+        return Err(ImportError::InvalidInput(format!(
+            "{path} was written by a newer version (format {})",
+            manifest.version
+        )));
+    }
+    let prefix = path.strip_suffix(EXPORT_MANIFEST).unwrap_or_default();
+    Ok(Some((prefix.to_string(), manifest)))
+}
+
+/// What a site export restores, checked before anything is written.
+struct ExportRestore<'a> {
+    site: &'a SiteSettings,
+    /// Attachments that match their hash, with their path in the import.
+    attachments: Vec<(String, &'a ExportedAttachment, &'a [u8])>,
+    /// The installed theme and the configuration to restore for it.
+    theme: Option<(&'a str, JsonMap<String, JsonValue>)>,
+}
+
+/// Checks the attachments and theme configuration of a site export. The
+/// theme itself is not part of an export and must already be installed.
+async fn check_export<'a>(
+    theme_service: &ThemeService,
+    files: &'a ImportFiles,
+    prefix: &str,
+    manifest: &'a ExportManifest,
+    report: &mut ImportReport,
+) -> Result<ExportRestore<'a>, ImportError> {
+    let mut attachments = Vec::new();
+    for attachment in &manifest.attachments {
+        let path = format!("{prefix}{}", attachment.path);
+        let Some(data) = files.get(&path) else {
+            report
+                .warnings
+                .push(ImportIssue::new(&path, "The attachment is missing"));
+            continue;
+        };
+        if format!("{:x}", md5::compute(data)) != attachment.hash {
+            report.warnings.push(ImportIssue::new(
+                &path,
+                "The attachment does not match its hash; links to it would break",
+            ));
+            continue;
+        }
+        attachments.push((path, attachment, data));
+    }
+
+    report.settings = true;
+    let theme = match &manifest.theme {
+        Some(theme) if theme_service.list_themes().await?.contains(&theme.id) => {
+            match theme_service
+                .resolve_restored_config(&theme.id, &theme.config)
+                .await
+            {
+                Ok(config) => Some((theme.id.as_str(), config)),
+                Err(error) => {
+                    report.warnings.push(ImportIssue::new(
+                        &theme.id,
+                        format!("The theme configuration was not restored: {error}"),
+                    ));
+                    None
+                }
+            }
+        }
+        Some(theme) => {
+            report.warnings.push(ImportIssue::new(
+                &theme.id,
+                "The theme is not installed; install it and import again to restore its configuration",
+            ));
+            None
+        }
+        None => None,
+    };
+    Ok(ExportRestore {
+        site: &manifest.site,
+        attachments,
+        theme,
+    })
+}
+
+/// Stores the attachments of a site export and restores its settings and
+/// theme configuration, as `check_export` found them.
+async fn restore_export<C>(
+    db: &C,
+    storage: &StorageService,
+    theme_service: &ThemeService,
+    restore: &ExportRestore<'_>,
+    stored: &mut Vec<attachment::Model>,
+    report: &mut ImportReport,
+) -> Result<(), ImportError>
+where
+    C: ConnectionTrait,
+{
+    for (path, attachment, data) in &restore.attachments {
+        let url = if report.applied {
+            let filename = Some(attachment.filename.clone()).filter(|name| !name.is_empty());
+            let attachment = storage
+                .upload(db, data.to_vec(), attachment.mime.clone(), filename, None)
+                .await
+                .map_err(|source| ImportError::Storage {
+                    path: path.clone(),
+                    source,
+                })?;
+            let url = attachment_url(&attachment.hash);
+            stored.push(attachment);
+            Some(url)
+        } else {
+            None
+        };
+        report.attachments.push(ImportedAttachment {
+            source: path.clone(),
+            url,
+        });
+    }
+
+    if report.applied {
+        config_entries::SITE_SETTINGS
+            .set(db, Some(restore.site))
+            .await?;
+        if let Some((theme_id, config)) = &restore.theme {
+            theme_service.restore_theme(db, theme_id, config).await?;
+        }
+    }
+    Ok(())
+}
+
+fn is_markdown(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    path.ends_with(".md") || path.ends_with(".markdown")
+}
+
+/// Reads a post from the Markdown file at `path`, with a warning about
+/// anything the reader should follow up on. The error explains why the file
+/// is not a post. Names in a site export are restored exactly as written.
+fn parse_post(
+    path: &str,
+    data: &[u8],
+    now: DateTimeUtc,
+    from_export: bool,
+) -> Result<(ImportPost, Option<String>), String> {
+    let text = std::str::from_utf8(data).map_err(|_| "The file is not valid UTF-8".to_string())?;
+    let (front_matter, content) =
+        split_front_matter(text).ok_or_else(|| "The file has no front matter".to_string())??;
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    let stem = file_name
+        .rsplit_once('.')
+        .map_or(file_name, |(stem, _)| stem);
+    if stem == "_index" {
+        return Err("Section index pages are not imported".to_string());
+    }
+    let (file_date, stem) = match DATED_FILE_NAME.captures(stem) {
+        Some(captures) => (
+            parse_date(&captures[1]),
+            captures.get(2).expect("group always matches").as_str(),
+        ),
+        None => (None, stem),
+    };
+    // Hugo page bundles keep a post in `<name>/index.md`.
+    let stem = match (stem, path.rsplit('/').nth(1)) {
+        ("index", Some(directory)) => directory,
+        _ => stem,
+    };
+
+    let name = front_matter
+        .string(&["slug"])
+        .unwrap_or_else(|| stem.to_string());
+    if name.is_empty() || (!from_export && name.contains('/')) {
+        return Err(format!("`{name}` cannot be used as a post name"));
+    }
+    let title = front_matter
+        .string(&["title"])
+        .unwrap_or_else(|| name.clone());
+    let created_at = match front_matter.string(&["date", "publishDate", "publish_date"]) {
+        Some(date) => {
+            Some(parse_date(&date).ok_or_else(|| format!("Cannot read the date `{date}`"))?)
+        }
+        None => file_date,
+    }
+    .unwrap_or(now);
+    let updated_at = front_matter
+        .string(&["lastmod", "last_modified_at", "updated", "modified"])
+        .and_then(|date| parse_date(&date))
+        .unwrap_or(created_at);
+    let is_page = ["type", "layout", "kind"].into_iter().any(|key| {
+        front_matter
+            .string(&[key])
+            .is_some_and(|value| value.eq_ignore_ascii_case("page"))
+    });
+    let draft = front_matter.bool("draft") == Some(true)
+        || front_matter.bool("published") == Some(false)
+        || path.split('/').any(|segment| segment == "_drafts");
+    // Site exports record the exact status; other generators only know drafts.
+    let status = match front_matter.string(&["status"]) {
+        Some(status) => Some(
+            serde_json::from_value::<PostStatus>(JsonValue::String(status.clone()))
+                .map_err(|_| format!("Unknown status `{status}`"))?,
+        ),
+        None => None,
+    };
+    let publish_at = front_matter
+        .string(&["publish_at"])
+        .and_then(|date| parse_date(&date));
+    let (status, publish_at) = match status {
+        Some(PostStatus::Scheduled) => (PostStatus::Scheduled, publish_at.or(Some(created_at))),
+        Some(status) => (status, publish_at),
+        None if draft => (PostStatus::Draft, None),
+        None if created_at > now => (PostStatus::Scheduled, Some(created_at)),
+        None => (PostStatus::Published, None),
+    };
+    // Exports leave out password hashes unless asked to keep them. Such posts
+    // come back as drafts, so they are never published without their lock.
+    let password_hash = front_matter.string(&["password_hash"]);
+    let (status, publish_at, warning) =
+        if password_hash.is_none() && front_matter.bool("protected") == Some(true) {
+            (
+                PostStatus::Draft,
+                None,
+                Some(format!(
+                    "`{name}` was password protected; set a new password before publishing it"
+                )),
+            )
+        } else {
+            (status, publish_at, None)
+        };
+
+    let post = ImportPost {
+        source: path.to_string(),
+        title,
+        content: content.trim_start_matches(['\r', '\n']).to_string(),
+        description: front_matter.string(&["description", "summary", "excerpt"]),
+        illustration: front_matter.illustration(),
+        kind: if is_page {
+            PostKind::Page
+        } else {
+            PostKind::Post
+        },
+        status,
+        publish_at,
+        created_at,
+        updated_at,
+        tags: front_matter.strings(&["tags", "tag"]),
+        categories: front_matter.strings(&["categories", "category"]),
+        functions: front_matter.strings(&["functions"]),
+        meta: match front_matter.0.get("meta") {
+            Some(JsonValue::Object(meta)) => meta.clone(),
+            _ => JsonMap::new(),
+        },
+        // Jekyll themes commonly pin with `pin` and Hugo orders by `weight`.
+        pinned: front_matter
+            .bool("pinned")
+            .or_else(|| front_matter.bool("pin"))
+            == Some(true),
+        featured: front_matter.bool("featured") == Some(true),
+        weight: front_matter
+            .string(&["weight"])
+            .and_then(|weight| weight.parse().ok())
+            .unwrap_or_default(),
+        password_hash,
+        name,
+    };
+    Ok((post, warning))
+}
+
+struct FrontMatter(JsonMap<String, JsonValue>);
+
+impl FrontMatter {
+    /// The first of `keys` holding a non-empty scalar.
+    fn string(&self, keys: &[&str]) -> Option<String> {
+        keys.iter().find_map(|key| match self.0.get(*key)? {
+            JsonValue::String(value) => Some(value.trim().to_string()).filter(|v| !v.is_empty()),
+            JsonValue::Number(value) => Some(value.to_string()),
+            _ => None,
+        })
+    }
+
+    /// Terms from the first of `keys` that is present, either as a list or
+    /// as one comma-separated string.
+    fn strings(&self, keys: &[&str]) -> Vec<String> {
+        let Some(value) = keys.iter().find_map(|key| self.0.get(*key)) else {
+            return Vec::new();
+        };
+        let values = match value {
+            JsonValue::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        values
+            .into_iter()
+            .flat_map(|value| match value {
+                JsonValue::String(value) => value.split(',').map(str::to_owned).collect(),
+                JsonValue::Number(value) => vec![value.to_string()],
+                _ => Vec::new(),
+            })
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+
+    fn bool(&self, key: &str) -> Option<bool> {
+        match self.0.get(key)? {
+            JsonValue::Bool(value) => Some(*value),
+            JsonValue::String(value) => value.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Hugo's `images` list, Jekyll's `image`, or the `cover.image` many Hugo
+    /// themes read.
+    fn illustration(&self) -> Option<String> {
+        let cover = match self.0.get("cover") {
+            Some(JsonValue::Object(cover)) => FrontMatter(cover.clone()).string(&["image"]),
+            _ => None,
+        };
+        self.strings(&["images"])
+            .into_iter()
+            .next()
+            .or_else(|| self.string(&["image"]))
+            .or(cover)
+    }
+}
+
+/// Splits a document into its front matter and its Markdown, or returns
+/// `None` when it has no front matter.
+fn split_front_matter(text: &str) -> Option<Result<(FrontMatter, &str), String>> {
+    let text = text.trim_start_matches('\u{feff}');
+    let mut lines = text.split_inclusive('\n');
+    let delimiter = lines.next()?.trim_end();
+    if delimiter != "---" && delimiter != "+++" {
+        return None;
+    }
+    let start = text.find('\n')? + 1;
+    let mut end = start;
+    for line in lines {
+        let trimmed = line.trim_end();
+        if trimmed == delimiter || (delimiter == "---" && trimmed == "...") {
+            let source = &text[start..end];
+            let content = &text[end + line.len()..];
+            let front_matter = if delimiter == "---" {
+                parse_yaml(source)
+            } else {
+                parse_toml(source)
+            };
+            return Some(front_matter.map(|front_matter| (front_matter, content)));
+        }
+        end += line.len();
+    }
+    Some(Err("The front matter is not closed".to_string()))
+}
+
+fn parse_yaml(source: &str) -> Result<FrontMatter, String> {
+    let documents = YamlLoader::load_from_str(source)
+        .map_err(|error| format!("Cannot read the YAML front matter: {error}"))?;
+    match documents.into_iter().next().map(yaml_to_json) {
+        Some(JsonValue::Object(values)) => Ok(FrontMatter(values)),
+        None | Some(JsonValue::Null) => Ok(FrontMatter(JsonMap::new())),
+        Some(_) => Err("The YAML front matter is not a mapping".to_string()),
+    }
+}
+
+fn parse_toml(source: &str) -> Result<FrontMatter, String> {
+    let table = source
+        .parse::<toml::Table>()
+        .map_err(|error| format!("Cannot read the TOML front matter: {error}"))?;
+    match toml_to_json(toml::Value::Table(table)) {
+        JsonValue::Object(values) => Ok(FrontMatter(values)),
+        _ => unreachable!("tables convert to objects"),
+    }
+}
+
+fn yaml_to_json(value: Yaml) -> JsonValue {
+    match value {
+        Yaml::String(value) => JsonValue::String(value),
+        Yaml::Integer(value) => JsonValue::from(value),
+        Yaml::Real(value) => value
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map_or(JsonValue::String(value), JsonValue::Number),
+        Yaml::Boolean(value) => JsonValue::Bool(value),
+        Yaml::Array(values) => JsonValue::Array(values.into_iter().map(yaml_to_json).collect()),
+        Yaml::Hash(values) => JsonValue::Object(
+            values
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    let key = match key {
+                        Yaml::String(key) => key,
+                        Yaml::Integer(key) => key.to_string(),
+                        _ => return None,
+                    };
+                    Some((key, yaml_to_json(value)))
+                })
+                .collect(),
+        ),
+        Yaml::Alias(_) | Yaml::Null | Yaml::BadValue => JsonValue::Null,
+    }
+}
+
+fn toml_to_json(value: toml::Value) -> JsonValue {
+    match value {
+        toml::Value::String(value) => JsonValue::String(value),
+        toml::Value::Integer(value) => JsonValue::from(value),
+        toml::Value::Float(value) => serde_json::Number::from_f64(value)
+            .map(JsonValue::Number)
+            .unwrap_or_default(),
+        toml::Value::Boolean(value) => JsonValue::Bool(value),
+        toml::Value::Datetime(value) => JsonValue::String(value.to_string()),
+        toml::Value::Array(values) => {
+            JsonValue::Array(values.into_iter().map(toml_to_json).collect())
+        }
+        toml::Value::Table(values) => JsonValue::Object(
+            values
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Reads the date formats Hugo and Jekyll accept. Dates without an offset are
+/// taken as UTC.
+fn parse_date(value: &str) -> Option<DateTimeUtc> {
+    let value = value.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.to_utc());
+    }
+    for format in ["%Y-%m-%d %H:%M:%S%.f %z", "%Y-%m-%d %H:%M %z"] {
+        if let Ok(date) = DateTime::parse_from_str(value, format) {
+            return Some(date.to_utc());
+        }
+    }
+    for format in [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+    ] {
+        if let Ok(date) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(date.and_utc());
+        }
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .map(|date| date.and_time(Default::default()).and_utc())
+}
+
+/// Front matter images are often written relative to the site root without
+/// a leading slash, so those are tried from the root as well.
+fn resolve_reference(files: &ImportFiles, from: &str, reference: &str) -> Option<String> {
+    files
+        .resolve(from, reference)
+        .or_else(|| files.resolve(from, &format!("/{}", reference.trim_start_matches("./"))))
+}
+
+fn image_references(content: &str) -> Vec<(Range<usize>, String)> {
+    IMAGE_REFERENCE
+        .captures_iter(content)
+        .filter_map(|captures| {
+            let source = captures.iter().skip(1).flatten().next()?;
+            Some((source.range(), source.as_str().to_string()))
+        })
+        .collect()
+}
+
+/// Replaces every image source for which `replacement` returns a URL.
+fn rewrite_image_references(content: &str, replacement: impl Fn(&str) -> Option<String>) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut copied = 0;
+    for (range, reference) in image_references(content) {
+        if let Some(url) = replacement(&reference) {
+            result.push_str(&content[copied..range.start]);
+            result.push_str(&url);
+            copied = range.end;
+        }
+    }
+    result.push_str(&content[copied..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use sea_orm::{
+        ActiveModelTrait, ConnectionTrait, Database, DatabaseBackend, DatabaseConnection,
+        EntityTrait, PaginatorTrait, Schema, Set,
+    };
+
+    use crate::{
+        config::ApplicationConfiguration,
+        entity::{
+            attachment, category, job,
+            post::{self, PostKind, PostStatus},
+            post_category, post_revision, post_tag, storage_engine, tag,
+        },
+        service::{
+            import::ImportFiles, scheduler::Job, site_settings::SiteSettingsService,
+            storage::StorageService, taxonomy::TaxonomyService, theme::ThemeService,
+        },
+    };
+
+    use super::{MarkdownImportService, parse_post};
+
+    #[test]
+    fn reads_hugo_and_jekyll_front_matter() {
+        let now = Utc::now();
+        let hugo = parse_post(
+            "content/posts/hello-world/index.md",
+            b"---\ntitle: \"Hello, world\"\ndate: 2024-01-31T08:00:00+08:00\nlastmod: 2024-02-01\ntags: [Rust, Web]\ncategories: Engineering\ndraft: true\ndescription: First post\nimages:\n  - cover.png\n---\n\nBody ![cover](cover.png)\n",
+            now,
+            false,
+        )
+        .unwrap()
+        .0;
+        assert_eq!(hugo.name, "hello-world");
+        assert_eq!(hugo.title, "Hello, world");
+        assert_eq!(
+            hugo.created_at,
+            Utc.with_ymd_and_hms(2024, 1, 31, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            hugo.updated_at,
+            Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(hugo.tags, ["Rust", "Web"]);
+        assert_eq!(hugo.categories, ["Engineering"]);
+        assert_eq!(hugo.status, PostStatus::Draft);
+        assert_eq!(hugo.description.as_deref(), Some("First post"));
+        assert_eq!(hugo.illustration.as_deref(), Some("cover.png"));
+        assert_eq!(hugo.content, "Body ![cover](cover.png)\n");
+
+        let toml = parse_post(
+            "content/about.md",
+            b"+++\ntitle = \"About\"\nslug = \"about-me\"\ndate = 2023-05-01T10:00:00Z\ntype = \"page\"\n+++\nAbout me\n",
+            now,
+            false,
+        )
+        .unwrap()
+        .0;
+        assert_eq!(toml.name, "about-me");
+        assert_eq!(toml.kind, PostKind::Page);
+        assert_eq!(toml.status, PostStatus::Published);
+        assert_eq!(
+            toml.created_at,
+            Utc.with_ymd_and_hms(2023, 5, 1, 10, 0, 0).unwrap()
+        );
+
+        let jekyll = parse_post(
+            "_posts/2022-03-04-old-notes.markdown",
+            b"---\nlayout: post\ntitle: Old notes\ncategories: [Notes]\n---\nNotes\n",
+            now,
+            false,
+        )
+        .unwrap()
+        .0;
+        assert_eq!(jekyll.name, "old-notes");
+        assert_eq!(jekyll.kind, PostKind::Post);
+        assert_eq!(
+            jekyll.created_at,
+            Utc.with_ymd_and_hms(2022, 3, 4, 0, 0, 0).unwrap()
+        );
+
+        let future = parse_post(
+            "content/posts/soon.md",
+            b"---\ntitle: Soon\ndate: 2999-01-01\n---\n",
+            now,
+            false,
+        )
+        .unwrap()
+        .0;
+        assert_eq!(future.status, PostStatus::Scheduled);
+        assert_eq!(future.publish_at, Some(future.created_at));
+
+        assert!(parse_post("README.md", b"# Readme\n", now, false).is_err());
+        assert!(
+            parse_post(
+                "content/posts/_index.md",
+                b"---\ntitle: Posts\n---\n",
+                now,
+                false
+            )
+            .is_err()
+        );
+    }
+
+    async fn database() -> DatabaseConnection {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        let schema = Schema::new(DatabaseBackend::Sqlite);
+        for statement in [
+            schema.create_table_from_entity(post::Entity),
+            schema.create_table_from_entity(tag::Entity),
+            schema.create_table_from_entity(category::Entity),
+            schema.create_table_from_entity(post_tag::Entity),
+            schema.create_table_from_entity(post_category::Entity),
+            schema.create_table_from_entity(post_revision::Entity),
+            schema.create_table_from_entity(storage_engine::Entity),
+            schema.create_table_from_entity(attachment::Entity),
+            schema.create_table_from_entity(job::Entity),
+        ] {
+            database.execute(&statement).await.unwrap();
+        }
+        storage_engine::ActiveModel {
+            name: Set("Local Storage".to_string()),
+            comments: Set(String::new()),
+            kind: Set("local".to_string()),
+            config_json: Set(None),
+            is_default: Set(true),
+            enabled: Set(true),
+            ..Default::default()
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+        job::ActiveModel {
+            name: Set(Job::PingSubscribers.name().to_string()),
+            ..Default::default()
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+        database
+    }
+
+    async fn ping_requested(database: &DatabaseConnection) -> bool {
+        job::Entity::find()
+            .one(database)
+            .await
+            .unwrap()
+            .unwrap()
+            .next_run_at
+            .is_some()
+    }
+
+    #[tokio::test]
+    async fn reports_a_dry_run_then_imports_posts_and_their_images() {
+        let temporary_directory = tempfile::tempdir().unwrap();
+        let config = ApplicationConfiguration::for_tests(temporary_directory.path());
+        let database = database().await;
+        let storage = StorageService::new(config.clone());
+        let theme_service = ThemeService::new(
+            database.clone(),
+            config,
+            SiteSettingsService::new(database.clone()),
+        );
+        let files = ImportFiles::from_iter([
+            (
+                "site/content/posts/first/index.md",
+                b"---\ntitle: First\ntags: [Rust]\nimages: [/images/cover.png]\n---\n![Diagram](diagram.png) and <img src=\"missing.png\">\n".to_vec(),
+            ),
+            ("site/content/posts/first/diagram.png", b"diagram".to_vec()),
+            ("site/static/images/cover.png", b"cover".to_vec()),
+            ("site/content/posts/broken.md", b"---\ntitle: [\n---\n".to_vec()),
+        ]);
+
+        let report =
+            MarkdownImportService::import(&database, &storage, &theme_service, &files, 1, false)
+                .await
+                .unwrap();
+        assert!(!report.applied);
+        assert_eq!(report.posts.len(), 1);
+        assert_eq!(report.posts[0].id, None);
+        assert_eq!(report.attachments.len(), 2);
+        assert!(report.attachments.iter().all(|file| file.url.is_none()));
+        assert_eq!(report.skipped[0].source, "site/content/posts/broken.md");
+        assert!(report.warnings[0].reason.contains("missing.png"));
+        assert_eq!(post::Entity::find().count(&database).await.unwrap(), 0);
+        assert!(!ping_requested(&database).await);
+
+        let report =
+            MarkdownImportService::import(&database, &storage, &theme_service, &files, 1, true)
+                .await
+                .unwrap();
+        let post = post::Entity::find_by_id(report.posts[0].id.unwrap())
+            .one(&database)
+            .await
+            .unwrap()
+            .unwrap();
+        let diagram_url = format!("/attachments/{:x}", md5::compute(b"diagram"));
+        let cover_url = format!("/attachments/{:x}", md5::compute(b"cover"));
+        assert!(ping_requested(&database).await);
+        assert_eq!(post.name, "first");
+        assert_eq!(
+            post.content,
+            format!("![Diagram]({diagram_url}) and <img src=\"missing.png\">\n")
+        );
+        assert_eq!(post.illustration, Some(cover_url));
+        assert_eq!(
+            TaxonomyService::terms_for_posts(&database, &[post.id])
+                .await
+                .unwrap()[&post.id]
+                .tags,
+            ["Rust"]
+        );
+        assert_eq!(
+            post_revision::Entity::find()
+                .count(&database)
+                .await
+                .unwrap(),
+            1
+        );
+
+        let report =
+            MarkdownImportService::import(&database, &storage, &theme_service, &files, 1, true)
+                .await
+                .unwrap();
+        assert!(report.posts.is_empty());
+        assert!(
+            report
+                .skipped
+                .iter()
+                .any(|issue| issue.reason.contains("already exists"))
+        );
+    }
+
+    #[tokio::test]
+    async fn leaves_nothing_behind_when_an_import_fails() {
+        let temporary_directory = tempfile::tempdir().unwrap();
+        let config = ApplicationConfiguration::for_tests(temporary_directory.path());
+        let database = database().await;
+        database
+            .execute_unprepared("DROP TABLE post_revisions")
+            .await
+            .unwrap();
+        let storage = StorageService::new(config.clone());
+        let theme_service = ThemeService::new(
+            database.clone(),
+            config,
+            SiteSettingsService::new(database.clone()),
+        );
+        let files = ImportFiles::from_iter([
+            (
+                "posts/first.md",
+                b"---\ntitle: First\n---\n![Diagram](diagram.png)\n".to_vec(),
+            ),
+            ("posts/diagram.png", b"diagram".to_vec()),
+        ]);
+
+        assert!(
+            MarkdownImportService::import(&database, &storage, &theme_service, &files, 1, true)
+                .await
+                .is_err()
+        );
+        assert_eq!(post::Entity::find().count(&database).await.unwrap(), 0);
+        assert_eq!(
+            attachment::Entity::find().count(&database).await.unwrap(),
+            0
+        );
+        let stored_files = std::fs::read_dir(temporary_directory.path().join("attachments/1"))
+            .map(|entries| entries.count())
+            .unwrap_or_default();
+        assert_eq!(stored_files, 0);
+    }
+}
@@ -1,7 +1,18 @@
+pub mod export;
+pub mod import;
 pub mod jwt;
+pub mod markdown_import;
+pub mod post;
+pub mod post_preview;
+pub mod post_render;
+pub mod post_revision;
+pub mod redirect;
 pub mod reloadable;
+pub mod scheduler;
+pub mod search;
 pub mod site_settings;
 pub mod storage;
 pub mod taxonomy;
 pub mod theme;
 pub mod user;
+pub mod wordpress_import;
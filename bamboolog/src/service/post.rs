@@ -0,0 +1,547 @@
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, Condition, ConnectionTrait, DbErr, EntityTrait,
+    Order, PaginatorTrait, QueryFilter, QueryOrder, prelude::DateTimeUtc, sea_query::Expr,
+};
+
+use crate::entity::{
+    post::{self, PostKind, PostStatus},
+    post_slug,
+};
+
+/// First path segments taken by other public routes. A page with one of these
+/// names could never be reached at `/{name}`.
+pub const RESERVED_PAGE_NAMES: [&str; 11] = [
+    "admin",
+    "api",
+    "archives",
+    "attachments",
+    "categories",
+    "index.xml",
+    "posts",
+    "search",
+    "sitemap.xml",
+    "static",
+    "tags",
+];
+
+/// Length of the `$2b$12$` prefix plus the 22-character salt of a bcrypt hash.
+const BCRYPT_SALT_END: usize = 29;
+
+pub struct PostService;
+
+impl PostService {
+    /// Matches posts that belong in public listings: home, archives, taxonomy
+    /// pages, RSS, and the sitemap.
+    pub fn listed_condition() -> Condition {
+        listed_condition_at(Utc::now())
+    }
+
+    pub fn listed_posts() -> sea_orm::Select<post::Entity> {
+        post::Entity::find()
+            .filter(Self::listed_condition())
+            .order_by_desc(post::Column::CreatedAt)
+    }
+
+    /// Listed posts in home page order: pinned posts first by weight, then
+    /// everything else newest first. Pagination is unaffected, so pinned posts
+    /// simply take the first places of page one.
+    pub fn home_posts() -> sea_orm::Select<post::Entity> {
+        let pinned_weight: Expr = Expr::case(
+            post::Column::Pinned.eq(true),
+            Expr::col(post::Column::Weight),
+        )
+        .finally(0)
+        .into();
+        post::Entity::find()
+            .filter(Self::listed_condition())
+            .order_by_desc(post::Column::Pinned)
+            .order_by(pinned_weight, Order::Desc)
+            .order_by_desc(post::Column::CreatedAt)
+    }
+
+    /// Listed posts marked as featured, heaviest first.
+    pub fn featured_posts() -> sea_orm::Select<post::Entity> {
+        post::Entity::find()
+            .filter(Self::listed_condition())
+            .filter(post::Column::Featured.eq(true))
+            .order_by_desc(post::Column::Weight)
+            .order_by_desc(post::Column::CreatedAt)
+    }
+
+    /// Matches posts and pages that public search may return. Unlisted posts
+    /// stay out, since finding them by search would defeat the point.
+    pub fn searchable_condition() -> Condition {
+        published_condition_at(Utc::now())
+    }
+
+    /// Matches posts and pages that may be opened through their public URL,
+    /// the query form of [`Self::is_publicly_readable`].
+    pub fn readable_condition() -> Condition {
+        readable_condition_at(Utc::now())
+    }
+
+    /// Published pages, in the order they should appear in navigation.
+    pub fn listed_pages() -> sea_orm::Select<post::Entity> {
+        post::Entity::find()
+            .filter(post::Column::Kind.eq(PostKind::Page))
+            .filter(published_condition_at(Utc::now()))
+            .order_by_asc(post::Column::Title)
+    }
+
+    pub fn public_path(post: &post::Model) -> String {
+        Self::path_of(post.kind, &post.name)
+    }
+
+    /// Public path of a post or page named `name`, which need not exist yet.
+    pub fn path_of(kind: PostKind, name: &str) -> String {
+        match kind {
+            PostKind::Post => format!("/posts/{name}"),
+            PostKind::Page => format!("/{name}"),
+        }
+    }
+
+    pub fn is_reserved_page_name(name: &str) -> bool {
+        RESERVED_PAGE_NAMES
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether a post may be opened through its public URL. Unlisted posts are
+    /// readable even though they never appear in listings.
+    pub fn is_publicly_readable(post: &post::Model, now: DateTimeUtc) -> bool {
+        match post.status {
+            PostStatus::Published | PostStatus::Unlisted => true,
+            PostStatus::Scheduled => post.publish_at.is_some_and(|publish_at| publish_at <= now),
+            PostStatus::Draft => false,
+        }
+    }
+
+    pub fn is_protected(post: &post::Model) -> bool {
+        post.password_hash.is_some()
+    }
+
+    pub fn hash_password(password: &str) -> Result<String, bcrypt::BcryptError> {
+        bcrypt::hash(password, bcrypt::DEFAULT_COST)
+    }
+
+    pub fn verify_password(post: &post::Model, password: &str) -> bool {
+        post.password_hash
+            .as_deref()
+            .is_some_and(|hash| bcrypt::verify(password, hash).unwrap_or(false))
+    }
+
+    /// The version, cost and salt of the post password hash, without the
+    /// digest. It changes whenever the password does, so access granted for an
+    /// old password can be told apart without exposing anything crackable.
+    pub fn password_fingerprint(post: &post::Model) -> Option<&str> {
+        post.password_hash
+            .as_deref()
+            .map(|hash| hash.get(..BCRYPT_SALT_END).unwrap_or(hash))
+    }
+
+    /// Whether a post other than `except` is called `name`.
+    pub async fn name_taken<C>(db: &C, name: &str, except: i32) -> Result<bool, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let count = post::Entity::find()
+            .filter(post::Column::Name.eq(name))
+            .filter(post::Column::Id.ne(except))
+            .count(db)
+            .await?;
+        Ok(count > 0)
+    }
+
+    /// Remembers the name a post had before a rename so its old URL keeps
+    /// working. The new name leaves every post's history, because it now
+    /// belongs to a live post.
+    pub async fn record_rename<C>(
+        db: &C,
+        post_id: i32,
+        previous_name: &str,
+        name: &str,
+    ) -> Result<(), DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if previous_name == name {
+            return Ok(());
+        }
+        post_slug::Entity::delete_many()
+            .filter(post_slug::Column::Name.is_in([previous_name, name]))
+            .exec(db)
+            .await?;
+        post_slug::ActiveModel {
+            post_id: ActiveValue::Set(post_id),
+            name: ActiveValue::Set(previous_name.to_string()),
+            created_at: ActiveValue::Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+        Ok(())
+    }
+
+    /// The post that used to be called `name`, if any.
+    pub async fn find_by_previous_name<C>(db: &C, name: &str) -> Result<Option<post::Model>, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let Some(slug) = post_slug::Entity::find()
+            .filter(post_slug::Column::Name.eq(name))
+            .one(db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        post::Entity::find_by_id(slug.post_id).one(db).await
+    }
+
+    pub async fn delete_slug_history<C>(db: &C, post_id: i32) -> Result<(), DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        post_slug::Entity::delete_many()
+            .filter(post_slug::Column::PostId.eq(post_id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    /// Moves scheduled posts whose `publish_at` has passed to published and
+    /// returns how many posts changed.
+    pub async fn publish_due_posts<C>(db: &C, now: DateTimeUtc) -> Result<u64, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let result = post::Entity::update_many()
+            .col_expr(post::Column::Status, Expr::value(PostStatus::Published))
+            .filter(post::Column::Status.eq(PostStatus::Scheduled))
+            .filter(post::Column::PublishAt.lte(now))
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected)
+    }
+}
+
+fn listed_condition_at(now: DateTimeUtc) -> Condition {
+    Condition::all()
+        .add(post::Column::Kind.eq(PostKind::Post))
+        .add(published_condition_at(now))
+}
+
+fn readable_condition_at(now: DateTimeUtc) -> Condition {
+    Condition::any()
+        .add(published_condition_at(now))
+        .add(post::Column::Status.eq(PostStatus::Unlisted))
+}
+
+fn published_condition_at(now: DateTimeUtc) -> Condition {
+    Condition::any()
+        .add(post::Column::Status.eq(PostStatus::Published))
+        .add(
+            Condition::all()
+                .add(post::Column::Status.eq(PostStatus::Scheduled))
+                .add(post::Column::PublishAt.lte(now)),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use sea_orm::{
+        ActiveModelTrait, ConnectionTrait, Database, DatabaseBackend, EntityTrait, QueryFilter,
+        QueryOrder, Schema, Set,
+    };
+
+    use crate::entity::{
+        post::{self, PostKind, PostStatus},
+        post_slug, user,
+    };
+
+    use super::{PostService, listed_condition_at, readable_condition_at};
+
+    #[test]
+    fn verifies_post_passwords_and_fingerprints_them_without_the_digest() {
+        let hash = bcrypt::hash("open sesame", 4).unwrap();
+        let post = post::Model {
+            id: 1,
+            name: "secret".to_string(),
+            title: "Secret".to_string(),
+            content: String::new(),
+            author: 1,
+            description: None,
+            illustration: None,
+            status: PostStatus::Published,
+            publish_at: None,
+            kind: PostKind::Post,
+            functions: post::PostFunctions::default(),
+            meta: post::PostMeta::default(),
+            pinned: false,
+            featured: false,
+            weight: 0,
+            password_hash: Some(hash.clone()),
+            created_at: Utc::now(),
+            updated_at: None,
+        };
+
+        assert!(PostService::is_protected(&post));
+        assert!(PostService::verify_password(&post, "open sesame"));
+        assert!(!PostService::verify_password(&post, "wrong"));
+        let fingerprint = PostService::password_fingerprint(&post).unwrap();
+        assert!(hash.starts_with(fingerprint));
+        assert_eq!(fingerprint.len() + 31, hash.len());
+    }
+
+    #[tokio::test]
+    async fn lists_published_and_due_scheduled_posts_only() {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        let schema = Schema::new(DatabaseBackend::Sqlite);
+        for statement in [
+            schema.create_table_from_entity(user::Entity),
+            schema.create_table_from_entity(post::Entity),
+        ] {
+            database.execute(&statement).await.unwrap();
+        }
+        let now = Utc::now();
+        for (id, kind, status, publish_at) in [
+            (1, PostKind::Post, PostStatus::Published, None),
+            (2, PostKind::Post, PostStatus::Draft, None),
+            (3, PostKind::Post, PostStatus::Unlisted, None),
+            (
+                4,
+                PostKind::Post,
+                PostStatus::Scheduled,
+                Some(now - Duration::hours(1)),
+            ),
+            (
+                5,
+                PostKind::Post,
+                PostStatus::Scheduled,
+                Some(now + Duration::hours(1)),
+            ),
+            (6, PostKind::Page, PostStatus::Published, None),
+        ] {
+            post::ActiveModel {
+                id: Set(id),
+                name: Set(format!("post-{id}")),
+                title: Set(format!("Post {id}")),
+                content: Set(String::new()),
+                author: Set(1),
+                status: Set(status),
+                publish_at: Set(publish_at),
+                kind: Set(kind),
+                ..Default::default()
+            }
+            .insert(&database)
+            .await
+            .unwrap();
+        }
+
+        let listed = post::Entity::find()
+            .filter(listed_condition_at(now))
+            .order_by_asc(post::Column::Id)
+            .all(&database)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|post| post.id)
+            .collect::<Vec<_>>();
+
+        assert_eq!(listed, [1, 4]);
+        let readable = post::Entity::find()
+            .filter(readable_condition_at(now))
+            .order_by_asc(post::Column::Id)
+            .all(&database)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|post| post.id)
+            .collect::<Vec<_>>();
+        assert_eq!(readable, [1, 3, 4, 6]);
+        let pages = PostService::listed_pages()
+            .all(&database)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|page| page.id)
+            .collect::<Vec<_>>();
+        assert_eq!(pages, [6]);
+    }
+
+    #[tokio::test]
+    async fn home_lists_pinned_posts_first_and_features_separately() {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        let schema = Schema::new(DatabaseBackend::Sqlite);
+        for statement in [
+            schema.create_table_from_entity(user::Entity),
+            schema.create_table_from_entity(post::Entity),
+        ] {
+            database.execute(&statement).await.unwrap();
+        }
+        let now = Utc::now();
+        for (id, pinned, featured, weight) in [
+            (1, false, false, 9),
+            (2, true, false, 1),
+            (3, false, true, 0),
+            (4, true, true, 5),
+            (5, false, true, 2),
+        ] {
+            post::ActiveModel {
+                id: Set(id),
+                name: Set(format!("post-{id}")),
+                title: Set(format!("Post {id}")),
+                content: Set(String::new()),
+                author: Set(1),
+                status: Set(PostStatus::Published),
+                kind: Set(PostKind::Post),
+                pinned: Set(pinned),
+                featured: Set(featured),
+                weight: Set(weight),
+                created_at: Set(now + Duration::minutes(id.into())),
+                ..Default::default()
+            }
+            .insert(&database)
+            .await
+            .unwrap();
+        }
+
+        let ids =
+            |posts: Vec<post::Model>| posts.into_iter().map(|post| post.id).collect::<Vec<_>>();
+        let home = PostService::home_posts().all(&database).await.unwrap();
+        assert_eq!(ids(home), [4, 2, 5, 3, 1]);
+        let featured = PostService::featured_posts().all(&database).await.unwrap();
+        assert_eq!(ids(featured), [4, 5, 3]);
+    }
+
+    #[test]
+    fn pages_live_at_top_level_urls_outside_reserved_routes() {
+        let mut page = post::Model {
+            id: 1,
+            name: "about".to_string(),
+            title: "About".to_string(),
+            content: String::new(),
+            author: 1,
+            description: None,
+            illustration: None,
+            status: PostStatus::Published,
+            publish_at: None,
+            kind: PostKind::Page,
+            functions: post::PostFunctions::default(),
+            meta: post::PostMeta::default(),
+            pinned: false,
+            featured: false,
+            weight: 0,
+            password_hash: None,
+            created_at: Utc::now(),
+            updated_at: None,
+        };
+        assert_eq!(PostService::public_path(&page), "/about");
+        page.kind = PostKind::Post;
+        assert_eq!(PostService::public_path(&page), "/posts/about");
+
+        assert!(PostService::is_reserved_page_name("Archives"));
+        assert!(PostService::is_reserved_page_name("sitemap.xml"));
+        assert!(!PostService::is_reserved_page_name("about"));
+    }
+
+    #[test]
+    fn unlisted_and_due_posts_are_publicly_readable() {
+        let now = Utc::now();
+        let post = |status, publish_at| post::Model {
+            id: 1,
+            name: "post".to_string(),
+            title: "Post".to_string(),
+            content: String::new(),
+            author: 1,
+            description: None,
+            illustration: None,
+            status,
+            publish_at,
+            kind: PostKind::Post,
+            functions: post::PostFunctions::default(),
+            meta: post::PostMeta::default(),
+            pinned: false,
+            featured: false,
+            weight: 0,
+            password_hash: None,
+            created_at: now,
+            updated_at: None,
+        };
+
+        assert!(PostService::is_publicly_readable(
+            &post(PostStatus::Unlisted, None),
+            now
+        ));
+        assert!(!PostService::is_publicly_readable(
+            &post(PostStatus::Draft, None),
+            now
+        ));
+        assert!(!PostService::is_publicly_readable(
+            &post(PostStatus::Scheduled, None),
+            now
+        ));
+        assert!(!PostService::is_publicly_readable(
+            &post(PostStatus::Scheduled, Some(now + Duration::minutes(5))),
+            now
+        ));
+        assert!(PostService::is_publicly_readable(
+            &post(PostStatus::Scheduled, Some(now - Duration::minutes(5))),
+            now
+        ));
+    }
+
+    #[tokio::test]
+    async fn resolves_previous_names_to_the_current_post() {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        let schema = Schema::new(DatabaseBackend::Sqlite);
+        for statement in [
+            schema.create_table_from_entity(post::Entity),
+            schema.create_table_from_entity(post_slug::Entity),
+        ] {
+            database.execute(&statement).await.unwrap();
+        }
+        let post = post::ActiveModel {
+            name: Set("third".to_string()),
+            title: Set("Post".to_string()),
+            content: Set(String::new()),
+            author: Set(1),
+            ..Default::default()
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+
+        PostService::record_rename(&database, post.id, "first", "second")
+            .await
+            .unwrap();
+        PostService::record_rename(&database, post.id, "second", "third")
+            .await
+            .unwrap();
+
+        for name in ["first", "second"] {
+            let found = PostService::find_by_previous_name(&database, name)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(found.id, post.id);
+        }
+        // Renaming back to an old name removes it from the history.
+        PostService::record_rename(&database, post.id, "third", "first")
+            .await
+            .unwrap();
+        assert!(
+            PostService::find_by_previous_name(&database, "first")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            PostService::find_by_previous_name(&database, "third")
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use chrono::{Duration, Utc};
+use rand::distr::{Alphanumeric, SampleString};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter,
+    QueryOrder, prelude::DateTimeUtc,
+};
+
+use crate::entity::post_preview;
+
+/// How long a preview link works unless the author asks otherwise.
+pub const DEFAULT_PREVIEW_LIFETIME: Duration = Duration::days(7);
+/// Preview links are meant for a review round, not as a permanent back door.
+pub const MAX_PREVIEW_LIFETIME: Duration = Duration::days(30);
+
+const PREVIEW_TOKEN_LENGTH: usize = 32;
+
+pub struct PostPreviewService;
+
+impl PostPreviewService {
+    /// Issues a new preview link for a post. Expired links of the same post
+    /// are cleared at the same time.
+    pub async fn issue<C>(
+        db: &C,
+        post_id: i32,
+        created_by: i32,
+        lifetime: Duration,
+    ) -> Result<post_preview::Model, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let now = Utc::now();
+        post_preview::Entity::delete_many()
+            .filter(post_preview::Column::PostId.eq(post_id))
+            .filter(post_preview::Column::ExpiresAt.lte(now))
+            .exec(db)
+            .await?;
+        // Generated up front: the thread-local generator must not be held
+        // across the insert.
+        let token = Alphanumeric.sample_string(&mut rand::rng(), PREVIEW_TOKEN_LENGTH);
+        post_preview::ActiveModel {
+            post_id: ActiveValue::Set(post_id),
+            token: ActiveValue::Set(token),
+            expires_at: ActiveValue::Set(now + lifetime),
+            created_by: ActiveValue::Set(created_by),
+            created_at: ActiveValue::Set(now),
+            ..Default::default()
+        }
+        .insert(db)
+        .await
+    }
+
+    /// Preview links of a post, newest first, including expired ones.
+    pub async fn list<C>(db: &C, post_id: i32) -> Result<Vec<post_preview::Model>, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        post_preview::Entity::find()
+            .filter(post_preview::Column::PostId.eq(post_id))
+            .order_by_desc(post_preview::Column::Id)
+            .all(db)
+            .await
+    }
+
+    /// Revokes one preview link and reports whether it existed.
+    pub async fn revoke<C>(db: &C, post_id: i32, preview_id: i32) -> Result<bool, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let result = post_preview::Entity::delete_many()
+            .filter(post_preview::Column::Id.eq(preview_id))
+            .filter(post_preview::Column::PostId.eq(post_id))
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    /// Whether `token` is a live preview link for the post.
+    pub async fn is_valid<C>(
+        db: &C,
+        post_id: i32,
+        token: &str,
+        now: DateTimeUtc,
+    ) -> Result<bool, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if token.is_empty() {
+            return Ok(false);
+        }
+        Ok(post_preview::Entity::find()
+            .filter(post_preview::Column::PostId.eq(post_id))
+            .filter(post_preview::Column::Token.eq(token))
+            .filter(post_preview::Column::ExpiresAt.gt(now))
+            .one(db)
+            .await?
+            .is_some())
+    }
+
+    pub async fn delete_post_previews<C>(db: &C, post_id: i32) -> Result<(), DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        post_preview::Entity::delete_many()
+            .filter(post_preview::Column::PostId.eq(post_id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+}
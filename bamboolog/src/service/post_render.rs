@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DbErr, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder, Set,
+    sea_query::OnConflict,
+};
+
+use crate::{
+    config::SiteSettings,
+    entity::{
+        post,
+        post_render::{self, RenderedToc},
+    },
+    service::theme::ThemeService,
+    utils::{html_to_text, text_length},
+};
+
+/// Bump whenever a change to the Markdown pipeline changes its output, so
+/// stored renders are rebuilt on their next view.
+pub const RENDERER_VERSION: u32 = 2;
+const EXCERPT_CHARACTERS: usize = 240;
+const REBUILD_BATCH_SIZE: u64 = 100;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PostRenderError {
+    #[error("Failed to render Markdown: {0}")]
+    Markdown(markdown::message::Message),
+    #[error(transparent)]
+    Database(#[from] DbErr),
+}
+
+pub struct PostRenderService;
+
+impl PostRenderService {
+    /// Stored renders of `posts` keyed by post id. Missing and stale renders
+    /// are rendered again and stored; failing to store one is only logged, so
+    /// pages still work before the table has been synced.
+    pub async fn rendered<C>(
+        db: &C,
+        theme_service: &ThemeService,
+        site: &SiteSettings,
+        posts: &[post::Model],
+    ) -> Result<HashMap<i32, post_render::Model>, PostRenderError>
+    where
+        C: ConnectionTrait,
+    {
+        let theme = theme_service.markdown_fingerprint().await;
+        let stored = post_render::Entity::find()
+            .filter(post_render::Column::PostId.is_in(posts.iter().map(|post| post.id)))
+            .all(db)
+            .await
+            .inspect_err(|error| tracing::warn!("Failed to read stored post renders: {error}"))
+            .unwrap_or_default();
+        let mut stored = stored
+            .into_iter()
+            .map(|render| (render.post_id, render))
+            .collect::<HashMap<_, _>>();
+
+        let mut renders = HashMap::with_capacity(posts.len());
+        for post in posts {
+            let render_key = render_key(post, site, &theme);
+            let render = match stored.remove(&post.id) {
+                Some(render) if render.render_key == render_key => render,
+                _ => {
+                    let render = render(theme_service, site, post, render_key).await?;
+                    if let Err(error) = store(db, &render).await {
+                        tracing::warn!("Failed to store the render of post {}: {error}", post.id);
+                    }
+                    render
+                }
+            };
+            renders.insert(post.id, render);
+        }
+        Ok(renders)
+    }
+
+    pub async fn rendered_one<C>(
+        db: &C,
+        theme_service: &ThemeService,
+        site: &SiteSettings,
+        post: &post::Model,
+    ) -> Result<post_render::Model, PostRenderError>
+    where
+        C: ConnectionTrait,
+    {
+        Ok(
+            Self::rendered(db, theme_service, site, std::slice::from_ref(post))
+                .await?
+                .remove(&post.id)
+                .expect("every requested post is rendered"),
+        )
+    }
+
+    /// Drops the stored render of a post, for callers that change its content.
+    pub async fn invalidate<C>(db: &C, post_id: i32) -> Result<(), DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        post_render::Entity::delete_many()
+            .filter(post_render::Column::PostId.eq(post_id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    /// Renders and stores every post and page, returning how many there were.
+    pub async fn rebuild_all<C>(
+        db: &C,
+        theme_service: &ThemeService,
+        site: &SiteSettings,
+    ) -> Result<u64, PostRenderError>
+    where
+        C: ConnectionTrait,
+    {
+        let theme = theme_service.markdown_fingerprint().await;
+        let mut pages = post::Entity::find()
+            .order_by_asc(post::Column::Id)
+            .paginate(db, REBUILD_BATCH_SIZE);
+        let mut rebuilt = 0;
+        while let Some(posts) = pages.fetch_and_next().await? {
+            for post in posts {
+                let render_key = render_key(&post, site, &theme);
+                store(db, &render(theme_service, site, &post, render_key).await?).await?;
+                rebuilt += 1;
+            }
+        }
+        Ok(rebuilt)
+    }
+}
+
+/// Changes whenever the content, the renderer, the active theme's shortcodes,
+/// or a setting that affects rendering changes.
+fn render_key(post: &post::Model, site: &SiteSettings, theme: &str) -> String {
+    let options = site.render_options();
+    format!(
+        "{:x}",
+        md5::compute(format!(
+            "{RENDERER_VERSION}\n{}\n{theme}\n{}",
+            options.highlight_code, post.content
+        ))
+    )
+}
+
+async fn render(
+    theme_service: &ThemeService,
+    site: &SiteSettings,
+    post: &post::Model,
+    render_key: String,
+) -> Result<post_render::Model, PostRenderError> {
+    let rendered = theme_service
+        .render_markdown(&post.content, site.render_options())
+        .await
+        .map_err(PostRenderError::Markdown)?;
+    let text = html_to_text(&rendered.html);
+    let length = text_length(&text);
+    Ok(post_render::Model {
+        post_id: post.id,
+        render_key,
+        excerpt: excerpt(&text, EXCERPT_CHARACTERS),
+        word_count: length.words as i64,
+        cjk_character_count: length.cjk_characters as i64,
+        html: rendered.html,
+        toc: RenderedToc(rendered.toc),
+        rendered_at: Utc::now(),
+    })
+}
+
+async fn store<C>(db: &C, render: &post_render::Model) -> Result<(), DbErr>
+where
+    C: ConnectionTrait,
+{
+    post_render::Entity::insert(post_render::ActiveModel {
+        post_id: Set(render.post_id),
+        render_key: Set(render.render_key.clone()),
+        html: Set(render.html.clone()),
+        excerpt: Set(render.excerpt.clone()),
+        word_count: Set(render.word_count),
+        cjk_character_count: Set(render.cjk_character_count),
+        toc: Set(render.toc.clone()),
+        rendered_at: Set(render.rendered_at),
+    })
+    .on_conflict(
+        OnConflict::column(post_render::Column::PostId)
+            .update_columns([
+                post_render::Column::RenderKey,
+                post_render::Column::Html,
+                post_render::Column::Excerpt,
+                post_render::Column::WordCount,
+                post_render::Column::CjkCharacterCount,
+                post_render::Column::Toc,
+                post_render::Column::RenderedAt,
+            ])
+            .to_owned(),
+    )
+    .exec(db)
+    .await?;
+    Ok(())
+}
+
+fn excerpt(text: &str, max_characters: usize) -> String {
+    let mut result = text.chars().take(max_characters).collect::<String>();
+    if text.chars().count() > max_characters {
+        result.push_str("...");
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use sea_orm::{
+        ActiveModelTrait, ConnectionTrait, Database, DatabaseBackend, DatabaseConnection,
+        EntityTrait, IntoActiveModel, Schema, Set,
+    };
+
+    use crate::{
+        config::{ApplicationConfiguration, SiteSettings},
+        entity::{post, post_render, user},
+        service::{
+            reloadable::ReloadableService, site_settings::SiteSettingsService, theme::ThemeService,
+        },
+    };
+
+    use super::PostRenderService;
+
+    async fn database() -> DatabaseConnection {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        let schema = Schema::new(DatabaseBackend::Sqlite);
+        for statement in [
+            schema.create_table_from_entity(user::Entity),
+            schema.create_table_from_entity(post::Entity),
+            schema.create_table_from_entity(post_render::Entity),
+        ] {
+            database.execute(&statement).await.unwrap();
+        }
+        database
+    }
+
+    #[tokio::test]
+    async fn stores_renders_and_replaces_stale_ones() {
+        let temporary_directory = tempfile::tempdir().unwrap();
+        let database = database().await;
+        let config = ApplicationConfiguration::for_tests(temporary_directory.path());
+        let theme_service = ThemeService::new(
+            database.clone(),
+            config,
+            SiteSettingsService::new(database.clone()),
+        );
+        let mut site = SiteSettings::default();
+        let post = post::ActiveModel {
+            name: Set("first".to_string()),
+            title: Set("First".to_string()),
+            content: Set("# Hello\n\nSome *rendered* words &amp; more.".to_string()),
+            author: Set(1),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+
+        let render = PostRenderService::rendered_one(&database, &theme_service, &site, &post)
+            .await
+            .unwrap();
+        assert!(render.html.contains("<em>rendered</em>"));
+        assert_eq!(render.excerpt, "Hello Some rendered words & more.");
+        assert_eq!(render.word_count, 6);
+        assert_eq!(render.toc.0[0].id, "user-content-hello");
+        let stored = post_render::Entity::find_by_id(post.id)
+            .one(&database)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored, render);
+
+        let mut edited = post.clone().into_active_model();
+        edited.content = Set("Changed".to_string());
+        let edited = edited.update(&database).await.unwrap();
+        let render = PostRenderService::rendered_one(&database, &theme_service, &site, &edited)
+            .await
+            .unwrap();
+        assert_eq!(render.excerpt, "Changed");
+
+        let mut edited = edited.into_active_model();
+        edited.content = Set("## 你好\n\n这是 **Rust** 博客。".to_string());
+        let edited = edited.update(&database).await.unwrap();
+        let render = PostRenderService::rendered_one(&database, &theme_service, &site, &edited)
+            .await
+            .unwrap();
+        assert_eq!(render.excerpt, "你好 这是 Rust 博客。");
+        assert_eq!((render.word_count, render.cjk_character_count), (7, 6));
+
+        site.code_highlight_theme = "InspiredGitHub".to_string();
+        let rehighlighted =
+            PostRenderService::rendered_one(&database, &theme_service, &site, &edited)
+                .await
+                .unwrap();
+        assert_ne!(rehighlighted.render_key, render.render_key);
+
+        PostRenderService::invalidate(&database, post.id)
+            .await
+            .unwrap();
+        assert!(
+            post_render::Entity::find_by_id(post.id)
+                .one(&database)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            PostRenderService::rebuild_all(&database, &theme_service, &site)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(
+            post_render::Entity::find_by_id(post.id)
+                .one(&database)
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[tokio::test]
+    async fn renders_again_when_a_shortcode_template_changes() {
+        let assets = tempfile::tempdir().unwrap();
+        let theme = assets.path().join("themes/installed/default");
+        std::fs::create_dir_all(theme.join("layouts/shortcodes")).unwrap();
+        std::fs::write(
+            theme.join("manifest.toml"),
+            "[[shortcodes]]\nname = \"note\"\ntemplate = \"shortcodes/note.html\"\n",
+        )
+        .unwrap();
+        for layout in ["home", "post", "archive", "terms", "taxonomy", "not-found"] {
+            std::fs::write(theme.join("layouts").join(layout), "").unwrap();
+        }
+        let template = theme.join("layouts/shortcodes/note.html");
+        std::fs::write(&template, "<aside>old</aside>").unwrap();
+        let database = database().await;
+        let theme_service = ThemeService::new(
+            database.clone(),
+            ApplicationConfiguration::for_tests(assets.path()),
+            SiteSettingsService::new(database.clone()),
+        );
+        theme_service.reload().await;
+        let site = SiteSettings::default();
+        let post = post::ActiveModel {
+            name: Set("note".to_string()),
+            title: Set("Note".to_string()),
+            content: Set("{{< note >}}".to_string()),
+            author: Set(1),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+        let render = PostRenderService::rendered_one(&database, &theme_service, &site, &post)
+            .await
+            .unwrap();
+        assert!(render.html.contains("old"));
+
+        std::fs::write(&template, "<aside>new</aside>").unwrap();
+        theme_service.reload().await;
+        let render = PostRenderService::rendered_one(&database, &theme_service, &site, &post)
+            .await
+            .unwrap();
+        assert!(render.html.contains("new"));
+    }
+}
@@ -0,0 +1,272 @@
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbErr, EntityTrait,
+    IntoActiveModel, QueryFilter, QueryOrder,
+};
+use similar::TextDiff;
+
+use crate::{
+    entity::{post, post_revision},
+    service::post::PostService,
+};
+
+pub struct PostRevisionService;
+
+impl PostRevisionService {
+    /// Records the saved state of a freshly created post.
+    pub async fn record_created<C>(
+        db: &C,
+        post: &post::Model,
+        editor: i32,
+    ) -> Result<post_revision::Model, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        insert_revision(db, post, editor).await
+    }
+
+    /// Records the state of a post after an update. Posts created before
+    /// revisions existed first get their previous state recorded, so the
+    /// update can still be undone. Saves that leave the snapshot unchanged,
+    /// such as tag-only edits, do not add a revision.
+    pub async fn record_updated<C>(
+        db: &C,
+        previous: &post::Model,
+        updated: &post::Model,
+        editor: i32,
+    ) -> Result<Option<post_revision::Model>, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let latest = match Self::latest(db, updated.id).await? {
+            Some(latest) => latest,
+            None => insert_revision(db, previous, previous.author).await?,
+        };
+        if same_snapshot(&latest, updated) {
+            return Ok(None);
+        }
+        insert_revision(db, updated, editor).await.map(Some)
+    }
+
+    pub async fn latest<C>(db: &C, post_id: i32) -> Result<Option<post_revision::Model>, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        post_revision::Entity::find()
+            .filter(post_revision::Column::PostId.eq(post_id))
+            .order_by_desc(post_revision::Column::Id)
+            .one(db)
+            .await
+    }
+
+    /// Makes an older revision the current version of its post. The restore
+    /// is itself recorded, so it can be undone like any other save.
+    pub async fn restore<C>(
+        db: &C,
+        post: post::Model,
+        revision: post_revision::Model,
+        editor: i32,
+    ) -> Result<post::Model, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let previous = post.clone();
+        let mut active_model = post.into_active_model();
+        active_model.title = ActiveValue::Set(revision.title);
+        active_model.name = ActiveValue::Set(revision.name);
+        active_model.content = ActiveValue::Set(revision.content);
+        active_model.description = ActiveValue::Set(revision.description);
+        active_model.illustration = ActiveValue::Set(revision.illustration);
+        active_model.updated_at = ActiveValue::Set(Some(Utc::now()));
+        let updated = active_model.update(db).await?;
+        Self::record_updated(db, &previous, &updated, editor).await?;
+        PostService::record_rename(db, updated.id, &previous.name, &updated.name).await?;
+        Ok(updated)
+    }
+
+    pub async fn delete_post_revisions<C>(db: &C, post_id: i32) -> Result<(), DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        post_revision::Entity::delete_many()
+            .filter(post_revision::Column::PostId.eq(post_id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    /// Unified diff between two revisions. Metadata fields are rendered as a
+    /// header above the content so renames and description edits show up too.
+    pub fn unified_diff(from: &post_revision::Model, to: &post_revision::Model) -> String {
+        let old = revision_document(from);
+        let new = revision_document(to);
+        TextDiff::from_lines(&old, &new)
+            .unified_diff()
+            .header(
+                &format!("revision {}", from.id),
+                &format!("revision {}", to.id),
+            )
+            .to_string()
+    }
+}
+
+async fn insert_revision<C>(
+    db: &C,
+    post: &post::Model,
+    editor: i32,
+) -> Result<post_revision::Model, DbErr>
+where
+    C: ConnectionTrait,
+{
+    post_revision::ActiveModel {
+        post_id: ActiveValue::Set(post.id),
+        title: ActiveValue::Set(post.title.clone()),
+        name: ActiveValue::Set(post.name.clone()),
+        content: ActiveValue::Set(post.content.clone()),
+        description: ActiveValue::Set(post.description.clone()),
+        illustration: ActiveValue::Set(post.illustration.clone()),
+        editor: ActiveValue::Set(editor),
+        created_at: ActiveValue::Set(Utc::now()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
+
+fn same_snapshot(revision: &post_revision::Model, post: &post::Model) -> bool {
+    revision.title == post.title
+        && revision.name == post.name
+        && revision.content == post.content
+        && revision.description == post.description
+        && revision.illustration == post.illustration
+}
+
+fn revision_document(revision: &post_revision::Model) -> String {
+    let mut document = format!(
+        "title: {}\nname: {}\ndescription: {}\nillustration: {}\n\n{}",
+        revision.title,
+        revision.name,
+        revision.description.as_deref().unwrap_or_default(),
+        revision.illustration.as_deref().unwrap_or_default(),
+        revision.content,
+    );
+    if !document.ends_with('\n') {
+        document.push('\n');
+    }
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use sea_orm::{
+        ActiveModelTrait, ActiveValue, ConnectionTrait, Database, DatabaseBackend,
+        DatabaseConnection, EntityTrait, IntoActiveModel, Schema,
+    };
+
+    use crate::entity::{post, post_revision, user};
+
+    use super::PostRevisionService;
+
+    async fn database_with_post() -> (DatabaseConnection, post::Model) {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        let schema = Schema::new(DatabaseBackend::Sqlite);
+        for statement in [
+            schema.create_table_from_entity(user::Entity),
+            schema.create_table_from_entity(post::Entity),
+            schema.create_table_from_entity(post_revision::Entity),
+        ] {
+            database.execute(&statement).await.unwrap();
+        }
+        let post = post::ActiveModel {
+            name: ActiveValue::Set("post".to_string()),
+            title: ActiveValue::Set("Original".to_string()),
+            content: ActiveValue::Set("first line\nsecond line\n".to_string()),
+            author: ActiveValue::Set(1),
+            created_at: ActiveValue::Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+        (database, post)
+    }
+
+    async fn update_content(
+        database: &DatabaseConnection,
+        post: &post::Model,
+        content: &str,
+    ) -> post::Model {
+        let mut active_model = post.clone().into_active_model();
+        active_model.content = ActiveValue::Set(content.to_string());
+        active_model.update(database).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn records_the_previous_state_of_posts_without_history() {
+        let (database, post) = database_with_post().await;
+        let updated = update_content(&database, &post, "first line\nchanged line\n").await;
+
+        PostRevisionService::record_updated(&database, &post, &updated, 2)
+            .await
+            .unwrap();
+
+        let revisions = post_revision::Entity::find().all(&database).await.unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].content, "first line\nsecond line\n");
+        assert_eq!(revisions[0].editor, 1);
+        assert_eq!(revisions[1].content, "first line\nchanged line\n");
+        assert_eq!(revisions[1].editor, 2);
+        assert_eq!(
+            PostRevisionService::unified_diff(&revisions[0], &revisions[1]),
+            "--- revision 1\n+++ revision 2\n@@ -4,4 +4,4 @@\n illustration: \n \n first line\n-second line\n+changed line\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn skips_saves_that_do_not_change_the_snapshot() {
+        let (database, post) = database_with_post().await;
+        PostRevisionService::record_created(&database, &post, 1)
+            .await
+            .unwrap();
+
+        let recorded = PostRevisionService::record_updated(&database, &post, &post, 1)
+            .await
+            .unwrap();
+
+        assert!(recorded.is_none());
+        assert_eq!(
+            post_revision::Entity::find()
+                .all(&database)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn restores_an_older_revision_as_a_new_revision() {
+        let (database, post) = database_with_post().await;
+        let original = PostRevisionService::record_created(&database, &post, 1)
+            .await
+            .unwrap();
+        let updated = update_content(&database, &post, "rewritten\n").await;
+        PostRevisionService::record_updated(&database, &post, &updated, 1)
+            .await
+            .unwrap();
+
+        let restored = PostRevisionService::restore(&database, updated, original, 2)
+            .await
+            .unwrap();
+
+        assert_eq!(restored.content, "first line\nsecond line\n");
+        let latest = PostRevisionService::latest(&database, post.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(latest.id, 3);
+        assert_eq!(latest.content, restored.content);
+        assert_eq!(latest.editor, 2);
+    }
+}
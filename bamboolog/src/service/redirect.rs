@@ -0,0 +1,462 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use chrono::Utc;
+use regex::Regex;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, ExprTrait, QueryFilter, QueryOrder,
+    prelude::DateTimeUtc, sea_query::Expr,
+};
+use tokio::{sync::RwLock, task::JoinHandle};
+
+use crate::{
+    entity::redirect::{self, RedirectMatch},
+    service::reloadable::ReloadableService,
+};
+
+pub const SUPPORTED_REDIRECT_STATUSES: [StatusCode; 3] = [
+    StatusCode::MOVED_PERMANENTLY,
+    StatusCode::FOUND,
+    StatusCode::GONE,
+];
+
+/// How often counted hits are written back to the `redirects` table.
+const HIT_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RedirectAction {
+    Redirect {
+        status: StatusCode,
+        location: String,
+    },
+    Gone,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RedirectRuleError {
+    #[error("Redirect status must be 301, 302 or 410, got {0}")]
+    UnsupportedStatus(i32),
+    #[error("Redirect source must be a path starting with `/`")]
+    InvalidSource,
+    #[error("Redirects with status {0} need a target")]
+    MissingTarget(i32),
+    #[error("Invalid redirect pattern: {0}")]
+    InvalidPattern(#[from] regex::Error),
+}
+
+/// A rule with its pattern compiled once, when the rules are loaded.
+struct CompiledRule {
+    rule: redirect::Model,
+    pattern: Option<Regex>,
+}
+
+/// The rules in the order they are tried: exact rules by source, then
+/// prefix rules longest first, then regex rules in creation order.
+#[derive(Default)]
+struct RedirectRules {
+    exact: HashMap<String, CompiledRule>,
+    fallback: Vec<CompiledRule>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PendingHits {
+    count: i64,
+    last_hit_at: DateTimeUtc,
+}
+
+/// Resolves redirect rules from an in-memory copy that is reloaded whenever
+/// the rules change. Hits are counted in memory and written back in batches,
+/// so a crawler probing missing paths never causes a write per request.
+#[derive(Clone)]
+pub struct RedirectService {
+    rules: Arc<RwLock<RedirectRules>>,
+    hits: Arc<Mutex<HashMap<i32, PendingHits>>>,
+    dep_db: DatabaseConnection,
+}
+
+impl RedirectService {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self {
+            rules: Arc::new(RwLock::new(RedirectRules::default())),
+            hits: Arc::new(Mutex::new(HashMap::new())),
+            dep_db: db,
+        }
+    }
+
+    pub fn validate(
+        match_kind: RedirectMatch,
+        source: &str,
+        target: Option<&str>,
+        status_code: i32,
+    ) -> Result<(), RedirectRuleError> {
+        let status = u16::try_from(status_code)
+            .ok()
+            .and_then(|status| StatusCode::from_u16(status).ok())
+            .filter(|status| SUPPORTED_REDIRECT_STATUSES.contains(status))
+            .ok_or(RedirectRuleError::UnsupportedStatus(status_code))?;
+        match match_kind {
+            RedirectMatch::Regex => {
+                Regex::new(source)?;
+            }
+            RedirectMatch::Exact | RedirectMatch::Prefix if !source.starts_with('/') => {
+                return Err(RedirectRuleError::InvalidSource);
+            }
+            RedirectMatch::Exact | RedirectMatch::Prefix => {}
+        }
+        if status != StatusCode::GONE && target.is_none_or(|target| target.trim().is_empty()) {
+            return Err(RedirectRuleError::MissingTarget(status_code));
+        }
+        Ok(())
+    }
+
+    /// Finds the rule for a path that would otherwise be a 404 and counts the
+    /// hit. Exact rules win over prefix rules, the longest prefix wins, and
+    /// regex rules are tried last in creation order.
+    pub async fn resolve(&self, path: &str) -> Option<RedirectAction> {
+        let (id, action) = {
+            let rules = self.rules.read().await;
+            rules
+                .exact
+                .get(path)
+                .into_iter()
+                .chain(&rules.fallback)
+                .find_map(|compiled| Some((compiled.rule.id, compiled.apply(path)?)))?
+        };
+
+        let now = Utc::now();
+        let mut hits = self.hits.lock().unwrap_or_else(|error| error.into_inner());
+        hits.entry(id)
+            .and_modify(|pending| {
+                pending.count += 1;
+                pending.last_hit_at = now;
+            })
+            .or_insert(PendingHits {
+                count: 1,
+                last_hit_at: now,
+            });
+        Some(action)
+    }
+
+    /// Writes the hits counted since the last flush. Counts that could not be
+    /// written are kept for the next attempt.
+    pub async fn flush_hits(&self) -> Result<(), DbErr> {
+        let pending =
+            std::mem::take(&mut *self.hits.lock().unwrap_or_else(|error| error.into_inner()));
+        let mut pending = pending.into_iter();
+        while let Some((id, hits)) = pending.next() {
+            let result = redirect::Entity::update_many()
+                .col_expr(
+                    redirect::Column::Hits,
+                    Expr::col(redirect::Column::Hits).add(hits.count),
+                )
+                .col_expr(redirect::Column::LastHitAt, Expr::value(hits.last_hit_at))
+                .filter(redirect::Column::Id.eq(id))
+                .exec(&self.dep_db)
+                .await;
+            if let Err(error) = result {
+                let mut unwritten = self.hits.lock().unwrap_or_else(|error| error.into_inner());
+                for (id, hits) in std::iter::once((id, hits)).chain(pending) {
+                    unwritten
+                        .entry(id)
+                        .and_modify(|newer| newer.count += hits.count)
+                        .or_insert(hits);
+                }
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes counted hits every [`HIT_FLUSH_INTERVAL`].
+    pub fn start(&self) -> JoinHandle<()> {
+        let service = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HIT_FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(error) = service.flush_hits().await {
+                    tracing::error!("Failed to record redirect hits: {error}");
+                }
+            }
+        })
+    }
+
+    async fn load_rules(&self) -> Result<RedirectRules, DbErr> {
+        let mut rules = RedirectRules::default();
+        for rule in redirect::Entity::find()
+            .order_by_asc(redirect::Column::Id)
+            .all(&self.dep_db)
+            .await?
+        {
+            let Some(compiled) = CompiledRule::new(rule) else {
+                continue;
+            };
+            match compiled.rule.match_kind {
+                RedirectMatch::Exact => {
+                    rules
+                        .exact
+                        .entry(compiled.rule.source.clone())
+                        .or_insert(compiled);
+                }
+                _ => rules.fallback.push(compiled),
+            }
+        }
+        rules
+            .fallback
+            .sort_by_key(|compiled| match compiled.rule.match_kind {
+                RedirectMatch::Prefix => (0, usize::MAX - compiled.rule.source.len()),
+                _ => (1, 0),
+            });
+        Ok(rules)
+    }
+}
+
+#[async_trait]
+impl ReloadableService for RedirectService {
+    async fn reload(&self) {
+        match self.load_rules().await {
+            Ok(rules) => *self.rules.write().await = rules,
+            Err(error) => tracing::error!("Failed to load redirect rules: {error}"),
+        }
+    }
+}
+
+impl CompiledRule {
+    /// `None` for a regex rule whose pattern no longer compiles. Patterns are
+    /// anchored, so they match whole paths rather than any substring.
+    fn new(rule: redirect::Model) -> Option<Self> {
+        let pattern = match rule.match_kind {
+            RedirectMatch::Regex => Some(
+                Regex::new(&format!("^(?:{})$", rule.source))
+                    .inspect_err(|error| {
+                        tracing::warn!(
+                            "Skipping redirect {} with invalid pattern: {error}",
+                            rule.id
+                        )
+                    })
+                    .ok()?,
+            ),
+            RedirectMatch::Exact | RedirectMatch::Prefix => None,
+        };
+        Some(Self { rule, pattern })
+    }
+
+    fn apply(&self, path: &str) -> Option<RedirectAction> {
+        let rule = &self.rule;
+        let target = rule.target.as_deref().unwrap_or_default();
+        let location = match (&rule.match_kind, &self.pattern) {
+            (RedirectMatch::Exact, _) => (path == rule.source).then(|| target.to_string())?,
+            (RedirectMatch::Prefix, _) => {
+                format!("{target}{}", path.strip_prefix(&rule.source)?)
+            }
+            (RedirectMatch::Regex, pattern) => {
+                let captures = pattern.as_ref()?.captures(path)?;
+                let mut location = String::new();
+                captures.expand(target, &mut location);
+                location
+            }
+        };
+
+        match StatusCode::from_u16(u16::try_from(rule.status_code).ok()?).ok()? {
+            StatusCode::GONE => Some(RedirectAction::Gone),
+            status if SUPPORTED_REDIRECT_STATUSES.contains(&status) => {
+                Some(RedirectAction::Redirect { status, location })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use chrono::Utc;
+    use sea_orm::{
+        ActiveModelTrait, ConnectionTrait, Database, DatabaseBackend, EntityTrait, Schema, Set,
+    };
+
+    use crate::entity::redirect::{self, RedirectMatch};
+
+    use crate::service::reloadable::ReloadableService;
+
+    use super::{CompiledRule, RedirectAction, RedirectRuleError, RedirectService};
+
+    fn rule(
+        match_kind: RedirectMatch,
+        source: &str,
+        target: &str,
+        status_code: i32,
+    ) -> CompiledRule {
+        CompiledRule::new(redirect::Model {
+            id: 1,
+            source: source.to_string(),
+            match_kind,
+            target: Some(target.to_string()),
+            status_code,
+            hits: 0,
+            last_hit_at: None,
+            created_at: Utc::now(),
+        })
+        .unwrap()
+    }
+
+    fn moved(location: &str) -> Option<RedirectAction> {
+        Some(RedirectAction::Redirect {
+            status: StatusCode::MOVED_PERMANENTLY,
+            location: location.to_string(),
+        })
+    }
+
+    #[test]
+    fn applies_each_match_kind() {
+        let exact = rule(RedirectMatch::Exact, "/about.html", "/about", 301);
+        assert_eq!(exact.apply("/about.html"), moved("/about"));
+        assert_eq!(exact.apply("/about.html/more"), None);
+
+        let prefix = rule(RedirectMatch::Prefix, "/blog/", "/posts/", 301);
+        assert_eq!(prefix.apply("/blog/old-thing"), moved("/posts/old-thing"));
+        assert_eq!(prefix.apply("/archive/old-thing"), None);
+
+        let pattern = rule(
+            RedirectMatch::Regex,
+            r"^/\d{4}/\d{2}/(?<name>[^/]+)\.html$",
+            "/posts/${name}",
+            301,
+        );
+        assert_eq!(
+            pattern.apply("/2019/05/old-thing.html"),
+            moved("/posts/old-thing")
+        );
+
+        let unanchored = rule(RedirectMatch::Regex, r"/old/(\w+)", "/new/$1", 301);
+        assert_eq!(unanchored.apply("/old/thing"), moved("/new/thing"));
+        assert_eq!(unanchored.apply("/archive/old/thing"), None);
+        assert_eq!(unanchored.apply("/old/thing/more"), None);
+
+        let gone = rule(RedirectMatch::Exact, "/removed", "", 410);
+        assert_eq!(gone.apply("/removed"), Some(RedirectAction::Gone));
+    }
+
+    #[test]
+    fn validates_rules() {
+        assert!(RedirectService::validate(RedirectMatch::Exact, "/a", Some("/b"), 302).is_ok());
+        assert!(RedirectService::validate(RedirectMatch::Exact, "/a", None, 410).is_ok());
+        assert!(matches!(
+            RedirectService::validate(RedirectMatch::Exact, "/a", Some("/b"), 307),
+            Err(RedirectRuleError::UnsupportedStatus(307))
+        ));
+        assert!(matches!(
+            RedirectService::validate(RedirectMatch::Prefix, "a", Some("/b"), 301),
+            Err(RedirectRuleError::InvalidSource)
+        ));
+        assert!(matches!(
+            RedirectService::validate(RedirectMatch::Exact, "/a", None, 301),
+            Err(RedirectRuleError::MissingTarget(301))
+        ));
+        assert!(matches!(
+            RedirectService::validate(RedirectMatch::Regex, "(", Some("/b"), 301),
+            Err(RedirectRuleError::InvalidPattern(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn prefers_exact_then_longest_prefix_and_counts_hits() {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        let schema = Schema::new(DatabaseBackend::Sqlite);
+        database
+            .execute(&schema.create_table_from_entity(redirect::Entity))
+            .await
+            .unwrap();
+        for (match_kind, source, target) in [
+            (RedirectMatch::Regex, "^/blog/.*$", "/regex"),
+            (RedirectMatch::Prefix, "/blog/", "/short/"),
+            (RedirectMatch::Prefix, "/blog/2019/", "/long/"),
+            (RedirectMatch::Exact, "/blog/2019/special", "/exact"),
+        ] {
+            redirect::ActiveModel {
+                source: Set(source.to_string()),
+                match_kind: Set(match_kind),
+                target: Set(Some(target.to_string())),
+                status_code: Set(301),
+                created_at: Set(Utc::now()),
+                ..Default::default()
+            }
+            .insert(&database)
+            .await
+            .unwrap();
+        }
+
+        let redirects = RedirectService::new(database.clone());
+        redirects.reload().await;
+
+        let location = |resolved: Option<RedirectAction>| match resolved {
+            Some(RedirectAction::Redirect { location, .. }) => location,
+            other => panic!("unexpected resolution: {other:?}"),
+        };
+        assert_eq!(
+            location(redirects.resolve("/blog/2019/special").await),
+            "/exact"
+        );
+        assert_eq!(
+            location(redirects.resolve("/blog/2019/other").await),
+            "/long/other"
+        );
+        assert_eq!(
+            location(redirects.resolve("/blog/2019/other").await),
+            "/long/other"
+        );
+        assert_eq!(
+            location(redirects.resolve("/blog/other").await),
+            "/short/other"
+        );
+        assert!(redirects.resolve("/elsewhere").await.is_none());
+
+        let hits = || async {
+            redirect::Entity::find()
+                .all(&database)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|rule| rule.hits)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(hits().await, [0, 0, 0, 0]);
+        redirects.flush_hits().await.unwrap();
+        assert_eq!(hits().await, [0, 1, 2, 1]);
+        redirects.flush_hits().await.unwrap();
+        assert_eq!(hits().await, [0, 1, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn serves_rule_changes_after_a_reload() {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        let schema = Schema::new(DatabaseBackend::Sqlite);
+        database
+            .execute(&schema.create_table_from_entity(redirect::Entity))
+            .await
+            .unwrap();
+        let redirects = RedirectService::new(database.clone());
+        redirects.reload().await;
+
+        redirect::ActiveModel {
+            source: Set("/old".to_string()),
+            match_kind: Set(RedirectMatch::Exact),
+            target: Set(None),
+            status_code: Set(410),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+        assert_eq!(redirects.resolve("/old").await, None);
+
+        redirects.reload().await;
+        assert_eq!(redirects.resolve("/old").await, Some(RedirectAction::Gone));
+    }
+}
@@ -0,0 +1,566 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, prelude::DateTimeUtc, sea_query::Expr,
+};
+use serde_json::Value as JsonValue;
+use tokio::{sync::Notify, task::JoinHandle};
+
+use crate::{
+    config::{ApplicationConfiguration, SiteSettings},
+    entity::{
+        attachment, config_entry,
+        job::{self, JobStatus},
+        post, post_revision,
+    },
+    service::{
+        post::PostService, site_settings::SiteSettingsService, storage::StorageService,
+        theme::read_stored_theme_configs,
+    },
+};
+
+/// How often the scheduler looks for due jobs when nothing wakes it earlier.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Attachments younger than this are never collected, so uploads made for a
+/// post that has not been saved yet survive.
+const ATTACHMENT_GC_GRACE: chrono::Duration = chrono::Duration::days(7);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Job {
+    PublishScheduledPosts,
+    AttachmentGc,
+    PingSubscribers,
+}
+
+impl Job {
+    pub const ALL: [Job; 3] = [
+        Job::PublishScheduledPosts,
+        Job::AttachmentGc,
+        Job::PingSubscribers,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PublishScheduledPosts => "publish_scheduled_posts",
+            Self::AttachmentGc => "attachment_gc",
+            Self::PingSubscribers => "ping_subscribers",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|job| job.name() == name)
+    }
+
+    /// Periodic jobs run again after this interval; on-demand jobs return
+    /// `None` and only run when [`SchedulerService::request`] marks them due.
+    fn interval(self) -> Option<chrono::Duration> {
+        match self {
+            Self::PublishScheduledPosts => Some(chrono::Duration::minutes(1)),
+            Self::AttachmentGc => Some(chrono::Duration::days(1)),
+            Self::PingSubscribers => None,
+        }
+    }
+}
+
+enum JobOutcome {
+    Succeeded(String),
+    Skipped(String),
+}
+
+/// In-process job runner whose schedule and last results live in the `jobs`
+/// table, so they survive restarts and can be inspected through the API.
+#[derive(Clone)]
+pub struct SchedulerService {
+    wake: Arc<Notify>,
+    dep_db: DatabaseConnection,
+    dep_app_cfg: Arc<ApplicationConfiguration>,
+    dep_site_settings: SiteSettingsService,
+    dep_storage: StorageService,
+}
+
+impl SchedulerService {
+    pub fn new(
+        db: DatabaseConnection,
+        app_cfg: Arc<ApplicationConfiguration>,
+        site_settings: SiteSettingsService,
+        storage: StorageService,
+    ) -> Self {
+        Self {
+            wake: Arc::new(Notify::new()),
+            dep_db: db,
+            dep_app_cfg: app_cfg,
+            dep_site_settings: site_settings,
+            dep_storage: storage,
+        }
+    }
+
+    pub fn start(&self) -> JoinHandle<()> {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            if let Err(error) = scheduler.seed_jobs().await {
+                tracing::error!("Failed to prepare scheduled jobs: {error}");
+            }
+            loop {
+                if let Err(error) = scheduler.run_due_jobs().await {
+                    tracing::error!("Failed to run scheduled jobs: {error}");
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                    _ = scheduler.wake.notified() => {}
+                }
+            }
+        })
+    }
+
+    /// Runs due jobs soon instead of waiting for the next poll.
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    /// Marks a job as due. It accepts a transaction, so a content change and
+    /// the jobs that follow from it are committed together.
+    pub async fn request<C>(db: &C, job: Job) -> Result<(), DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        job::Entity::update_many()
+            .col_expr(job::Column::NextRunAt, Expr::value(Utc::now()))
+            .filter(job::Column::Name.eq(job.name()))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn list_jobs(&self) -> Result<Vec<job::Model>, DbErr> {
+        job::Entity::find()
+            .order_by_asc(job::Column::Name)
+            .all(&self.dep_db)
+            .await
+    }
+
+    /// Creates missing job rows and releases jobs left running by a previous
+    /// process that stopped mid-run.
+    pub async fn seed_jobs(&self) -> Result<(), DbErr> {
+        let now = Utc::now();
+        for job in Job::ALL {
+            let existing = job::Entity::find()
+                .filter(job::Column::Name.eq(job.name()))
+                .one(&self.dep_db)
+                .await?;
+            match existing {
+                None => {
+                    job::ActiveModel {
+                        name: ActiveValue::Set(job.name().to_string()),
+                        status: ActiveValue::Set(JobStatus::Idle),
+                        next_run_at: ActiveValue::Set(job.interval().map(|_| now)),
+                        ..Default::default()
+                    }
+                    .insert(&self.dep_db)
+                    .await?;
+                }
+                Some(existing) if existing.status == JobStatus::Running => {
+                    let mut active_model = existing.into_active_model();
+                    active_model.status = ActiveValue::Set(JobStatus::Idle);
+                    active_model.next_run_at = ActiveValue::Set(Some(now));
+                    active_model.update(&self.dep_db).await?;
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn run_due_jobs(&self) -> Result<(), DbErr> {
+        let due = job::Entity::find()
+            .filter(job::Column::NextRunAt.lte(Utc::now()))
+            .filter(job::Column::Status.ne(JobStatus::Running))
+            .order_by_asc(job::Column::NextRunAt)
+            .all(&self.dep_db)
+            .await?;
+
+        for row in due {
+            let Some(job) = Job::from_name(&row.name) else {
+                tracing::warn!("Skipping unknown job `{}`", row.name);
+                continue;
+            };
+            self.run_job(job, row).await?;
+        }
+        Ok(())
+    }
+
+    async fn run_job(&self, job: Job, row: job::Model) -> Result<(), DbErr> {
+        let started_at = Utc::now();
+        let mut active_model = row.into_active_model();
+        active_model.status = ActiveValue::Set(JobStatus::Running);
+        active_model.last_started_at = ActiveValue::Set(Some(started_at));
+        // Cleared while running, so a request made during the run is kept.
+        active_model.next_run_at = ActiveValue::Set(None);
+        let row = active_model.update(&self.dep_db).await?;
+
+        let outcome = self.execute(job, started_at).await;
+        let finished_at = Utc::now();
+        let (status, message) = match outcome {
+            Ok(JobOutcome::Succeeded(message)) => (JobStatus::Succeeded, message),
+            Ok(JobOutcome::Skipped(message)) => (JobStatus::Skipped, message),
+            Err(error) => {
+                tracing::error!("Job `{}` failed: {error:#}", job.name());
+                (JobStatus::Failed, format!("{error:#}"))
+            }
+        };
+
+        let requested_at = job::Entity::find_by_id(row.id)
+            .one(&self.dep_db)
+            .await?
+            .and_then(|current| current.next_run_at);
+        let mut active_model = row.into_active_model();
+        active_model.status = ActiveValue::Set(status);
+        active_model.last_finished_at = ActiveValue::Set(Some(finished_at));
+        active_model.last_message = ActiveValue::Set(Some(message));
+        active_model.run_count = ActiveValue::Set(active_model.run_count.as_ref() + 1);
+        active_model.next_run_at = ActiveValue::Set(
+            requested_at.or_else(|| job.interval().map(|interval| finished_at + interval)),
+        );
+        active_model.update(&self.dep_db).await?;
+        Ok(())
+    }
+
+    async fn execute(&self, job: Job, now: DateTimeUtc) -> Result<JobOutcome, anyhow::Error> {
+        match job {
+            Job::PublishScheduledPosts => self.publish_scheduled_posts(now).await,
+            Job::AttachmentGc => self.collect_attachments(now).await,
+            Job::PingSubscribers => self.ping_subscribers().await,
+        }
+    }
+
+    async fn publish_scheduled_posts(&self, now: DateTimeUtc) -> Result<JobOutcome, anyhow::Error> {
+        let published = PostService::publish_due_posts(&self.dep_db, now).await?;
+        if published == 0 {
+            return Ok(JobOutcome::Skipped(
+                "No scheduled posts are due".to_string(),
+            ));
+        }
+        Self::request(&self.dep_db, Job::PingSubscribers).await?;
+        self.wake();
+        Ok(JobOutcome::Succeeded(format!(
+            "Published {published} scheduled posts"
+        )))
+    }
+
+    async fn collect_attachments(&self, now: DateTimeUtc) -> Result<JobOutcome, anyhow::Error> {
+        if !self.dep_site_settings.read().await.attachment_gc_enabled {
+            return Ok(JobOutcome::Skipped(
+                "Attachment collection is disabled".to_string(),
+            ));
+        }
+
+        let candidates = attachment::Entity::find()
+            .filter(attachment::Column::CreatedAt.lt(now - ATTACHMENT_GC_GRACE))
+            .all(&self.dep_db)
+            .await?;
+        if candidates.is_empty() {
+            return Ok(JobOutcome::Skipped("No attachments to inspect".to_string()));
+        }
+
+        let references = self.attachment_references().await?;
+        let mut deleted = 0;
+        for candidate in candidates {
+            if references
+                .iter()
+                .any(|reference| reference.contains(&candidate.hash))
+            {
+                continue;
+            }
+            self.dep_storage.delete(&self.dep_db, candidate.id).await?;
+            deleted += 1;
+        }
+        Ok(JobOutcome::Succeeded(format!(
+            "Deleted {deleted} unreferenced attachments"
+        )))
+    }
+
+    /// Every text that may link to an attachment by its hash.
+    async fn attachment_references(&self) -> Result<Vec<String>, anyhow::Error> {
+        let mut references = Vec::new();
+        for post in post::Entity::find().all(&self.dep_db).await? {
+            references.push(post.content);
+            references.extend(post.description);
+            references.extend(post.illustration);
+            // Theme fields such as a hero image live in the metadata.
+            references.push(JsonValue::Object(post.meta.0).to_string());
+        }
+        // Restoring a revision must not bring back links to deleted files.
+        for revision in post_revision::Entity::find().all(&self.dep_db).await? {
+            references.push(revision.content);
+            references.extend(revision.description);
+            references.extend(revision.illustration);
+        }
+        references.extend(
+            config_entry::Entity::find()
+                .all(&self.dep_db)
+                .await?
+                .into_iter()
+                .map(|entry| entry.value),
+        );
+        references.extend(read_stored_theme_configs(&self.dep_app_cfg.asset_dir)?);
+        Ok(references)
+    }
+
+    /// Notifies feed subscribers and purges caches of the public pages.
+    async fn ping_subscribers(&self) -> Result<JobOutcome, anyhow::Error> {
+        let site = self.dep_site_settings.read().await.clone();
+        let feed_url = feed_url(&site);
+        let targets = configured_urls(&site.ping_urls)
+            .map(|url| (url, true))
+            .chain(configured_urls(&site.cache_purge_urls).map(|url| (url, false)))
+            .collect::<Vec<_>>();
+        if targets.is_empty() {
+            return Ok(JobOutcome::Skipped(
+                "No ping or cache purge URLs are configured".to_string(),
+            ));
+        }
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        let mut failures = Vec::new();
+        for (target, websub) in &targets {
+            let request = client.post(*target);
+            let request = if *websub {
+                request.form(&[("hub.mode", "publish"), ("hub.url", feed_url.as_str())])
+            } else {
+                request
+            };
+            let result = request
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+            if let Err(error) = result {
+                tracing::warn!("Failed to ping {target}: {error}");
+                failures.push(target.to_string());
+            }
+        }
+
+        if !failures.is_empty() {
+            anyhow::bail!("Failed to ping {}", failures.join(", "));
+        }
+        Ok(JobOutcome::Succeeded(format!(
+            "Pinged {} endpoints",
+            targets.len()
+        )))
+    }
+}
+
+fn configured_urls(urls: &[String]) -> impl Iterator<Item = &str> {
+    urls.iter()
+        .map(|url| url.trim())
+        .filter(|url| !url.is_empty())
+}
+
+fn feed_url(site: &SiteSettings) -> String {
+    format!("{}/index.xml", site.base_url.trim_end_matches('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use sea_orm::{
+        ActiveModelTrait, ConnectionTrait, Database, DatabaseBackend, DatabaseConnection,
+        EntityTrait, Schema, Set,
+    };
+
+    use crate::{
+        config::ApplicationConfiguration,
+        entity::{
+            attachment, config_entry,
+            job::{self, JobStatus},
+            post::{self, PostMeta, PostStatus},
+            post_revision, storage_engine, user,
+        },
+        service::{site_settings::SiteSettingsService, storage::StorageService},
+    };
+
+    use super::{Job, SchedulerService};
+
+    async fn scheduler() -> (SchedulerService, DatabaseConnection, tempfile::TempDir) {
+        let temporary_directory = tempfile::tempdir().unwrap();
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        let schema = Schema::new(DatabaseBackend::Sqlite);
+        for statement in [
+            schema.create_table_from_entity(user::Entity),
+            schema.create_table_from_entity(post::Entity),
+            schema.create_table_from_entity(job::Entity),
+            schema.create_table_from_entity(post_revision::Entity),
+            schema.create_table_from_entity(storage_engine::Entity),
+            schema.create_table_from_entity(attachment::Entity),
+            schema.create_table_from_entity(config_entry::Entity),
+        ] {
+            database.execute(&statement).await.unwrap();
+        }
+        let config = ApplicationConfiguration::for_tests(temporary_directory.path());
+        let scheduler = SchedulerService::new(
+            database.clone(),
+            config.clone(),
+            SiteSettingsService::new(database.clone()),
+            StorageService::new(config),
+        );
+        (scheduler, database, temporary_directory)
+    }
+
+    async fn job_row(database: &DatabaseConnection, job: Job) -> job::Model {
+        job::Entity::find()
+            .all(database)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|row| row.name == job.name())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn publishes_due_posts_and_requests_a_ping() {
+        let (scheduler, database, _directory) = scheduler().await;
+        scheduler.seed_jobs().await.unwrap();
+        post::ActiveModel {
+            name: Set("later".to_string()),
+            title: Set("Later".to_string()),
+            content: Set(String::new()),
+            author: Set(1),
+            status: Set(PostStatus::Scheduled),
+            publish_at: Set(Some(Utc::now() - Duration::minutes(1))),
+            ..Default::default()
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+
+        scheduler.run_due_jobs().await.unwrap();
+
+        let post = post::Entity::find().one(&database).await.unwrap().unwrap();
+        assert_eq!(post.status, PostStatus::Published);
+        let publish = job_row(&database, Job::PublishScheduledPosts).await;
+        assert_eq!(publish.status, JobStatus::Succeeded);
+        assert_eq!(publish.run_count, 1);
+        assert!(publish.next_run_at.unwrap() > Utc::now());
+        let gc = job_row(&database, Job::AttachmentGc).await;
+        assert_eq!(gc.status, JobStatus::Skipped);
+
+        let ping = job_row(&database, Job::PingSubscribers).await;
+        assert_eq!(ping.run_count, 0);
+        assert!(ping.next_run_at.is_some());
+        scheduler.run_due_jobs().await.unwrap();
+        let ping = job_row(&database, Job::PingSubscribers).await;
+        assert_eq!(ping.status, JobStatus::Skipped);
+        assert!(ping.next_run_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn collects_only_old_unreferenced_attachments() {
+        let (scheduler, database, _directory) = scheduler().await;
+        scheduler
+            .dep_site_settings
+            .write()
+            .await
+            .attachment_gc_enabled = true;
+        scheduler.seed_jobs().await.unwrap();
+        storage_engine::ActiveModel {
+            id: Set(1),
+            name: Set("Local".to_string()),
+            comments: Set(String::new()),
+            kind: Set("local".to_string()),
+            config_json: Set(None),
+            is_default: Set(true),
+            enabled: Set(true),
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+        for (hash, age) in [
+            ("referenced", Duration::days(30)),
+            ("hero", Duration::days(30)),
+            ("orphaned", Duration::days(30)),
+            ("recent", Duration::hours(1)),
+        ] {
+            attachment::ActiveModel {
+                hash: Set(hash.to_string()),
+                storage_engine_id: Set(1),
+                object_key: Set(format!("attachments/1/{hash}.png")),
+                filename: Set(format!("{hash}.png")),
+                mime: Set("image/png".to_string()),
+                byte_size: Set(0),
+                created_at: Set(Utc::now() - age),
+                ..Default::default()
+            }
+            .insert(&database)
+            .await
+            .unwrap();
+        }
+        post::ActiveModel {
+            name: Set("post".to_string()),
+            title: Set("Post".to_string()),
+            content: Set("![image](/attachments/referenced)".to_string()),
+            author: Set(1),
+            meta: Set(PostMeta(
+                serde_json::json!({ "hero": { "src": "/attachments/hero" } })
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            )),
+            ..Default::default()
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+
+        scheduler.run_due_jobs().await.unwrap();
+
+        let mut remaining = attachment::Entity::find()
+            .all(&database)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|attachment| attachment.hash)
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(remaining, ["hero", "recent", "referenced"]);
+        assert_eq!(
+            job_row(&database, Job::AttachmentGc).await.status,
+            JobStatus::Succeeded
+        );
+    }
+
+    #[tokio::test]
+    async fn on_demand_jobs_run_only_when_requested() {
+        let (scheduler, database, _directory) = scheduler().await;
+        scheduler.seed_jobs().await.unwrap();
+        scheduler.run_due_jobs().await.unwrap();
+        assert_eq!(job_row(&database, Job::PingSubscribers).await.run_count, 0);
+
+        SchedulerService::request(&database, Job::PingSubscribers)
+            .await
+            .unwrap();
+        scheduler.run_due_jobs().await.unwrap();
+
+        assert_eq!(job_row(&database, Job::PingSubscribers).await.run_count, 1);
+    }
+
+    #[tokio::test]
+    async fn releases_jobs_left_running_by_a_previous_process() {
+        let (scheduler, database, _directory) = scheduler().await;
+        job::ActiveModel {
+            name: Set(Job::AttachmentGc.name().to_string()),
+            status: Set(JobStatus::Running),
+            ..Default::default()
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+
+        scheduler.seed_jobs().await.unwrap();
+
+        let gc = job_row(&database, Job::AttachmentGc).await;
+        assert_eq!(gc.status, JobStatus::Idle);
+        assert!(gc.next_run_at.is_some());
+    }
+}
@@ -0,0 +1,758 @@
+use std::collections::HashMap;
+
+use sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, DatabaseBackend, DbErr, EntityTrait, FromQueryResult,
+    QueryFilter, QuerySelect, QueryTrait, Select, sea_query::LikeExpr,
+};
+
+use crate::entity::post;
+
+/// Upper bound on matches returned for one query. The cap applies after the
+/// scope, so hidden posts never crowd out visible ones; it only bounds
+/// pathological queries that match most of the site.
+const MAX_SEARCH_MATCHES: u64 = 500;
+/// Characters of context kept around the first match in generated snippets.
+const SNIPPET_CONTEXT: usize = 80;
+// Private-use characters mark highlights until the snippet has been escaped,
+// so content can never smuggle markup into the result.
+const HIGHLIGHT_START: char = '\u{E000}';
+const HIGHLIGHT_END: char = '\u{E001}';
+
+/// A ranked match. `snippet` is HTML-safe with matches wrapped in `<mark>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub post_id: i32,
+    pub score: f64,
+    pub snippet: String,
+}
+
+#[derive(Debug, FromQueryResult)]
+struct SearchRow {
+    post_id: i32,
+    score: f64,
+    snippet: Option<String>,
+}
+
+pub struct SearchService;
+
+impl SearchService {
+    /// Creates the full-text index for the connected backend. SQLite keeps an
+    /// FTS5 table in step with `posts` through triggers, PostgreSQL uses a GIN
+    /// index over a `tsvector` expression, and MySQL a FULLTEXT index.
+    pub async fn ensure_index<C>(db: &C) -> Result<(), DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        match db.get_database_backend() {
+            #[cfg(feature = "sqlite")]
+            DatabaseBackend::Sqlite => sqlite::ensure_index(db).await,
+            #[cfg(feature = "postgres")]
+            DatabaseBackend::Postgres => postgres::ensure_index(db).await,
+            #[cfg(feature = "mysql")]
+            DatabaseBackend::MySql => mysql::ensure_index(db).await,
+            backend => Err(unsupported_backend(backend)),
+        }
+    }
+
+    /// Selects every post matching `query`, with no cap on their number. Used
+    /// by the admin post list, so the content of protected posts is searched
+    /// too.
+    pub fn matching_condition(backend: DatabaseBackend, query: &str) -> Result<Condition, DbErr> {
+        let terms = query_terms(query);
+        if terms.is_empty() {
+            return Ok(Condition::all().add(post::Column::Id.is_in(Vec::<i32>::new())));
+        }
+        let mut matches = QueryTrait::into_query(search_select(backend, &terms, true)?);
+        matches
+            .clear_selects()
+            .clear_order_by()
+            .column((post::Entity, post::Column::Id));
+        Ok(Condition::all().add(post::Column::Id.in_subquery(matches)))
+    }
+
+    /// Ranked matches among the posts selected by `scope`, paired with
+    /// highlighted snippets. Protected posts only match on their title and
+    /// description, so a search cannot reveal what their content holds.
+    pub async fn search<C>(
+        db: &C,
+        query: &str,
+        scope: Condition,
+    ) -> Result<Vec<(post::Model, SearchHit)>, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let rows = search_rows(db, query, scope, false).await?;
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut posts = post::Entity::find()
+            .filter(post::Column::Id.is_in(rows.iter().map(|row| row.post_id)))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|post| (post.id, post))
+            .collect::<HashMap<_, _>>();
+
+        let terms = query_terms(query);
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let post = posts.remove(&row.post_id)?;
+                // Protected posts only show what their listing already shows.
+                let snippet = if post.password_hash.is_some() {
+                    escape_html(post.description.as_deref().unwrap_or_default())
+                } else {
+                    match row.snippet.filter(|snippet| !snippet.trim().is_empty()) {
+                        Some(snippet) => finish_snippet(&snippet),
+                        None => fallback_snippet(&post.content, &terms),
+                    }
+                };
+                Some((
+                    post,
+                    SearchHit {
+                        post_id: row.post_id,
+                        score: row.score,
+                        snippet,
+                    },
+                ))
+            })
+            .collect())
+    }
+}
+
+/// The best matches among the posts in `scope`. Without `protected_content`,
+/// posts with a password only match on their title and description.
+async fn search_rows<C>(
+    db: &C,
+    query: &str,
+    scope: Condition,
+    protected_content: bool,
+) -> Result<Vec<SearchRow>, DbErr>
+where
+    C: ConnectionTrait,
+{
+    let terms = query_terms(query);
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+    search_select(db.get_database_backend(), &terms, protected_content)?
+        .filter(scope)
+        .limit(MAX_SEARCH_MATCHES)
+        .into_model::<SearchRow>()
+        .all(db)
+        .await
+}
+
+/// Ranked matches for `terms`, selecting `post_id`, `score` and `snippet`.
+fn search_select(
+    backend: DatabaseBackend,
+    terms: &[String],
+    protected_content: bool,
+) -> Result<Select<post::Entity>, DbErr> {
+    match backend {
+        #[cfg(feature = "sqlite")]
+        DatabaseBackend::Sqlite => Ok(sqlite::search_select(terms, protected_content)),
+        #[cfg(feature = "postgres")]
+        DatabaseBackend::Postgres => Ok(postgres::search_select(terms, protected_content)),
+        #[cfg(feature = "mysql")]
+        DatabaseBackend::MySql => Ok(mysql::search_select(terms, protected_content)),
+        backend => Err(unsupported_backend(backend)),
+    }
+}
+
+/// The columns every backend selects, before matching and ranking.
+fn search_base() -> Select<post::Entity> {
+    post::Entity::find()
+        .select_only()
+        .column_as(post::Column::Id, "post_id")
+}
+
+fn unsupported_backend(backend: DatabaseBackend) -> DbErr {
+    DbErr::Custom(format!(
+        "Full-text search is not enabled for {backend:?}; rebuild with its cargo feature"
+    ))
+}
+
+/// Whitespace-separated terms with quotes removed, so user input is never
+/// interpreted as backend query syntax.
+fn query_terms(query: &str) -> Vec<String> {
+    query
+        .split_whitespace()
+        .map(|term| term.replace('"', ""))
+        .filter(|term| !term.is_empty())
+        .collect()
+}
+
+fn finish_snippet(raw: &str) -> String {
+    let collapsed = raw.split_whitespace().collect::<Vec<_>>().join(" ");
+    escape_html(&collapsed)
+        .replace(HIGHLIGHT_START, "<mark>")
+        .replace(HIGHLIGHT_END, "</mark>")
+}
+
+/// Builds a snippet around the first occurrence of any term, for backends
+/// without a highlighting function and for short terms matched by `LIKE`.
+fn fallback_snippet(content: &str, terms: &[String]) -> String {
+    let text = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    let lowercase_terms = terms
+        .iter()
+        .map(|term| term.to_lowercase())
+        .collect::<Vec<_>>();
+    let characters = text.chars().collect::<Vec<_>>();
+    let lowercase = characters
+        .iter()
+        .map(|character| character.to_lowercase().next().unwrap_or(*character))
+        .collect::<Vec<_>>();
+
+    let find = |from: usize| {
+        lowercase_terms
+            .iter()
+            .filter_map(|term| {
+                let needle = term.chars().collect::<Vec<_>>();
+                (from..lowercase.len())
+                    .find(|&index| lowercase[index..].starts_with(&needle))
+                    .map(|index| (index, needle.len()))
+            })
+            .min()
+    };
+
+    let first = find(0).map_or(0, |(index, _)| index);
+    let start = first.saturating_sub(SNIPPET_CONTEXT / 2);
+    let end = (first + SNIPPET_CONTEXT).min(characters.len());
+    let mut raw = String::new();
+    if start > 0 {
+        raw.push('…');
+    }
+    let mut position = start;
+    while position < end {
+        match find(position).filter(|(index, _)| *index < end) {
+            Some((index, length)) => {
+                raw.extend(&characters[position..index]);
+                raw.push(HIGHLIGHT_START);
+                let match_end = (index + length).min(end);
+                raw.extend(&characters[index..match_end]);
+                raw.push(HIGHLIGHT_END);
+                position = match_end;
+            }
+            None => {
+                raw.extend(&characters[position..end]);
+                position = end;
+            }
+        }
+    }
+    if end < characters.len() {
+        raw.push('…');
+    }
+    finish_snippet(&raw)
+}
+
+/// A `LIKE` pattern matching `value` anywhere, with `%` and `_` in it taken
+/// literally.
+pub fn like_contains(value: &str) -> LikeExpr {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    LikeExpr::new(format!("%{escaped}%")).escape('\\')
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use sea_orm::{
+        ColumnTrait, Condition, ConnectionTrait, DatabaseBackend, DbErr, JoinType, QueryFilter,
+        QueryOrder, QuerySelect, QueryTrait, Select, Statement,
+        sea_query::{Alias, Expr},
+    };
+
+    use crate::entity::post;
+
+    use super::{HIGHLIGHT_END, HIGHLIGHT_START, like_contains, search_base};
+
+    /// The trigram tokenizer matches substrings, which also covers CJK text
+    /// without word boundaries, but it cannot match terms shorter than this.
+    const TRIGRAM_LENGTH: usize = 3;
+
+    pub(super) async fn ensure_index<C>(db: &C) -> Result<(), DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        let created = db
+            .query_one_raw(Statement::from_string(
+                DatabaseBackend::Sqlite,
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'posts_fts'",
+            ))
+            .await?
+            .is_some();
+        for statement in [
+            "CREATE VIRTUAL TABLE IF NOT EXISTS posts_fts USING fts5(title, description, content, content='posts', content_rowid='id', tokenize='trigram')",
+            "CREATE TRIGGER IF NOT EXISTS posts_fts_insert AFTER INSERT ON posts BEGIN \
+                INSERT INTO posts_fts(rowid, title, description, content) VALUES (new.id, new.title, new.description, new.content); \
+            END",
+            "CREATE TRIGGER IF NOT EXISTS posts_fts_delete AFTER DELETE ON posts BEGIN \
+                INSERT INTO posts_fts(posts_fts, rowid, title, description, content) VALUES ('delete', old.id, old.title, old.description, old.content); \
+            END",
+            "CREATE TRIGGER IF NOT EXISTS posts_fts_update AFTER UPDATE OF title, description, content ON posts BEGIN \
+                INSERT INTO posts_fts(posts_fts, rowid, title, description, content) VALUES ('delete', old.id, old.title, old.description, old.content); \
+                INSERT INTO posts_fts(rowid, title, description, content) VALUES (new.id, new.title, new.description, new.content); \
+            END",
+        ] {
+            db.execute_unprepared(statement).await?;
+        }
+        if !created {
+            // Picks up posts written before the index existed.
+            db.execute_unprepared("INSERT INTO posts_fts(posts_fts) VALUES ('rebuild')")
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn search_select(terms: &[String], protected_content: bool) -> Select<post::Entity> {
+        let (long, short): (Vec<_>, Vec<_>) = terms
+            .iter()
+            .partition(|term| term.chars().count() >= TRIGRAM_LENGTH);
+        let mut select = search_base();
+        if long.is_empty() {
+            select = select
+                .expr_as(Expr::val(0.0), "score")
+                .expr_as(Expr::cust("NULL"), "snippet")
+                .order_by_desc(post::Column::Id);
+        } else {
+            let query = long
+                .iter()
+                .map(|term| format!("\"{term}\""))
+                .collect::<Vec<_>>()
+                .join(" ");
+            select = select
+                .expr_as(Expr::cust("-bm25(posts_fts, 10.0, 5.0, 1.0)"), "score")
+                .expr_as(
+                    Expr::cust_with_values(
+                        "snippet(posts_fts, -1, ?, ?, '…', 64)",
+                        [HIGHLIGHT_START.to_string(), HIGHLIGHT_END.to_string()],
+                    ),
+                    "snippet",
+                )
+                .filter(Expr::cust_with_values("posts_fts MATCH ?", [query.clone()]));
+            if !protected_content {
+                select = select.filter(
+                    Condition::any()
+                        .add(post::Column::PasswordHash.is_null())
+                        .add(Expr::cust_with_values(
+                            "posts.id IN (SELECT rowid FROM posts_fts WHERE posts_fts MATCH ?)",
+                            [format!("{{title description}} : ({query})")],
+                        )),
+                );
+            }
+            QueryTrait::query(&mut select)
+                .join(
+                    JoinType::InnerJoin,
+                    Alias::new("posts_fts"),
+                    Expr::cust("posts_fts.rowid = posts.id"),
+                )
+                .order_by_expr(Expr::cust("score"), sea_orm::Order::Desc);
+        }
+        for term in short {
+            let pattern = || like_contains(term);
+            let mut matches = Condition::any()
+                .add(post::Column::Title.like(pattern()))
+                .add(post::Column::Description.like(pattern()));
+            matches = if protected_content {
+                matches.add(post::Column::Content.like(pattern()))
+            } else {
+                matches.add(
+                    Condition::all()
+                        .add(post::Column::PasswordHash.is_null())
+                        .add(post::Column::Content.like(pattern())),
+                )
+            };
+            select = select.filter(matches);
+        }
+        select
+    }
+}
+
+#[cfg(feature = "postgres")]
+mod postgres {
+    use sea_orm::{
+        ColumnTrait, Condition, ConnectionTrait, DbErr, QueryFilter, QuerySelect, QueryTrait,
+        Select, sea_query::Expr,
+    };
+
+    use crate::entity::post;
+
+    use super::{HIGHLIGHT_END, HIGHLIGHT_START, search_base};
+
+    /// Must match the indexed expression exactly for the planner to use it.
+    const DOCUMENT: &str =
+        "to_tsvector('simple', title || ' ' || coalesce(description, '') || ' ' || content)";
+    /// What protected posts may match on.
+    const LISTING: &str = "to_tsvector('simple', title || ' ' || coalesce(description, ''))";
+
+    pub(super) async fn ensure_index<C>(db: &C) -> Result<(), DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        db.execute_unprepared(&format!(
+            "CREATE INDEX IF NOT EXISTS posts_search_idx ON posts USING GIN ({DOCUMENT})"
+        ))
+        .await?;
+        Ok(())
+    }
+
+    pub(super) fn search_select(terms: &[String], protected_content: bool) -> Select<post::Entity> {
+        let query = terms.join(" ");
+        let mut select = search_base()
+            .expr_as(
+                Expr::cust_with_values(
+                    format!("ts_rank({DOCUMENT}, plainto_tsquery('simple', $1))::float8"),
+                    [query.clone()],
+                ),
+                "score",
+            )
+            .expr_as(
+                Expr::cust_with_values(
+                    "ts_headline('simple', content, plainto_tsquery('simple', $1), $2)",
+                    [
+                        query.clone(),
+                        format!(
+                            "StartSel=\"{HIGHLIGHT_START}\", StopSel=\"{HIGHLIGHT_END}\", MaxWords=35, MinWords=15"
+                        ),
+                    ],
+                ),
+                "snippet",
+            )
+            .filter(Expr::cust_with_values(
+                format!("{DOCUMENT} @@ plainto_tsquery('simple', $1)"),
+                [query.clone()],
+            ));
+        if !protected_content {
+            select = select.filter(
+                Condition::any()
+                    .add(post::Column::PasswordHash.is_null())
+                    .add(Expr::cust_with_values(
+                        format!("{LISTING} @@ plainto_tsquery('simple', $1)"),
+                        [query],
+                    )),
+            );
+        }
+        QueryTrait::query(&mut select).order_by_expr(Expr::cust("score"), sea_orm::Order::Desc);
+        select
+    }
+}
+
+#[cfg(feature = "mysql")]
+mod mysql {
+    use sea_orm::{
+        ColumnTrait, Condition, ConnectionTrait, DatabaseBackend, DbErr, FromQueryResult,
+        QueryFilter, QuerySelect, QueryTrait, Select, Statement, sea_query::Expr,
+    };
+
+    use crate::entity::post;
+
+    use super::search_base;
+
+    const MATCH: &str = "MATCH(title, description, content) AGAINST (? IN NATURAL LANGUAGE MODE)";
+    /// What protected posts may match on. MySQL needs a FULLTEXT index over
+    /// exactly these columns.
+    const LISTING_MATCH: &str = "MATCH(title, description) AGAINST (? IN NATURAL LANGUAGE MODE)";
+
+    #[derive(FromQueryResult)]
+    struct IndexCount {
+        count: i64,
+    }
+
+    pub(super) async fn ensure_index<C>(db: &C) -> Result<(), DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        for (name, columns) in [
+            ("posts_search_idx", "title, description, content"),
+            ("posts_listing_search_idx", "title, description"),
+        ] {
+            // MySQL has no `ADD INDEX IF NOT EXISTS`.
+            let existing = IndexCount::find_by_statement(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                "SELECT COUNT(*) AS count FROM information_schema.statistics \
+                WHERE table_schema = DATABASE() AND table_name = 'posts' AND index_name = ?",
+                [name.into()],
+            ))
+            .one(db)
+            .await?
+            .map_or(0, |row| row.count);
+            if existing == 0 {
+                db.execute_unprepared(&format!(
+                    "ALTER TABLE posts ADD FULLTEXT INDEX {name} ({columns})"
+                ))
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// MySQL has no highlighting function, so snippets are built afterwards.
+    pub(super) fn search_select(terms: &[String], protected_content: bool) -> Select<post::Entity> {
+        let query = terms.join(" ");
+        let mut select = search_base()
+            .expr_as(Expr::cust_with_values(MATCH, [query.clone()]), "score")
+            .expr_as(Expr::cust("NULL"), "snippet")
+            .filter(Expr::cust_with_values(MATCH, [query.clone()]));
+        if !protected_content {
+            select = select.filter(
+                Condition::any()
+                    .add(post::Column::PasswordHash.is_null())
+                    .add(Expr::cust_with_values(LISTING_MATCH, [query])),
+            );
+        }
+        QueryTrait::query(&mut select).order_by_expr(Expr::cust("score"), sea_orm::Order::Desc);
+        select
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use chrono::{Duration, Utc};
+    use sea_orm::{
+        ActiveModelTrait, ActiveValue, ConnectionTrait, Database, DatabaseBackend,
+        DatabaseConnection, EntityTrait, IntoActiveModel, QueryFilter, QueryTrait, Schema,
+    };
+
+    use crate::{
+        entity::post::{self, PostStatus},
+        service::post::PostService,
+    };
+
+    use super::{MAX_SEARCH_MATCHES, SearchService, fallback_snippet, query_terms, sqlite};
+
+    async fn database_with_posts() -> DatabaseConnection {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        let schema = Schema::new(DatabaseBackend::Sqlite);
+        database
+            .execute(&schema.create_table_from_entity(post::Entity))
+            .await
+            .unwrap();
+        // Created before the index, like posts on an upgraded site.
+        insert_post(
+            &database,
+            "ownership",
+            "Understanding ownership",
+            "Rust moves values unless they implement Copy.",
+            PostStatus::Published,
+        )
+        .await;
+        SearchService::ensure_index(&database).await.unwrap();
+        insert_post(
+            &database,
+            "lifetimes",
+            "Lifetimes",
+            "Borrowing in Rust relies on lifetimes. 借用检查器会拒绝悬垂引用。",
+            PostStatus::Published,
+        )
+        .await;
+        insert_post(
+            &database,
+            "draft",
+            "Rust draft",
+            "Unfinished notes about Rust.",
+            PostStatus::Draft,
+        )
+        .await;
+        database
+    }
+
+    async fn insert_post(
+        database: &DatabaseConnection,
+        name: &str,
+        title: &str,
+        content: &str,
+        status: PostStatus,
+    ) -> post::Model {
+        post::ActiveModel {
+            name: ActiveValue::Set(name.to_string()),
+            title: ActiveValue::Set(title.to_string()),
+            content: ActiveValue::Set(content.to_string()),
+            author: ActiveValue::Set(1),
+            status: ActiveValue::Set(status),
+            created_at: ActiveValue::Set(Utc::now() - Duration::hours(1)),
+            ..Default::default()
+        }
+        .insert(database)
+        .await
+        .unwrap()
+    }
+
+    async fn admin_matches(database: &DatabaseConnection, query: &str) -> Vec<i32> {
+        let condition = SearchService::matching_condition(DatabaseBackend::Sqlite, query).unwrap();
+        post::Entity::find()
+            .filter(condition)
+            .all(database)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|post| post.id)
+            .collect()
+    }
+
+    async fn search_names(database: &DatabaseConnection, query: &str) -> Vec<String> {
+        SearchService::search(database, query, PostService::listed_condition())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(post, _)| post.name)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn ranks_visible_posts_and_highlights_matches() {
+        let database = database_with_posts().await;
+
+        let results = SearchService::search(&database, "rust", PostService::listed_condition())
+            .await
+            .unwrap();
+
+        let names = results
+            .iter()
+            .map(|(post, _)| post.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), 2);
+        assert!(!names.contains(&"draft"));
+        assert!(results[0].1.score >= results[1].1.score);
+        let (_, lifetimes) = results
+            .iter()
+            .find(|(post, _)| post.name == "lifetimes")
+            .unwrap();
+        assert!(lifetimes.snippet.contains("<mark>Rust</mark>"));
+    }
+
+    #[tokio::test]
+    async fn matches_protected_posts_only_on_what_their_listing_shows() {
+        let database = database_with_posts().await;
+        let post = insert_post(
+            &database,
+            "secret",
+            "Secret",
+            "Rust plans nobody else should read.",
+            PostStatus::Published,
+        )
+        .await;
+        let mut active_model = post.into_active_model();
+        active_model.description = ActiveValue::Set(Some("Members <only>".to_string()));
+        active_model.password_hash = ActiveValue::Set(Some("$2b$04$hash".to_string()));
+        active_model.update(&database).await.unwrap();
+
+        assert_eq!(search_names(&database, "plans").await, Vec::<String>::new());
+        assert_eq!(search_names(&database, "no").await, Vec::<String>::new());
+        assert!(
+            !search_names(&database, "rust")
+                .await
+                .contains(&"secret".to_string())
+        );
+        let results = SearchService::search(&database, "members", PostService::listed_condition())
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].1.snippet, "Members &lt;only&gt;");
+
+        assert_eq!(admin_matches(&database, "plans").await, [results[0].0.id]);
+    }
+
+    #[tokio::test]
+    async fn caps_matches_after_applying_the_scope() {
+        let database = database_with_posts().await;
+        for index in 0..MAX_SEARCH_MATCHES {
+            insert_post(
+                &database,
+                &format!("draft-{index}"),
+                "Crowded draft",
+                "Crowded crowded crowded.",
+                PostStatus::Draft,
+            )
+            .await;
+        }
+        insert_post(
+            &database,
+            "visible",
+            "Visible",
+            "Crowded once.",
+            PostStatus::Published,
+        )
+        .await;
+
+        assert_eq!(search_names(&database, "crowded").await, ["visible"]);
+        // The admin list is not capped.
+        assert_eq!(
+            admin_matches(&database, "crowded").await.len() as u64,
+            MAX_SEARCH_MATCHES + 1
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_the_index_in_step_with_edits_and_deletes() {
+        let database = database_with_posts().await;
+        let post = post::Entity::find()
+            .all(&database)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|post| post.name == "ownership")
+            .unwrap();
+        let mut active_model = post.into_active_model();
+        active_model.content = ActiveValue::Set("Now about traits.".to_string());
+        let post = active_model.update(&database).await.unwrap();
+
+        assert_eq!(search_names(&database, "Copy").await, Vec::<String>::new());
+        assert_eq!(search_names(&database, "traits").await, ["ownership"]);
+
+        post::Entity::delete_by_id(post.id)
+            .exec(&database)
+            .await
+            .unwrap();
+        assert_eq!(
+            search_names(&database, "traits").await,
+            Vec::<String>::new()
+        );
+    }
+
+    #[tokio::test]
+    async fn matches_cjk_and_short_terms() {
+        let database = database_with_posts().await;
+
+        assert_eq!(search_names(&database, "检查器").await, ["lifetimes"]);
+        assert_eq!(search_names(&database, "借用").await, ["lifetimes"]);
+        assert_eq!(search_names(&database, "rust 借用").await, ["lifetimes"]);
+    }
+
+    #[test]
+    fn treats_query_syntax_as_plain_text() {
+        assert_eq!(query_terms(" \"rust\"  OR* "), ["rust", "OR*"]);
+    }
+
+    #[test]
+    fn escapes_generated_snippets() {
+        let snippet = fallback_snippet("<b>Rust</b> & friends", &["rust".to_string()]);
+
+        assert_eq!(
+            snippet,
+            "&lt;b&gt;<mark>Rust</mark>&lt;/b&gt; &amp; friends"
+        );
+    }
+
+    #[test]
+    fn quotes_long_terms_for_fts5_and_matches_short_ones_with_like() {
+        let statement = sqlite::search_select(&["rust".to_string(), "ab".to_string()], true)
+            .build(DatabaseBackend::Sqlite);
+
+        assert!(statement.sql.contains("posts_fts MATCH ?"));
+        assert!(statement.sql.contains(r#""posts"."content" LIKE ?"#));
+        let values = statement.values.unwrap().0;
+        assert_eq!(values[2], "\"rust\"".into());
+        assert_eq!(values[3], "%ab%".into());
+    }
+}
@@ -39,14 +39,17 @@ impl StorageService {
         }
     }
 
-    pub async fn upload(
+    pub async fn upload<C>(
         &self,
-        db: &DatabaseConnection,
+        db: &C,
         data: Vec<u8>,
         mime_type: String,
         filename: Option<String>,
         engine_id: Option<i32>,
-    ) -> Result<attachment::Model, anyhow::Error> {
+    ) -> Result<attachment::Model, anyhow::Error>
+    where
+        C: ConnectionTrait,
+    {
         let byte_size = i64::try_from(data.len()).unwrap_or(i64::MAX);
         let hash = format!("{:x}", md5::compute(&data));
 
@@ -124,6 +127,39 @@ impl StorageService {
             .into_response())
     }
 
+    /// Reads the stored bytes of an attachment from its storage engine.
+    pub async fn read(
+        &self,
+        db: &DatabaseConnection,
+        attach: &attachment::Model,
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        let engine = storage_engine::Entity::find_by_id(attach.storage_engine_id)
+            .one(db)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Storage engine not found"))?;
+        let provider = self.provider_for(&engine).await?;
+        let object = provider.get(&attach.object_key).await?;
+        let bytes = axum::body::to_bytes(object.body, usize::MAX).await?;
+        Ok(bytes.to_vec())
+    }
+
+    /// `{hash}.{extension}`, the name an attachment gets when it is written
+    /// out as a plain file. The extension comes from the original file name,
+    /// or from the MIME type when that has none.
+    pub fn file_name(attach: &attachment::Model) -> String {
+        let extension = attach
+            .filename
+            .rsplit_once('.')
+            .map(|(_, extension)| extension)
+            .filter(|extension| !extension.is_empty() && !extension.contains('/'))
+            .or_else(|| {
+                mime_guess::get_mime_extensions_str(&attach.mime)
+                    .and_then(|extensions| extensions.first().copied())
+            })
+            .unwrap_or("bin");
+        format!("{}.{extension}", attach.hash)
+    }
+
     pub async fn delete(&self, db: &DatabaseConnection, id: i32) -> Result<(), anyhow::Error> {
         let attach = attachment::Entity::find_by_id(id)
             .one(db)
@@ -142,10 +178,39 @@ impl StorageService {
         Ok(())
     }
 
-    async fn resolve_engine(
+    /// Deletes the stored objects of `attachments` whose rows no longer
+    /// exist, as after uploading them in a transaction that was rolled back.
+    pub async fn discard_orphans(
+        &self,
         db: &DatabaseConnection,
+        attachments: &[attachment::Model],
+    ) -> Result<(), anyhow::Error> {
+        for attach in attachments {
+            let kept = attachment::Entity::find()
+                .filter(attachment::Column::Hash.eq(&attach.hash))
+                .one(db)
+                .await?
+                .is_some();
+            if kept {
+                continue;
+            }
+            let engine = storage_engine::Entity::find_by_id(attach.storage_engine_id)
+                .one(db)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Storage engine not found"))?;
+            let provider = self.provider_for(&engine).await?;
+            provider.delete(&attach.object_key).await?;
+        }
+        Ok(())
+    }
+
+    async fn resolve_engine<C>(
+        db: &C,
         engine_id: Option<i32>,
-    ) -> Result<storage_engine::Model, anyhow::Error> {
+    ) -> Result<storage_engine::Model, anyhow::Error>
+    where
+        C: ConnectionTrait,
+    {
         if let Some(id) = engine_id {
             let engine = storage_engine::Entity::find_by_id(id)
                 .one(db)
@@ -1,11 +1,14 @@
 use std::collections::{HashMap, HashSet};
 
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, EntityTrait, ExprTrait, QueryFilter,
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, ExprTrait, QueryFilter,
     QueryOrder, QuerySelect, Set, sea_query::Expr,
 };
 
-use crate::entity::{category, post, post_category, post_tag, tag};
+use crate::{
+    entity::{category, post, post_category, post_tag, tag},
+    service::post::PostService,
+};
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct PostTerms {
@@ -28,6 +31,10 @@ impl TaxonomyKind {
     }
 }
 
+/// A shared tag says more about two posts being alike than a shared category.
+const RELATED_TAG_WEIGHT: usize = 2;
+const RELATED_CATEGORY_WEIGHT: usize = 1;
+
 pub struct TaxonomyService;
 
 impl TaxonomyService {
@@ -139,15 +146,85 @@ impl TaxonomyService {
 
     pub fn visible_posts_for_term(kind: TaxonomyKind, term: &str) -> sea_orm::Select<post::Entity> {
         match kind {
-            TaxonomyKind::Tag => visible_posts()
+            TaxonomyKind::Tag => PostService::listed_posts()
                 .inner_join(tag::Entity)
                 .filter(tag::Column::Name.eq(term)),
-            TaxonomyKind::Category => visible_posts()
+            TaxonomyKind::Category => PostService::listed_posts()
                 .inner_join(category::Entity)
                 .filter(category::Column::Name.eq(term)),
         }
     }
 
+    /// Up to `limit` listed posts sharing terms with `post`, most shared terms
+    /// first and newer posts first among equals.
+    pub async fn related_posts<C>(
+        db: &C,
+        post: &post::Model,
+        terms: &PostTerms,
+        limit: u64,
+    ) -> Result<Vec<post::Model>, sea_orm::DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        if limit == 0 || (terms.tags.is_empty() && terms.categories.is_empty()) {
+            return Ok(Vec::new());
+        }
+
+        let mut candidates = HashMap::new();
+        if !terms.tags.is_empty() {
+            let tagged = PostService::listed_posts()
+                .inner_join(tag::Entity)
+                .filter(tag::Column::Name.is_in(terms.tags.iter().cloned()))
+                .filter(post::Column::Id.ne(post.id))
+                .all(db)
+                .await?;
+            candidates.extend(tagged.into_iter().map(|post| (post.id, post)));
+        }
+        if !terms.categories.is_empty() {
+            let categorized = PostService::listed_posts()
+                .inner_join(category::Entity)
+                .filter(category::Column::Name.is_in(terms.categories.iter().cloned()))
+                .filter(post::Column::Id.ne(post.id))
+                .all(db)
+                .await?;
+            candidates.extend(categorized.into_iter().map(|post| (post.id, post)));
+        }
+
+        let candidate_ids = candidates.keys().copied().collect::<Vec<_>>();
+        let candidate_terms = Self::terms_for_posts(db, &candidate_ids).await?;
+        let mut scored = candidates
+            .into_values()
+            .map(|candidate| {
+                let shared = candidate_terms.get(&candidate.id);
+                let score = shared.map_or(0, |shared| {
+                    let tags = shared
+                        .tags
+                        .iter()
+                        .filter(|tag| terms.tags.contains(tag))
+                        .count();
+                    let categories = shared
+                        .categories
+                        .iter()
+                        .filter(|category| terms.categories.contains(category))
+                        .count();
+                    tags * RELATED_TAG_WEIGHT + categories * RELATED_CATEGORY_WEIGHT
+                });
+                (score, candidate)
+            })
+            .collect::<Vec<_>>();
+        scored.sort_by(|(left_score, left), (right_score, right)| {
+            right_score
+                .cmp(left_score)
+                .then_with(|| right.created_at.cmp(&left.created_at))
+                .then_with(|| right.id.cmp(&left.id))
+        });
+        Ok(scored
+            .into_iter()
+            .take(usize::try_from(limit).unwrap_or(usize::MAX))
+            .map(|(_, candidate)| candidate)
+            .collect())
+    }
+
     pub async fn visible_term_counts<C>(
         db: &C,
         kind: TaxonomyKind,
@@ -162,7 +239,7 @@ impl TaxonomyService {
                     .column(tag::Column::Name)
                     .column_as(Expr::col((post::Entity, post::Column::Id)).count(), "count")
                     .inner_join(post::Entity)
-                    .filter(visible_posts_condition())
+                    .filter(PostService::listed_condition())
                     .group_by(tag::Column::Id)
                     .order_by_asc(tag::Column::Name)
                     .into_tuple::<(String, i64)>()
@@ -175,7 +252,7 @@ impl TaxonomyService {
                     .column(category::Column::Name)
                     .column_as(Expr::col((post::Entity, post::Column::Id)).count(), "count")
                     .inner_join(post::Entity)
-                    .filter(visible_posts_condition())
+                    .filter(PostService::listed_condition())
                     .group_by(category::Column::Id)
                     .order_by_asc(category::Column::Name)
                     .into_tuple::<(String, i64)>()
@@ -191,18 +268,6 @@ impl TaxonomyService {
     }
 }
 
-fn visible_posts() -> sea_orm::Select<post::Entity> {
-    post::Entity::find()
-        .filter(visible_posts_condition())
-        .order_by_desc(post::Column::CreatedAt)
-}
-
-fn visible_posts_condition() -> Condition {
-    Condition::any()
-        .add(post::Column::Hidden.eq(false))
-        .add(post::Column::Hidden.is_null())
-}
-
 fn normalize_terms(values: Vec<String>) -> Vec<String> {
     let mut seen = HashSet::new();
     values
@@ -256,11 +321,18 @@ where
 
 #[cfg(test)]
 mod tests {
-    use sea_orm::{ActiveModelTrait, ConnectionTrait, Database, DatabaseBackend, Schema, Set};
+    use chrono::{Duration, Utc};
+    use sea_orm::{
+        ActiveModelTrait, ConnectionTrait, Database, DatabaseBackend, IntoActiveModel, Schema, Set,
+    };
 
-    use crate::entity::{self, post, user};
+    use crate::entity::{
+        self,
+        post::{self, PostStatus},
+        user,
+    };
 
-    use super::{TaxonomyKind, TaxonomyService};
+    use super::{PostTerms, TaxonomyKind, TaxonomyService};
 
     async fn database_with_taxonomy_schema() -> sea_orm::DatabaseConnection {
         let database = Database::connect("sqlite::memory:").await.unwrap();
@@ -282,7 +354,7 @@ mod tests {
         database: &sea_orm::DatabaseConnection,
         id: i32,
         name: &str,
-        hidden: bool,
+        status: PostStatus,
     ) -> post::Model {
         post::ActiveModel {
             id: Set(id),
@@ -290,7 +362,7 @@ mod tests {
             title: Set(name.to_string()),
             content: Set(String::new()),
             author: Set(1),
-            hidden: Set(Some(hidden)),
+            status: Set(status),
             ..Default::default()
         }
         .insert(database)
@@ -312,9 +384,10 @@ mod tests {
         .insert(&database)
         .await
         .unwrap();
-        let first = insert_post(&database, 1, "first", false).await;
-        let second = insert_post(&database, 2, "second", false).await;
-        let hidden = insert_post(&database, 3, "hidden", true).await;
+        let first = insert_post(&database, 1, "first", PostStatus::Published).await;
+        let second = insert_post(&database, 2, "second", PostStatus::Published).await;
+        let hidden = insert_post(&database, 3, "hidden", PostStatus::Draft).await;
+        let unlisted = insert_post(&database, 4, "unlisted", PostStatus::Unlisted).await;
 
         TaxonomyService::replace_post_terms(
             &database,
@@ -332,14 +405,16 @@ mod tests {
         )
         .await
         .unwrap();
-        TaxonomyService::replace_post_terms(
-            &database,
-            hidden.id,
-            Some(vec!["Rust".to_string()]),
-            None,
-        )
-        .await
-        .unwrap();
+        for post_id in [hidden.id, unlisted.id] {
+            TaxonomyService::replace_post_terms(
+                &database,
+                post_id,
+                Some(vec!["Rust".to_string()]),
+                None,
+            )
+            .await
+            .unwrap();
+        }
 
         let rust_posts = TaxonomyService::visible_posts_for_term(TaxonomyKind::Tag, "Rust")
             .all(&database)
@@ -373,4 +448,92 @@ mod tests {
         assert_eq!(terms[&first.id].tags, ["Databases"]);
         assert_eq!(terms[&first.id].categories, ["Engineering"]);
     }
+
+    #[tokio::test]
+    async fn ranks_related_posts_by_weighted_overlap_then_recency() {
+        let database = database_with_taxonomy_schema().await;
+        let now = Utc::now();
+        let mut posts = Vec::new();
+        for (id, name, status, tags, categories) in [
+            (
+                1,
+                "current",
+                PostStatus::Published,
+                vec!["Rust", "Web"],
+                vec!["Engineering"],
+            ),
+            (
+                2,
+                "same-tags",
+                PostStatus::Published,
+                vec!["Rust", "Web"],
+                vec![],
+            ),
+            (
+                3,
+                "tag-and-category",
+                PostStatus::Published,
+                vec!["Rust"],
+                vec!["Engineering"],
+            ),
+            (4, "older-tag", PostStatus::Published, vec!["Web"], vec![]),
+            (5, "newer-tag", PostStatus::Published, vec!["Rust"], vec![]),
+            (
+                6,
+                "draft",
+                PostStatus::Draft,
+                vec!["Rust", "Web"],
+                vec!["Engineering"],
+            ),
+            (
+                7,
+                "unrelated",
+                PostStatus::Published,
+                vec!["Cooking"],
+                vec!["Life"],
+            ),
+        ] {
+            let mut post = insert_post(&database, id, name, status)
+                .await
+                .into_active_model();
+            post.created_at = Set(now - Duration::days(i64::from(10 - id)));
+            posts.push(post.update(&database).await.unwrap());
+            TaxonomyService::replace_post_terms(
+                &database,
+                id,
+                Some(tags.into_iter().map(str::to_string).collect()),
+                Some(categories.into_iter().map(str::to_string).collect()),
+            )
+            .await
+            .unwrap();
+        }
+        let terms = PostTerms {
+            tags: vec!["Rust".to_string(), "Web".to_string()],
+            categories: vec!["Engineering".to_string()],
+        };
+
+        let related = TaxonomyService::related_posts(&database, &posts[0], &terms, 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|post| post.name)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            related,
+            ["same-tags", "tag-and-category", "newer-tag", "older-tag"]
+        );
+        assert_eq!(
+            TaxonomyService::related_posts(&database, &posts[0], &terms, 1)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(
+            TaxonomyService::related_posts(&database, &posts[0], &PostTerms::default(), 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
 }
@@ -11,7 +11,7 @@ use axum::response::IntoResponse;
 use axum::response::Response;
 use axum_extra::response::FileStream;
 use minijinja::{AutoEscape, Environment, Value};
-use sea_orm::DatabaseConnection;
+use sea_orm::{ConnectionTrait, DatabaseConnection};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map as JsonMap, Value as JsonValue, json};
 use tokio::fs::File;
@@ -25,7 +25,9 @@ use crate::config::{
 };
 use crate::service::reloadable::ReloadableService;
 use crate::service::site_settings::SiteSettingsService;
-use crate::utils::FailibleOperationExts;
+use crate::utils::{
+    FailibleOperationExts, RenderOptions, RenderedMarkdown, ThemeShortcodes, render_markdown_with,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ThemeServiceSettings {
@@ -56,6 +58,8 @@ struct LoadedTheme {
     id: String,
     renderer_env: Environment<'static>,
     manifest: ThemeManifest,
+    /// Hash of the shortcode declarations and their template sources.
+    shortcodes_hash: String,
     config: JsonMap<String, JsonValue>,
     translations: JsonMap<String, JsonValue>,
 }
@@ -64,6 +68,8 @@ struct LoadedTheme {
 pub struct ThemeConfiguration {
     pub theme: ThemeDetails,
     pub schema: Vec<ThemeConfigField>,
+    /// Fields posts may set in their `meta`.
+    pub post_meta: Vec<ThemeConfigField>,
     pub values: JsonMap<String, JsonValue>,
 }
 
@@ -83,6 +89,12 @@ fn installed_themes_directory(asset_dir: &Path) -> PathBuf {
     asset_dir.join("themes").join("installed")
 }
 
+fn theme_static_root(asset_dir: &Path, theme_id: &str) -> PathBuf {
+    installed_themes_directory(asset_dir)
+        .join(theme_id)
+        .join("static")
+}
+
 fn theme_config_path(asset_dir: &Path, theme_id: &str) -> PathBuf {
     asset_dir
         .join("themes")
@@ -90,6 +102,25 @@ fn theme_config_path(asset_dir: &Path, theme_id: &str) -> PathBuf {
         .join(format!("{theme_id}.json"))
 }
 
+/// Raw contents of every stored theme configuration, including inactive themes.
+pub fn read_stored_theme_configs(asset_dir: &Path) -> Result<Vec<String>, io::Error> {
+    let directory = asset_dir.join("themes").join("config");
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+
+    let mut configs = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|extension| extension.to_str()) == Some("json") {
+            configs.push(fs::read_to_string(path)?);
+        }
+    }
+    Ok(configs)
+}
+
 const REQUIRED_LAYOUTS: [&str; 6] = ["home", "post", "archive", "terms", "taxonomy", "not-found"];
 const MAX_THEME_EXTRACTED_SIZE: u64 = 100 * 1024 * 1024;
 
@@ -234,6 +265,7 @@ fn validate_theme_directory(theme_directory: &Path) -> Result<ThemeManifest, The
     })?;
     manifest
         .validate_config_schema()
+        .and_then(|()| manifest.validate_shortcodes())
         .map_err(|error| ThemeInstallError::InvalidArchive(error.to_string()))?;
 
     for layout in REQUIRED_LAYOUTS {
@@ -246,6 +278,19 @@ fn validate_theme_directory(theme_directory: &Path) -> Result<ThemeManifest, The
             )));
         }
     }
+    for shortcode in &manifest.shortcodes {
+        if !is_safe_relative_path(&shortcode.template)
+            || !theme_directory
+                .join("layouts")
+                .join(&shortcode.template)
+                .is_file()
+        {
+            return Err(ThemeInstallError::InvalidArchive(format!(
+                "missing shortcode template: layouts/{}",
+                shortcode.template
+            )));
+        }
+    }
     Ok(manifest)
 }
 
@@ -334,6 +379,7 @@ impl<'a> ThemeLoader<'a> {
         }
         let manifest: ThemeManifest = toml::from_str(&fs::read_to_string(manifest_file)?)?;
         manifest.validate_config_schema()?;
+        manifest.validate_shortcodes()?;
 
         Ok(manifest)
     }
@@ -353,6 +399,23 @@ impl<'a> ThemeLoader<'a> {
     }
 }
 
+fn shortcodes_hash(manifest: &ThemeManifest, renderer_env: &Environment<'static>) -> String {
+    let mut input = String::new();
+    for shortcode in &manifest.shortcodes {
+        let source = renderer_env
+            .get_template(&shortcode.template)
+            .map(|template| template.source().to_string())
+            .unwrap_or_default();
+        input.push_str(&format!(
+            "{}\n{}\n{}\n{source}\n",
+            shortcode.name,
+            shortcode.template,
+            shortcode.iframe_hosts.join(",")
+        ));
+    }
+    format!("{:x}", md5::compute(input))
+}
+
 fn is_safe_relative_path(path: &str) -> bool {
     let path = Path::new(path);
     !path.as_os_str().is_empty()
@@ -494,6 +557,7 @@ impl ThemeService {
                 author: loaded_theme.manifest.author.clone(),
             },
             schema: loaded_theme.manifest.config.clone(),
+            post_meta: loaded_theme.manifest.post_meta.clone(),
             values: loaded_theme.config.clone(),
         })
     }
@@ -527,10 +591,59 @@ impl ThemeService {
                 author: manifest.author.clone(),
             },
             schema: manifest.config,
+            post_meta: manifest.post_meta,
             values,
         })
     }
 
+    /// Checks `values` against the schema of an installed theme, returning
+    /// the configuration `restore_theme` should store for it.
+    pub async fn resolve_restored_config(
+        &self,
+        theme_id: &str,
+        values: &JsonMap<String, JsonValue>,
+    ) -> Result<JsonMap<String, JsonValue>, ThemeError> {
+        if !is_valid_theme_id(theme_id)
+            || !self.list_themes().await?.iter().any(|id| id == theme_id)
+        {
+            return Err(ThemeError::NoTheme(theme_id.to_owned()));
+        }
+        let settings = ThemeServiceSettings {
+            current: theme_id.to_owned(),
+        };
+        let base_url = self.dep_site_settings.read().await.base_url.clone();
+        let manifest = ThemeLoader::new(&self.dep_app_cfg, &settings, base_url).get_manifest()?;
+        Ok(manifest.resolve_config(values, true)?)
+    }
+
+    /// Stores `values`, as `resolve_restored_config` returned them, as the
+    /// configuration of an installed theme and makes it the active theme from
+    /// the next reload. The setting is written through `db`, so it may be part
+    /// of the caller's transaction.
+    pub async fn restore_theme<C>(
+        &self,
+        db: &C,
+        theme_id: &str,
+        values: &JsonMap<String, JsonValue>,
+    ) -> Result<(), ThemeError>
+    where
+        C: ConnectionTrait,
+    {
+        write_theme_config_file(
+            &theme_config_path(&self.dep_app_cfg.asset_dir, theme_id),
+            values,
+        )?;
+        config_entries::THEME_SERVICE_SETTINGS
+            .set(
+                db,
+                Some(ThemeServiceSettings {
+                    current: theme_id.to_owned(),
+                }),
+            )
+            .await?;
+        Ok(())
+    }
+
     pub async fn render(
         &self,
         name: impl AsRef<str>,
@@ -574,6 +687,76 @@ impl ThemeService {
         Ok(template.render(ctx)?)
     }
 
+    /// Checks post metadata against the active theme's `post_meta` schema and
+    /// fills in its defaults. `stored` is the post's current metadata, empty
+    /// for a new post. Without a loaded theme there is no schema, so the
+    /// metadata is kept as it is.
+    pub async fn resolve_post_meta(
+        &self,
+        values: &JsonMap<String, JsonValue>,
+        stored: &JsonMap<String, JsonValue>,
+    ) -> Result<JsonMap<String, JsonValue>, ThemeConfigError> {
+        let state = self.state.read().await;
+        match &state.current_theme {
+            Some(loaded_theme) => loaded_theme.manifest.resolve_post_meta(values, stored),
+            None => Ok(values.clone()),
+        }
+    }
+
+    /// Whether the current theme provides a layout. Layouts outside
+    /// `REQUIRED_LAYOUTS` are optional, and callers fall back to a required one.
+    pub async fn has_layout(&self, name: impl AsRef<str>) -> bool {
+        let state = self.state.read().await;
+        state.current_theme.as_ref().is_some_and(|loaded_theme| {
+            let mapped_file = loaded_theme.manifest.map_layout_file(name.as_ref());
+            loaded_theme.renderer_env.get_template(&mapped_file).is_ok()
+        })
+    }
+
+    /// Identifies what the active theme contributes to rendered post content:
+    /// its id and its shortcode templates. Empty without a loaded theme.
+    pub async fn markdown_fingerprint(&self) -> String {
+        let state = self.state.read().await;
+        state
+            .current_theme
+            .as_ref()
+            .map(|loaded_theme| format!("{}:{}", loaded_theme.id, loaded_theme.shortcodes_hash))
+            .unwrap_or_default()
+    }
+
+    /// Renders post content with the active theme's shortcodes available
+    /// alongside the built-in ones.
+    pub async fn render_markdown(
+        &self,
+        source: &str,
+        options: RenderOptions<'_>,
+    ) -> Result<RenderedMarkdown, markdown::message::Message> {
+        let state = self.state.read().await;
+        let theme_shortcodes = state
+            .current_theme
+            .as_ref()
+            .map(|loaded_theme| ThemeShortcodes {
+                environment: &loaded_theme.renderer_env,
+                shortcodes: &loaded_theme.manifest.shortcodes,
+            });
+        render_markdown_with(
+            source,
+            RenderOptions {
+                theme_shortcodes,
+                ..options
+            },
+        )
+    }
+
+    /// The `static` directory of the loaded theme, served under `/static/theme/`.
+    pub async fn active_static_root(&self) -> Option<PathBuf> {
+        let state = self.state.read().await;
+        state
+            .current_theme
+            .as_ref()
+            .map(|loaded_theme| theme_static_root(&self.dep_app_cfg.asset_dir, &loaded_theme.id))
+    }
+
     #[instrument]
     pub async fn serve_static(&self, path: String) -> Result<Response, ThemeError> {
         // Check if the theme is loaded
@@ -583,13 +766,8 @@ impl ThemeService {
         }
 
         // Serve static file
-        let static_root = self
-            .dep_app_cfg
-            .asset_dir
-            .join("themes")
-            .join("installed")
-            .join(&state.current_settings.current)
-            .join("static");
+        let static_root =
+            theme_static_root(&self.dep_app_cfg.asset_dir, &state.current_settings.current);
 
         let content_type = mime_guess::from_path(&path).first_or_octet_stream();
 
@@ -788,12 +966,15 @@ impl ReloadableService for ThemeService {
             Ok(v) => v,
         };
 
+        let shortcodes_hash = shortcodes_hash(&manifest, &renderer_env);
+
         {
             let mut state = self.state.write().await;
             let loaded_theme = LoadedTheme {
                 id: settings.current.clone(),
                 manifest,
                 renderer_env,
+                shortcodes_hash,
                 config,
                 translations,
             };
@@ -829,6 +1010,9 @@ pub enum ThemeError {
     #[error(transparent)]
     ThemeConfigError(#[from] ThemeConfigError),
 
+    #[error(transparent)]
+    SettingsError(#[from] config_entries::ConfigError),
+
     #[error("Theme render contexts must be JSON objects")]
     InvalidRenderContext,
 }
@@ -0,0 +1,1053 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::LazyLock,
+    time::Duration,
+};
+
+use chrono::{NaiveDateTime, Utc};
+use quick_xml::{Reader, XmlVersion, escape::resolve_predefined_entity, events::Event};
+use regex::{Captures, Regex};
+use reqwest::Url;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait,
+    PaginatorTrait, QueryFilter, TransactionTrait, prelude::DateTimeUtc,
+};
+use serde_json::Map as JsonMap;
+
+use crate::{
+    entity::{
+        attachment,
+        post::{PostKind, PostStatus},
+        redirect::{self, RedirectMatch},
+        user,
+    },
+    service::{
+        import::{
+            ImportError, ImportFiles, ImportIssue, ImportPost, ImportReport, ImportedAttachment,
+            ImportedRedirect, MAX_IMPORT_SIZE, finish_import, percent_decode, store_import_file,
+            taken_post_names,
+        },
+        post::PostService,
+        scheduler::{Job, SchedulerService},
+        storage::StorageService,
+    },
+    utils::html_to_text,
+};
+
+/// Where WordPress keeps uploaded media, in URLs and in copies of a site.
+const UPLOADS_DIRECTORY: &str = "wp-content/uploads/";
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+/// Stands in for `<pre>` blocks while paragraphs are split on blank lines.
+const PREFORMATTED_PLACEHOLDER: char = '\u{fffc}';
+
+/// Block editor delimiters, which only matter to the WordPress editor.
+static BLOCK_COMMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)<!--\s*/?wp:.*?-->\n?").expect("block comment pattern is valid")
+});
+static CAPTION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)\[caption\b[^\]]*\](.*?)\[/caption\]").expect("caption pattern is valid")
+});
+static CAPTIONED_IMAGE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)^\s*((?:<a\b[^>]*>\s*)?<img\b[^>]*>(?:\s*</a>)?)(.*)$")
+        .expect("captioned image pattern is valid")
+});
+static EMBED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)\[embed\b[^\]]*\](.*?)\[/embed\]").expect("embed pattern is valid")
+});
+/// Media shortcodes of WordPress itself, and any other shortcode with
+/// attributes, which plugins usually provide.
+static UNSUPPORTED_SHORTCODE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"\[(gallery|audio|video|playlist)\b[^\]]*\]|\[([a-z][a-z0-9_-]*)\s+[a-z_-]+\s*=\s*["'][^\]]*\]"#,
+    )
+    .expect("shortcode pattern is valid")
+});
+static PREFORMATTED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<pre\b.*?</pre>").expect("pre pattern is valid"));
+static BLANK_LINE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\n[ \t]*\n").expect("blank line pattern is valid"));
+/// Blocks starting with one of these need no paragraph around them.
+static BLOCK_ELEMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)^</?(address|article|aside|audio|blockquote|canvas|dd|details|div|dl|dt|figcaption|figure|footer|form|h[1-6]|header|hr|iframe|li|nav|ol|p|pre|section|summary|table|tbody|td|tfoot|th|thead|tr|ul|video)\b",
+    )
+    .expect("block element pattern is valid")
+});
+static YOUTUBE_URL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^https?://(?:www\.|m\.)?(?:youtube\.com/watch\?v=|youtu\.be/)([A-Za-z0-9_-]{11})\S*$",
+    )
+    .expect("YouTube URL pattern is valid")
+});
+static BARE_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^https?://\S+$").expect("URL pattern is valid"));
+static URL_ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)(\s(?:src|href)\s*=\s*["'])([^"']+)(["'])"#)
+        .expect("URL attribute pattern is valid")
+});
+/// Responsive image candidates point at the old site and at generated sizes
+/// that are not imported.
+static RESPONSIVE_ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)\s(?:srcset|sizes)\s*=\s*(?:"[^"]*"|'[^']*')"#)
+        .expect("responsive attribute pattern is valid")
+});
+/// The size suffix of the images WordPress generates, as in
+/// `photo-300x200.jpg`.
+static SIZE_SUFFIX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"-\d+x\d+(\.[A-Za-z0-9]+)$").expect("size pattern is valid"));
+
+/// Imports WordPress eXtended RSS (WXR) exports, as written by Tools → Export.
+pub struct WordPressImportService;
+
+impl WordPressImportService {
+    /// Imports the posts and pages of every `.xml` export in `files`. Posts
+    /// belong to the user with their WordPress author's username, or to
+    /// `author` when there is none. Uploaded media is stored as attachments,
+    /// from the copies under `wp-content/uploads/` in `files` when present
+    /// and downloaded otherwise, and old permalinks become redirects. Without
+    /// `apply` nothing is written or downloaded.
+    pub async fn import(
+        db: &DatabaseConnection,
+        storage: &StorageService,
+        files: &ImportFiles,
+        author: i32,
+        apply: bool,
+    ) -> Result<ImportReport, ImportError> {
+        let now = Utc::now();
+        let mut report = ImportReport {
+            applied: apply,
+            ..Default::default()
+        };
+        let mut items = Vec::new();
+        for (path, data) in files.iter() {
+            if !path.to_ascii_lowercase().ends_with(".xml") {
+                continue;
+            }
+            let text = std::str::from_utf8(data)
+                .map_err(|_| ImportError::InvalidInput(format!("{path} is not valid UTF-8")))?;
+            items.extend(
+                parse_export(text)
+                    .map_err(|error| ImportError::InvalidInput(format!("{path}: {error}")))?,
+            );
+        }
+        if items.is_empty() {
+            return Err(ImportError::InvalidInput(
+                "no WordPress export with posts was found".to_string(),
+            ));
+        }
+
+        let mut uploads = Uploads::new(files);
+        let attachments = items
+            .iter()
+            .filter(|item| item.post_type == "attachment" && !item.attachment_url.is_empty())
+            .filter_map(|item| Some((item.post_id?, uploads.add(&item.attachment_url, true))))
+            .collect::<HashMap<_, _>>();
+        let users = user::Entity::find()
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|user| (user.username, user.id))
+            .collect::<HashMap<_, _>>();
+        let mut unknown_authors = HashSet::new();
+        let mut taken_names = taken_post_names(db).await?;
+
+        let mut posts = Vec::new();
+        for item in &items {
+            let source = item.source();
+            let kind = match item.post_type.as_str() {
+                "post" => PostKind::Post,
+                "page" => PostKind::Page,
+                "attachment" => continue,
+                other => {
+                    report.skipped.push(ImportIssue::new(
+                        source,
+                        format!("The post type `{other}` is not supported"),
+                    ));
+                    continue;
+                }
+            };
+            let created_at = parse_date(&item.date_gmt)
+                .or_else(|| parse_date(&item.date))
+                .unwrap_or(now);
+            let (status, publish_at) = match item.status.as_str() {
+                "publish" => (PostStatus::Published, None),
+                "future" => (PostStatus::Scheduled, Some(created_at)),
+                "draft" | "pending" => (PostStatus::Draft, None),
+                "private" => {
+                    report.warnings.push(ImportIssue::new(
+                        &source,
+                        "Private on the old site; imported as a draft",
+                    ));
+                    (PostStatus::Draft, None)
+                }
+                other => {
+                    report.skipped.push(ImportIssue::new(
+                        source,
+                        format!("Posts with the status `{other}` are not imported"),
+                    ));
+                    continue;
+                }
+            };
+
+            let (content, shortcodes) = convert_content(&item.content);
+            for shortcode in shortcodes {
+                report.warnings.push(ImportIssue::new(
+                    &source,
+                    format!("The shortcode [{shortcode}] has no equivalent and was kept as text"),
+                ));
+            }
+            for url in upload_references(&content) {
+                uploads.add(&url, false);
+            }
+            let mut tags = Vec::new();
+            let mut categories = Vec::new();
+            for (domain, name) in &item.terms {
+                match domain.as_str() {
+                    "post_tag" => tags.push(name.clone()),
+                    "category" => categories.push(name.clone()),
+                    _ => report.warnings.push(ImportIssue::new(
+                        &source,
+                        format!("The `{domain}` term `{name}` was not mapped"),
+                    )),
+                }
+            }
+            if item.comments > 0 {
+                report.warnings.push(ImportIssue::new(
+                    &source,
+                    format!("{} comments were not imported", item.comments),
+                ));
+            }
+            if kind == PostKind::Page && item.parent.is_some_and(|parent| parent != 0) {
+                report.warnings.push(ImportIssue::new(
+                    &source,
+                    "Nested under another page on the old site; imported at the top level",
+                ));
+            }
+            let author = match users.get(&item.creator) {
+                Some(id) => *id,
+                None => {
+                    if !item.creator.is_empty() && unknown_authors.insert(item.creator.clone()) {
+                        report.warnings.push(ImportIssue::new(
+                            &item.creator,
+                            "No user has this username; the posts were imported under the chosen author",
+                        ));
+                    }
+                    author
+                }
+            };
+
+            let name = Some(percent_decode(item.post_name.trim()))
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| {
+                    format!("{}-{}", item.post_type, item.post_id.unwrap_or_default())
+                });
+            let post = ImportPost {
+                source,
+                name,
+                title: item.title.trim().to_string(),
+                content,
+                description: Some(html_to_text(&item.excerpt)).filter(|text| !text.is_empty()),
+                illustration: None,
+                kind,
+                status,
+                publish_at,
+                created_at,
+                updated_at: parse_date(&item.modified_gmt).unwrap_or(created_at),
+                tags,
+                categories,
+                functions: Vec::new(),
+                meta: JsonMap::new(),
+                pinned: item.sticky,
+                featured: false,
+                weight: 0,
+                password_hash: None,
+            };
+            if let Err(reason) = post.claim_name(&mut taken_names) {
+                report.skipped.push(ImportIssue::new(post.source, reason));
+                continue;
+            }
+
+            let target = post.public_path();
+            match permalink_path(&item.link) {
+                Some(path) if path != target && path != "/" => {
+                    report.redirects.push(ImportedRedirect {
+                        source: path,
+                        target,
+                    });
+                }
+                Some(_) => {}
+                None if item.link.is_empty() => {}
+                None => report.warnings.push(ImportIssue::new(
+                    &post.source,
+                    format!("The permalink `{}` cannot be redirected", item.link),
+                )),
+            }
+            posts.push(WordPressPost {
+                post,
+                author,
+                password: item.password.clone(),
+                thumbnail: item
+                    .meta("_thumbnail_id")
+                    .and_then(|id| id.trim().parse().ok())
+                    .and_then(|id: i64| attachments.get(&id).cloned()),
+            });
+        }
+
+        if apply {
+            for WordPressPost { post, password, .. } in &mut posts {
+                if !password.is_empty() {
+                    post.password_hash = Some(PostService::hash_password(password)?);
+                }
+            }
+        }
+
+        // Everything is checked by now; the writes either all happen or none
+        // of them do.
+        let transaction = db.begin().await?;
+        let mut stored = Vec::new();
+        let result = async {
+            if apply {
+                uploads
+                    .store(&transaction, storage, &mut stored, &mut report)
+                    .await?;
+            }
+            report.attachments = uploads.report();
+
+            for WordPressPost {
+                mut post,
+                author,
+                thumbnail,
+                ..
+            } in posts
+            {
+                let mut imported = post.report();
+                if apply {
+                    post.content = uploads.rewrite(&post.content);
+                    post.illustration = thumbnail.and_then(|key| uploads.stored_url(&key));
+                    imported.id = Some(post.create(&transaction, author).await?.id);
+                }
+                report.posts.push(imported);
+            }
+            if apply {
+                for rule in &report.redirects {
+                    let exists = redirect::Entity::find()
+                        .filter(redirect::Column::MatchKind.eq(RedirectMatch::Exact))
+                        .filter(redirect::Column::Source.eq(&rule.source))
+                        .count(&transaction)
+                        .await?
+                        > 0;
+                    if exists {
+                        continue;
+                    }
+                    redirect::ActiveModel {
+                        source: ActiveValue::Set(rule.source.clone()),
+                        match_kind: ActiveValue::Set(RedirectMatch::Exact),
+                        target: ActiveValue::Set(Some(rule.target.clone())),
+                        status_code: ActiveValue::Set(301),
+                        hits: ActiveValue::Set(0),
+                        created_at: ActiveValue::Set(now),
+                        ..Default::default()
+                    }
+                    .insert(&transaction)
+                    .await?;
+                }
+            }
+            if apply && !report.posts.is_empty() {
+                SchedulerService::request(&transaction, Job::PingSubscribers).await?;
+            }
+            Ok(())
+        }
+        .await;
+        finish_import(db, storage, transaction, &stored, result).await?;
+
+        Ok(report)
+    }
+}
+
+struct WordPressPost {
+    post: ImportPost,
+    author: i32,
+    password: String,
+    /// Upload key of the featured image.
+    thumbnail: Option<String>,
+}
+
+/// An `<item>` of an export: a post, page, attachment, or anything else a
+/// plugin stores as a post.
+#[derive(Debug, Default)]
+struct WxrItem {
+    title: String,
+    link: String,
+    creator: String,
+    content: String,
+    excerpt: String,
+    post_id: Option<i64>,
+    date: String,
+    date_gmt: String,
+    modified_gmt: String,
+    post_name: String,
+    status: String,
+    parent: Option<i64>,
+    post_type: String,
+    password: String,
+    /// Sticky posts stay at the top of the WordPress front page.
+    sticky: bool,
+    attachment_url: String,
+    /// Taxonomy and name of each term, as in `("post_tag", "Rust")`.
+    terms: Vec<(String, String)>,
+    meta: Vec<(String, String)>,
+    comments: usize,
+}
+
+impl WxrItem {
+    fn source(&self) -> String {
+        match (&self.post_id, self.title.trim()) {
+            (Some(id), "") => format!("{} {id}", self.post_type),
+            (Some(id), title) => format!("{} {id} ({title})", self.post_type),
+            (None, title) => format!("{} ({title})", self.post_type),
+        }
+    }
+
+    fn meta(&self, key: &str) -> Option<&str> {
+        self.meta
+            .iter()
+            .find(|(meta_key, _)| meta_key == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reads the items of an export. Namespace prefixes are matched as written,
+/// since WordPress always uses the same ones.
+fn parse_export(xml: &str) -> Result<Vec<WxrItem>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut items = Vec::new();
+    let mut item: Option<WxrItem> = None;
+    let mut term_domain = String::new();
+    let mut meta_key = String::new();
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|error| format!("invalid XML at byte {}: {error}", reader.error_position()))?;
+        match event {
+            Event::Start(element) => {
+                let name = element.name().as_ref().to_string();
+                match (path.last().map(String::as_str), name.as_str()) {
+                    (Some("channel"), "item") => item = Some(WxrItem::default()),
+                    (Some("item"), "category") => {
+                        term_domain = element
+                            .try_get_attribute("domain")
+                            .map_err(|error| error.to_string())?
+                            .map(|domain| domain.normalized_value(XmlVersion::Implicit1_0))
+                            .transpose()
+                            .map_err(|error| error.to_string())?
+                            .map(|domain| domain.into_owned())
+                            .unwrap_or_else(|| "category".to_string());
+                    }
+                    (Some("item"), "wp:comment") => {
+                        if let Some(item) = &mut item {
+                            item.comments += 1;
+                        }
+                    }
+                    _ => {}
+                }
+                path.push(name);
+                text.clear();
+            }
+            Event::Text(content) => text.push_str(&content.xml10_content()),
+            Event::CData(content) => text.push_str(&content.xml10_content()),
+            Event::GeneralRef(reference) => {
+                match reference
+                    .resolve_char_ref()
+                    .map_err(|error| error.to_string())?
+                {
+                    Some(character) => text.push(character),
+                    None => text.push_str(
+                        resolve_predefined_entity(&reference)
+                            .ok_or_else(|| format!("unknown entity `&{};`", &*reference))?,
+                    ),
+                }
+            }
+            Event::End(_) => {
+                let name = path.pop().unwrap_or_default();
+                let parent = path.last().map(String::as_str);
+                let value = std::mem::take(&mut text);
+                match (parent, &mut item) {
+                    (Some("channel"), Some(_)) if name == "item" => {
+                        items.extend(item.take());
+                    }
+                    (Some("item"), Some(item)) => match name.as_str() {
+                        "title" => item.title = value,
+                        "link" => item.link = value.trim().to_string(),
+                        "dc:creator" => item.creator = value.trim().to_string(),
+                        "content:encoded" => item.content = value,
+                        "excerpt:encoded" => item.excerpt = value,
+                        "wp:post_id" => item.post_id = value.trim().parse().ok(),
+                        "wp:post_date" => item.date = value,
+                        "wp:post_date_gmt" => item.date_gmt = value,
+                        "wp:post_modified_gmt" => item.modified_gmt = value,
+                        "wp:post_name" => item.post_name = value,
+                        "wp:status" => item.status = value.trim().to_string(),
+                        "wp:post_parent" => item.parent = value.trim().parse().ok(),
+                        "wp:post_type" => item.post_type = value.trim().to_string(),
+                        "wp:post_password" => item.password = value,
+                        "wp:is_sticky" => item.sticky = value.trim() == "1",
+                        "wp:attachment_url" => item.attachment_url = value.trim().to_string(),
+                        "category" => {
+                            let value = value.trim();
+                            if !value.is_empty() {
+                                item.terms
+                                    .push((std::mem::take(&mut term_domain), value.to_string()));
+                            }
+                        }
+                        _ => {}
+                    },
+                    (Some("wp:postmeta"), Some(item)) => match name.as_str() {
+                        "wp:meta_key" => meta_key = value,
+                        "wp:meta_value" => item.meta.push((std::mem::take(&mut meta_key), value)),
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(items)
+}
+
+/// Dates in exports are written as `2024-01-31 08:00:00`; unset GMT dates are
+/// all zeroes and are not parsed.
+fn parse_date(value: &str) -> Option<DateTimeUtc> {
+    NaiveDateTime::parse_from_str(value.trim(), "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|date| date.and_utc())
+}
+
+/// Converts post HTML as the WordPress editor stores it into content for the
+/// Markdown renderer, returning it with the shortcodes it could not convert.
+///
+/// The classic editor leaves paragraphs implicit, separated by blank lines;
+/// those are wrapped in `<p>` so that Markdown syntax in the text is not
+/// interpreted. Captions become figures and embedded YouTube videos become
+/// the `youtube` shortcode.
+fn convert_content(html: &str) -> (String, Vec<String>) {
+    let html = html.replace("\r\n", "\n");
+    let html = BLOCK_COMMENT.replace_all(&html, "");
+    let html = CAPTION.replace_all(&html, |captures: &Captures| {
+        match CAPTIONED_IMAGE.captures(&captures[1]) {
+            Some(image) => format!(
+                "<figure>{}<figcaption>{}</figcaption></figure>",
+                &image[1],
+                image[2].trim()
+            ),
+            None => captures[1].to_string(),
+        }
+    });
+    let html = EMBED.replace_all(&html, |captures: &Captures| {
+        format!("\n\n{}\n\n", captures[1].trim())
+    });
+    let html = RESPONSIVE_ATTRIBUTE.replace_all(&html, "");
+
+    let mut shortcodes = Vec::new();
+    for captures in UNSUPPORTED_SHORTCODE.captures_iter(&html) {
+        let name = captures
+            .get(1)
+            .or_else(|| captures.get(2))
+            .map_or("", |name| name.as_str())
+            .to_string();
+        if !shortcodes.contains(&name) {
+            shortcodes.push(name);
+        }
+    }
+
+    let mut preformatted = Vec::new();
+    let html = PREFORMATTED.replace_all(&html, |captures: &Captures| {
+        preformatted.push(captures[0].to_string());
+        format!(
+            "\n\n{PREFORMATTED_PLACEHOLDER}{}{PREFORMATTED_PLACEHOLDER}\n\n",
+            preformatted.len() - 1
+        )
+    });
+
+    let blocks = BLANK_LINE
+        .split(&html)
+        .map(str::trim)
+        .filter(|block| !block.is_empty())
+        .map(|block| {
+            if let Some(index) = block
+                .strip_prefix(PREFORMATTED_PLACEHOLDER)
+                .and_then(|block| block.strip_suffix(PREFORMATTED_PLACEHOLDER))
+                .and_then(|index| index.parse::<usize>().ok())
+            {
+                return preformatted[index].clone();
+            }
+            if let Some(video) = YOUTUBE_URL.captures(block) {
+                return format!("{{{{< youtube {} >}}}}", &video[1]);
+            }
+            if BARE_URL.is_match(block) {
+                return format!("<p><a href=\"{block}\">{block}</a></p>");
+            }
+            if BLOCK_ELEMENT.is_match(block) {
+                return block.to_string();
+            }
+            format!(
+                "<p>{}</p>",
+                block
+                    .lines()
+                    .map(str::trim)
+                    .collect::<Vec<_>>()
+                    .join("<br>\n")
+            )
+        })
+        .collect::<Vec<_>>();
+    (blocks.join("\n\n"), shortcodes)
+}
+
+/// URLs of uploaded media linked or embedded in converted content.
+fn upload_references(content: &str) -> Vec<String> {
+    URL_ATTRIBUTE
+        .captures_iter(content)
+        .map(|captures| captures[2].to_string())
+        .filter(|url| url.contains(UPLOADS_DIRECTORY))
+        .collect()
+}
+
+/// The path a permalink was served from, or `None` when it cannot be matched
+/// by path alone, as with the plain `?p=123` permalinks.
+fn permalink_path(link: &str) -> Option<String> {
+    let url = Url::parse(link).ok()?;
+    if url.query().is_some() {
+        return None;
+    }
+    Some(url.path().to_string())
+}
+
+async fn download(client: &reqwest::Client, url: &str) -> Result<Vec<u8>, reqwest::Error> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// Media the export refers to, keyed by their path below `wp-content/uploads/`
+/// or, outside of it, by their URL.
+struct Uploads<'a> {
+    files: &'a ImportFiles,
+    /// Paths of bundled copies by upload key.
+    bundled: HashMap<String, &'a str>,
+    /// Keys of the media library, which generated sizes map back to.
+    library: HashSet<String>,
+    entries: BTreeMap<String, Upload>,
+}
+
+struct Upload {
+    /// Where to download the file when it is not bundled.
+    url: String,
+    stored_url: Option<String>,
+}
+
+impl<'a> Uploads<'a> {
+    fn new(files: &'a ImportFiles) -> Self {
+        let bundled = files
+            .iter()
+            .filter_map(|(path, _)| Some((path.rsplit_once("uploads/")?.1.to_string(), path)))
+            .collect();
+        Self {
+            files,
+            bundled,
+            library: HashSet::new(),
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// The key a URL is stored under. Generated sizes of known media map to
+    /// the original, since only originals are imported.
+    fn key(&self, url: &str) -> String {
+        let url = url.split(['?', '#']).next().unwrap_or_default();
+        let Some((_, key)) = url.split_once(UPLOADS_DIRECTORY) else {
+            return url.to_string();
+        };
+        let key = percent_decode(key);
+        let known = |key: &str| self.bundled.contains_key(key) || self.library.contains(key);
+        if known(&key) {
+            return key;
+        }
+        let original = SIZE_SUFFIX.replace(&key, "$1").into_owned();
+        if known(&original) { original } else { key }
+    }
+
+    /// Records a URL and returns its key. Media library entries should be
+    /// added before the content referring to them.
+    fn add(&mut self, url: &str, library: bool) -> String {
+        let key = self.key(url);
+        if library {
+            self.library.insert(key.clone());
+        }
+        self.entries.entry(key.clone()).or_insert_with(|| Upload {
+            url: url.to_string(),
+            stored_url: None,
+        });
+        key
+    }
+
+    /// Stores every upload. Media that cannot be downloaded is reported and
+    /// keeps pointing at the old site.
+    async fn store<C>(
+        &mut self,
+        db: &C,
+        storage: &StorageService,
+        stored: &mut Vec<attachment::Model>,
+        report: &mut ImportReport,
+    ) -> Result<(), ImportError>
+    where
+        C: ConnectionTrait,
+    {
+        let client = reqwest::Client::builder()
+            .timeout(DOWNLOAD_TIMEOUT)
+            .build()
+            .map_err(|error| ImportError::InvalidInput(error.to_string()))?;
+        for (key, upload) in &mut self.entries {
+            let data = match self.bundled.get(key).and_then(|path| self.files.get(path)) {
+                Some(data) => data.to_vec(),
+                None => match download(&client, &upload.url).await {
+                    Ok(data) if data.len() as u64 <= MAX_IMPORT_SIZE => data,
+                    Ok(_) => {
+                        report
+                            .warnings
+                            .push(ImportIssue::new(&upload.url, "The file is too large"));
+                        continue;
+                    }
+                    Err(error) => {
+                        report.warnings.push(ImportIssue::new(
+                            &upload.url,
+                            format!("Failed to download the file: {error}"),
+                        ));
+                        continue;
+                    }
+                },
+            };
+            upload.stored_url = Some(store_import_file(db, storage, key, data, stored).await?);
+        }
+        Ok(())
+    }
+
+    fn stored_url(&self, key: &str) -> Option<String> {
+        self.entries.get(key)?.stored_url.clone()
+    }
+
+    /// Points links to stored media at their attachments.
+    fn rewrite(&self, content: &str) -> String {
+        URL_ATTRIBUTE
+            .replace_all(content, |captures: &Captures| {
+                match self.stored_url(&self.key(&captures[2])) {
+                    Some(url) if captures[2].contains(UPLOADS_DIRECTORY) => {
+                        format!("{}{url}{}", &captures[1], &captures[3])
+                    }
+                    _ => captures[0].to_string(),
+                }
+            })
+            .into_owned()
+    }
+
+    fn report(&self) -> Vec<ImportedAttachment> {
+        self.entries
+            .iter()
+            .map(|(key, upload)| ImportedAttachment {
+                source: self
+                    .bundled
+                    .get(key)
+                    .map_or_else(|| upload.url.clone(), |path| path.to_string()),
+                url: upload.stored_url.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use sea_orm::{
+        ActiveModelTrait, ConnectionTrait, Database, DatabaseBackend, DatabaseConnection,
+        EntityTrait, PaginatorTrait, Schema, Set,
+    };
+
+    use crate::{
+        config::ApplicationConfiguration,
+        entity::{
+            attachment, category, job,
+            post::{self, PostKind, PostStatus},
+            post_category, post_revision, post_tag, redirect, storage_engine, tag, user,
+        },
+        service::{import::ImportFiles, storage::StorageService, taxonomy::TaxonomyService},
+    };
+
+    use super::{WordPressImportService, convert_content, parse_export};
+
+    const EXPORT: &str = r#"<?xml version="1.0" encoding="UTF-8" ?>
+<rss version="2.0"
+    xmlns:excerpt="http://wordpress.org/export/1.2/excerpt/"
+    xmlns:content="http://purl.org/rss/1.0/modules/content/"
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:wp="http://wordpress.org/export/1.2/">
+<channel>
+    <title>Old blog</title>
+    <link>https://old.example</link>
+    <item>
+        <title>Photo</title>
+        <wp:post_id>10</wp:post_id>
+        <wp:post_type><![CDATA[attachment]]></wp:post_type>
+        <wp:status><![CDATA[inherit]]></wp:status>
+        <wp:attachment_url><![CDATA[https://old.example/wp-content/uploads/2024/01/photo.png]]></wp:attachment_url>
+    </item>
+    <item>
+        <title>Hello &amp; welcome</title>
+        <link>https://old.example/2024/01/hello-world/</link>
+        <dc:creator><![CDATA[alice]]></dc:creator>
+        <content:encoded><![CDATA[<!-- wp:paragraph -->
+First *line*
+second line
+
+[caption id="attachment_10" width="300"]<img src="https://old.example/wp-content/uploads/2024/01/photo-300x200.png" srcset="https://old.example/wp-content/uploads/2024/01/photo.png 600w" /> A photo[/caption]
+
+<img src="http://127.0.0.1:1/wp-content/uploads/missing.png" />]]></content:encoded>
+        <excerpt:encoded><![CDATA[<p>A short <em>greeting</em></p>]]></excerpt:encoded>
+        <wp:post_id>11</wp:post_id>
+        <wp:post_date><![CDATA[2024-01-31 16:00:00]]></wp:post_date>
+        <wp:post_date_gmt><![CDATA[2024-01-31 08:00:00]]></wp:post_date_gmt>
+        <wp:post_name><![CDATA[hello-world]]></wp:post_name>
+        <wp:status><![CDATA[publish]]></wp:status>
+        <wp:post_parent>0</wp:post_parent>
+        <wp:post_type><![CDATA[post]]></wp:post_type>
+        <wp:post_password><![CDATA[]]></wp:post_password>
+        <category domain="category" nicename="notes"><![CDATA[Notes]]></category>
+        <category domain="post_tag" nicename="rust"><![CDATA[Rust]]></category>
+        <category domain="series" nicename="intro"><![CDATA[Intro]]></category>
+        <wp:postmeta>
+            <wp:meta_key><![CDATA[_thumbnail_id]]></wp:meta_key>
+            <wp:meta_value><![CDATA[10]]></wp:meta_value>
+        </wp:postmeta>
+        <wp:comment>
+            <wp:comment_id>1</wp:comment_id>
+            <wp:comment_content><![CDATA[Nice]]></wp:comment_content>
+        </wp:comment>
+    </item>
+    <item>
+        <title>About</title>
+        <link>https://old.example/?page_id=12</link>
+        <dc:creator><![CDATA[bob]]></dc:creator>
+        <content:encoded><![CDATA[About me]]></content:encoded>
+        <wp:post_id>12</wp:post_id>
+        <wp:post_date_gmt><![CDATA[0000-00-00 00:00:00]]></wp:post_date_gmt>
+        <wp:post_name><![CDATA[about]]></wp:post_name>
+        <wp:status><![CDATA[draft]]></wp:status>
+        <wp:post_parent>3</wp:post_parent>
+        <wp:post_type><![CDATA[page]]></wp:post_type>
+        <wp:post_password><![CDATA[secret]]></wp:post_password>
+    </item>
+    <item>
+        <title>Deleted</title>
+        <wp:post_id>13</wp:post_id>
+        <wp:status><![CDATA[trash]]></wp:status>
+        <wp:post_type><![CDATA[post]]></wp:post_type>
+    </item>
+    <item>
+        <title>Menu</title>
+        <wp:post_id>14</wp:post_id>
+        <wp:status><![CDATA[publish]]></wp:status>
+        <wp:post_type><![CDATA[nav_menu_item]]></wp:post_type>
+    </item>
+</channel>
+</rss>
+"#;
+
+    #[test]
+    fn reads_export_items() {
+        let items = parse_export(EXPORT).unwrap();
+        assert_eq!(items.len(), 5);
+        let post = &items[1];
+        assert_eq!(post.title, "Hello & welcome");
+        assert_eq!(post.link, "https://old.example/2024/01/hello-world/");
+        assert_eq!(post.creator, "alice");
+        assert_eq!(post.post_id, Some(11));
+        assert_eq!(post.status, "publish");
+        assert_eq!(
+            post.terms,
+            [
+                ("category".to_string(), "Notes".to_string()),
+                ("post_tag".to_string(), "Rust".to_string()),
+                ("series".to_string(), "Intro".to_string()),
+            ]
+        );
+        assert_eq!(post.meta("_thumbnail_id"), Some("10"));
+        assert_eq!(post.comments, 1);
+        assert_eq!(items[2].parent, Some(3));
+        assert_eq!(items[2].password, "secret");
+
+        assert!(parse_export("<rss><channel><item></channel></rss>").is_err());
+    }
+
+    #[test]
+    fn converts_editor_html() {
+        let (content, shortcodes) = convert_content(
+            "<!-- wp:paragraph -->\r\nSome *text*\r\nnext line\r\n<!-- /wp:paragraph -->\r\n\r\n<h2>Title</h2>\n\n[embed]https://www.youtube.com/watch?v=dQw4w9WgXcQ[/embed]\n\nhttps://example.com/page\n\n<pre>fn main() {\n\n}</pre>\n\n[caption id=\"a\"]<a href=\"/full.png\"><img src=\"/small.png\" sizes=\"100vw\"></a> The caption[/caption]\n\n[gallery ids=\"1,2\"] and [contact-form id=\"3\"]",
+        );
+        assert_eq!(
+            content,
+            "<p>Some *text*<br>\nnext line</p>\n\n<h2>Title</h2>\n\n{{< youtube dQw4w9WgXcQ >}}\n\n<p><a href=\"https://example.com/page\">https://example.com/page</a></p>\n\n<pre>fn main() {\n\n}</pre>\n\n<figure><a href=\"/full.png\"><img src=\"/small.png\"></a><figcaption>The caption</figcaption></figure>\n\n<p>[gallery ids=\"1,2\"] and [contact-form id=\"3\"]</p>"
+        );
+        assert_eq!(shortcodes, ["gallery", "contact-form"]);
+    }
+
+    async fn database() -> DatabaseConnection {
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        let schema = Schema::new(DatabaseBackend::Sqlite);
+        for statement in [
+            schema.create_table_from_entity(user::Entity),
+            schema.create_table_from_entity(post::Entity),
+            schema.create_table_from_entity(tag::Entity),
+            schema.create_table_from_entity(category::Entity),
+            schema.create_table_from_entity(post_tag::Entity),
+            schema.create_table_from_entity(post_category::Entity),
+            schema.create_table_from_entity(post_revision::Entity),
+            schema.create_table_from_entity(redirect::Entity),
+            schema.create_table_from_entity(storage_engine::Entity),
+            schema.create_table_from_entity(attachment::Entity),
+            schema.create_table_from_entity(job::Entity),
+        ] {
+            database.execute(&statement).await.unwrap();
+        }
+        storage_engine::ActiveModel {
+            name: Set("Local Storage".to_string()),
+            comments: Set(String::new()),
+            kind: Set("local".to_string()),
+            config_json: Set(None),
+            is_default: Set(true),
+            enabled: Set(true),
+            ..Default::default()
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+        database
+    }
+
+    #[tokio::test]
+    async fn imports_posts_media_and_permalinks() {
+        let temporary_directory = tempfile::tempdir().unwrap();
+        let storage = StorageService::new(ApplicationConfiguration::for_tests(
+            temporary_directory.path(),
+        ));
+        let database = database().await;
+        let alice = user::ActiveModel {
+            username: Set("alice".to_string()),
+            email: Set("alice@example.com".to_string()),
+            nickname: Set("Alice".to_string()),
+            password_hash: Set(String::new()),
+            created_at: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(&database)
+        .await
+        .unwrap();
+        let files = ImportFiles::from_iter([
+            ("export/blog.xml", EXPORT.as_bytes().to_vec()),
+            (
+                "export/wp-content/uploads/2024/01/photo.png",
+                b"photo".to_vec(),
+            ),
+        ]);
+
+        let report = WordPressImportService::import(&database, &storage, &files, 7, false)
+            .await
+            .unwrap();
+        assert_eq!(report.posts.len(), 2);
+        assert!(report.posts.iter().all(|post| post.id.is_none()));
+        assert_eq!(report.attachments.len(), 2);
+        assert_eq!(report.skipped.len(), 2);
+        assert_eq!(report.redirects.len(), 1);
+        assert_eq!(report.redirects[0].source, "/2024/01/hello-world/");
+        assert_eq!(report.redirects[0].target, "/posts/hello-world");
+        for expected in [
+            "`series` term `Intro`",
+            "1 comments",
+            "No user has this username",
+            "imported at the top level",
+            "cannot be redirected",
+        ] {
+            assert!(
+                report
+                    .warnings
+                    .iter()
+                    .any(|issue| issue.reason.contains(expected)),
+                "missing warning {expected}"
+            );
+        }
+        assert_eq!(post::Entity::find().count(&database).await.unwrap(), 0);
+
+        let report = WordPressImportService::import(&database, &storage, &files, 7, true)
+            .await
+            .unwrap();
+        let photo_url = format!("/attachments/{:x}", md5::compute(b"photo"));
+        assert!(
+            report
+                .warnings
+                .iter()
+                .any(|issue| issue.reason.contains("Failed to download"))
+        );
+        let hello = post::Entity::find_by_id(report.posts[0].id.unwrap())
+            .one(&database)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(hello.name, "hello-world");
+        assert_eq!(hello.title, "Hello & welcome");
+        assert_eq!(hello.author, alice.id);
+        assert_eq!(hello.status, PostStatus::Published);
+        assert_eq!(
+            hello.created_at,
+            Utc.with_ymd_and_hms(2024, 1, 31, 8, 0, 0).unwrap()
+        );
+        assert_eq!(hello.description.as_deref(), Some("A short greeting"));
+        assert_eq!(hello.illustration.as_deref(), Some(photo_url.as_str()));
+        assert!(
+            hello
+                .content
+                .contains(&format!("<img src=\"{photo_url}\" />"))
+        );
+        assert!(
+            hello
+                .content
+                .contains("http://127.0.0.1:1/wp-content/uploads/missing.png")
+        );
+        let terms = TaxonomyService::terms_for_posts(&database, &[hello.id])
+            .await
+            .unwrap();
+        assert_eq!(terms[&hello.id].tags, ["Rust"]);
+        assert_eq!(terms[&hello.id].categories, ["Notes"]);
+
+        let about = post::Entity::find_by_id(report.posts[1].id.unwrap())
+            .one(&database)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(about.kind, PostKind::Page);
+        assert_eq!(about.status, PostStatus::Draft);
+        assert_eq!(about.author, 7);
+        assert!(bcrypt::verify("secret", about.password_hash.as_deref().unwrap()).unwrap());
+
+        let rule = redirect::Entity::find()
+            .one(&database)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(rule.source, "/2024/01/hello-world/");
+        assert_eq!(rule.target.as_deref(), Some("/posts/hello-world"));
+        assert_eq!(rule.status_code, 301);
+    }
+}
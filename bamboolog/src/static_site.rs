@@ -0,0 +1,640 @@
+//! Renders the public site into a directory that any static file host can
+//! serve without a running Bamboolog server.
+//!
+//! Every page is requested from the same router the server uses, so themes
+//! render exactly as they do live. Paginated listings move from `?page=N` to
+//! `/page/N/` and attachments gain a file extension, since static hosts cannot
+//! route query strings or guess content types; links are rewritten to match.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs as std_fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+};
+
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    http::{Request, StatusCode},
+};
+use chrono::Utc;
+use regex::{Captures, Regex};
+use sea_orm::{DatabaseConnection, DbErr, EntityTrait, PaginatorTrait};
+use tokio::fs;
+use tower::ServiceExt;
+
+use crate::{
+    config::{ApplicationConfiguration, SiteSettings},
+    entity::{attachment, post},
+    service::{
+        import::percent_decode,
+        post::PostService,
+        storage::StorageService,
+        taxonomy::{TaxonomyKind, TaxonomyService},
+    },
+    utils::Pagination,
+    web::AppServices,
+};
+
+/// Requested to render the theme's not-found layout; no route serves it.
+const NOT_FOUND_PROBE: &str = "/404.html";
+
+static URL_ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)(\s(?:href|src|action|poster|content)\s*=\s*)(?:"([^"]*)"|'([^']*)')"#)
+        .unwrap()
+});
+
+#[derive(Debug, thiserror::Error)]
+pub enum StaticBuildError {
+    #[error(transparent)]
+    Database(#[from] DbErr),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("`{path}` responded with {status}")]
+    Route { path: String, status: StatusCode },
+    #[error("Failed to read the response of `{path}`: {source}")]
+    Body { path: String, source: axum::Error },
+    #[error("Failed to read attachment `{hash}`: {source}")]
+    Storage { hash: String, source: anyhow::Error },
+}
+
+#[derive(Debug, Default)]
+pub struct StaticBuildSummary {
+    pub pages: usize,
+    pub attachments: usize,
+    /// Public paths left out because they cannot work without the server,
+    /// such as password-protected posts, which get a placeholder page.
+    pub skipped: Vec<String>,
+}
+
+/// Writes every public page, feed and theme asset under `out`, with the
+/// attachments they, the site settings or the theme configuration link to.
+/// Existing files with the same names are overwritten.
+pub async fn build_static_site(
+    config: &Arc<ApplicationConfiguration>,
+    database: DatabaseConnection,
+    out: &Path,
+) -> Result<StaticBuildSummary, StaticBuildError> {
+    let services = AppServices::configure(config, database).await;
+    let database = &services.database;
+    let site = services.site_settings_service.read().await.clone();
+    fs::create_dir_all(out).await?;
+
+    let attachments = attachment::Entity::find().all(database).await?;
+    let attachment_files = attachments
+        .iter()
+        .map(|attachment| {
+            (
+                attachment.hash.clone(),
+                StorageService::file_name(attachment),
+            )
+        })
+        .collect();
+
+    if let Some(static_root) = services.theme_service.active_static_root().await
+        && static_root.is_dir()
+    {
+        copy_directory(&static_root, &out.join("static").join("theme"))?;
+    }
+
+    let mut builder = StaticSiteBuilder {
+        app: services.router(config.clone()),
+        out,
+        rewriter: UrlRewriter::new(&site.base_url, attachment_files),
+        summary: StaticBuildSummary::default(),
+    };
+    builder.build_pages(database, &site).await?;
+
+    // Settings may link to files that no page shows, such as the favicon.
+    let mut configured = vec![serde_json::to_string(&site).unwrap_or_default()];
+    if let Ok(theme) = services.theme_service.active_theme_configuration().await {
+        configured.push(serde_json::Value::Object(theme.values).to_string());
+    }
+    let mut summary = builder.summary;
+    for attachment in attachments {
+        let linked = builder
+            .rewriter
+            .linked_attachments
+            .contains(&attachment.hash)
+            || configured
+                .iter()
+                .any(|text| text.contains(&attachment.hash));
+        if !linked {
+            continue;
+        }
+        let bytes = services
+            .storage_service
+            .read(database, &attachment)
+            .await
+            .map_err(|source| StaticBuildError::Storage {
+                hash: attachment.hash.clone(),
+                source,
+            })?;
+        let file_name = StorageService::file_name(&attachment);
+        write_file(&out.join("attachments").join(file_name), &bytes).await?;
+        summary.attachments += 1;
+    }
+    Ok(summary)
+}
+
+struct StaticSiteBuilder<'a> {
+    app: Router,
+    out: &'a Path,
+    rewriter: UrlRewriter,
+    summary: StaticBuildSummary,
+}
+
+impl StaticSiteBuilder<'_> {
+    async fn build_pages(
+        &mut self,
+        database: &DatabaseConnection,
+        site: &SiteSettings,
+    ) -> Result<(), StaticBuildError> {
+        let listed = PostService::listed_posts().count(database).await?;
+        self.write_listing("/", listed, site).await?;
+        self.write_listing("/archives", listed, site).await?;
+
+        for kind in [TaxonomyKind::Tag, TaxonomyKind::Category] {
+            let field = kind.path_segment();
+            self.write_page(&format!("/{field}"), 1).await?;
+            for (term, count) in TaxonomyService::visible_term_counts(database, kind).await? {
+                let path = format!("/{field}/{}", encode_segment(&term));
+                self.write_listing(&path, count, site).await?;
+            }
+        }
+
+        let now = Utc::now();
+        for post in post::Entity::find().all(database).await? {
+            if !PostService::is_publicly_readable(&post, now) {
+                continue;
+            }
+            let path = PostService::public_path(&post)
+                .split('/')
+                .map(encode_segment)
+                .collect::<Vec<_>>()
+                .join("/");
+            if PostService::is_protected(&post) {
+                self.write_protected_placeholder(&path, &post.title).await?;
+                self.summary.skipped.push(path);
+                continue;
+            }
+            self.write_page(&path, 1).await?;
+        }
+
+        if site.rss_enabled {
+            self.write_response("/index.xml", StatusCode::OK).await?;
+        }
+        if site.sitemap_enabled {
+            self.write_response("/sitemap.xml", StatusCode::OK).await?;
+        }
+        if !site.code_highlight_theme.is_empty() {
+            let stylesheet = format!(
+                "/static/highlight/{}.css",
+                encode_segment(&site.code_highlight_theme)
+            );
+            self.write_response(&stylesheet, StatusCode::OK).await?;
+        }
+
+        let not_found = self.fetch(NOT_FOUND_PROBE, StatusCode::NOT_FOUND).await?;
+        let not_found = self
+            .rewriter
+            .rewrite_html(&String::from_utf8_lossy(&not_found));
+        write_file(&self.out.join("404.html"), not_found.as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Writes every page of a listing with `total` posts, and at least the
+    /// first one so empty listings still exist.
+    async fn write_listing(
+        &mut self,
+        path: &str,
+        total: u64,
+        site: &SiteSettings,
+    ) -> Result<(), StaticBuildError> {
+        let page_size = site.public_posts_per_page();
+        let pages = Pagination::new(None, Some(page_size), page_size)
+            .total_pages(total)
+            .max(1);
+        for page in 1..=pages {
+            self.write_page(path, page).await?;
+        }
+        Ok(())
+    }
+
+    async fn write_page(&mut self, path: &str, page: u64) -> Result<(), StaticBuildError> {
+        let Some(file) = local_path(self.out, &static_path(path, page)) else {
+            self.summary.skipped.push(path.to_string());
+            return Ok(());
+        };
+        let uri = if page > 1 {
+            format!("{path}?page={page}")
+        } else {
+            path.to_string()
+        };
+        let html = self.fetch(&uri, StatusCode::OK).await?;
+        let html = self.rewriter.rewrite_html(&String::from_utf8_lossy(&html));
+        write_file(&file.join("index.html"), html.as_bytes()).await?;
+        self.summary.pages += 1;
+        Ok(())
+    }
+
+    /// Stands in for a password-protected post, whose password form needs the
+    /// server, so the links listings, the feed and the sitemap keep to it
+    /// still resolve.
+    async fn write_protected_placeholder(
+        &mut self,
+        path: &str,
+        title: &str,
+    ) -> Result<(), StaticBuildError> {
+        let Some(file) = local_path(self.out, &static_path(path, 1)) else {
+            return Ok(());
+        };
+        let html = format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+             <meta name=\"robots\" content=\"noindex\"><title>{title}</title></head>\
+             <body><h1>{title}</h1>\
+             <p>This post is password protected and is not part of the static site.</p>\
+             <p><a href=\"/\">Home</a></p></body></html>",
+            title = escape_html(title),
+        );
+        let html = self.rewriter.rewrite_html(&html);
+        write_file(&file.join("index.html"), html.as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Writes a response unchanged, at the path it is served from.
+    async fn write_response(
+        &mut self,
+        path: &str,
+        expected: StatusCode,
+    ) -> Result<(), StaticBuildError> {
+        let body = self.fetch(path, expected).await?;
+        match local_path(self.out, path) {
+            Some(file) => write_file(&file, &body).await?,
+            None => self.summary.skipped.push(path.to_string()),
+        }
+        Ok(())
+    }
+
+    async fn fetch(&self, uri: &str, expected: StatusCode) -> Result<Bytes, StaticBuildError> {
+        let request = Request::get(uri)
+            .body(Body::empty())
+            .expect("static build paths are percent-encoded");
+        let response = self
+            .app
+            .clone()
+            .oneshot(request)
+            .await
+            .unwrap_or_else(|never| match never {});
+        if response.status() != expected {
+            return Err(StaticBuildError::Route {
+                path: uri.to_string(),
+                status: response.status(),
+            });
+        }
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .map_err(|source| StaticBuildError::Body {
+                path: uri.to_string(),
+                source,
+            })
+    }
+}
+
+/// Maps server URLs in rendered HTML to their static counterparts, under the
+/// path of `base_url` when the site does not live at the root of its host.
+struct UrlRewriter {
+    base_url: String,
+    base_path: String,
+    attachment_files: HashMap<String, String>,
+    /// Hashes of the attachments rewritten pages link to.
+    linked_attachments: HashSet<String>,
+}
+
+impl UrlRewriter {
+    fn new(base_url: &str, attachment_files: HashMap<String, String>) -> Self {
+        let base_url = base_url.trim().trim_end_matches('/').to_string();
+        let base_path = reqwest::Url::parse(&base_url)
+            .map(|url| url.path().trim_end_matches('/').to_string())
+            .unwrap_or_default();
+        Self {
+            base_url,
+            base_path,
+            attachment_files,
+            linked_attachments: HashSet::new(),
+        }
+    }
+
+    fn rewrite_html(&mut self, html: &str) -> String {
+        URL_ATTRIBUTE
+            .replace_all(html, |captures: &Captures| {
+                let (value, quote) = match captures.get(2) {
+                    Some(value) => (value.as_str(), '"'),
+                    None => (&captures[3], '\''),
+                };
+                match self.rewrite_url(value) {
+                    Some(url) => format!("{}{quote}{url}{quote}", &captures[1]),
+                    None => captures[0].to_string(),
+                }
+            })
+            .into_owned()
+    }
+
+    /// The static form of a root-relative URL or one under `base_url`. Other
+    /// URLs are left alone.
+    fn rewrite_url(&mut self, value: &str) -> Option<String> {
+        let (local, absolute) = match value.strip_prefix(self.base_url.as_str()) {
+            Some(rest)
+                if !self.base_url.is_empty()
+                    && (rest.is_empty() || rest.starts_with(['/', '?', '#'])) =>
+            {
+                (rest, true)
+            }
+            _ if value.starts_with('/') && !value.starts_with("//") => (value, false),
+            _ => return None,
+        };
+        let (local, fragment) = match local.split_once('#') {
+            Some((local, fragment)) => (local, format!("#{fragment}")),
+            None => (local, String::new()),
+        };
+        let (path, query) = match local.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (local, None),
+        };
+        let path = if path.is_empty() { "/" } else { path };
+
+        let page = query
+            .and_then(|query| query.strip_prefix("page="))
+            .and_then(|page| page.parse::<u64>().ok());
+        let mut url = match page {
+            Some(page) => static_path(path, page),
+            None => {
+                let attachment = path.strip_prefix("/attachments/").and_then(|hash| {
+                    self.attachment_files
+                        .get_key_value(hash)
+                        .map(|(hash, file_name)| (hash.clone(), file_name.clone()))
+                });
+                let mut url = match attachment {
+                    Some((hash, file_name)) => {
+                        self.linked_attachments.insert(hash);
+                        format!("/attachments/{file_name}")
+                    }
+                    None => path.to_string(),
+                };
+                if let Some(query) = query {
+                    url.push('?');
+                    url.push_str(query);
+                }
+                url
+            }
+        };
+        url.push_str(&fragment);
+
+        let prefix = if absolute {
+            &self.base_url
+        } else {
+            &self.base_path
+        };
+        Some(format!("{prefix}{url}"))
+    }
+}
+
+/// The static URL of page `page` of the listing at `path`.
+fn static_path(path: &str, page: u64) -> String {
+    if page > 1 {
+        format!("{}/page/{page}/", path.trim_end_matches('/'))
+    } else {
+        path.to_string()
+    }
+}
+
+/// Where the file for a URL path lives under `out`, or `None` when a decoded
+/// segment could escape it.
+fn local_path(out: &Path, url_path: &str) -> Option<PathBuf> {
+    let mut file = out.to_path_buf();
+    for segment in url_path.split('/').filter(|segment| !segment.is_empty()) {
+        let segment = percent_decode(segment);
+        if matches!(segment.as_str(), "." | "..") || segment.contains(['/', '\\']) {
+            return None;
+        }
+        file.push(segment);
+    }
+    Some(file)
+}
+
+fn encode_segment(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
+
+async fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(path, contents).await
+}
+
+fn copy_directory(from: &Path, to: &Path) -> io::Result<()> {
+    std_fs::create_dir_all(to)?;
+    for entry in std_fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_directory(&entry.path(), &target)?;
+        } else {
+            std_fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, fs};
+
+    use chrono::{TimeZone, Utc};
+    use sea_orm::{ActiveModelTrait, Database, Set};
+
+    use crate::{
+        config::{ApplicationConfiguration, SiteSettings, config_entries},
+        entity::post::{self, PostKind, PostStatus},
+        maintenance::sync_entities,
+        service::{storage::StorageService, taxonomy::TaxonomyService},
+    };
+
+    use super::{UrlRewriter, build_static_site};
+
+    const LAYOUTS: [(&str, &str); 6] = [
+        (
+            "home",
+            r#"{% for post in posts %}<a href="{{ post.url }}">{{ post.title }}</a>{% endfor %}{% if pagination.has_next %}<a href="{{ pagination.next_url }}">Next</a>{% endif %}<link rel="stylesheet" href="{{ 'style.css' | theme_static }}">"#,
+        ),
+        ("post", "<article>{{ content | safe }}</article>"),
+        ("archive", "Archives"),
+        ("terms", "Terms"),
+        (
+            "taxonomy",
+            r#"{{ page.title }}{% if pagination.has_next %}<a href="{{ pagination.next_url }}">Next</a>{% endif %}"#,
+        ),
+        ("not-found", r#"Missing <a href="/">Home</a>"#),
+    ];
+
+    #[tokio::test]
+    async fn builds_a_self_contained_site_under_the_base_path() {
+        let assets = tempfile::tempdir().unwrap();
+        let theme = assets.path().join("themes/installed/default");
+        fs::create_dir_all(theme.join("layouts")).unwrap();
+        fs::create_dir_all(theme.join("static")).unwrap();
+        fs::write(theme.join("manifest.toml"), "name = 'Default'\n").unwrap();
+        fs::write(theme.join("static/style.css"), "body {}").unwrap();
+        for (layout, template) in LAYOUTS {
+            fs::write(theme.join("layouts").join(layout), template).unwrap();
+        }
+        let config = ApplicationConfiguration::for_tests(assets.path());
+        let database = Database::connect("sqlite::memory:").await.unwrap();
+        sync_entities(&database).await.unwrap();
+        let storage = StorageService::new(config.clone());
+        let mut uploads = Vec::new();
+        for (data, file_name) in [
+            (b"image".as_slice(), "cover.png"),
+            (b"icon", "favicon.png"),
+            (b"hidden", "hidden.png"),
+            (b"unused", "unused.png"),
+        ] {
+            let upload = storage
+                .upload(
+                    &database,
+                    data.to_vec(),
+                    "image/png".to_string(),
+                    Some(file_name.to_string()),
+                    None,
+                )
+                .await
+                .unwrap();
+            uploads.push(upload);
+        }
+        let [image, favicon, hidden, unused] = &uploads[..] else {
+            unreachable!()
+        };
+        config_entries::SITE_SETTINGS
+            .set(
+                &database,
+                Some(&SiteSettings {
+                    base_url: "https://example.test/blog/".to_string(),
+                    posts_per_page: 1,
+                    favicon_url: format!("/attachments/{}", favicon.hash),
+                    ..Default::default()
+                }),
+            )
+            .await
+            .unwrap();
+        for (day, name, password_hash, cover) in [
+            (1, "first", None, image),
+            (2, "second", None, image),
+            (3, "secret", Some("$2b$12$hash".to_string()), hidden),
+        ] {
+            let created_at = Utc.with_ymd_and_hms(2024, 1, day, 8, 0, 0).unwrap();
+            let post = post::ActiveModel {
+                name: Set(name.to_string()),
+                title: Set(name.to_string()),
+                content: Set(format!("![Cover](/attachments/{})", cover.hash)),
+                author: Set(1),
+                kind: Set(PostKind::Post),
+                status: Set(PostStatus::Published),
+                password_hash: Set(password_hash),
+                created_at: Set(created_at),
+                ..Default::default()
+            }
+            .insert(&database)
+            .await
+            .unwrap();
+            TaxonomyService::replace_post_terms(
+                &database,
+                post.id,
+                Some(vec!["Rust".to_string()]),
+                None,
+            )
+            .await
+            .unwrap();
+        }
+
+        let out = tempfile::tempdir().unwrap();
+        let summary = build_static_site(&config, database, out.path())
+            .await
+            .unwrap();
+        let read = |path: &str| fs::read_to_string(out.path().join(path)).unwrap();
+
+        assert_eq!(summary.attachments, 2);
+        assert_eq!(summary.skipped, vec!["/posts/secret".to_string()]);
+        let home = read("index.html");
+        assert!(home.contains(r#"href="/blog/posts/secret""#));
+        assert!(home.contains(r#"href="/blog/page/2/""#));
+        assert!(home.contains(r#"href="https://example.test/blog/static/theme/style.css""#));
+        assert!(read("page/3/index.html").contains(r#"href="/blog/posts/first""#));
+        assert!(read("tags/Rust/index.html").contains(r#"href="/blog/tags/Rust/page/2/""#));
+        assert!(out.path().join("tags/Rust/page/3/index.html").exists());
+        assert!(out.path().join("archives/page/3/index.html").exists());
+        assert!(out.path().join("categories/index.html").exists());
+        assert!(
+            read("posts/first/index.html")
+                .contains(&format!(r#"src="/blog/attachments/{}.png""#, image.hash))
+        );
+        let secret = read("posts/secret/index.html");
+        assert!(secret.contains("<h1>secret</h1>"));
+        assert!(secret.contains(r#"href="/blog/""#));
+        assert!(!secret.contains(&hidden.hash));
+        assert_eq!(
+            fs::read(out.path().join(format!("attachments/{}.png", image.hash))).unwrap(),
+            b"image"
+        );
+        assert!(
+            out.path()
+                .join(format!("attachments/{}.png", favicon.hash))
+                .exists()
+        );
+        for skipped in [hidden, unused] {
+            assert!(
+                !out.path()
+                    .join(format!("attachments/{}.png", skipped.hash))
+                    .exists()
+            );
+        }
+        assert_eq!(read("static/theme/style.css"), "body {}");
+        assert!(read("index.xml").contains("https://example.test/blog/posts/first"));
+        assert!(read("sitemap.xml").contains("https://example.test/blog/posts/second"));
+        assert_eq!(read("404.html"), r#"Missing <a href="/blog/">Home</a>"#);
+        assert_eq!(summary.pages, 3 + 3 + 1 + 1 + 3 + 2);
+    }
+
+    #[test]
+    fn rewrites_only_urls_served_by_the_site() {
+        let mut rewriter = UrlRewriter::new(
+            "https://example.test",
+            HashMap::from([("abc".to_string(), "abc.jpg".to_string())]),
+        );
+
+        assert_eq!(
+            rewriter.rewrite_html(
+                r#"<a href="/?page=2">Next</a> <img src='https://example.test/attachments/abc'> <a href="https://other.test/?page=2"> <a href="//cdn.test/x.js"> <a href="/posts/a?page=x#top">"#
+            ),
+            r#"<a href="/page/2/">Next</a> <img src='https://example.test/attachments/abc.jpg'> <a href="https://other.test/?page=2"> <a href="//cdn.test/x.js"> <a href="/posts/a?page=x#top">"#
+        );
+    }
+}
@@ -1,58 +1,468 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashSet, sync::LazyLock};
+
+use latex2mathml::{DisplayStyle, latex_to_mathml};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use syntect::{
+    highlighting::ThemeSet,
+    html::{ClassStyle, ClassedHTMLGenerator, css_for_theme_with_class_style},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+use super::shortcode::{Shortcodes, ThemeShortcodes};
+
+/// Prefix ammonia puts in front of every `id` in rendered content, so authored
+/// anchors cannot clash with the ids of the surrounding theme.
+pub const USER_CONTENT_ID_PREFIX: &str = "user-content-";
+
+static HEADING_OPEN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<h([1-6])(\s[^>]*)?>").expect("heading pattern is valid"));
+static ID_ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"\sid\s*=\s*(?:"([^"]*)"|'([^']*)')"#).expect("id pattern is valid")
+});
+static TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<[^>]*>").expect("tag pattern is valid"));
+static CODE_BLOCK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?s)<pre><code class="language-([^"\s]+)">(.*?)</code></pre>"#)
+        .expect("code block pattern is valid")
+});
+
+/// Highlighted tokens get classes like `hl-keyword`, so they cannot collide
+/// with class names a theme uses for its own markup.
+const HIGHLIGHT_CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static HIGHLIGHT_THEMES: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+
+/// `$$...$$` blocks, ```` ```math ```` fences, and `$...$` spans as the
+/// Markdown compiler emits them.
+static MATH: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?s)<pre><code class="language-math(?: math-display)?">(.*?)</code></pre>|<code class="language-math math-inline">(.*?)</code>"#,
+    )
+    .expect("math pattern is valid")
+});
+
+/// Presentation MathML produced by the LaTeX converter. `annotation-xml` and
+/// `mglyph` are left out: they let HTML back in or fetch images.
+const MATHML_TAGS: [&str; 27] = [
+    "math",
+    "menclose",
+    "merror",
+    "mfrac",
+    "mi",
+    "mmultiscripts",
+    "mn",
+    "mo",
+    "mover",
+    "mpadded",
+    "mphantom",
+    "mprescripts",
+    "mroot",
+    "mrow",
+    "ms",
+    "mspace",
+    "msqrt",
+    "mstyle",
+    "msub",
+    "msubsup",
+    "msup",
+    "mtable",
+    "mtd",
+    "mtext",
+    "mtr",
+    "munder",
+    "munderover",
+];
+const MATHML_ATTRIBUTES: [&str; 17] = [
+    "accent",
+    "accentunder",
+    "columnalign",
+    "display",
+    "displaystyle",
+    "fence",
+    "form",
+    "linethickness",
+    "lspace",
+    "mathvariant",
+    "maxsize",
+    "minsize",
+    "rspace",
+    "scriptlevel",
+    "separator",
+    "stretchy",
+    "width",
+];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderOptions<'a> {
+    /// Turns fenced code blocks into classed spans, styled by
+    /// [`highlight_stylesheet`], instead of leaving them to a client-side
+    /// highlighter.
+    pub highlight_code: bool,
+    /// Shortcodes declared by the active theme, in addition to the built-in
+    /// ones.
+    pub theme_shortcodes: Option<ThemeShortcodes<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RenderedMarkdown {
+    pub html: String,
+    /// Headings nested under the closest preceding heading of a higher level.
+    pub toc: Vec<TocEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TocEntry {
+    pub level: u8,
+    pub text: String,
+    /// The `id` of the heading in `html`, including the `user-content-` prefix.
+    pub id: String,
+    pub children: Vec<TocEntry>,
+}
 
 /// Renders Markdown with raw HTML enabled, then removes unsafe markup.
-pub fn render_markdown(source: &str) -> Result<String, markdown::message::Message> {
+/// Headings without an id get one slugged from their text.
+pub fn render_markdown(source: &str) -> Result<RenderedMarkdown, markdown::message::Message> {
+    render_markdown_with(source, RenderOptions::default())
+}
+
+pub fn render_markdown_with(
+    source: &str,
+    render_options: RenderOptions,
+) -> Result<RenderedMarkdown, markdown::message::Message> {
+    // Shortcodes are expanded and sanitized on their own, and only swapped in
+    // for their placeholders once the rest of the document is clean.
+    let shortcodes = Shortcodes::expand(source, render_options.theme_shortcodes);
+    let rendered = compile_markdown(shortcodes.source())?;
+    let (mut rendered, headings) = anchor_headings(&rendered);
+    if render_options.highlight_code {
+        rendered = highlight_code_blocks(&rendered);
+    }
+    // Sanitizing last means highlighted output is held to the same rules as
+    // everything the author wrote.
+    let html = content_sanitizer()
+        .attribute_filter(prefix_fragment_link)
+        .clean(&rendered)
+        .to_string();
+    Ok(RenderedMarkdown {
+        html: shortcodes.restore(&html),
+        toc: heading_tree(headings),
+    })
+}
+
+/// Compiles Markdown with raw HTML and math enabled. The result is not
+/// sanitized yet.
+pub(super) fn compile_markdown(source: &str) -> Result<String, markdown::message::Message> {
     let mut options = markdown::Options::gfm();
     options.compile.allow_dangerous_html = true;
     options.compile.gfm_tagfilter = false;
+    options.parse.constructs.math_flow = true;
+    options.parse.constructs.math_text = true;
 
     let rendered = markdown::to_html_with_options(source, &options)?;
-    Ok(ammonia::Builder::default()
+    Ok(math_to_mathml(&rendered))
+}
+
+/// The rules every piece of rendered content is held to. Callers add their
+/// own attribute filter, which should include [`prefix_fragment_link`].
+pub(super) fn content_sanitizer() -> ammonia::Builder<'static> {
+    let mut sanitizer = ammonia::Builder::default();
+    sanitizer
         // Syntax highlighters and themes commonly select code by class name.
         .add_generic_attributes(["class", "id"])
         .add_generic_attribute_prefixes(["aria-", "data-"])
-        .id_prefix(Some("user-content-"))
+        .id_prefix(Some(USER_CONTENT_ID_PREFIX))
         .add_tags(["input"])
         .add_tag_attribute_values("input", "type", ["checkbox"])
         .add_tag_attribute_values("input", "checked", [""])
         .add_tag_attribute_values("input", "disabled", [""])
         .add_tag_attribute_values("details", "open", [""])
-        // Keep fragment links aligned with the prefixed IDs above.
-        .attribute_filter(|tag, attribute, value| {
-            if tag == "a"
-                && attribute == "href"
-                && value.starts_with('#')
-                && !value.starts_with("#user-content-")
-            {
-                Some(Cow::Owned(format!("#user-content-{}", &value[1..])))
-            } else {
-                Some(Cow::Borrowed(value))
+        .add_tags(MATHML_TAGS);
+    for tag in MATHML_TAGS {
+        sanitizer.add_tag_attributes(tag, MATHML_ATTRIBUTES);
+    }
+    sanitizer
+}
+
+/// Keeps fragment links aligned with the prefixed IDs of the sanitizer.
+pub(super) fn prefix_fragment_link<'u>(
+    tag: &str,
+    attribute: &str,
+    value: &'u str,
+) -> Option<Cow<'u, str>> {
+    if tag == "a"
+        && attribute == "href"
+        && value.starts_with('#')
+        && !value.starts_with("#user-content-")
+    {
+        Some(Cow::Owned(format!("#user-content-{}", &value[1..])))
+    } else {
+        Some(Cow::Borrowed(value))
+    }
+}
+
+/// Gives every heading an id and lists the headings in document order.
+/// Raw HTML headings count too, keeping an id the author already chose.
+fn anchor_headings(html: &str) -> (String, Vec<TocEntry>) {
+    let mut result = String::with_capacity(html.len());
+    let mut headings = Vec::new();
+    let mut used_ids = HashSet::new();
+    let mut rest = html;
+
+    while let Some(captures) = HEADING_OPEN.captures(rest) {
+        let open = captures.get(0).expect("the whole match always exists");
+        let level = captures[1]
+            .parse::<u8>()
+            .expect("the level is a single digit");
+        let attributes = captures.get(2).map_or("", |attributes| attributes.as_str());
+        let after_open = &rest[open.end()..];
+        let Some(close) = after_open.find(&format!("</h{level}>")) else {
+            break;
+        };
+        let text = heading_text(&after_open[..close]);
+
+        result.push_str(&rest[..open.start()]);
+        let existing_id = ID_ATTRIBUTE.captures(attributes).and_then(|id| {
+            id.get(1)
+                .or_else(|| id.get(2))
+                .map(|value| value.as_str().to_string())
+        });
+        let id = match existing_id {
+            Some(id) => {
+                result.push_str(open.as_str());
+                id
+            }
+            None => {
+                let id = unique_slug(&text, &mut used_ids);
+                result.push_str(&format!("<h{level} id=\"{id}\"{attributes}>"));
+                id
+            }
+        };
+        used_ids.insert(id.clone());
+        headings.push(TocEntry {
+            level,
+            text,
+            id: format!(
+                "{USER_CONTENT_ID_PREFIX}{}",
+                id.strip_prefix(USER_CONTENT_ID_PREFIX).unwrap_or(&id)
+            ),
+            children: Vec::new(),
+        });
+        rest = after_open;
+    }
+    result.push_str(rest);
+    (result, headings)
+}
+
+fn heading_text(inner_html: &str) -> String {
+    decode_entities(&TAG.replace_all(inner_html, ""))
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The text of rendered content with tags removed and whitespace collapsed,
+/// for excerpts and word counts.
+pub fn html_to_text(html: &str) -> String {
+    decode_entities(&TAG.replace_all(html, "").replace("&nbsp;", " "))
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// How long a piece of plain text is, for reading time estimates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextLength {
+    /// Words, counting every CJK character as one.
+    pub words: u64,
+    /// CJK characters among `words`; these scripts are read by character
+    /// rather than by space-separated word.
+    pub cjk_characters: u64,
+}
+
+pub fn text_length(text: &str) -> TextLength {
+    let mut length = TextLength::default();
+    let mut in_word = false;
+    for character in text.chars() {
+        if is_cjk(character) {
+            length.words += 1;
+            length.cjk_characters += 1;
+            in_word = false;
+        } else if character.is_whitespace() || is_cjk_punctuation(character) {
+            in_word = false;
+        } else if !in_word {
+            length.words += 1;
+            in_word = true;
+        }
+    }
+    length
+}
+
+/// Han ideographs, kana, and Hangul syllables.
+fn is_cjk(character: char) -> bool {
+    matches!(
+        character,
+        '\u{3040}'..='\u{30FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{AC00}'..='\u{D7AF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{20000}'..='\u{2FA1F}'
+    )
+}
+
+/// Ideographic spaces and punctuation, and full-width forms of ASCII
+/// punctuation, which separate CJK text the way spaces separate words.
+fn is_cjk_punctuation(character: char) -> bool {
+    matches!(
+        character,
+        '\u{3000}'..='\u{303F}' | '\u{FE30}'..='\u{FE4F}'
+    ) || ('\u{FF01}'..='\u{FF65}').contains(&character) && !character.is_alphanumeric()
+}
+
+/// Reverses the escaping the Markdown compiler applies to text.
+fn decode_entities(html: &str) -> String {
+    html.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Converts math written in LaTeX to MathML, so browsers render it without
+/// scripts. Formulas the converter rejects stay as code.
+fn math_to_mathml(html: &str) -> String {
+    MATH.replace_all(html, |captures: &regex::Captures| {
+        let (latex, display) = match (captures.get(1), captures.get(2)) {
+            (Some(block), _) => (block.as_str(), DisplayStyle::Block),
+            (None, Some(inline)) => (inline.as_str(), DisplayStyle::Inline),
+            (None, None) => unreachable!("one of the alternatives always matches"),
+        };
+        latex_to_mathml(decode_entities(latex).trim(), display)
+            .inspect_err(|error| tracing::warn!("Failed to convert math to MathML: {error}"))
+            .unwrap_or_else(|_| captures[0].to_string())
+    })
+    .into_owned()
+}
+
+/// Names accepted by [`highlight_stylesheet`] and the highlight theme setting.
+pub fn highlight_themes() -> Vec<&'static str> {
+    HIGHLIGHT_THEMES.themes.keys().map(String::as_str).collect()
+}
+
+/// CSS for the classes emitted by highlighted code blocks.
+pub fn highlight_stylesheet(theme: &str) -> Option<String> {
+    let theme = HIGHLIGHT_THEMES.themes.get(theme)?;
+    css_for_theme_with_class_style(theme, HIGHLIGHT_CLASS_STYLE)
+        .inspect_err(|error| tracing::warn!("Failed to build highlight stylesheet: {error}"))
+        .ok()
+}
+
+/// Highlights fenced code blocks in a language syntect knows. Other blocks
+/// keep their `language-*` class for client-side highlighters.
+fn highlight_code_blocks(html: &str) -> String {
+    CODE_BLOCK
+        .replace_all(html, |captures: &regex::Captures| {
+            let language = &captures[1];
+            match highlight_code(language, &decode_entities(&captures[2])) {
+                Some(highlighted) => format!(
+                    "<pre class=\"hl-code\"><code class=\"language-{language}\">{highlighted}</code></pre>"
+                ),
+                None => captures[0].to_string(),
             }
         })
-        .clean(&rendered)
-        .to_string())
+        .into_owned()
+}
+
+fn highlight_code(language: &str, code: &str) -> Option<String> {
+    let syntax = SYNTAXES.find_syntax_by_token(language)?;
+    let mut generator =
+        ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAXES, HIGHLIGHT_CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        generator
+            .parse_html_for_line_which_includes_newline(line)
+            .inspect_err(|error| tracing::warn!("Failed to highlight {language} code: {error}"))
+            .ok()?;
+    }
+    Some(generator.finalize())
+}
+
+/// GitHub-style slug: lowercase words joined by `-`, punctuation dropped, and
+/// `-1`, `-2`, ... appended to repeats.
+fn unique_slug(text: &str, used_ids: &mut HashSet<String>) -> String {
+    let mut base = text
+        .to_lowercase()
+        .chars()
+        .filter_map(|character| match character {
+            ' ' => Some('-'),
+            '-' | '_' => Some(character),
+            character if character.is_alphanumeric() => Some(character),
+            _ => None,
+        })
+        .collect::<String>();
+    if base.is_empty() {
+        base = "section".to_string();
+    }
+
+    let mut slug = base.clone();
+    let mut suffix = 0;
+    while used_ids.contains(&slug) {
+        suffix += 1;
+        slug = format!("{base}-{suffix}");
+    }
+    slug
+}
+
+fn heading_tree(headings: Vec<TocEntry>) -> Vec<TocEntry> {
+    let mut roots = Vec::new();
+    // Open branches, outermost first.
+    let mut stack: Vec<TocEntry> = Vec::new();
+    for heading in headings {
+        while stack.last().is_some_and(|open| open.level >= heading.level) {
+            close_branch(&mut stack, &mut roots);
+        }
+        stack.push(heading);
+    }
+    while !stack.is_empty() {
+        close_branch(&mut stack, &mut roots);
+    }
+    roots
+}
+
+fn close_branch(stack: &mut Vec<TocEntry>, roots: &mut Vec<TocEntry>) {
+    let entry = stack.pop().expect("only called with open branches");
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(entry),
+        None => roots.push(entry),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::render_markdown;
+    use super::{
+        RenderOptions, TextLength, TocEntry, highlight_stylesheet, highlight_themes,
+        render_markdown, render_markdown_with, text_length,
+    };
 
     #[test]
     fn renders_gfm_and_sanitizes_raw_html() {
-        let rendered =
-            render_markdown("# Heading\n\n~~removed~~\n\n<script>alert(1)</script>").unwrap();
+        let rendered = render_markdown("# Heading\n\n~~removed~~\n\n<script>alert(1)</script>")
+            .unwrap()
+            .html;
 
-        assert!(rendered.contains("<h1>Heading</h1>"));
+        assert!(rendered.contains("<h1 id=\"user-content-heading\">Heading</h1>"));
         assert!(rendered.contains("<del>removed</del>"));
         assert!(!rendered.contains("<script"));
     }
 
     #[test]
-    fn does_not_execute_theme_specific_shortcodes() {
-        let source = "{{< bilibili BV1xx411c7mD >}}";
-        let rendered = render_markdown(source).unwrap();
+    fn leaves_unregistered_shortcodes_as_text() {
+        let source = "{{< douyin 7100000000000000000 >}}";
+        let rendered = render_markdown(source).unwrap().html;
 
-        assert!(rendered.contains("bilibili BV1xx411c7mD"));
+        assert!(rendered.contains("douyin 7100000000000000000"));
         assert!(!rendered.contains("<iframe"));
     }
 
@@ -60,7 +470,8 @@ mod tests {
     fn renders_safe_raw_html_and_preserves_classes() {
         let rendered =
             render_markdown("<div class=\"callout\">Note</div>\n\n```rust\nlet value = 1;\n```")
-                .unwrap();
+                .unwrap()
+                .html;
 
         assert!(rendered.contains("<div class=\"callout\">Note</div>"));
         assert!(rendered.contains("<code class=\"language-rust\">"));
@@ -71,7 +482,8 @@ mod tests {
         let rendered = render_markdown(
             "<script>alert(1)</script><img src=\"/safe.png\" onerror=\"alert(1)\"><a href=\"javascript:alert(1)\">bad</a>",
         )
-        .unwrap();
+        .unwrap()
+        .html;
 
         assert!(!rendered.contains("<script"));
         assert!(!rendered.contains("onerror"));
@@ -81,7 +493,9 @@ mod tests {
 
     #[test]
     fn preserves_gfm_footnotes_and_task_lists() {
-        let rendered = render_markdown("- [x] Done\n\nA note[^1].\n\n[^1]: Footnote").unwrap();
+        let rendered = render_markdown("- [x] Done\n\nA note[^1].\n\n[^1]: Footnote")
+            .unwrap()
+            .html;
 
         assert!(rendered.contains("<input type=\"checkbox\""));
         assert!(rendered.contains("disabled=\"\""));
@@ -96,9 +510,127 @@ mod tests {
     fn prefixes_raw_html_anchor_ids_and_links() {
         let rendered =
             render_markdown("<a href=\"#details\">Jump</a><h2 id=\"details\">Details</h2>")
-                .unwrap();
+                .unwrap()
+                .html;
 
         assert!(rendered.contains("href=\"#user-content-details\""));
         assert!(rendered.contains("id=\"user-content-details\""));
     }
+
+    #[test]
+    fn anchors_headings_and_builds_a_nested_toc() {
+        let rendered = render_markdown(
+            "# Intro & *Setup*\n\n## Install\n\n### From source\n\n## Install\n\n<h2 id=\"raw\">Raw</h2>\n\n# 中文 标题",
+        )
+        .unwrap();
+
+        assert!(
+            rendered
+                .html
+                .contains("<h1 id=\"user-content-intro--setup\">")
+        );
+        assert!(rendered.html.contains("<h2 id=\"user-content-install\">"));
+        assert!(rendered.html.contains("<h2 id=\"user-content-install-1\">"));
+        assert!(rendered.html.contains("<h2 id=\"user-content-raw\">"));
+        let entry = |level, text: &str, id: &str, children| TocEntry {
+            level,
+            text: text.to_string(),
+            id: id.to_string(),
+            children,
+        };
+        assert_eq!(
+            rendered.toc,
+            [
+                entry(
+                    1,
+                    "Intro & Setup",
+                    "user-content-intro--setup",
+                    vec![
+                        entry(
+                            2,
+                            "Install",
+                            "user-content-install",
+                            vec![entry(3, "From source", "user-content-from-source", vec![])],
+                        ),
+                        entry(2, "Install", "user-content-install-1", vec![]),
+                        entry(2, "Raw", "user-content-raw", vec![]),
+                    ],
+                ),
+                entry(1, "中文 标题", "user-content-中文-标题", vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn highlights_known_languages_with_classed_spans_when_enabled() {
+        let source =
+            "```rust\nlet s = \"<script>alert(1)</script>\";\n```\n\n```nonexistent\nplain\n```";
+
+        let plain = render_markdown(source).unwrap().html;
+        let highlighted = render_markdown_with(
+            source,
+            RenderOptions {
+                highlight_code: true,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .html;
+
+        assert!(!plain.contains("hl-"));
+        assert!(highlighted.contains("<pre class=\"hl-code\"><code class=\"language-rust\">"));
+        assert!(highlighted.contains("<span class=\"hl-storage hl-type hl-rust\">let</span>"));
+        assert!(highlighted.contains("&lt;script&gt;"));
+        assert!(!highlighted.contains("<script"));
+        assert!(
+            highlighted.contains("<pre><code class=\"language-nonexistent\">plain\n</code></pre>")
+        );
+    }
+
+    #[test]
+    fn builds_stylesheets_for_the_bundled_highlight_themes() {
+        assert!(highlight_themes().contains(&"InspiredGitHub"));
+        assert!(
+            highlight_stylesheet("InspiredGitHub")
+                .unwrap()
+                .contains(".hl-code")
+        );
+        assert!(highlight_stylesheet("missing").is_none());
+    }
+
+    #[test]
+    fn counts_cjk_text_by_character() {
+        assert_eq!(
+            text_length("Hello, world & more"),
+            TextLength {
+                words: 4,
+                cjk_characters: 0
+            }
+        );
+        assert_eq!(
+            text_length("使用 Rust 编写的博客。ブログです！"),
+            TextLength {
+                words: 13,
+                cjk_characters: 12
+            }
+        );
+    }
+
+    #[test]
+    fn renders_math_as_sanitized_mathml() {
+        let rendered = render_markdown(
+            "Inline $x^2 < y$ math.\n\n$$\n\\frac{1}{2}\n$$\n\n$\\mathbb{R}$ <math><annotation-xml encoding=\"text/html\"><img src=x onerror=alert(1)></annotation-xml></math>",
+        )
+        .unwrap()
+        .html;
+
+        assert!(rendered.contains("<math display=\"inline\"><msup><mi>x</mi><mn>2</mn></msup><mo>&lt;</mo><mi>y</mi></math>"));
+        assert!(
+            rendered.contains("<math display=\"block\"><mfrac><mn>1</mn><mn>2</mn></mfrac></math>")
+        );
+        assert!(rendered.contains("<mi mathvariant=\"double-struck\">R</mi>"));
+        assert!(!rendered.contains("annotation-xml"));
+        assert!(!rendered.contains("onerror"));
+        assert!(!rendered.contains("language-math"));
+    }
 }
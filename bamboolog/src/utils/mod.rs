@@ -6,3 +6,5 @@ mod pagination;
 pub use pagination::*;
 mod content;
 pub use content::*;
+mod shortcode;
+pub use shortcode::*;
@@ -0,0 +1,736 @@
+use std::{borrow::Cow, collections::BTreeMap};
+
+use ammonia::Url;
+use minijinja::{Environment, Value, context};
+use rand::distr::{Alphanumeric, SampleString};
+
+use super::content::{compile_markdown, content_sanitizer, prefix_fragment_link};
+use crate::config::ThemeShortcode;
+
+/// Shortcodes available to every theme. A theme shortcode with the same name
+/// takes precedence.
+pub const BUILTIN_SHORTCODES: [&str; 5] = ["youtube", "bilibili", "figure", "gist", "callout"];
+
+const YOUTUBE_HOSTS: &[&str] = &["www.youtube-nocookie.com", "www.youtube.com"];
+const BILIBILI_HOSTS: &[&str] = &["player.bilibili.com"];
+const GIST_HOSTS: &[&str] = &["gist.github.com"];
+const IFRAME_ATTRIBUTES: [&str; 8] = [
+    "allow",
+    "allowfullscreen",
+    "height",
+    "loading",
+    "referrerpolicy",
+    "src",
+    "title",
+    "width",
+];
+const EMBED_PERMISSIONS: &str =
+    "accelerometer; clipboard-write; encrypted-media; gyroscope; picture-in-picture";
+
+/// Shortcode templates of the active theme.
+#[derive(Debug, Clone, Copy)]
+pub struct ThemeShortcodes<'a> {
+    pub environment: &'a Environment<'static>,
+    pub shortcodes: &'a [ThemeShortcode],
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ShortcodeError {
+    #[error("Shortcode `{shortcode}` needs `{argument}`")]
+    MissingArgument {
+        shortcode: String,
+        argument: &'static str,
+    },
+    #[error("Shortcode `{shortcode}` got an invalid `{argument}`: {value}")]
+    InvalidArgument {
+        shortcode: String,
+        argument: &'static str,
+        value: String,
+    },
+    #[error("Failed to render shortcode content: {0}")]
+    Markdown(markdown::message::Message),
+    #[error(transparent)]
+    Template(#[from] minijinja::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Delimiter {
+    /// `{{< >}}`: inner content is passed on as written.
+    Angle,
+    /// `{{% %}}`: inner content is rendered as Markdown first.
+    Percent,
+}
+
+impl Delimiter {
+    fn close(self) -> &'static str {
+        match self {
+            Self::Angle => ">}}",
+            Self::Percent => "%}}",
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Tag<'a> {
+    /// `{{</* ... */>}}`, written out literally without the comment markers.
+    Escaped(String),
+    Opening {
+        name: &'a str,
+        args: Vec<String>,
+        params: BTreeMap<String, String>,
+        self_closing: bool,
+    },
+    Closing(&'a str),
+}
+
+#[derive(Debug)]
+struct ShortcodeCall {
+    name: String,
+    args: Vec<String>,
+    params: BTreeMap<String, String>,
+    inner: Option<String>,
+    delimiter: Delimiter,
+}
+
+impl ShortcodeCall {
+    /// A named parameter, falling back to a positional one.
+    fn argument(&self, key: &str, position: Option<usize>) -> Option<&str> {
+        self.params
+            .get(key)
+            .or_else(|| position.and_then(|position| self.args.get(position)))
+            .map(String::as_str)
+    }
+
+    fn required(&self, key: &'static str, position: Option<usize>) -> Result<&str, ShortcodeError> {
+        self.argument(key, position)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| ShortcodeError::MissingArgument {
+                shortcode: self.name.clone(),
+                argument: key,
+            })
+    }
+
+    fn checked<'v>(
+        &self,
+        key: &'static str,
+        value: &'v str,
+        allowed: impl Fn(char) -> bool,
+    ) -> Result<&'v str, ShortcodeError> {
+        if !value.is_empty() && value.chars().all(allowed) {
+            Ok(value)
+        } else {
+            Err(ShortcodeError::InvalidArgument {
+                shortcode: self.name.clone(),
+                argument: key,
+                value: value.to_string(),
+            })
+        }
+    }
+
+    fn inner_markdown(&self) -> Result<String, ShortcodeError> {
+        compile_markdown(self.inner.as_deref().unwrap_or_default())
+            .map_err(ShortcodeError::Markdown)
+    }
+}
+
+/// Shortcodes of one document, replaced by placeholders that survive Markdown
+/// compilation and sanitizing as plain words.
+pub(super) struct Shortcodes<'a> {
+    theme: Option<ThemeShortcodes<'a>>,
+    placeholder_prefix: String,
+    source: String,
+    /// Sanitized output of each shortcode, indexed by placeholder number.
+    outputs: Vec<String>,
+}
+
+impl<'a> Shortcodes<'a> {
+    pub(super) fn expand(source: &str, theme: Option<ThemeShortcodes<'a>>) -> Self {
+        // A per-render nonce keeps authors from referring to placeholders.
+        let nonce = Alphanumeric
+            .sample_string(&mut rand::rng(), 16)
+            .to_ascii_lowercase();
+        let mut shortcodes = Self {
+            theme,
+            placeholder_prefix: format!("bamboologshortcode{nonce}n"),
+            source: String::new(),
+            outputs: Vec::new(),
+        };
+        shortcodes.source = shortcodes.replace(source);
+        shortcodes
+    }
+
+    /// The document with every known shortcode replaced by a placeholder.
+    pub(super) fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Swaps placeholders in sanitized HTML for the shortcode output. Only
+    /// placeholders in text are replaced, never ones inside tags, and a
+    /// placeholder that makes up a whole paragraph replaces the paragraph.
+    pub(super) fn restore(&self, html: &str) -> String {
+        self.restore_before(html, self.outputs.len())
+    }
+
+    fn restore_before(&self, html: &str, limit: usize) -> String {
+        if self.outputs.is_empty() {
+            return html.to_string();
+        }
+        let mut result = String::with_capacity(html.len());
+        let mut rest = html;
+        let mut in_tag = false;
+        let mut in_quotes = false;
+        while let Some(character) = rest.chars().next() {
+            if !in_tag {
+                if let Some(paragraph) = rest.strip_prefix("<p>")
+                    && let Some((index, after)) = self.placeholder(paragraph, limit)
+                    && let Some(after) = after.strip_prefix("</p>")
+                {
+                    result.push_str(&self.restore_before(&self.outputs[index], index));
+                    rest = after;
+                    continue;
+                }
+                if let Some((index, after)) = self.placeholder(rest, limit) {
+                    result.push_str(&self.restore_before(&self.outputs[index], index));
+                    rest = after;
+                    continue;
+                }
+            }
+            // The sanitizer always quotes attributes with `"`, and escapes `<`
+            // in text, so this is enough to tell tags from text.
+            match character {
+                '<' if !in_tag => in_tag = true,
+                '"' if in_tag => in_quotes = !in_quotes,
+                '>' if in_tag && !in_quotes => in_tag = false,
+                _ => {}
+            }
+            result.push(character);
+            rest = &rest[character.len_utf8()..];
+        }
+        result
+    }
+
+    fn placeholder<'h>(&self, text: &'h str, limit: usize) -> Option<(usize, &'h str)> {
+        let digits = text.strip_prefix(&self.placeholder_prefix)?;
+        let length = digits.bytes().take_while(u8::is_ascii_digit).count();
+        let index = digits[..length].parse::<usize>().ok()?;
+        let after = digits[length..].strip_prefix('z')?;
+        (index < limit).then_some((index, after))
+    }
+
+    fn replace(&mut self, source: &str) -> String {
+        let mut result = String::with_capacity(source.len());
+        let mut rest = source;
+        while let Some(start) = find_delimiter(rest) {
+            result.push_str(&rest[..start]);
+            let text = &rest[start..];
+            let Some((tag, length)) = parse_tag(text) else {
+                result.push_str(&text[..3]);
+                rest = &text[3..];
+                continue;
+            };
+            let original = &text[..length];
+            rest = &text[length..];
+
+            let (name, args, params, self_closing) = match tag {
+                Tag::Escaped(literal) => {
+                    result.push_str(&literal);
+                    continue;
+                }
+                Tag::Closing(_) => {
+                    result.push_str(original);
+                    continue;
+                }
+                Tag::Opening {
+                    name,
+                    args,
+                    params,
+                    self_closing,
+                } => (name, args, params, self_closing),
+            };
+            if !self.is_registered(name) {
+                result.push_str(original);
+                continue;
+            }
+
+            let closing = (!self_closing).then(|| find_closing(rest, name)).flatten();
+            let inner = closing.map(|(inner_end, _)| self.replace(&rest[..inner_end]));
+            let call = ShortcodeCall {
+                name: name.to_string(),
+                args,
+                params,
+                inner,
+                delimiter: delimiter_of(text),
+            };
+            match self.render(&call) {
+                Ok(html) => {
+                    result.push_str(&format!(
+                        "{}{}z",
+                        self.placeholder_prefix,
+                        self.outputs.len()
+                    ));
+                    self.outputs.push(html);
+                    if let Some((_, closing_end)) = closing {
+                        rest = &rest[closing_end..];
+                    }
+                }
+                Err(error) => {
+                    tracing::warn!("Leaving shortcode `{name}` unexpanded: {error}");
+                    result.push_str(original);
+                }
+            }
+        }
+        result.push_str(rest);
+        result
+    }
+
+    fn theme_shortcode(
+        &self,
+        name: &str,
+    ) -> Option<(&'a Environment<'static>, &'a ThemeShortcode)> {
+        let theme = self.theme?;
+        theme
+            .shortcodes
+            .iter()
+            .find(|shortcode| shortcode.name == name)
+            .map(|shortcode| (theme.environment, shortcode))
+    }
+
+    fn is_registered(&self, name: &str) -> bool {
+        BUILTIN_SHORTCODES.contains(&name) || self.theme_shortcode(name).is_some()
+    }
+
+    fn render(&self, call: &ShortcodeCall) -> Result<String, ShortcodeError> {
+        if let Some((environment, shortcode)) = self.theme_shortcode(&call.name) {
+            let inner = match (&call.inner, call.delimiter) {
+                (None, _) => None,
+                (Some(_), Delimiter::Percent) => {
+                    Some(Value::from_safe_string(call.inner_markdown()?))
+                }
+                (Some(inner), Delimiter::Angle) => Some(Value::from(inner.as_str())),
+            };
+            let html = environment
+                .get_template(&shortcode.template)?
+                .render(context! {
+                    name => call.name,
+                    args => call.args,
+                    params => call.params,
+                    inner => inner,
+                })?;
+            return Ok(sanitize(&html, &shortcode.iframe_hosts));
+        }
+
+        let (html, iframe_hosts) = match call.name.as_str() {
+            "youtube" => (youtube(call)?, YOUTUBE_HOSTS),
+            "bilibili" => (bilibili(call)?, BILIBILI_HOSTS),
+            "gist" => (gist(call)?, GIST_HOSTS),
+            "figure" => (figure(call)?, &[][..]),
+            "callout" => (callout(call)?, &[][..]),
+            _ => unreachable!("only registered shortcodes are rendered"),
+        };
+        Ok(sanitize(&html, iframe_hosts))
+    }
+}
+
+/// Holds shortcode output to the same rules as authored content, except that
+/// it may embed iframes from its own hosts.
+fn sanitize(html: &str, iframe_hosts: &[impl AsRef<str>]) -> String {
+    let iframe_hosts = iframe_hosts
+        .iter()
+        .map(|host| host.as_ref().to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    let mut sanitizer = content_sanitizer();
+    if !iframe_hosts.is_empty() {
+        sanitizer
+            .add_tags(["iframe"])
+            .add_tag_attributes("iframe", IFRAME_ATTRIBUTES);
+    }
+    sanitizer.attribute_filter(move |tag, attribute, value| {
+        if tag == "iframe" && attribute == "src" {
+            is_allowed_embed(value, &iframe_hosts).then_some(Cow::Borrowed(value))
+        } else {
+            prefix_fragment_link(tag, attribute, value)
+        }
+    });
+    sanitizer.clean(html).to_string()
+}
+
+fn is_allowed_embed(src: &str, iframe_hosts: &[String]) -> bool {
+    Url::parse(src).is_ok_and(|url| {
+        url.scheme() == "https"
+            && url
+                .host_str()
+                .is_some_and(|host| iframe_hosts.iter().any(|allowed| allowed == host))
+    })
+}
+
+fn find_delimiter(text: &str) -> Option<usize> {
+    text.match_indices("{{")
+        .map(|(index, _)| index)
+        .find(|&index| matches!(text.as_bytes().get(index + 2), Some(b'<' | b'%')))
+}
+
+fn delimiter_of(text: &str) -> Delimiter {
+    if text.as_bytes()[2] == b'%' {
+        Delimiter::Percent
+    } else {
+        Delimiter::Angle
+    }
+}
+
+/// Parses the tag at the start of `text`, returning it with its length.
+fn parse_tag(text: &str) -> Option<(Tag<'_>, usize)> {
+    let delimiter = delimiter_of(text);
+    let close = delimiter.close();
+    let mut cursor = text[3..].trim_start();
+
+    if let Some(comment) = cursor.strip_prefix("/*") {
+        let end = comment.find("*/")?;
+        let after = comment[end + 2..].trim_start().strip_prefix(close)?;
+        let literal = format!("{}{}{close}", &text[..3], &comment[..end]);
+        return Some((Tag::Escaped(literal), text.len() - after.len()));
+    }
+
+    let closing = match cursor.strip_prefix('/') {
+        Some(after) => {
+            cursor = after.trim_start();
+            true
+        }
+        None => false,
+    };
+    let name_length = cursor
+        .bytes()
+        .take_while(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_'))
+        .count();
+    if name_length == 0 {
+        return None;
+    }
+    let name = &cursor[..name_length];
+    cursor = &cursor[name_length..];
+
+    let mut args = Vec::new();
+    let mut params = BTreeMap::new();
+    let mut self_closing = false;
+    loop {
+        let trimmed = cursor.trim_start();
+        if let Some(after) = trimmed.strip_prefix(close) {
+            let length = text.len() - after.len();
+            let tag = if closing {
+                Tag::Closing(name)
+            } else {
+                Tag::Opening {
+                    name,
+                    args,
+                    params,
+                    self_closing,
+                }
+            };
+            return Some((tag, length));
+        }
+        // Arguments have to be separated from the name and each other.
+        if trimmed.len() == cursor.len() || self_closing || closing {
+            return None;
+        }
+        if let Some(after) = trimmed.strip_prefix('/')
+            && after.trim_start().starts_with(close)
+        {
+            self_closing = true;
+            cursor = after;
+            continue;
+        }
+
+        let key_length = trimmed
+            .bytes()
+            .take_while(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_'))
+            .count();
+        match trimmed[key_length..].strip_prefix('=') {
+            Some(value) if key_length > 0 => {
+                let (value, after) = parse_value(value, close)?;
+                params.insert(trimmed[..key_length].to_string(), value);
+                cursor = after;
+            }
+            _ => {
+                let (value, after) = parse_value(trimmed, close)?;
+                args.push(value);
+                cursor = after;
+            }
+        }
+    }
+}
+
+/// Reads a `"quoted"`, `` `raw` `` or bare value.
+fn parse_value<'t>(text: &'t str, close: &str) -> Option<(String, &'t str)> {
+    if let Some(quoted) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut characters = quoted.char_indices();
+        while let Some((index, character)) = characters.next() {
+            match character {
+                '"' => return Some((value, &quoted[index + 1..])),
+                '\\' => value.push(characters.next()?.1),
+                character => value.push(character),
+            }
+        }
+        return None;
+    }
+    if let Some(raw) = text.strip_prefix('`') {
+        let end = raw.find('`')?;
+        return Some((raw[..end].to_string(), &raw[end + 1..]));
+    }
+    let end = text
+        .char_indices()
+        .find(|&(index, character)| {
+            character.is_whitespace() || character == '"' || text[index..].starts_with(close)
+        })
+        .map_or(text.len(), |(index, _)| index);
+    (end > 0).then(|| (text[..end].to_string(), &text[end..]))
+}
+
+/// Finds the tag closing `name`, skipping nested pairs of the same shortcode.
+/// Returns where the inner content ends and where the closing tag ends.
+fn find_closing(text: &str, name: &str) -> Option<(usize, usize)> {
+    let mut depth = 0_usize;
+    let mut offset = 0;
+    while let Some(start) = find_delimiter(&text[offset..]) {
+        let start = offset + start;
+        let Some((tag, length)) = parse_tag(&text[start..]) else {
+            offset = start + 3;
+            continue;
+        };
+        match tag {
+            Tag::Opening {
+                name: nested,
+                self_closing: false,
+                ..
+            } if nested == name => depth += 1,
+            Tag::Closing(closed) if closed == name => {
+                if depth == 0 {
+                    return Some((start, start + length));
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+        offset = start + length;
+    }
+    None
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
+
+fn is_identifier(character: char) -> bool {
+    character.is_ascii_alphanumeric() || matches!(character, '-' | '_')
+}
+
+fn embed(name: &str, src: &str, title: &str) -> String {
+    format!(
+        "<div class=\"shortcode-embed shortcode-{name}\"><iframe src=\"{}\" title=\"{}\" loading=\"lazy\" referrerpolicy=\"strict-origin-when-cross-origin\" allow=\"{EMBED_PERMISSIONS}\" allowfullscreen></iframe></div>",
+        escape_html(src),
+        escape_html(title)
+    )
+}
+
+/// `{{< youtube ID >}}`, optionally with `start` seconds and a `title`.
+fn youtube(call: &ShortcodeCall) -> Result<String, ShortcodeError> {
+    let id = call.checked("id", call.required("id", Some(0))?, is_identifier)?;
+    let mut src = format!("https://www.youtube-nocookie.com/embed/{id}");
+    if let Some(start) = call.argument("start", None) {
+        let start = call.checked("start", start, |character| character.is_ascii_digit())?;
+        src.push_str(&format!("?start={start}"));
+    }
+    let title = call.argument("title", None).unwrap_or("YouTube video");
+    Ok(embed("youtube", &src, title))
+}
+
+/// `{{< bilibili BV... >}}` or `{{< bilibili av... >}}`, optionally with a
+/// part number `p` and a `title`.
+fn bilibili(call: &ShortcodeCall) -> Result<String, ShortcodeError> {
+    let id = call.checked("id", call.required("id", Some(0))?, |character| {
+        character.is_ascii_alphanumeric()
+    })?;
+    let video = match id.strip_prefix("av").or_else(|| id.strip_prefix("AV")) {
+        Some(aid) if aid.bytes().all(|byte| byte.is_ascii_digit()) => format!("aid={aid}"),
+        _ if id.bytes().all(|byte| byte.is_ascii_digit()) => format!("aid={id}"),
+        _ => format!("bvid={id}"),
+    };
+    let mut src = format!("https://player.bilibili.com/player.html?{video}&autoplay=0");
+    if let Some(part) = call.argument("p", Some(1)) {
+        let part = call.checked("p", part, |character| character.is_ascii_digit())?;
+        src.push_str(&format!("&p={part}"));
+    }
+    let title = call.argument("title", None).unwrap_or("Bilibili video");
+    Ok(embed("bilibili", &src, title))
+}
+
+/// `{{< gist USER ID [FILE] >}}`. Gists are embedded through their `.pibb`
+/// page, since the usual embed is a script.
+fn gist(call: &ShortcodeCall) -> Result<String, ShortcodeError> {
+    let user = call.checked("user", call.required("user", Some(0))?, is_identifier)?;
+    let id = call.checked("id", call.required("id", Some(1))?, |character| {
+        character.is_ascii_alphanumeric()
+    })?;
+    let mut src = format!("https://gist.github.com/{user}/{id}.pibb");
+    if let Some(file) = call.argument("file", Some(2)) {
+        let file = call.checked("file", file, |character| {
+            is_identifier(character) || character == '.'
+        })?;
+        src.push_str(&format!("?file={file}"));
+    }
+    let title = call.argument("title", None).unwrap_or("GitHub Gist");
+    Ok(embed("gist", &src, title))
+}
+
+/// `{{< figure src="..." alt="..." caption="..." >}}`, optionally linked with
+/// `link`.
+fn figure(call: &ShortcodeCall) -> Result<String, ShortcodeError> {
+    let src = call.required("src", Some(0))?;
+    let mut image = format!("<img src=\"{}\"", escape_html(src));
+    for attribute in ["alt", "title"] {
+        if let Some(value) = call.argument(attribute, None) {
+            image.push_str(&format!(" {attribute}=\"{}\"", escape_html(value)));
+        }
+    }
+    for attribute in ["width", "height"] {
+        if let Some(value) = call.argument(attribute, None) {
+            let value = call.checked(attribute, value, |character| character.is_ascii_digit())?;
+            image.push_str(&format!(" {attribute}=\"{value}\""));
+        }
+    }
+    image.push('>');
+    if let Some(link) = call.argument("link", None) {
+        image = format!("<a href=\"{}\">{image}</a>", escape_html(link));
+    }
+    let caption = call
+        .argument("caption", None)
+        .map(|caption| format!("<figcaption>{}</figcaption>", escape_html(caption)))
+        .unwrap_or_default();
+    Ok(format!(
+        "<figure class=\"shortcode-figure\">{image}{caption}</figure>"
+    ))
+}
+
+/// `{{< callout warning title="..." >}}Markdown{{< /callout >}}`. The type
+/// defaults to `note` and becomes a `callout-*` class.
+fn callout(call: &ShortcodeCall) -> Result<String, ShortcodeError> {
+    let kind = call.checked(
+        "type",
+        call.argument("type", Some(0)).unwrap_or("note"),
+        |character| character.is_ascii_lowercase() || character == '-',
+    )?;
+    let title = call
+        .argument("title", None)
+        .map(|title| format!("<p class=\"callout-title\">{}</p>", escape_html(title)))
+        .unwrap_or_default();
+    Ok(format!(
+        "<div class=\"callout callout-{kind}\">{title}{}</div>",
+        call.inner_markdown()?
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use minijinja::Environment;
+
+    use super::ThemeShortcodes;
+    use crate::{
+        config::ThemeShortcode,
+        utils::{RenderOptions, render_markdown, render_markdown_with},
+    };
+
+    #[test]
+    fn expands_built_in_shortcodes() {
+        let rendered = render_markdown(
+            "{{< youtube dQw4w9WgXcQ start=\"42\" >}}\n\n{{< bilibili BV1xx411c7mD >}}\n\n{{< gist octocat 6cad326836d38bd3a7ae file=\"hello.rb\" >}}\n\n{{< figure src=\"/a.png\" alt=\"A <b>\" caption=\"Fig. 1\" link=\"/a\" >}}\n\n{{% callout warning title=\"Careful\" %}}\n**Hot** [link](#below)\n{{% /callout %}}",
+        )
+        .unwrap()
+        .html;
+
+        assert!(rendered.contains(
+            "<div class=\"shortcode-embed shortcode-youtube\"><iframe src=\"https://www.youtube-nocookie.com/embed/dQw4w9WgXcQ?start=42\""
+        ));
+        assert!(rendered.contains(
+            "src=\"https://player.bilibili.com/player.html?bvid=BV1xx411c7mD&amp;autoplay=0\""
+        ));
+        assert!(rendered.contains(
+            "src=\"https://gist.github.com/octocat/6cad326836d38bd3a7ae.pibb?file=hello.rb\""
+        ));
+        assert!(rendered.contains("<figure class=\"shortcode-figure\"><a href=\"/a\" rel=\"noopener noreferrer\"><img src=\"/a.png\" alt=\"A &lt;b&gt;\"></a><figcaption>Fig. 1</figcaption></figure>"));
+        assert!(rendered.contains("<div class=\"callout callout-warning\"><p class=\"callout-title\">Careful</p><p><strong>Hot</strong> <a href=\"#user-content-below\" rel=\"noopener noreferrer\">link</a></p>"));
+        assert!(!rendered.contains("<p><div"));
+        assert!(!rendered.contains("bamboologshortcode"));
+    }
+
+    #[test]
+    fn leaves_escaped_and_invalid_shortcodes_as_text() {
+        let rendered = render_markdown(
+            "{{</* youtube dQw4w9WgXcQ */>}}\n\n{{< youtube \"bad id\" >}}\n\n{{< youtube >}}",
+        )
+        .unwrap()
+        .html;
+
+        assert!(rendered.contains("{{&lt; youtube dQw4w9WgXcQ &gt;}}"));
+        assert!(rendered.contains("{{&lt; youtube \"bad id\" &gt;}}"));
+        assert!(rendered.contains("{{&lt; youtube &gt;}}"));
+        assert!(!rendered.contains("<iframe"));
+    }
+
+    #[test]
+    fn allows_iframes_only_from_the_shortcode_hosts() {
+        let rendered = render_markdown(
+            "<iframe src=\"https://www.youtube-nocookie.com/embed/x\"></iframe>\n\n[title](/a \"{{< youtube dQw4w9WgXcQ >}}\")",
+        )
+        .unwrap()
+        .html;
+
+        assert!(!rendered.contains("<iframe"));
+
+        let mut environment = Environment::new();
+        environment
+            .add_template(
+                "shortcodes/vimeo.html",
+                "<iframe src=\"https://player.vimeo.com/video/{{ args[0] }}\"></iframe><iframe src=\"https://evil.example/{{ args[0] }}\"></iframe><script>alert(1)</script>",
+            )
+            .unwrap();
+        environment
+            .add_template(
+                "shortcodes/aside.html",
+                "<aside title=\"{{ params.title }}\">{{ inner }}</aside>",
+            )
+            .unwrap();
+        let shortcodes = [
+            ThemeShortcode {
+                name: "vimeo".to_string(),
+                template: "shortcodes/vimeo.html".to_string(),
+                iframe_hosts: vec!["player.vimeo.com".to_string()],
+            },
+            ThemeShortcode {
+                name: "aside".to_string(),
+                template: "shortcodes/aside.html".to_string(),
+                iframe_hosts: Vec::new(),
+            },
+        ];
+        let rendered = render_markdown_with(
+            "{{< vimeo 76979871 >}}\n\n{{< aside title=\"\\\"><b>x\" >}}<i>raw</i> {{< vimeo 1 >}}{{< /aside >}}",
+            RenderOptions {
+                theme_shortcodes: Some(ThemeShortcodes {
+                    environment: &environment,
+                    shortcodes: &shortcodes,
+                }),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .html;
+
+        assert!(rendered.contains("<iframe src=\"https://player.vimeo.com/video/76979871\">"));
+        assert!(rendered.contains("<iframe src=\"https://player.vimeo.com/video/1\">"));
+        assert!(!rendered.contains("evil.example"));
+        assert!(!rendered.contains("<script"));
+        assert!(rendered.contains("<aside title=\"&quot;&gt;&lt;b&gt;x\">&lt;i&gt;raw&lt;/i&gt; "));
+    }
+}
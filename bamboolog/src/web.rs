@@ -3,7 +3,9 @@ use crate::{
     router::get_routes,
     service::{
         jwt::JwtService,
+        redirect::RedirectService,
         reloadable::{ReloadableService, ServiceReloader},
+        scheduler::SchedulerService,
         site_settings::SiteSettingsService,
         storage::StorageService,
         theme::ThemeService,
@@ -57,6 +59,12 @@ async fn configure_site_settings_service(database: &DatabaseConnection) -> SiteS
     service
 }
 
+async fn configure_redirect_service(database: &DatabaseConnection) -> RedirectService {
+    let service = RedirectService::new(database.to_owned());
+    service.reload().await;
+    service
+}
+
 async fn configure_theme_service(
     database: &DatabaseConnection,
     config: &Arc<ApplicationConfiguration>,
@@ -71,32 +79,81 @@ async fn configure_theme_service(
     service
 }
 
+/// Every service the routes depend on, configured as the server uses them.
+pub(crate) struct AppServices {
+    pub(crate) database: DatabaseConnection,
+    pub(crate) jwt_service: JwtService,
+    pub(crate) site_settings_service: SiteSettingsService,
+    pub(crate) theme_service: ThemeService,
+    pub(crate) storage_service: StorageService,
+    pub(crate) scheduler_service: SchedulerService,
+    pub(crate) redirect_service: RedirectService,
+    pub(crate) service_reloader: ServiceReloader,
+}
+
+impl AppServices {
+    /// Loads the services without starting background jobs.
+    pub(crate) async fn configure(
+        config: &Arc<ApplicationConfiguration>,
+        database: DatabaseConnection,
+    ) -> Self {
+        let jwt_service = configure_jwt_service(&database).await;
+        let site_settings_service = configure_site_settings_service(&database).await;
+        let theme_service =
+            configure_theme_service(&database, config, &site_settings_service).await;
+        let storage_service = StorageService::new(config.clone());
+        let scheduler_service = SchedulerService::new(
+            database.clone(),
+            config.clone(),
+            site_settings_service.clone(),
+            storage_service.clone(),
+        );
+        let redirect_service = configure_redirect_service(&database).await;
+        let service_reloader = ServiceReloader::new(vec![
+            Box::new(jwt_service.clone()),
+            Box::new(site_settings_service.clone()),
+            Box::new(theme_service.clone()),
+            Box::new(redirect_service.clone()),
+        ]);
+
+        Self {
+            database,
+            jwt_service,
+            site_settings_service,
+            theme_service,
+            storage_service,
+            scheduler_service,
+            redirect_service,
+            service_reloader,
+        }
+    }
+
+    pub(crate) fn router(&self, config: Arc<ApplicationConfiguration>) -> Router {
+        get_routes(&config).layer(
+            ServiceBuilder::new()
+                .layer(Extension(config))
+                .layer(Extension(self.database.clone()))
+                .layer(Extension(self.jwt_service.clone()))
+                .layer(Extension(self.site_settings_service.clone()))
+                .layer(Extension(self.theme_service.clone()))
+                .layer(Extension(self.storage_service.clone()))
+                .layer(Extension(self.scheduler_service.clone()))
+                .layer(Extension(self.redirect_service.clone()))
+                .layer(Extension(self.service_reloader.clone()))
+                .layer(middleware::map_response(set_security_headers)),
+        )
+    }
+}
+
 async fn build_app(config: Arc<ApplicationConfiguration>) -> Router {
     let database = config
         .connect_database()
         .await
         .expect("Failed to connect to database");
-    let jwt_service = configure_jwt_service(&database).await;
-    let site_settings_service = configure_site_settings_service(&database).await;
-    let theme_service = configure_theme_service(&database, &config, &site_settings_service).await;
-    let storage_service = StorageService::new(config.clone());
-    let service_reloader = ServiceReloader::new(vec![
-        Box::new(jwt_service.clone()),
-        Box::new(site_settings_service.clone()),
-        Box::new(theme_service.clone()),
-    ]);
-
-    get_routes(&config).layer(
-        ServiceBuilder::new()
-            .layer(Extension(config))
-            .layer(Extension(database))
-            .layer(Extension(jwt_service))
-            .layer(Extension(site_settings_service))
-            .layer(Extension(theme_service))
-            .layer(Extension(storage_service))
-            .layer(Extension(service_reloader))
-            .layer(middleware::map_response(set_security_headers)),
-    )
+    let services = AppServices::configure(&config, database).await;
+    services.scheduler_service.start();
+    services.redirect_service.start();
+    services.router(config)
 }
 
 pub async fn run(config: Arc<ApplicationConfiguration>) {